@@ -0,0 +1,41 @@
+//! Command-line tool that restores the items recorded in an encrypted
+//! mirror file to the Secret Service.
+//!
+//! Usage: `restore <mirror-file>`, with the mirror key in the
+//! `MIRROR_KEY` environment variable. Add `--list` to list the
+//! items that would be restored instead of restoring them.
+use std::path::Path;
+
+use dbus_secret_service_keyring_store::{Store, mirror};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (list, file) = match args.as_slice() {
+        [file] => (false, file),
+        [flag, file] if flag == "--list" => (true, file),
+        _ => {
+            eprintln!("Usage: restore [--list] <mirror-file>");
+            std::process::exit(2);
+        }
+    };
+    let Ok(key) = std::env::var("MIRROR_KEY") else {
+        eprintln!("The mirror key must be in the MIRROR_KEY environment variable");
+        std::process::exit(2);
+    };
+    let path = Path::new(file);
+    if list {
+        let records = mirror::surviving(mirror::read(path, &key).unwrap());
+        for record in records {
+            println!(
+                "{} (in {}): {:?}",
+                record.label, record.collection, record.attributes
+            );
+        }
+    } else {
+        let count = Store::new()
+            .unwrap()
+            .restore_from_mirror(path, &key)
+            .unwrap();
+        println!("Restored {count} items");
+    }
+}
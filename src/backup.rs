@@ -0,0 +1,210 @@
+/*!
+
+Encrypted backups.
+
+[Store::export_collection](crate::Store::export_collection) writes every item
+in a collection to a backup file: each item's label, attributes, secret,
+and the secret's content type are kept in a [Record], encrypted and authenticated in the same way as the
+records of a [mirror](crate::mirror) file. Rather than a key, though, backups
+are protected by a passphrase, which is stretched with PBKDF2-HMAC-SHA256
+(and a random salt kept at the start of the file) to make guessing it slow.
+The file ends with a MAC of everything before it, so a backup that has been
+truncated or had records removed, reordered or added can't be read.
+
+As in mirror files, secrets are kept as they are stored in the Secret
+Service, after the store's [transform](crate::transform) pipeline has been
+applied, and opaque attributes are kept in their concealed form, so items
+restored by a store with the same configuration are readable by it. [read]
+recovers the records from a backup file, and
+[Store::import_backup](crate::Store::import_backup) recreates their items
+in a collection, dealing with existing items as its [ConflictPolicy] says.
+
+Items can also be imported from the text that `secret-tool search --all`
+prints (see [parse_secret_tool] for the format), with
+[Store::import_secret_tool](crate::Store::import_secret_tool). This eases
+the migration of credentials managed by shell scripts.
+
+*/
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use keyring_core::{Error, Result};
+
+use crate::config::SCHEMA_ATTRIBUTE;
+use crate::mirror::{Keys, Record, open_all, random};
+use crate::service::CONTENT_TYPE;
+
+/// The first bytes of every backup file.
+const MAGIC: &[u8] = b"keyring backup 2\n";
+const SALT_LENGTH: usize = 16;
+const MAC_LENGTH: usize = 32;
+
+/// What to do when an item being imported has the same attributes as an
+/// item already in the collection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave the existing item alone, and don't import the backed-up one.
+    Skip,
+    /// Replace the existing item with the backed-up one.
+    Overwrite,
+    /// Import the backed-up item alongside the existing one, with the
+    /// given suffix appended to its label. (Entries that match both items
+    /// are then ambiguous.)
+    DuplicateWithSuffix(String),
+}
+
+/// What [Store::import_backup](crate::Store::import_backup) did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Items created, including duplicates of existing items.
+    pub created: usize,
+    /// Existing items replaced.
+    pub overwritten: usize,
+    /// Items not imported because of existing items.
+    pub skipped: usize,
+}
+
+/// The records of the items in the output of `secret-tool search --all`.
+///
+/// The output describes each item in a block of lines. The block starts
+/// with the item's D-Bus path in brackets, followed by `key = value` lines:
+///
+/// ```text
+/// [/org/freedesktop/secrets/collection/login/1]
+/// label = Password for alice on example.com
+/// secret = hunter2
+/// created = 2024-01-01 12:00:00
+/// modified = 2024-01-01 12:00:00
+/// schema = org.freedesktop.Secret.Generic
+/// attribute.service = example.com
+/// attribute.username = alice
+/// ```
+///
+/// The `label` and `secret` give the item's label and secret, the
+/// `schema` (if any) gives its `xdg:schema` attribute, and each
+/// `attribute.<name>` gives one of its attributes. Other keys are
+/// ignored, as are blank lines. A block without a secret, or a line
+/// that isn't part of a block or isn't a `key = value` line, produces
+/// a [BadStoreFormat](Error::BadStoreFormat) error. The records' secrets
+/// have the content type of the secrets this crate stores by default,
+/// `application/octet-stream`.
+pub fn parse_secret_tool(text: &str) -> Result<Vec<Record>> {
+    let bad = |line: usize, reason: &str| {
+        Error::BadStoreFormat(format!("secret-tool output line {line} {reason}"))
+    };
+    let mut records: Vec<(usize, Record, bool)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(path) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let record = Record {
+                path: path.to_string(),
+                deleted: false,
+                collection: String::new(),
+                label: String::new(),
+                attributes: HashMap::new(),
+                secret: Vec::new(),
+                content_type: CONTENT_TYPE.to_string(),
+            };
+            records.push((number, record, false));
+            continue;
+        }
+        let Some((_, record, has_secret)) = records.last_mut() else {
+            return Err(bad(number, "isn't part of an item"));
+        };
+        let Some((key, value)) = line.split_once(" = ") else {
+            return Err(bad(number, "isn't a 'key = value' line"));
+        };
+        match key {
+            "label" => record.label = value.to_string(),
+            "secret" => {
+                record.secret = value.as_bytes().to_vec();
+                *has_secret = true;
+            }
+            "schema" => {
+                let schema = value.to_string();
+                record
+                    .attributes
+                    .insert(SCHEMA_ATTRIBUTE.to_string(), schema);
+            }
+            _ => {
+                if let Some(name) = key.strip_prefix("attribute.") {
+                    record
+                        .attributes
+                        .insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+    }
+    let mut result = Vec::with_capacity(records.len());
+    for (number, record, has_secret) in records {
+        if !has_secret {
+            return Err(bad(number, "starts an item without a secret"));
+        }
+        result.push(record);
+    }
+    Ok(result)
+}
+
+/// Write records to a new backup file, protected by a passphrase.
+///
+/// An existing file is replaced. New files are only readable and
+/// writable by their owner.
+pub(crate) fn write(path: &Path, passphrase: &str, records: &[Record]) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(Error::Invalid(
+            "passphrase".to_string(),
+            "cannot be empty".to_string(),
+        ));
+    }
+    let mut salt = [0u8; SALT_LENGTH];
+    random(&mut salt)?;
+    let keys = Keys::stretch(passphrase, &salt);
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&salt);
+    for record in records {
+        data.extend_from_slice(&keys.seal(record)?);
+    }
+    let tag = keys.tag(&data);
+    data.extend_from_slice(&tag);
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(&data))
+        .map_err(|e| Error::PlatformFailure(Box::new(e)))
+}
+
+/// Read all the records in a backup file.
+///
+/// Files that aren't backups, and backups that are incomplete or can't be
+/// authenticated with the passphrase, produce a
+/// [BadStoreFormat](Error::BadStoreFormat) error.
+pub fn read(path: &Path, passphrase: &str) -> Result<Vec<Record>> {
+    let data = std::fs::read(path).map_err(|e| Error::PlatformFailure(Box::new(e)))?;
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        return Err(Error::BadStoreFormat("Not a backup file".to_string()));
+    };
+    if rest.len() < SALT_LENGTH + MAC_LENGTH {
+        return Err(Error::BadStoreFormat(
+            "Backup file is truncated".to_string(),
+        ));
+    }
+    let (body, tag) = data.split_at(data.len() - MAC_LENGTH);
+    let salt = &rest[..SALT_LENGTH];
+    let keys = Keys::stretch(passphrase, salt);
+    if !keys.verify(body, tag) {
+        return Err(Error::BadStoreFormat(
+            "Backup file is truncated or damaged (or the passphrase is wrong)".to_string(),
+        ));
+    }
+    open_all(&body[MAGIC.len() + SALT_LENGTH..], &keys)
+}
@@ -0,0 +1,481 @@
+/*!
+
+Direct D-Bus access.
+
+Some information about the Secret Service (such as which process
+provides it) isn't available through the Secret Service API itself.
+This module gets it by talking directly to the session bus.
+
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use dbus::arg::{RefArg, Variant};
+use dbus::blocking::Connection;
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::message::MatchRule;
+use dbus_secret_service::Error as ServiceError;
+use keyring_core::Result;
+use num_bigint::BigUint;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::errors::decode_error;
+use crate::events::{ItemEvent, ItemEventKind};
+use crate::info::ItemSnapshot;
+
+/// The well-known bus name of the Secret Service.
+pub(crate) const SECRETS_NAME: &str = "org.freedesktop.secrets";
+/// The object path of the Secret Service.
+pub(crate) const SECRETS_PATH: &str = "/org/freedesktop/secrets";
+/// The prefix of the paths of collection aliases.
+pub(crate) const ALIASES_PREFIX: &str = "/org/freedesktop/secrets/aliases/";
+/// The path of the session collection, whose items are forgotten at logout.
+pub(crate) const SESSION_COLLECTION_PATH: &str = "/org/freedesktop/secrets/aliases/session";
+/// The well-known names that Secret Service implementations take
+/// besides [SECRETS_NAME], which help tell them apart.
+const IMPLEMENTATION_NAMES: [&str; 5] = [
+    "org.keepassxc.KeePassXC.MainWindow",
+    "org.kde.kwalletd5",
+    "org.kde.kwalletd6",
+    "org.kde.secretservicecompat",
+    "org.gnome.keyring",
+];
+
+const TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Connect to the session bus.
+pub(crate) fn connect() -> Result<Connection> {
+    Connection::new_session().map_err(dbus_error)
+}
+
+/// Returns the unique name of the connection that owns the Secret Service name.
+pub(crate) fn name_owner(conn: &Connection) -> Result<String> {
+    let proxy = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", TIMEOUT);
+    let (owner,): (String,) = proxy
+        .method_call("org.freedesktop.DBus", "GetNameOwner", (SECRETS_NAME,))
+        .map_err(dbus_error)?;
+    Ok(owner)
+}
+
+/// Arrange for `changed` to be set whenever the owner of the Secret Service name
+/// changes (as it does when the daemon providing the service restarts).
+///
+/// The notifications are only seen when the connection's messages are
+/// [dispatched](dispatch).
+pub(crate) fn watch_owner(conn: &Connection, changed: Arc<AtomicBool>) -> Result<()> {
+    let rule = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged")
+        .with_sender("org.freedesktop.DBus");
+    conn.add_match(rule, move |(name, _, _): (String, String, String), _, _| {
+        if name == SECRETS_NAME {
+            changed.store(true, Ordering::Release);
+        }
+        true
+    })
+    .map_err(dbus_error)?;
+    Ok(())
+}
+
+/// Deliver the Secret Service's item signals to a channel, until `stop`
+/// is set, the channel's receiver is dropped, or the connection fails.
+///
+/// The connection should be one of the caller's own, since this takes
+/// over dispatching its messages. It's listening by the time this returns.
+pub(crate) fn watch_items(
+    conn: Connection,
+    events: mpsc::Sender<ItemEvent>,
+    stop: Arc<AtomicBool>,
+) -> Result<()> {
+    let arrived: Arc<Mutex<Vec<(ItemEventKind, String, String)>>> = Arc::default();
+    for (member, kind) in [
+        ("ItemCreated", ItemEventKind::Created),
+        ("ItemChanged", ItemEventKind::Changed),
+        ("ItemDeleted", ItemEventKind::Deleted),
+    ] {
+        let rule = MatchRule::new_signal("org.freedesktop.Secret.Collection", member)
+            .with_sender(SECRETS_NAME);
+        let arrived = arrived.clone();
+        conn.add_match(rule, move |(item,): (dbus::Path<'static>,), _, msg| {
+            let collection = msg.path().map(|p| p.to_string()).unwrap_or_default();
+            arrived
+                .lock()
+                .expect("Mutex failure in credential store: please report a bug")
+                .push((kind, item.to_string(), collection));
+            true
+        })
+        .map_err(dbus_error)?;
+    }
+    std::thread::Builder::new()
+        .name("secret-service-events".to_string())
+        .spawn(move || {
+            while !stop.load(Ordering::Acquire) {
+                if conn.process(Duration::from_millis(100)).is_err() {
+                    return;
+                }
+                let batch = std::mem::take(
+                    &mut *arrived
+                        .lock()
+                        .expect("Mutex failure in credential store: please report a bug"),
+                );
+                for (kind, path, collection) in batch {
+                    // read after the signal's dispatch, since it needs the connection
+                    let attributes = match kind {
+                        ItemEventKind::Deleted => None,
+                        _ => item_properties(&conn, &path).ok().map(|s| s.attributes),
+                    };
+                    let event = ItemEvent {
+                        kind,
+                        path,
+                        collection,
+                        attributes,
+                    };
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+        .map_err(|e| keyring_core::Error::PlatformFailure(Box::new(e)))?;
+    Ok(())
+}
+
+/// Handle all the messages that have arrived on a connection, without waiting.
+pub(crate) fn dispatch(conn: &Connection) -> Result<()> {
+    while conn.process(Duration::ZERO).map_err(dbus_error)? {}
+    Ok(())
+}
+
+/// Returns the well-known names held by the owner of the Secret Service
+/// name (given by its unique name), out of that name and the
+/// [implementation names](IMPLEMENTATION_NAMES).
+///
+/// Only those names are asked about, so the number of calls doesn't
+/// grow with the number of clients on the bus.
+pub(crate) fn owned_names(conn: &Connection, owner: &str) -> Vec<String> {
+    let proxy = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", TIMEOUT);
+    let mut result = vec![SECRETS_NAME.to_string()];
+    for name in IMPLEMENTATION_NAMES {
+        // names that aren't owned are errors
+        let owned: std::result::Result<(String,), _> =
+            proxy.method_call("org.freedesktop.DBus", "GetNameOwner", (name,));
+        if owned.is_ok_and(|(o,)| o == owner) {
+            result.push(name.to_string());
+        }
+    }
+    result
+}
+
+/// Returns whether the bus can start a Secret Service daemon on demand.
+pub(crate) fn is_activatable(conn: &Connection) -> Result<bool> {
+    let proxy = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", TIMEOUT);
+    let (names,): (Vec<String>,) = proxy
+        .method_call("org.freedesktop.DBus", "ListActivatableNames", ())
+        .map_err(dbus_error)?;
+    Ok(names.iter().any(|name| name == SECRETS_NAME))
+}
+
+/// Make an alias refer to a Secret Service collection.
+pub(crate) fn set_alias(conn: &Connection, alias: &str, collection: &str) -> Result<()> {
+    let path =
+        dbus::Path::new(collection).map_err(|_| crate::errors::missing_collection(collection))?;
+    let proxy = conn.with_proxy(SECRETS_NAME, SECRETS_PATH, TIMEOUT);
+    proxy
+        .method_call("org.freedesktop.Secret.Service", "SetAlias", (alias, path))
+        .map_err(dbus_error)
+}
+
+/// Fetch the secrets of several items in one `GetSecrets` call.
+///
+/// The call is made in a session opened (and closed) for the purpose,
+/// which is encrypted (as the store's own sessions are) if `encrypted`.
+/// Items that are missing or locked are left out of the result.
+pub(crate) fn get_secrets(
+    conn: &Connection,
+    items: &[dbus::Path<'static>],
+    encrypted: bool,
+) -> Result<HashMap<dbus::Path<'static>, Vec<u8>>> {
+    let service = conn.with_proxy(SECRETS_NAME, SECRETS_PATH, TIMEOUT);
+    let (session, key) = match encrypted {
+        false => {
+            let (_, session): (Variant<Box<dyn RefArg>>, dbus::Path<'static>) = service
+                .method_call(
+                    "org.freedesktop.Secret.Service",
+                    "OpenSession",
+                    ("plain", Variant(String::new())),
+                )
+                .map_err(dbus_error)?;
+            (session, None)
+        }
+        true => {
+            let (session, key) = open_dh_session(conn)?;
+            (session, Some(key))
+        }
+    };
+    let result: std::result::Result<(HashMap<dbus::Path<'static>, Secret>,), _> = service
+        .method_call(
+            "org.freedesktop.Secret.Service",
+            "GetSecrets",
+            (items.to_vec(), session.clone()),
+        );
+    let _: std::result::Result<(), _> = conn
+        .with_proxy(SECRETS_NAME, session, TIMEOUT)
+        .method_call("org.freedesktop.Secret.Session", "Close", ());
+    let (secrets,) = result.map_err(dbus_error)?;
+    let mut result = HashMap::with_capacity(secrets.len());
+    for (path, (_, parameters, value, _)) in secrets {
+        let secret = match &key {
+            None => value,
+            Some(key) => decrypt(key, &parameters, &value)?,
+        };
+        result.insert(path, secret);
+    }
+    Ok(result)
+}
+
+/// The prime of the 1024-bit MODP group of RFC 2409, whose generator is 2.
+const DH_PRIME: [u8; 128] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC9, 0x0F, 0xDA, 0xA2, 0x21, 0x68, 0xC2, 0x34,
+    0xC4, 0xC6, 0x62, 0x8B, 0x80, 0xDC, 0x1C, 0xD1, 0x29, 0x02, 0x4E, 0x08, 0x8A, 0x67, 0xCC, 0x74,
+    0x02, 0x0B, 0xBE, 0xA6, 0x3B, 0x13, 0x9B, 0x22, 0x51, 0x4A, 0x08, 0x79, 0x8E, 0x34, 0x04, 0xDD,
+    0xEF, 0x95, 0x19, 0xB3, 0xCD, 0x3A, 0x43, 0x1B, 0x30, 0x2B, 0x0A, 0x6D, 0xF2, 0x5F, 0x14, 0x37,
+    0x4F, 0xE1, 0x35, 0x6D, 0x6D, 0x51, 0xC2, 0x45, 0xE4, 0x85, 0xB5, 0x76, 0x62, 0x5E, 0x7E, 0xC6,
+    0xF4, 0x4C, 0x42, 0xE9, 0xA6, 0x37, 0xED, 0x6B, 0x0B, 0xFF, 0x5C, 0xB6, 0xF4, 0x06, 0xB7, 0xED,
+    0xEE, 0x38, 0x6B, 0xFB, 0x5A, 0x89, 0x9F, 0xA5, 0xAE, 0x9F, 0x24, 0x11, 0x7C, 0x4B, 0x1F, 0xE6,
+    0x49, 0x28, 0x66, 0x51, 0xEC, 0xE6, 0x53, 0x81, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// Open a `dh-ietf1024-sha256-aes128-cbc-pkcs7` session, returning its
+/// path and the AES key that secrets in it are encrypted with.
+fn open_dh_session(conn: &Connection) -> Result<(dbus::Path<'static>, Zeroizing<[u8; 16]>)> {
+    let prime = BigUint::from_bytes_be(&DH_PRIME);
+    let mut private = Zeroizing::new([0u8; 128]);
+    crate::mirror::random(private.as_mut_slice())?;
+    let private = BigUint::from_bytes_be(private.as_slice());
+    let public = BigUint::from(2u32).modpow(&private, &prime);
+    let service = conn.with_proxy(SECRETS_NAME, SECRETS_PATH, TIMEOUT);
+    let (output, session): (Variant<Box<dyn RefArg>>, dbus::Path<'static>) = service
+        .method_call(
+            "org.freedesktop.Secret.Service",
+            "OpenSession",
+            (
+                "dh-ietf1024-sha256-aes128-cbc-pkcs7",
+                Variant(public.to_bytes_be()),
+            ),
+        )
+        .map_err(dbus_error)?;
+    let Some(server) = dbus::arg::cast::<Vec<u8>>(&output.0) else {
+        return Err(decode_error(ServiceError::Parse));
+    };
+    let shared = BigUint::from_bytes_be(server).modpow(&private, &prime);
+    let shared = Zeroizing::new(shared.to_bytes_be());
+    let mut padded = Zeroizing::new(vec![0u8; DH_PRIME.len().saturating_sub(shared.len())]);
+    padded.extend_from_slice(&shared);
+    let mut key = Zeroizing::new([0u8; 16]);
+    hkdf::Hkdf::<Sha256>::new(None, &padded)
+        .expand(&[], key.as_mut_slice())
+        .expect("HKDF can make a 16-byte key");
+    Ok((session, key))
+}
+
+/// Decrypt a secret's value, given its parameters (the IV), with a
+/// session's AES key.
+fn decrypt(key: &[u8; 16], parameters: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    use aes::cipher::block_padding::Pkcs7;
+    use aes::cipher::{BlockDecryptMut, KeyIvInit};
+
+    let iv: [u8; 16] = parameters
+        .try_into()
+        .map_err(|_| decode_error(ServiceError::Parse))?;
+    cbc::Decryptor::<aes::Aes128>::new(key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(value)
+        .map_err(|e| decode_error(e.into()))
+}
+
+/// Unlock objects (items or collections) if that doesn't need a prompt.
+///
+/// Returns whether they were unlocked. If the Secret Service wants to
+/// prompt the user, the prompt is dismissed without being shown.
+pub(crate) fn unlock_without_prompt(
+    conn: &Connection,
+    objects: &[dbus::Path<'static>],
+) -> Result<bool> {
+    match request_unlock(conn, objects)? {
+        None => Ok(true),
+        Some(prompt) => {
+            dismiss(conn, prompt);
+            Ok(false)
+        }
+    }
+}
+
+/// Unlock objects (items or collections), showing any prompt this needs
+/// as a child of the given window.
+///
+/// The window identifier is passed to the Secret Service as is. If the
+/// user doesn't answer within `timeout` seconds, the prompt is dismissed.
+pub(crate) fn unlock_in_window(
+    conn: &Connection,
+    objects: &[dbus::Path<'static>],
+    window: &str,
+    timeout: Option<u64>,
+) -> Result<()> {
+    let Some(prompt) = request_unlock(conn, objects)? else {
+        return Ok(());
+    };
+    if timeout == Some(0) {
+        dismiss(conn, prompt);
+        return Err(decode_error(ServiceError::Prompt));
+    }
+    let dismissed: Arc<Mutex<Option<bool>>> = Arc::default();
+    let rule = MatchRule::new_signal("org.freedesktop.Secret.Prompt", "Completed")
+        .with_path(prompt.clone());
+    let completed = dismissed.clone();
+    let token = conn
+        .add_match(
+            rule,
+            move |(dismissed, _): (bool, Variant<Box<dyn RefArg>>), _, _| {
+                *completed
+                    .lock()
+                    .expect("Mutex failure in credential store: please report a bug") =
+                    Some(dismissed);
+                true
+            },
+        )
+        .map_err(dbus_error)?;
+    let shown: std::result::Result<(), _> = conn
+        .with_proxy(SECRETS_NAME, prompt.clone(), TIMEOUT)
+        .method_call("org.freedesktop.Secret.Prompt", "Prompt", (window,));
+    let deadline = timeout.map(|seconds| Instant::now() + Duration::from_secs(seconds));
+    let result = shown.map_err(dbus_error).and_then(|()| {
+        loop {
+            let answer = *dismissed
+                .lock()
+                .expect("Mutex failure in credential store: please report a bug");
+            match answer {
+                Some(false) => break Ok(()),
+                Some(true) => break Err(decode_error(ServiceError::Prompt)),
+                None if deadline.is_some_and(|d| Instant::now() >= d) => {
+                    dismiss(conn, prompt.clone());
+                    break Err(decode_error(ServiceError::Prompt));
+                }
+                None => {
+                    conn.process(Duration::from_millis(100))
+                        .map_err(dbus_error)?;
+                }
+            }
+        }
+    });
+    let _ = conn.remove_match(token);
+    result
+}
+
+/// Ask the Secret Service to unlock objects, returning the path of the
+/// prompt it needs to show first (if any).
+fn request_unlock(
+    conn: &Connection,
+    objects: &[dbus::Path<'static>],
+) -> Result<Option<dbus::Path<'static>>> {
+    let service = conn.with_proxy(SECRETS_NAME, SECRETS_PATH, TIMEOUT);
+    let (_, prompt): (Vec<dbus::Path<'static>>, dbus::Path<'static>) = service
+        .method_call(
+            "org.freedesktop.Secret.Service",
+            "Unlock",
+            (objects.to_vec(),),
+        )
+        .map_err(dbus_error)?;
+    Ok(if &*prompt == "/" { None } else { Some(prompt) })
+}
+
+/// Dismiss a prompt without showing it (failures are ignored).
+fn dismiss(conn: &Connection, prompt: dbus::Path<'static>) {
+    let _: std::result::Result<(), _> = conn.with_proxy(SECRETS_NAME, prompt, TIMEOUT).method_call(
+        "org.freedesktop.Secret.Prompt",
+        "Dismiss",
+        (),
+    );
+}
+
+/// A secret as the Secret Service transfers it: session, parameters, value and content type.
+type Secret = (dbus::Path<'static>, Vec<u8>, Vec<u8>, String);
+
+/// Returns the properties of a Secret Service item, read in one `GetAll` call.
+///
+/// The snapshot's secret is left unset.
+pub(crate) fn item_properties(conn: &Connection, path: &str) -> Result<ItemSnapshot> {
+    let proxy = conn.with_proxy(SECRETS_NAME, path, TIMEOUT);
+    let properties = proxy
+        .get_all("org.freedesktop.Secret.Item")
+        .map_err(dbus_error)?;
+    let mut snapshot = ItemSnapshot::default();
+    for (name, Variant(value)) in properties {
+        match name.as_str() {
+            "Label" => snapshot.label = value.as_str().unwrap_or_default().to_string(),
+            "Created" => snapshot.created = value.as_u64().unwrap_or_default(),
+            "Modified" => snapshot.modified = value.as_u64().unwrap_or_default(),
+            "Locked" => snapshot.locked = value.as_u64().unwrap_or_default() != 0,
+            "Attributes" => {
+                // dictionaries iterate as alternating keys and values
+                if let Some(mut entries) = value.as_iter() {
+                    while let (Some(k), Some(v)) = (entries.next(), entries.next()) {
+                        if let (Some(k), Some(v)) = (k.as_str(), v.as_str()) {
+                            snapshot.attributes.insert(k.to_string(), v.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Returns the creation time of a Secret Service collection.
+pub(crate) fn collection_created(conn: &Connection, path: &str) -> Result<u64> {
+    let proxy = conn.with_proxy(SECRETS_NAME, path, TIMEOUT);
+    proxy
+        .get("org.freedesktop.Secret.Collection", "Created")
+        .map_err(dbus_error)
+}
+
+/// Returns the process ID of the connection with the given name, if the bus knows it.
+pub(crate) fn process_id(conn: &Connection, name: &str) -> Option<u32> {
+    let proxy = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", TIMEOUT);
+    let result: std::result::Result<(u32,), _> = proxy.method_call(
+        "org.freedesktop.DBus",
+        "GetConnectionUnixProcessID",
+        (name,),
+    );
+    result.ok().map(|(pid,)| pid)
+}
+
+/// Returns the name of the process with the given ID, if it can be found.
+pub(crate) fn process_name(pid: u32) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(comm.trim().to_string())
+}
+
+/// Returns the names of the interfaces implemented by a Secret Service object.
+pub(crate) fn interfaces(conn: &Connection, path: &str) -> Result<Vec<String>> {
+    let proxy = conn.with_proxy(SECRETS_NAME, path, TIMEOUT);
+    let (xml,): (String,) = proxy
+        .method_call("org.freedesktop.DBus.Introspectable", "Introspect", ())
+        .map_err(dbus_error)?;
+    let mut result = Vec::new();
+    let mut rest = xml.as_str();
+    while let Some(start) = rest.find("<interface name=\"") {
+        rest = &rest[start + 17..];
+        if let Some(end) = rest.find('"') {
+            result.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+    }
+    Ok(result)
+}
+
+/// Map a raw D-Bus error the same way as errors from the Secret Service.
+pub(crate) fn dbus_error(err: dbus::Error) -> keyring_core::Error {
+    decode_error(ServiceError::Dbus(err))
+}
@@ -0,0 +1,93 @@
+//! The in-memory secret cache of stores configured with `secret-cache-ttl`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use zeroize::Zeroizing;
+
+/// What a cached secret was read for: an entry's search attributes
+/// (sorted) and the collection its search was scoped to, if any.
+pub(crate) type Key = (Vec<(String, String)>, Option<String>);
+
+/// Cached secrets and when they expire.
+type Entries = HashMap<Key, (Zeroizing<Vec<u8>>, Instant)>;
+
+/// Build the key for a set of search attributes and a search scope.
+pub(crate) fn key(attributes: &HashMap<String, String>, scope: Option<String>) -> Key {
+    let mut attributes: Vec<_> = attributes
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    attributes.sort();
+    (attributes, scope)
+}
+
+/// Secrets read by a store's entries, each kept for a limited time.
+/// Secrets are wiped from memory when they're evicted or flushed.
+///
+/// The cache is flushed whenever the store writes anything, so readers
+/// take a [stamp](SecretCache::stamp) before they read a secret, and the
+/// secret is only cached if nothing was written in the meantime.
+#[derive(Debug)]
+pub(crate) struct SecretCache {
+    ttl: Duration,
+    flushes: AtomicU64,
+    entries: Mutex<Entries>,
+}
+
+impl SecretCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        SecretCache {
+            ttl,
+            flushes: AtomicU64::new(0),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached secret for a key, unless it has expired.
+    pub(crate) fn get(&self, key: &Key) -> Option<Vec<u8>> {
+        let mut entries = self.entries();
+        match entries.get(key) {
+            Some((secret, expiry)) if *expiry > Instant::now() => Some(secret.to_vec()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Identify the state of the cache before reading a secret.
+    pub(crate) fn stamp(&self) -> u64 {
+        self.flushes.load(Ordering::Acquire)
+    }
+
+    /// Cache a secret, unless the cache was flushed since the stamp was taken.
+    ///
+    /// Expired secrets are evicted at the same time.
+    pub(crate) fn put(&self, key: Key, secret: Vec<u8>, stamp: u64) {
+        let secret = Zeroizing::new(secret);
+        let mut entries = self.entries();
+        if self.stamp() != stamp {
+            return;
+        }
+        let now = Instant::now();
+        entries.retain(|_, (_, expiry)| *expiry > now);
+        entries.insert(key, (secret, now + self.ttl));
+    }
+
+    /// Forget every cached secret.
+    pub(crate) fn flush(&self) {
+        let mut entries = self.entries();
+        self.flushes.fetch_add(1, Ordering::AcqRel);
+        entries.clear();
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug")
+    }
+}
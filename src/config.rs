@@ -0,0 +1,695 @@
+/*!
+
+Store configuration.
+
+This module parses the key-value configuration given to
+[Store::new_with_configuration](crate::Store::new_with_configuration)
+into the settings used by the store and its credentials.
+
+*/
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use keyring_core::{Error, Result};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::mapping::to_hex;
+use crate::pattern::Pattern;
+
+/// The libsecret schema for generic secrets.
+const GENERIC_SCHEMA: &str = "org.freedesktop.Secret.Generic";
+
+/// The `application` attribute that the Python keyring package gives its items.
+const PYTHON_APPLICATION: &str = "Python keyring library";
+
+/// The attribute in which libsecret records an item's schema.
+pub(crate) const SCHEMA_ATTRIBUTE: &str = "xdg:schema";
+
+/// The attribute in which an item may record when it expires, in seconds
+/// since the UNIX epoch. Quota eviction deletes expired items first.
+pub(crate) const EXPIRES_ATTRIBUTE: &str = "expires";
+
+/// The settings of a store.
+#[derive(Default)]
+pub(crate) struct Config {
+    pub(crate) schema: Schema,
+    /// Attributes added to every item the store creates.
+    pub(crate) default_attributes: HashMap<String, String>,
+    /// If set, the name of the attribute that holds the user.
+    pub(crate) username_attribute: Option<String>,
+    /// If set, the name of the attribute that holds the service.
+    pub(crate) service_attribute: Option<String>,
+    /// If set, the name of the attribute that holds the target.
+    pub(crate) target_attribute: Option<String>,
+    /// If set, the libsecret schema that items are stamped with.
+    pub(crate) xdg_schema: Option<String>,
+    /// Whether items are created the way the Python keyring package creates them.
+    pub(crate) python_keyring: bool,
+    /// Whether entries also find items written by older versions of keyring-rs.
+    pub(crate) legacy_items: bool,
+    /// Whether to plan changes to the Secret Service rather than make them.
+    pub(crate) dry_run: bool,
+    /// If set, the salt used to hash the username attribute.
+    pub(crate) username_salt: Option<String>,
+    /// Whether default labels contain the user when the username is hashed.
+    pub(crate) username_in_label: bool,
+    /// The attributes whose values are concealed.
+    pub(crate) opaque: Opaque,
+    /// Whether to use a plain session if encryption can't be negotiated.
+    pub(crate) allow_plain_fallback: bool,
+    /// Whether to stamp created items with their provenance.
+    pub(crate) provenance: bool,
+    /// Whether creating an item fails rather than replace a matching one.
+    pub(crate) create_if_absent: bool,
+    /// Whether collections are only created by explicit calls.
+    pub(crate) explicit_collections: bool,
+    /// Whether to create the default collection if there isn't one.
+    pub(crate) create_default_collection: bool,
+    /// If set, the most items the store will create in a target collection.
+    pub(crate) max_items_per_target: Option<usize>,
+    /// Whether to evict the oldest items rather than exceed the quota.
+    pub(crate) quota_eviction: bool,
+    /// How many extra workers run read operations, each with its own session.
+    pub(crate) read_workers: usize,
+    /// Whether to scrub items before deleting them.
+    pub(crate) secure_delete: bool,
+    /// Artificial delays added to operations, for testing.
+    pub(crate) latency: Latency,
+    /// When to renew the Secret Service session.
+    pub(crate) rekey: Rekey,
+    /// How operations that fail transiently are retried.
+    pub(crate) retry: Retry,
+    /// Whether to renew the session when the Secret Service daemon restarts.
+    pub(crate) rebind_on_restart: bool,
+    /// Which collection to use when several have a target's label.
+    pub(crate) duplicate_collections: DuplicatePolicy,
+    /// What searches do with the locked items they find.
+    pub(crate) locked_items: LockedPolicy,
+    pub(crate) ambiguous_items: AmbiguityPolicy,
+    /// If set, the file that writes are mirrored to.
+    pub(crate) mirror_file: Option<PathBuf>,
+    /// The key used to encrypt the mirror file.
+    pub(crate) mirror_key: Option<Zeroizing<String>>,
+    /// If set, the key that secrets are encrypted with before they're stored.
+    pub(crate) envelope_key: Option<Zeroizing<String>>,
+    /// Whether to check that created items can be found again.
+    pub(crate) verify_creation: bool,
+    /// Whether items are created in the session collection by default.
+    pub(crate) session_collection: bool,
+    /// If set, how long an operation may wait to start before it's cancelled.
+    pub(crate) deadline: Option<Duration>,
+    /// If set, how long a caller waits for an operation to finish.
+    pub(crate) timeout: Option<Duration>,
+    /// Whether to use a plain (unencrypted) session.
+    pub(crate) plain_session: bool,
+    /// If set, how many seconds to wait for the user to answer a prompt.
+    pub(crate) prompt_timeout: Option<u64>,
+    /// Whether to fail rather than prompt the user to unlock anything.
+    pub(crate) no_prompt: bool,
+    /// If set, the identifier of the window that prompts are shown for.
+    pub(crate) prompt_window: Option<String>,
+    /// If set, the target of entries created without a target or collection.
+    pub(crate) default_target: Option<String>,
+    /// Whether to wait for the first operation to open a session.
+    pub(crate) lazy_connect: bool,
+    /// Whether to lock items again after accessing them, if they were locked.
+    pub(crate) relock_after_access: bool,
+    /// Whether to search for an entry's item only in its collection.
+    pub(crate) scoped_search: bool,
+    /// Whether specifiers remember the paths of their items.
+    pub(crate) cache_item_paths: bool,
+    /// If set, how long secrets read by specifiers are cached.
+    pub(crate) secret_cache_ttl: Option<Duration>,
+}
+
+impl Config {
+    pub(crate) fn parse(config: &HashMap<&str, &str>) -> Result<Self> {
+        let mut result = Config::default();
+        let flags = [
+            "username-in-label",
+            "allow-plain-fallback",
+            "provenance",
+            "explicit-collections",
+            "quota-eviction",
+            "secure-delete",
+            "rebind-on-restart",
+            "session-collection",
+            "verify-creation",
+            "lazy-connect",
+            "relock-after-access",
+            "scoped-search",
+            "create-default-collection",
+            "cache-item-paths",
+            "create-if-absent",
+            "python-keyring",
+            "legacy-items",
+            "dry-run",
+            "no-prompt",
+        ];
+        for (key, value) in config {
+            if flags.contains(key) && *value != "true" && *value != "false" {
+                return Err(Error::Invalid(
+                    key.to_string(),
+                    "must be 'true' or 'false'".to_string(),
+                ));
+            }
+            if let Some(name) = key.strip_prefix("pattern:") {
+                let pattern = Pattern::new(value).map_err(|reason| {
+                    Error::Invalid(
+                        key.to_string(),
+                        format!("is not a valid regular expression: {reason}"),
+                    )
+                })?;
+                result
+                    .schema
+                    .patterns
+                    .insert(attribute_name(key, name)?, pattern);
+                continue;
+            }
+            if let Some(name) = key.strip_prefix("latency:") {
+                result.latency.parse(key, name, value)?;
+                continue;
+            }
+            if let Some(name) = key.strip_prefix("attribute:") {
+                result
+                    .default_attributes
+                    .insert(attribute_name(key, name)?, value.to_string());
+                continue;
+            }
+            match *key {
+                "required-attributes" => {
+                    result.schema.required = split_list(value);
+                }
+                "username-attribute" => {
+                    result.username_attribute = Some(attribute_name(key, value)?)
+                }
+                "service-attribute" => result.service_attribute = Some(attribute_name(key, value)?),
+                "target-attribute" => result.target_attribute = Some(attribute_name(key, value)?),
+                "xdg-schema" => {
+                    result.xdg_schema = match *value {
+                        "false" => None,
+                        "true" => Some(GENERIC_SCHEMA.to_string()),
+                        "" => {
+                            return Err(Error::Invalid(
+                                key.to_string(),
+                                "must be 'true', 'false', or a schema name".to_string(),
+                            ));
+                        }
+                        name => Some(name.to_string()),
+                    }
+                }
+                "duplicate-collections" => {
+                    result.duplicate_collections = match *value {
+                        "first" => DuplicatePolicy::First,
+                        "newest" => DuplicatePolicy::Newest,
+                        "error" => DuplicatePolicy::Error,
+                        _ => {
+                            return Err(Error::Invalid(
+                                key.to_string(),
+                                "must be 'first', 'newest', or 'error'".to_string(),
+                            ));
+                        }
+                    }
+                }
+                "ambiguous-items" => {
+                    result.ambiguous_items = match *value {
+                        "error" => AmbiguityPolicy::Error,
+                        "newest" => AmbiguityPolicy::Newest,
+                        "target" => AmbiguityPolicy::Target,
+                        _ => {
+                            return Err(Error::Invalid(
+                                key.to_string(),
+                                "must be 'error', 'newest', or 'target'".to_string(),
+                            ));
+                        }
+                    }
+                }
+                "locked-items" => {
+                    result.locked_items = match *value {
+                        "unlock" => LockedPolicy::Unlock,
+                        "skip" => LockedPolicy::Skip,
+                        "error" => LockedPolicy::Error,
+                        _ => {
+                            return Err(Error::Invalid(
+                                key.to_string(),
+                                "must be 'unlock', 'skip', or 'error'".to_string(),
+                            ));
+                        }
+                    }
+                }
+                "username-salt" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.username_salt = Some(value.to_string());
+                }
+                "username-in-label" => result.username_in_label = *value == "true",
+                "allow-plain-fallback" => result.allow_plain_fallback = *value == "true",
+                "provenance" => result.provenance = *value == "true",
+                "explicit-collections" => result.explicit_collections = *value == "true",
+                "quota-eviction" => result.quota_eviction = *value == "true",
+                "secure-delete" => result.secure_delete = *value == "true",
+                "rebind-on-restart" => result.rebind_on_restart = *value == "true",
+                "session-collection" => result.session_collection = *value == "true",
+                "lazy-connect" => result.lazy_connect = *value == "true",
+                "relock-after-access" => result.relock_after_access = *value == "true",
+                "scoped-search" => result.scoped_search = *value == "true",
+                "cache-item-paths" => result.cache_item_paths = *value == "true",
+                "create-if-absent" => result.create_if_absent = *value == "true",
+                "python-keyring" => result.python_keyring = *value == "true",
+                "legacy-items" => result.legacy_items = *value == "true",
+                "dry-run" => result.dry_run = *value == "true",
+                "no-prompt" => result.no_prompt = *value == "true",
+                "create-default-collection" => result.create_default_collection = *value == "true",
+                "verify-creation" => result.verify_creation = *value == "true",
+                "max-items-per-target" => {
+                    result.max_items_per_target = Some(positive_integer(key, value)? as usize)
+                }
+                "read-workers" => result.read_workers = positive_integer(key, value)? as usize,
+                "retry-attempts" => result.retry.attempts = positive_integer(key, value)?,
+                "retry-backoff" => {
+                    result.retry.backoff = Duration::from_millis(positive_integer(key, value)?)
+                }
+                "rekey-after-operations" => {
+                    result.rekey.operations = Some(positive_integer(key, value)?)
+                }
+                "encryption" => {
+                    result.plain_session = match *value {
+                        "plain" => true,
+                        "dh-ietf1024-sha256-aes128-cbc-pkcs7" => false,
+                        _ => {
+                            return Err(Error::Invalid(
+                                key.to_string(),
+                                "must be 'plain' or 'dh-ietf1024-sha256-aes128-cbc-pkcs7'"
+                                    .to_string(),
+                            ));
+                        }
+                    }
+                }
+                "prompt-timeout" => match value.parse::<u64>() {
+                    Ok(seconds) => result.prompt_timeout = Some(seconds),
+                    Err(_) => {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must be a number of seconds".to_string(),
+                        ));
+                    }
+                },
+                "prompt-window" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.prompt_window = Some(value.to_string());
+                }
+                "default-target" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.default_target = Some(value.to_string());
+                }
+                "operation-timeout" => {
+                    result.timeout = Some(Duration::from_millis(positive_integer(key, value)?))
+                }
+                "secret-cache-ttl" => {
+                    result.secret_cache_ttl =
+                        Some(Duration::from_millis(positive_integer(key, value)?))
+                }
+                "operation-deadline" => {
+                    result.deadline = Some(Duration::from_millis(positive_integer(key, value)?))
+                }
+                "rekey-after-seconds" => {
+                    result.rekey.interval = Some(Duration::from_secs(positive_integer(key, value)?))
+                }
+                "opaque-attributes" => result.opaque.names = split_list(value),
+                "mirror-file" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.mirror_file = Some(PathBuf::from(value));
+                }
+                "mirror-key" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.mirror_key = Some(Zeroizing::new(value.to_string()));
+                }
+                "envelope-key" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.envelope_key = Some(Zeroizing::new(value.to_string()));
+                }
+                "opaque-key" => {
+                    if value.is_empty() {
+                        return Err(Error::Invalid(
+                            key.to_string(),
+                            "must not be empty".to_string(),
+                        ));
+                    }
+                    result.opaque.key = Some(Zeroizing::new(value.to_string()));
+                }
+                _ => {
+                    return Err(Error::NotSupportedByStore(format!(
+                        "Secret Service configuration key '{key}' is not supported"
+                    )));
+                }
+            }
+        }
+        if !result.opaque.names.is_empty() && result.opaque.key.is_none() {
+            return Err(Error::Invalid(
+                "opaque-key".to_string(),
+                "is required when opaque attributes are configured".to_string(),
+            ));
+        }
+        result.check_attribute_names()?;
+        if result.python_keyring {
+            result.check_python_keyring()?;
+            result
+                .default_attributes
+                .entry("application".to_string())
+                .or_insert(PYTHON_APPLICATION.to_string());
+        }
+        if result.mirror_file.is_some() != result.mirror_key.is_some() {
+            return Err(Error::Invalid(
+                "mirror-key".to_string(),
+                "must be given if and only if mirror-file is".to_string(),
+            ));
+        }
+        Ok(result)
+    }
+
+    /// Check that nothing configured stops Python keyring from finding the
+    /// store's items by their `service` and `username` attributes.
+    fn check_python_keyring(&self) -> Result<()> {
+        let conflicts = [
+            ("service-attribute", self.service_attribute.is_some()),
+            ("username-attribute", self.username_attribute.is_some()),
+            ("username-salt", self.username_salt.is_some()),
+        ];
+        match conflicts.iter().find(|(_, set)| *set) {
+            Some((key, _)) => Err(Error::Invalid(
+                key.to_string(),
+                "cannot be combined with python-keyring".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the attributes controlled by the store have distinct names.
+    fn check_attribute_names(&self) -> Result<()> {
+        let names = [
+            ("service-attribute", &self.service_attribute, "service"),
+            ("username-attribute", &self.username_attribute, "username"),
+            ("target-attribute", &self.target_attribute, "target"),
+        ];
+        for (key, name, _) in names.iter() {
+            let Some(name) = name else {
+                continue;
+            };
+            let clashes = names
+                .iter()
+                .filter(|(_, other, default)| other.as_deref().unwrap_or(default) == name)
+                .count();
+            if clashes > 1 {
+                return Err(Error::Invalid(
+                    key.to_string(),
+                    "must differ from the names of the other controlled attributes".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the attribute name in a prefixed configuration key.
+fn attribute_name(key: &str, name: &str) -> Result<String> {
+    if name.is_empty() {
+        return Err(Error::Invalid(
+            key.to_string(),
+            "must name an attribute".to_string(),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Parse a configuration value that must be a positive integer.
+fn positive_integer(key: &str, value: &str) -> Result<u64> {
+    match value.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(Error::Invalid(
+            key.to_string(),
+            "must be a positive integer".to_string(),
+        )),
+    }
+}
+
+/// Split a comma-separated configuration value into its trimmed, non-empty parts.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// The attribute schema enforced on items created or updated by a store.
+#[derive(Debug, Default)]
+pub(crate) struct Schema {
+    /// Attributes every item must have.
+    pub(crate) required: Vec<String>,
+    /// Patterns the values of attributes must match, when present.
+    pub(crate) patterns: HashMap<String, Pattern>,
+}
+
+impl Schema {
+    /// Check that an item's attributes conform to the schema.
+    ///
+    /// All the item's attributes must include the required ones, and the
+    /// attributes being written must match their patterns. (Attributes that
+    /// are already on the item are not re-checked against patterns.)
+    pub(crate) fn validate<K: AsRef<str>, V: AsRef<str>>(
+        &self,
+        all: &HashMap<K, V>,
+        written: &HashMap<&str, &str>,
+    ) -> Result<()> {
+        for name in self.required.iter() {
+            if !all.keys().any(|k| k.as_ref() == name) {
+                return Err(Error::Invalid(
+                    name.to_string(),
+                    "is required by the store's schema".to_string(),
+                ));
+            }
+        }
+        for (name, pattern) in self.patterns.iter() {
+            if let Some(value) = written.get(name.as_str()) {
+                if !pattern.matches(value) {
+                    return Err(Error::Invalid(
+                        name.to_string(),
+                        format!("must match the pattern '{}'", pattern.source()),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How to choose among collections that share a target's label.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DuplicatePolicy {
+    /// Use the first collection the Secret Service lists.
+    #[default]
+    First,
+    /// Use the most recently created collection.
+    Newest,
+    /// Fail with an [AmbiguousCollection](crate::errors::StoreError::AmbiguousCollection) error.
+    Error,
+}
+
+/// What searches do with the locked items they find.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LockedPolicy {
+    /// Unlock them, prompting the user if necessary.
+    #[default]
+    Unlock,
+    /// Leave them out of the results.
+    Skip,
+    /// Fail with a [Locked](crate::errors::StoreError::Locked) error.
+    Error,
+}
+
+/// How entries choose among several items with their attributes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AmbiguityPolicy {
+    /// Fail with an [Ambiguous](keyring_core::Error::Ambiguous) error.
+    #[default]
+    Error,
+    /// Use the most recently modified item.
+    Newest,
+    /// Use the item in the entry's collection, if there's just one.
+    Target,
+}
+
+/// When a store renews its Secret Service session.
+///
+/// A renewed session negotiates a new key, which bounds the amount of
+/// data protected by any one session key in long-lived processes.
+#[derive(Debug, Default)]
+pub(crate) struct Rekey {
+    /// Renew after this many operations.
+    pub(crate) operations: Option<u64>,
+    /// Renew after this much time.
+    pub(crate) interval: Option<Duration>,
+}
+
+/// How a store retries operations that fail transiently, as when the
+/// Secret Service daemon is slow to answer while the session starts up.
+#[derive(Debug)]
+pub(crate) struct Retry {
+    /// How many times to retry an operation.
+    pub(crate) attempts: u64,
+    /// How long to wait before the first retry. Each later retry
+    /// waits twice as long as the one before.
+    pub(crate) backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 0,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// The kinds of operation that a store performs on the Secret Service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Operation {
+    Search,
+    Create,
+    Read,
+    Write,
+    Delete,
+    Unlock,
+}
+
+impl Operation {
+    const ALL: [Operation; 6] = [
+        Operation::Search,
+        Operation::Create,
+        Operation::Read,
+        Operation::Write,
+        Operation::Delete,
+        Operation::Unlock,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Operation::Search => "search",
+            Operation::Create => "create",
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Delete => "delete",
+            Operation::Unlock => "unlock",
+        }
+    }
+
+    /// Whether running the operation again has no further effect, so that
+    /// it can be retried even if a failed attempt may have taken effect.
+    pub(crate) fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Operation::Search | Operation::Read | Operation::Unlock
+        )
+    }
+}
+
+/// Artificial delays that simulate a slow Secret Service.
+#[derive(Debug, Default)]
+pub(crate) struct Latency {
+    delays: HashMap<Operation, Duration>,
+}
+
+impl Latency {
+    /// Parse the delay (in milliseconds) for the named operation.
+    fn parse(&mut self, key: &str, name: &str, value: &str) -> Result<()> {
+        let Some(operation) = Operation::ALL.into_iter().find(|o| o.name() == name) else {
+            return Err(Error::Invalid(
+                key.to_string(),
+                "must name one of the operations search, create, read, write, delete, or unlock"
+                    .to_string(),
+            ));
+        };
+        let Ok(millis) = value.parse::<u64>() else {
+            return Err(Error::Invalid(
+                key.to_string(),
+                "must be a number of milliseconds".to_string(),
+            ));
+        };
+        self.delays.insert(operation, Duration::from_millis(millis));
+        Ok(())
+    }
+
+    /// Wait for the delay configured for an operation, if any.
+    pub(crate) fn simulate(&self, operation: Operation) {
+        if let Some(delay) = self.delays.get(&operation) {
+            std::thread::sleep(*delay);
+        }
+    }
+}
+
+/// The attributes whose values are replaced by a keyed hash.
+///
+/// The hash is deterministic, so concealed values can still be searched
+/// for, but they can't be read by other clients of the Secret Service.
+#[derive(Default)]
+pub(crate) struct Opaque {
+    key: Option<Zeroizing<String>>,
+    names: Vec<String>,
+}
+
+impl Opaque {
+    /// Returns the attributes with the values of opaque attributes concealed.
+    pub(crate) fn conceal(&self, attributes: &HashMap<&str, &str>) -> HashMap<String, String> {
+        attributes
+            .iter()
+            .map(|(k, v)| {
+                let value = match &self.key {
+                    Some(key) if self.names.iter().any(|n| n == k) => {
+                        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                            .expect("HMAC accepts keys of any length");
+                        mac.update(k.as_bytes());
+                        mac.update(&[0u8]);
+                        mac.update(v.as_bytes());
+                        to_hex(&mac.finalize().into_bytes())
+                    }
+                    _ => v.to_string(),
+                };
+                (k.to_string(), value)
+            })
+            .collect()
+    }
+}
@@ -1,25 +1,49 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 
 use dbus_secret_service::Path;
 
 use keyring_core::Entry;
-use keyring_core::api::{Credential, CredentialApi};
+use keyring_core::api::{Credential, CredentialApi, CredentialPersistence};
 use keyring_core::error::{Error, Result};
 
-use crate::service::Service;
+use crate::bus::SESSION_COLLECTION_PATH;
+use crate::cache;
+use crate::config::{AmbiguityPolicy, SCHEMA_ATTRIBUTE};
+use crate::errors::{
+    already_exists, empty_target, is_locked_object, is_stale_path, unverified_creation,
+};
+use crate::hooks::{Access, AccessKind};
+use crate::info::{Candidate, ItemSnapshot, Provenance};
+use crate::mapping::borrow_attributes;
+use crate::service::{Pending, Service, wait_for, without_blocking};
+
+/// The attribute that holds a credential's note.
+const NOTE_ATTRIBUTE: &str = "note";
+
+/// The `application` attribute of items written by older versions of keyring-rs.
+pub(crate) const LEGACY_APPLICATION: &str = "rust-keyring";
 
 /// The specifier for an item in the secret-service.
 ///
-/// The label and target are captured from the modifiers
-/// used when the specifier was created, so they are
-/// available when a matching item needs to be created.
+/// The label, target, collection, content type, and extra attributes are
+/// captured from the modifiers used when the specifier was created, so they
+/// are available when a matching item needs to be created. The content type
+/// (if given) is also used whenever the specifier sets a secret.
+/// Otherwise, new items have secrets of type `application/octet-stream`,
+/// and updated secrets keep the type they have. The extra attributes are
+/// only given to new items; they aren't used to find existing ones.
 pub struct Specifier {
     ss: Arc<Service>,
     pub label: String,
     pub target: Option<String>,
+    pub collection: Option<String>,
+    pub content_type: Option<String>,
+    pub attributes: HashMap<String, String>,
     pub service: String,
     pub user: String,
+    cached: RwLock<Option<(Path<'static>, u64)>>,
 }
 
 impl std::fmt::Debug for Specifier {
@@ -27,31 +51,53 @@ impl std::fmt::Debug for Specifier {
         f.debug_struct("Specifier")
             .field("label", &self.label)
             .field("target", &self.target)
+            .field("collection", &self.collection)
+            .field("content_type", &self.content_type)
+            .field("attributes", &self.attributes)
             .field("service", &self.service)
             .field("user", &self.user)
             .finish()
     }
 }
 
+/// The modifiers a specifier is created with.
+#[derive(Debug, Default)]
+pub(crate) struct Modifiers<'a> {
+    pub(crate) label: Option<&'a str>,
+    pub(crate) target: Option<&'a str>,
+    pub(crate) collection: Option<&'a str>,
+    pub(crate) content_type: Option<&'a str>,
+    /// Modifiers that aren't otherwise understood, which become attributes.
+    pub(crate) attributes: HashMap<String, String>,
+}
+
 impl Specifier {
     /// Create a specifier.
     pub(crate) fn new(
         ss: Arc<Service>,
-        label: Option<&str>,
-        target: Option<&str>,
+        modifiers: Modifiers,
         service: &str,
         user: &str,
     ) -> Arc<Self> {
-        let label = match label {
-            None => format!("keyring:{user}@{service}"),
+        let label = match modifiers.label {
+            None => default_label(&ss, service, user),
             Some(l) => l.to_string(),
         };
+        // Python keyring stores passwords as text
+        let content_type = match ss.config.python_keyring {
+            true => modifiers.content_type.or(Some("text/plain")),
+            false => modifiers.content_type,
+        };
         Arc::new(Self {
             ss,
             label,
-            target: target.map(|s| s.to_string()),
+            target: modifiers.target.map(|s| s.to_string()),
+            collection: modifiers.collection.map(|s| s.to_string()),
+            content_type: content_type.map(|s| s.to_string()),
+            attributes: modifiers.attributes,
             service: service.to_string(),
             user: user.to_string(),
+            cached: RwLock::new(None),
         })
     }
 
@@ -59,8 +105,7 @@ impl Specifier {
     ///
     /// This may or may not match the one in the specifier.
     pub fn get_label(&self) -> Result<String> {
-        let path = self.get_unique_item()?;
-        self.ss.get_label(&path)
+        self.with_item(|path| self.ss.get_label(path).wait())
     }
 
     /// Sets the label on an existing, matching item.
@@ -68,99 +113,736 @@ impl Specifier {
     /// Note that this doesn't update the specifier. If you delete
     /// the item and recreate it, the label will come from the specifier.
     pub fn set_label(&self, label: &str) -> Result<()> {
-        let path = self.get_unique_item()?;
-        self.ss.set_label(&path, label)
+        self.with_item(|path| self.ss.set_label(path, label).wait())
+    }
+
+    /// Returns whether an existing, matching item (or its collection) is locked.
+    ///
+    /// Unlike other operations, this never unlocks anything, so it never
+    /// prompts the user. Applications can use it to show that the keyring
+    /// is locked without disturbing it.
+    pub fn is_locked(&self) -> Result<bool> {
+        let path = self.peek_item()?;
+        self.ss.is_locked(&path).wait()
+    }
+
+    /// Find this specifier's item for a batch operation.
+    ///
+    /// The item is found just as the single-entry operations find it, but
+    /// if it's locked it's left locked, for the batch to unlock together
+    /// with the others.
+    pub(crate) fn batch_item(&self) -> Result<Path<'static>> {
+        if let Some(path) = self.cached_item() {
+            return Ok(path);
+        }
+        let path = wait_for(self.lookup_item(false))?;
+        self.remember_item(&path);
+        Ok(path)
+    }
+
+    /// Find this specifier's item without unlocking anything.
+    pub(crate) fn peek_item(&self) -> Result<Path<'static>> {
+        let attributes = self.search_attributes();
+        let paths = self
+            .ss
+            .peek_matching_items(self.scope().as_deref(), &borrow_attributes(&attributes))
+            .wait()?;
+        self.unique_item(paths)
+    }
+
+    /// Returns the revision of an existing, matching item.
+    ///
+    /// The revision is the item's `Modified` timestamp, which the Secret
+    /// Service updates whenever the item changes. It has a resolution of
+    /// one second, so changes made in the same second as a read of the
+    /// revision cannot be detected.
+    pub fn get_revision(&self) -> Result<u64> {
+        self.with_item(|path| self.ss.get_revision(path).wait())
+    }
+
+    /// Returns when an existing, matching item was created, in seconds
+    /// since the Unix epoch.
+    ///
+    /// Tools that rotate credentials can use this (or
+    /// [get_modified](Specifier::get_modified)) to tell how old a secret is.
+    /// Both timestamps are also in the item's [snapshot](Specifier::get_snapshot).
+    pub fn get_created(&self) -> Result<u64> {
+        self.with_item(|path| self.ss.get_created(path).wait())
+    }
+
+    /// Returns when an existing, matching item was last changed, in seconds
+    /// since the Unix epoch. This is the same as its
+    /// [revision](Specifier::get_revision).
+    pub fn get_modified(&self) -> Result<u64> {
+        self.get_revision()
+    }
+
+    /// Returns the content type of an existing, matching item's secret.
+    pub fn get_content_type(&self) -> Result<String> {
+        self.with_item(|path| self.ss.get_content_type(path).wait())
+    }
+
+    /// Sets the content type of an existing, matching item's secret,
+    /// leaving the secret as it is.
+    ///
+    /// Note that this doesn't update the specifier, so if it was given a
+    /// `content-type` modifier, setting the secret changes the type back.
+    pub fn set_content_type(&self, content_type: &str) -> Result<()> {
+        self.with_item(|path| self.ss.set_content_type(path, content_type).wait())
+    }
+
+    /// Returns the secret of an existing, matching item, along with its revision.
+    pub fn get_secret_with_revision(&self) -> Result<(Vec<u8>, u64)> {
+        let snapshot = self.get_snapshot(true)?;
+        Ok((snapshot.secret.unwrap_or_default(), snapshot.modified))
+    }
+
+    /// Returns the label, attributes, timestamps and (if `with_secret` is
+    /// set) the secret of an existing, matching item.
+    ///
+    /// This reads everything in as few D-Bus calls as possible: one for the
+    /// item's properties and one more for its secret.
+    pub fn get_snapshot(&self, with_secret: bool) -> Result<ItemSnapshot> {
+        self.with_item(|path| self.ss.get_item_snapshot(path, with_secret).wait())
+    }
+
+    /// Sets the secret on an existing, matching item, if its revision matches.
+    ///
+    /// If the item has been modified since the given revision was read,
+    /// nothing is written and a [Conflict](crate::errors::StoreError::Conflict) error is returned.
+    /// Unlike [set_secret](CredentialApi::set_secret), this never creates an item.
+    pub fn set_secret_if_revision(&self, secret: &[u8], revision: u64) -> Result<()> {
+        self.with_item(|path| {
+            self.ss
+                .set_secret_if_revision(path, secret, self.content_type.as_deref(), revision)
+                .wait()
+        })
+    }
+
+    /// Updates the attributes on an existing, matching item, if its revision matches.
+    ///
+    /// If the item has been modified since the given revision was read,
+    /// nothing is written and a [Conflict](crate::errors::StoreError::Conflict) error is returned.
+    pub fn update_attributes_if_revision(
+        &self,
+        attributes: &HashMap<&str, &str>,
+        revision: u64,
+    ) -> Result<()> {
+        self.check_updatable(attributes)?;
+        self.with_item(|path| {
+            self.ss
+                .update_attributes_if_revision(path, attributes, revision)
+                .wait()
+        })
+    }
+
+    /// Returns the note on an existing, matching item, if it has one.
+    ///
+    /// Notes are kept in the item's `note` attribute, so (unlike the
+    /// secret) they are not encrypted.
+    pub fn get_note(&self) -> Result<Option<String>> {
+        Ok(self.get_attributes()?.remove(NOTE_ATTRIBUTE))
+    }
+
+    /// Sets the note on an existing, matching item.
+    pub fn set_note(&self, note: &str) -> Result<()> {
+        self.update_attributes(&HashMap::from([(NOTE_ATTRIBUTE, note)]))
+    }
+
+    /// Returns the provenance recorded on an existing, matching item, if any.
+    ///
+    /// See [Provenance] for details.
+    pub fn get_provenance(&self) -> Result<Option<Provenance>> {
+        Ok(Provenance::from_attributes(&self.get_attributes()?))
+    }
+
+    /// Like [get_secret](CredentialApi::get_secret), but doesn't wait for the store.
+    ///
+    /// If the store is busy with another operation, this returns a
+    /// [WouldBlock](crate::errors::StoreError::WouldBlock) error immediately.
+    pub fn try_get_secret(&self) -> Result<Vec<u8>> {
+        without_blocking(|| self.get_secret())
+    }
+
+    /// Like [set_secret](CredentialApi::set_secret), but doesn't wait for the store.
+    ///
+    /// See [try_get_secret](Specifier::try_get_secret) for details.
+    pub fn try_set_secret(&self, secret: &[u8]) -> Result<()> {
+        without_blocking(|| self.set_secret(secret))
+    }
+
+    /// Like [set_secret](CredentialApi::set_secret), but without blocking.
+    ///
+    /// The store's operations run on a worker thread, so this can be awaited
+    /// from any async runtime without stalling it (even if the Secret Service
+    /// prompts the user). Dropping the future before the operation it's
+    /// waiting for has started cancels that operation.
+    pub async fn set_secret_async(&self, secret: &[u8]) -> Result<()> {
+        self.ss
+            .hooks
+            .run_async(|| self.access(AccessKind::Write, "set_secret"), async {
+                // first try to find a unique, existing, matching item and set its password
+                // (unless the store only creates items)
+                match self
+                    .with_item_async(|p| match self.ss.config.create_if_absent {
+                        true => Pending::failed(already_exists(p)),
+                        false => self.ss.set_secret(p, secret, self.content_type.as_deref()),
+                    })
+                    .await
+                {
+                    Ok(()) => return Ok(()),
+                    Err(Error::NoEntry) => {}
+                    Err(err) => return Err(err),
+                }
+                // if there is no existing item, create one for this credential.
+                let collection = self.collection_name();
+                let attributes = self.search_attributes();
+                let created = self
+                    .ss
+                    .create_item(
+                        &collection,
+                        &self.label,
+                        borrow_attributes(&self.creation_attributes()),
+                        secret,
+                        self.content_type.as_deref(),
+                    )
+                    .await?;
+                // a dry run created nothing to check
+                if self.ss.config.dry_run {
+                    return Ok(());
+                }
+                // another process may have created a matching item at the same time
+                let paths = self.find_items(&attributes, true).await?;
+                self.verify_created(&created, &paths)?;
+                if paths.len() > 1 {
+                    self.ss.resolve_duplicates(&paths).await?;
+                    if self.ss.config.verify_creation {
+                        let remaining = self.find_items(&attributes, true).await?;
+                        self.verify_resolved(&created, &remaining)?;
+                    }
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    /// Like [get_secret](CredentialApi::get_secret), but without blocking.
+    ///
+    /// See [set_secret_async](Specifier::set_secret_async) for details.
+    pub async fn get_secret_async(&self) -> Result<Vec<u8>> {
+        self.ss
+            .hooks
+            .run_async(|| self.access(AccessKind::Read, "get_secret"), async {
+                let Some(cache) = self.ss.secret_cache() else {
+                    return self.with_item_async(|path| self.ss.get_secret(path)).await;
+                };
+                let key = self.cache_key();
+                if let Some(secret) = cache.get(&key) {
+                    return Ok(secret);
+                }
+                let stamp = cache.stamp();
+                let secret = self
+                    .with_item_async(|path| self.ss.get_secret(path))
+                    .await?;
+                cache.put(key, secret.clone(), stamp);
+                Ok(secret)
+            })
+            .await
+    }
+
+    /// Like [get_attributes](CredentialApi::get_attributes), but without blocking.
+    ///
+    /// See [set_secret_async](Specifier::set_secret_async) for details.
+    pub async fn get_attributes_async(&self) -> Result<HashMap<String, String>> {
+        self.ss
+            .hooks
+            .run_async(|| self.access(AccessKind::Read, "get_attributes"), async {
+                self.with_item_async(|path| self.ss.get_attributes(path))
+                    .await
+            })
+            .await
+    }
+
+    /// Like [update_attributes](CredentialApi::update_attributes), but without blocking.
+    ///
+    /// See [set_secret_async](Specifier::set_secret_async) for details.
+    pub async fn update_attributes_async(&self, attributes: &HashMap<&str, &str>) -> Result<()> {
+        self.ss
+            .hooks
+            .run_async(
+                || self.access(AccessKind::Write, "update_attributes"),
+                async {
+                    self.check_updatable(attributes)?;
+                    self.with_item_async(|path| self.ss.update_attributes(path, attributes))
+                        .await
+                },
+            )
+            .await
+    }
+
+    /// Like [delete_credential](CredentialApi::delete_credential), but without blocking.
+    ///
+    /// See [set_secret_async](Specifier::set_secret_async) for details.
+    pub async fn delete_credential_async(&self) -> Result<()> {
+        self.ss
+            .hooks
+            .run_async(
+                || self.access(AccessKind::Delete, "delete_credential"),
+                async {
+                    self.with_item_async(|path| self.ss.delete(path)).await?;
+                    self.forget_item();
+                    Ok(())
+                },
+            )
+            .await
+    }
+
+    /// Returns the D-Bus path of the collection that this specifier's item
+    /// is created in: the default collection, its target collection, or the
+    /// collection given by its `collection` modifier.
+    ///
+    /// If several collections have the target as their label, this reports
+    /// the one chosen by the store's `duplicate-collections` policy.
+    pub fn target_collection(&self) -> Result<String> {
+        Ok(self
+            .ss
+            .collection_path(&self.collection_name())
+            .wait()?
+            .to_string())
+    }
+
+    /// Returns the D-Bus object path of an existing, matching item.
+    ///
+    /// This is the path that tools like `secret-tool` and `dbus-monitor`
+    /// report, so applications can use it to correlate entries with them.
+    pub fn item_path(&self) -> Result<String> {
+        Ok(self.get_unique_item()?.to_string())
+    }
+
+    /// Returns the D-Bus object path of the collection that holds an
+    /// existing, matching item.
+    ///
+    /// This can differ from the [target collection](Specifier::target_collection)
+    /// if the item was created by another client.
+    pub fn item_collection(&self) -> Result<String> {
+        Ok(parent_path(&self.get_unique_item()?).to_string())
+    }
+
+    /// Moves an existing, matching item to another collection, named as a
+    /// `target` modifier names it.
+    ///
+    /// The item is recreated in that collection (which is created if need
+    /// be, unless the store has `explicit-collections`) with the same label,
+    /// attributes, and secret, and the original is deleted, all in a single
+    /// operation: the secret never passes through the application. The
+    /// specifier itself is unchanged, so it still finds the moved item
+    /// unless the store has `scoped-search`.
+    pub fn move_to_target(&self, target: &str) -> Result<()> {
+        if target.is_empty() {
+            return Err(empty_target());
+        }
+        let moved = self.with_item(|path| self.ss.move_item(path, target).wait())?;
+        self.remember_item(&moved);
+        Ok(())
+    }
+
+    /// Copies an existing, matching item to another collection, named as
+    /// a `target` modifier names it.
+    ///
+    /// The copy (in a collection that's created if need be, unless the
+    /// store has `explicit-collections`) has the same label, attributes,
+    /// and secret as the item, and replaces any item with the same
+    /// attributes there, so this can promote a secret from (say) a staging
+    /// collection to the default one. The secret never passes through the
+    /// application. Since the specifier now matches both items, stores
+    /// typically use `scoped-search` or an `ambiguous-items` policy of
+    /// `target` with this. Fails with [Invalid](Error::Invalid) if the
+    /// item is already in that collection.
+    pub fn clone_to(&self, target: &str) -> Result<()> {
+        if target.is_empty() {
+            return Err(empty_target());
+        }
+        self.with_item(|path| self.ss.copy_item(path, target).wait())?;
+        Ok(())
+    }
+
+    /// Renames the service and user of an existing, matching item.
+    ///
+    /// The item's controlled attributes are changed to those for the new
+    /// service and user in a single write and, if the item has the label
+    /// that the store gives the old service and user by default, it gets
+    /// the default label for the new ones. Its other attributes, its
+    /// secret, and its collection are unchanged. The specifier itself is
+    /// unchanged, so the result is an entry for the renamed item, with this
+    /// specifier's modifiers. Fails with an
+    /// [AlreadyExists](crate::errors::StoreError::AlreadyExists) error if
+    /// there's already an item for the new service and user.
+    pub fn rename(&self, service: &str, user: &str) -> Result<Entry> {
+        let old_label = default_label(&self.ss, &self.service, &self.user);
+        let modifiers = Modifiers {
+            label: Some(self.label.as_str()).filter(|l| *l != old_label),
+            target: self.target.as_deref(),
+            collection: self.collection.as_deref(),
+            content_type: self.content_type.as_deref(),
+            attributes: self.attributes.clone(),
+        };
+        let renamed = Specifier::new(self.ss.clone(), modifiers, service, user);
+        let attributes = renamed.search_attributes();
+        self.with_item(|path| {
+            let existing = renamed.find_items(&attributes, true).wait()?;
+            if let Some(other) = existing.iter().find(|p| *p != path) {
+                return Err(already_exists(other));
+            }
+            self.ss
+                .rename_item(path, &attributes, &old_label, &renamed.label)
+                .wait()
+        })?;
+        self.forget_item();
+        Ok(Entry::new_with_credential(renamed))
+    }
+
+    /// Reports how long this specifier's item lasts once it's created.
+    ///
+    /// Items in the session collection (which the store uses by default
+    /// when configured with `session-collection`, and which can be given
+    /// by a target such as `session` or `alias:session`, or by the
+    /// `collection` modifier) last until the user logs out; other items
+    /// last until they are deleted. The collection is recognized by its
+    /// path, so it can be given by any of its names.
+    pub fn persistence(&self) -> CredentialPersistence {
+        let name = self.collection_name();
+        persistence(self.ss.is_session_collection(&name).wait())
+    }
+
+    /// Creates the target collection in the specifier, if it doesn't exist.
+    ///
+    /// Stores configured with `explicit-collections` require this to be
+    /// called before items can be created in a new target collection.
+    pub fn create_target(&self) -> Result<()> {
+        match self.target.clone() {
+            None => Err(Error::Invalid("target".to_string(), "not set".to_string())),
+            Some(s) => self.ss.ensure_collection(&s).wait(),
+        }
     }
 
     /// Deletes the target collection in the specifier
+    ///
+    /// The collections behind the `default` and `session` aliases can't be
+    /// deleted (however the target names them): trying fails with a
+    /// [NotSupportedByStore](Error::NotSupportedByStore) error.
     pub fn delete_target(&self) -> Result<()> {
         match self.target.clone() {
             None => Err(Error::Invalid("target".to_string(), "not set".to_string())),
-            Some(s) => self.ss.delete_collection(&s),
+            Some(s) => self.ss.delete_collection(&s).wait(),
         }
     }
 
     fn get_unique_item(&self) -> Result<Path<'static>> {
-        let paths = self.ss.find_matching_items(&self.search_attributes())?;
+        wait_for(self.get_unique_item_async())
+    }
+
+    async fn get_unique_item_async(&self) -> Result<Path<'static>> {
+        if let Some(path) = self.cached_item() {
+            return Ok(path);
+        }
+        let path = self.lookup_item(true).await?;
+        self.remember_item(&path);
+        Ok(path)
+    }
+
+    /// Search for this specifier's item, falling back to legacy items and
+    /// resolving ambiguity as configured. Locked items are left locked
+    /// unless `unlock`.
+    async fn lookup_item(&self, unlock: bool) -> Result<Path<'static>> {
+        let attributes = self.search_attributes();
+        let mut paths = self.find_items(&attributes, unlock).await?;
+        if paths.is_empty() && self.ss.config.legacy_items {
+            let legacy = self.legacy_attributes();
+            paths = self
+                .ss
+                .find_items(None, &borrow_attributes(&legacy), unlock)
+                .await?;
+        }
+        if paths.len() > 1 && self.ss.config.ambiguous_items != AmbiguityPolicy::Error {
+            paths = self.ss.choose_items(paths, &self.collection_name()).await?;
+        }
+        self.unique_item(paths)
+    }
+
+    /// Run an operation on this specifier's item.
+    ///
+    /// If the item's path was cached (in stores configured with
+    /// `cache-item-paths`) and the operation fails because the path is
+    /// stale or the item is locked, the item is searched for again and
+    /// the operation retried once.
+    fn with_item<T>(&self, op: impl Fn(&Path<'static>) -> Result<T>) -> Result<T> {
+        let cached = self.cached_item().is_some();
+        let path = self.get_unique_item()?;
+        match op(&path) {
+            Err(err) if cached && (is_stale_path(&err) || is_locked_object(&err)) => {
+                self.forget_item();
+                op(&self.get_unique_item()?)
+            }
+            result => result,
+        }
+    }
+
+    /// Like [with_item](Specifier::with_item), but without blocking.
+    async fn with_item_async<T>(&self, op: impl Fn(&Path<'static>) -> Pending<T>) -> Result<T> {
+        let cached = self.cached_item().is_some();
+        let path = self.get_unique_item_async().await?;
+        match op(&path).await {
+            Err(err) if cached && (is_stale_path(&err) || is_locked_object(&err)) => {
+                self.forget_item();
+                op(&self.get_unique_item_async().await?).await
+            }
+            result => result,
+        }
+    }
+
+    /// The cached path of this specifier's item, unless the cache is
+    /// disabled or the Secret Service has restarted since it was found.
+    fn cached_item(&self) -> Option<Path<'static>> {
+        let cached = self
+            .cached
+            .read()
+            .expect("RwLock failure in credential store: please report a bug");
+        match cached.as_ref() {
+            Some((path, generation)) if *generation == self.ss.generation() => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    fn remember_item(&self, path: &Path<'static>) {
+        if self.ss.config.cache_item_paths {
+            *self
+                .cached
+                .write()
+                .expect("RwLock failure in credential store: please report a bug") =
+                Some((path.clone(), self.ss.generation()));
+        }
+    }
+
+    fn forget_item(&self) {
+        *self
+            .cached
+            .write()
+            .expect("RwLock failure in credential store: please report a bug") = None;
+    }
+
+    /// The only one of the items found for this specifier.
+    fn unique_item(&self, paths: Vec<Path<'static>>) -> Result<Path<'static>> {
         match paths.len() {
             0 => Err(Error::NoEntry),
             1 => Ok(paths[0].clone()),
             _ => {
-                let mut entries: Vec<Entry> = Vec::with_capacity(paths.len());
-                for path in paths {
-                    entries.push(Entry::new_with_credential(Wrapper::new(
-                        self.ss.clone(),
-                        path,
-                    )))
-                }
+                let entries = match self.ss.describe_candidates(&paths).wait() {
+                    Ok(candidates) => candidates
+                        .into_iter()
+                        .map(|c| {
+                            Entry::new_with_credential(Wrapper::with_candidate(self.ss.clone(), c))
+                        })
+                        .collect(),
+                    // the items can still be wrapped without their details
+                    Err(_) => paths
+                        .into_iter()
+                        .map(|p| Entry::new_with_credential(Wrapper::new(self.ss.clone(), p)))
+                        .collect(),
+                };
                 Err(Error::Ambiguous(entries))
             }
         }
     }
 
+    /// Describe an access to this specifier's credential to the store's hooks.
+    pub(crate) fn access(&self, kind: AccessKind, method: &'static str) -> Access {
+        Access {
+            kind,
+            method,
+            service: Some(self.service.clone()),
+            user: Some(self.user.clone()),
+            target: self.target.clone(),
+            path: None,
+        }
+    }
+
+    /// The name (or path) of the collection that this specifier's item is created in.
+    pub(crate) fn collection_name(&self) -> String {
+        let default = match self.ss.config.session_collection {
+            true => SESSION_COLLECTION_PATH,
+            false => "default",
+        };
+        self.collection
+            .clone()
+            .or_else(|| self.target.clone())
+            .unwrap_or(default.to_string())
+    }
+
+    /// Check that none of the given attributes are controlled by this store.
+    fn check_updatable(&self, attributes: &HashMap<&str, &str>) -> Result<()> {
+        for key in attributes.keys() {
+            if self.ss.is_controlled(key) {
+                return Err(Error::Invalid(
+                    key.to_string(),
+                    "cannot be updated".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// If the store verifies creation, check that a newly created item was
+    /// found by searching for it.
+    fn verify_created(&self, created: &Path<'static>, found: &[Path<'static>]) -> Result<()> {
+        if self.ss.config.verify_creation && !found.contains(created) {
+            return Err(unverified_creation(created, found.len()));
+        }
+        Ok(())
+    }
+
+    /// Check that only the newly created item remains after resolving duplicates.
+    fn verify_resolved(&self, created: &Path<'static>, remaining: &[Path<'static>]) -> Result<()> {
+        if remaining.len() != 1 {
+            return Err(unverified_creation(created, remaining.len()));
+        }
+        Ok(())
+    }
+
     /// Provide a HashMap of search attributes for this specifier.
-    fn search_attributes(&self) -> HashMap<&str, &str> {
-        let mut result: HashMap<&str, &str> = HashMap::new();
-        result.insert("service", self.service.as_str());
-        result.insert("username", self.user.as_str());
-        if self.target.is_some() {
-            result.insert("target", self.target.as_ref().unwrap());
+    ///
+    /// In stores configured with `xdg-schema`, these include the schema.
+    pub(crate) fn search_attributes(&self) -> HashMap<String, String> {
+        let mut attributes = self.ss.mapper().attributes(self);
+        if let Some(schema) = &self.ss.config.xdg_schema {
+            attributes.insert(SCHEMA_ATTRIBUTE.to_string(), schema.clone());
+        }
+        attributes
+    }
+
+    /// The attributes that older versions of keyring-rs gave this
+    /// specifier's item, before attribute names, hashing, and schemas
+    /// could be configured.
+    pub(crate) fn legacy_attributes(&self) -> HashMap<String, String> {
+        let mut attributes = HashMap::from([
+            ("service".to_string(), self.service.clone()),
+            ("username".to_string(), self.user.clone()),
+            ("application".to_string(), LEGACY_APPLICATION.to_string()),
+        ]);
+        if let Some(target) = &self.target {
+            attributes.insert("target".to_string(), target.clone());
+        }
+        attributes
+    }
+
+    /// The attributes to give a new item for this specifier: its search
+    /// attributes and any given as modifiers.
+    pub(crate) fn creation_attributes(&self) -> HashMap<String, String> {
+        let mut attributes = self.attributes.clone();
+        attributes.extend(self.search_attributes());
+        attributes
+    }
+
+    /// The collection that searches for this specifier's item are limited
+    /// to, in stores configured with `scoped-search`.
+    fn scope(&self) -> Option<String> {
+        match self.ss.config.scoped_search {
+            true => Some(self.collection_name()),
+            false => None,
         }
-        result
+    }
+
+    /// The key of this specifier's secret in the store's secret cache.
+    fn cache_key(&self) -> cache::Key {
+        cache::key(&self.search_attributes(), self.scope())
+    }
+
+    /// Find the items with this specifier's attributes, in its scope.
+    fn find_items(
+        &self,
+        attributes: &HashMap<String, String>,
+        unlock: bool,
+    ) -> Pending<Vec<Path<'static>>> {
+        let attributes = borrow_attributes(attributes);
+        self.ss
+            .find_items(self.scope().as_deref(), &attributes, unlock)
+    }
+}
+
+/// The label a specifier gets when it's created without a `label` modifier.
+fn default_label(ss: &Service, service: &str, user: &str) -> String {
+    if ss.config.python_keyring {
+        return format!("Password for '{user}' on '{service}'");
+    }
+    let hide_user = ss.config.username_salt.is_some() && !ss.config.username_in_label;
+    let user = if hide_user { None } else { Some(user) };
+    ss.localizer().default_label(service, user)
+}
+
+/// The persistence of items in a collection, given whether it's the
+/// session collection. If that can't be told, they're taken to last.
+fn persistence(in_session: Result<bool>) -> CredentialPersistence {
+    match in_session {
+        Ok(true) => CredentialPersistence::UntilLogout,
+        _ => CredentialPersistence::UntilDelete,
+    }
+}
+
+/// The path of the collection that holds an item.
+///
+/// Item paths are always the path of their collection plus one element.
+fn parent_path<'a>(item: &'a Path<'static>) -> &'a str {
+    item.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("/")
+}
+
+/// Read a snapshot of an entry's item, if the entry is one of this store's.
+pub(crate) fn snapshot(entry: &Entry, with_secret: bool) -> Option<Result<ItemSnapshot>> {
+    if let Some(specifier) = entry.as_any().downcast_ref::<Specifier>() {
+        Some(specifier.get_snapshot(with_secret))
+    } else {
+        let wrapper = entry.as_any().downcast_ref::<Wrapper>()?;
+        Some(wrapper.get_snapshot(with_secret))
     }
 }
 
 impl CredentialApi for Specifier {
     /// See the keyring-core API docs.
     fn set_secret(&self, secret: &[u8]) -> Result<()> {
-        // first try to find a unique, existing, matching item and set its password
-        match self.get_unique_item() {
-            Ok(p) => return self.ss.set_secret(&p, secret),
-            Err(Error::NoEntry) => {}
-            Err(err) => return Err(err),
-        }
-        // if there is no existing item, create one for this credential.
-        let collection = self.target.clone().unwrap_or("default".to_string());
-        self.ss
-            .create_item(&collection, &self.label, self.search_attributes(), secret)
+        wait_for(self.set_secret_async(secret))
     }
 
     /// See the keyring-core API docs.
+    ///
+    /// In stores configured with `secret-cache-ttl`, the secret may come
+    /// from the store's cache.
     fn get_secret(&self) -> Result<Vec<u8>> {
-        let path = self.get_unique_item()?;
-        self.ss.get_secret(&path)
+        wait_for(self.get_secret_async())
     }
 
     /// See the keyring-core API docs.
     fn get_attributes(&self) -> Result<HashMap<String, String>> {
-        let path = self.get_unique_item()?;
-        self.ss.get_attributes(&path)
+        wait_for(self.get_attributes_async())
     }
 
     /// See the keyring-core API docs.
     fn update_attributes(&self, attributes: &HashMap<&str, &str>) -> Result<()> {
-        for key in attributes.keys() {
-            if *key == "target" || *key == "service" || *key == "username" {
-                return Err(Error::Invalid(
-                    key.to_string(),
-                    "cannot be updated".to_string(),
-                ));
-            }
-        }
-        let path = self.get_unique_item()?;
-        self.ss.update_attributes(&path, attributes)
+        wait_for(self.update_attributes_async(attributes))
     }
 
     /// See the keyring-core API docs.
     fn delete_credential(&self) -> Result<()> {
-        let path = self.get_unique_item()?;
-        self.ss.delete(&path)
+        wait_for(self.delete_credential_async())
     }
 
     /// See the keyring-core API docs.
     fn get_credential(&self) -> Result<Option<Arc<Credential>>> {
         let path = self.get_unique_item()?;
-        Ok(Some(Wrapper::new(self.ss.clone(), path)))
+        let wrapper = Wrapper::new(self.ss.clone(), path);
+        // the wrapper is asked for to hold on to the item, so it needs to
+        // know the item now, in case it's recreated before the wrapper is used
+        wrapper.identity();
+        Ok(Some(wrapper))
     }
 
     /// See the keyring-core API docs.
@@ -184,84 +866,507 @@ impl CredentialApi for Specifier {
 /// Items in the Secret Service are uniquely identified by their path,
 /// but the path is (per the spec) not supposed to be exposed to clients,
 /// so it's held privately. It is available to the debugger.
+///
+/// The wrapper also remembers the item's attributes, from when it's first
+/// used. If the item is then deleted and recreated by another client, so
+/// that its path goes stale, the wrapper finds the recreated item by those
+/// attributes and retries the failed operation once.
 pub struct Wrapper {
     ss: Arc<Service>,
-    path: Path<'static>,
+    path: RwLock<Path<'static>>,
+    /// The item's attributes: `None` until they're first needed, and
+    /// `Some(None)` if they couldn't be fetched then.
+    identity: RwLock<Option<Option<HashMap<String, String>>>>,
+    candidate: Option<Candidate>,
+    generation: AtomicU64,
 }
 
 impl std::fmt::Debug for Wrapper {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Wrapper").field("path", &self.path).finish()
+        f.debug_struct("Wrapper")
+            .field("path", &self.path())
+            .finish()
     }
 }
 
 impl Wrapper {
+    /// Wrap an item. Its attributes are fetched when they're first needed.
     pub(crate) fn new(ss: Arc<Service>, path: Path<'static>) -> Arc<Self> {
-        Arc::new(Self { ss, path })
+        let generation = AtomicU64::new(ss.generation());
+        Arc::new(Self {
+            ss,
+            path: RwLock::new(path),
+            identity: RwLock::new(None),
+            candidate: None,
+            generation,
+        })
+    }
+
+    /// Wrap an item whose attributes are already known.
+    pub(crate) fn with_identity(
+        ss: Arc<Service>,
+        path: Path<'static>,
+        identity: HashMap<String, String>,
+    ) -> Arc<Self> {
+        let wrapper = Self::new(ss, path);
+        wrapper.set_identity(Some(identity));
+        wrapper
+    }
+
+    /// Wrap one of several items found for an entry.
+    pub(crate) fn with_candidate(ss: Arc<Service>, candidate: Candidate) -> Arc<Self> {
+        let generation = AtomicU64::new(ss.generation());
+        Arc::new(Self {
+            path: RwLock::new(Path::from(candidate.path.clone())),
+            identity: RwLock::new(Some(Some(candidate.attributes.clone()))),
+            candidate: Some(candidate),
+            ss,
+            generation,
+        })
+    }
+
+    /// Returns the details of the wrapped item, if it was one of several
+    /// found for an entry.
+    ///
+    /// The entries in an [Ambiguous](Error::Ambiguous) error report the
+    /// collection, label, attributes and modification time their items
+    /// had when the search was made (see [Candidate]). Other wrappers
+    /// return `None`.
+    pub fn candidate(&self) -> Option<&Candidate> {
+        self.candidate.as_ref()
+    }
+
+    /// Returns the D-Bus object path of the wrapped item.
+    ///
+    /// See [Specifier::item_path].
+    pub fn item_path(&self) -> String {
+        self.path().to_string()
+    }
+
+    /// Returns the D-Bus object path of the collection that holds the wrapped item.
+    pub fn item_collection(&self) -> String {
+        parent_path(&self.path()).to_string()
+    }
+
+    /// Reports how long the wrapped item lasts.
+    ///
+    /// See [Specifier::persistence]: items in the session collection last
+    /// until the user logs out, and other items until they are deleted.
+    pub fn persistence(&self) -> CredentialPersistence {
+        let collection = self.item_collection();
+        persistence(self.ss.is_session_collection(&collection).wait())
+    }
+
+    /// Moves the wrapped item to another collection.
+    ///
+    /// See [Specifier::move_to_target] for details. The wrapper follows
+    /// the item to its new path.
+    pub fn move_to_target(&self, target: &str) -> Result<()> {
+        if target.is_empty() {
+            return Err(empty_target());
+        }
+        let moved = self.with_item(|path| self.ss.move_item(path, target).wait())?;
+        self.adopt(vec![moved])?;
+        Ok(())
+    }
+
+    /// Copies the wrapped item to another collection.
+    ///
+    /// See [Specifier::clone_to] for details. The wrapper still wraps
+    /// the original item.
+    pub fn clone_to(&self, target: &str) -> Result<()> {
+        if target.is_empty() {
+            return Err(empty_target());
+        }
+        self.with_item(|path| self.ss.copy_item(path, target).wait())?;
+        Ok(())
     }
 
     /// Returns the label on the wrapped item.
     pub fn get_label(&self) -> Result<String> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.get_label(&self.path)
+        self.with_item(|path| self.ss.get_label(path).wait())
     }
 
     /// Sets the label on the wrapped item.
     pub fn set_label(&self, label: &str) -> Result<()> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.set_label(&self.path, label)
+        self.with_item(|path| self.ss.set_label(path, label).wait())
+    }
+
+    /// Returns whether the wrapped item (or its collection) is locked.
+    ///
+    /// See [Specifier::is_locked] for details.
+    pub fn is_locked(&self) -> Result<bool> {
+        self.ss.is_locked(&self.path()).wait()
+    }
+
+    /// Returns the revision of the wrapped item.
+    ///
+    /// See [Specifier::get_revision] for details.
+    pub fn get_revision(&self) -> Result<u64> {
+        self.with_item(|path| self.ss.get_revision(path).wait())
+    }
+
+    /// Returns when the wrapped item was created.
+    ///
+    /// See [Specifier::get_created] for details.
+    pub fn get_created(&self) -> Result<u64> {
+        self.with_item(|path| self.ss.get_created(path).wait())
+    }
+
+    /// Returns when the wrapped item was last changed.
+    ///
+    /// See [Specifier::get_modified] for details.
+    pub fn get_modified(&self) -> Result<u64> {
+        self.get_revision()
+    }
+
+    /// Returns the content type of the wrapped item's secret.
+    ///
+    /// See [Specifier::get_content_type] for details.
+    pub fn get_content_type(&self) -> Result<String> {
+        self.with_item(|path| self.ss.get_content_type(path).wait())
+    }
+
+    /// Sets the content type of the wrapped item's secret, leaving the
+    /// secret as it is.
+    pub fn set_content_type(&self, content_type: &str) -> Result<()> {
+        self.with_item(|path| self.ss.set_content_type(path, content_type).wait())
+    }
+
+    /// Returns the secret of the wrapped item, along with its revision.
+    pub fn get_secret_with_revision(&self) -> Result<(Vec<u8>, u64)> {
+        let snapshot = self.get_snapshot(true)?;
+        Ok((snapshot.secret.unwrap_or_default(), snapshot.modified))
+    }
+
+    /// Returns a snapshot of the wrapped item.
+    ///
+    /// See [Specifier::get_snapshot] for details.
+    pub fn get_snapshot(&self, with_secret: bool) -> Result<ItemSnapshot> {
+        self.with_item(|path| self.ss.get_item_snapshot(path, with_secret).wait())
+    }
+
+    /// Sets the secret on the wrapped item, if its revision matches.
+    ///
+    /// See [Specifier::set_secret_if_revision] for details.
+    pub fn set_secret_if_revision(&self, secret: &[u8], revision: u64) -> Result<()> {
+        self.with_item(|path| {
+            self.ss
+                .set_secret_if_revision(path, secret, None, revision)
+                .wait()
+        })
+    }
+
+    /// Returns the note on the wrapped item, if it has one.
+    ///
+    /// See [Specifier::get_note] for details.
+    pub fn get_note(&self) -> Result<Option<String>> {
+        Ok(self.get_attributes()?.remove(NOTE_ATTRIBUTE))
+    }
+
+    /// Sets the note on the wrapped item.
+    pub fn set_note(&self, note: &str) -> Result<()> {
+        self.update_attributes(&HashMap::from([(NOTE_ATTRIBUTE, note)]))
+    }
+
+    /// Returns the provenance recorded on the wrapped item, if any.
+    pub fn get_provenance(&self) -> Result<Option<Provenance>> {
+        Ok(Provenance::from_attributes(&self.get_attributes()?))
+    }
+
+    /// Like [get_secret](CredentialApi::get_secret), but doesn't wait for the store.
+    ///
+    /// See [Specifier::try_get_secret] for details.
+    pub fn try_get_secret(&self) -> Result<Vec<u8>> {
+        without_blocking(|| self.get_secret())
+    }
+
+    /// Like [set_secret](CredentialApi::set_secret), but doesn't wait for the store.
+    ///
+    /// See [Specifier::try_get_secret] for details.
+    pub fn try_set_secret(&self, secret: &[u8]) -> Result<()> {
+        without_blocking(|| self.set_secret(secret))
+    }
+
+    /// Like [set_secret](CredentialApi::set_secret), but without blocking.
+    ///
+    /// See [Specifier::set_secret_async] for details.
+    pub async fn set_secret_async(&self, secret: &[u8]) -> Result<()> {
+        self.ss
+            .hooks
+            .run_async(|| self.access(AccessKind::Write, "set_secret"), async {
+                self.with_item_async(|path| self.ss.set_secret(path, secret, None))
+                    .await
+            })
+            .await
+    }
+
+    /// Like [get_secret](CredentialApi::get_secret), but without blocking.
+    ///
+    /// See [Specifier::set_secret_async] for details.
+    pub async fn get_secret_async(&self) -> Result<Vec<u8>> {
+        self.ss
+            .hooks
+            .run_async(|| self.access(AccessKind::Read, "get_secret"), async {
+                self.with_item_async(|path| self.ss.get_secret(path)).await
+            })
+            .await
+    }
+
+    /// Like [get_attributes](CredentialApi::get_attributes), but without blocking.
+    ///
+    /// See [Specifier::set_secret_async] for details.
+    pub async fn get_attributes_async(&self) -> Result<HashMap<String, String>> {
+        self.ss
+            .hooks
+            .run_async(|| self.access(AccessKind::Read, "get_attributes"), async {
+                self.with_item_async(|path| self.ss.get_attributes(path))
+                    .await
+            })
+            .await
+    }
+
+    /// Like [update_attributes](CredentialApi::update_attributes), but without blocking.
+    ///
+    /// See [Specifier::set_secret_async] for details.
+    pub async fn update_attributes_async(&self, attributes: &HashMap<&str, &str>) -> Result<()> {
+        self.ss
+            .hooks
+            .run_async(
+                || self.access(AccessKind::Write, "update_attributes"),
+                async {
+                    self.with_item_async(|path| self.ss.update_attributes(path, attributes))
+                        .await?;
+                    let identity = self.ss.get_attributes(&self.path()).await.ok();
+                    self.set_identity(identity);
+                    Ok(())
+                },
+            )
+            .await
+    }
+
+    /// Like [delete_credential](CredentialApi::delete_credential), but without blocking.
+    ///
+    /// See [Specifier::set_secret_async] for details.
+    pub async fn delete_credential_async(&self) -> Result<()> {
+        self.ss
+            .hooks
+            .run_async(
+                || self.access(AccessKind::Delete, "delete_credential"),
+                async { self.with_item_async(|path| self.ss.delete(path)).await },
+            )
+            .await
+    }
+
+    /// Updates the attributes on the wrapped item, if its revision matches.
+    pub fn update_attributes_if_revision(
+        &self,
+        attributes: &HashMap<&str, &str>,
+        revision: u64,
+    ) -> Result<()> {
+        self.with_item(|path| {
+            self.ss
+                .update_attributes_if_revision(path, attributes, revision)
+                .wait()?;
+            self.remember(path);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn path(&self) -> Path<'static> {
+        self.path
+            .read()
+            .expect("RwLock failure in credential store: please report a bug")
+            .clone()
+    }
+
+    /// Record the current attributes of the item, after changing them.
+    fn remember(&self, path: &Path<'static>) {
+        self.set_identity(self.ss.get_attributes(path).wait().ok());
+    }
+
+    fn set_identity(&self, identity: Option<HashMap<String, String>>) {
+        *self
+            .identity
+            .write()
+            .expect("RwLock failure in credential store: please report a bug") = Some(identity);
+    }
+
+    /// Whether the daemon has restarted since the wrapped path was found.
+    fn restarted(&self) -> bool {
+        let generation = self.ss.generation();
+        self.generation.swap(generation, Ordering::AcqRel) != generation
+    }
+
+    /// Run an operation on the wrapped item, after ensuring it's unlocked
+    /// (or, in stores that relock items, leaving that to the operation).
+    ///
+    /// If the operation fails because the item's path is stale, the item
+    /// is found again by its attributes and the operation is retried once.
+    /// After the Secret Service daemon restarts, the item is found again
+    /// before the operation is tried.
+    fn with_item<T>(&self, op: impl Fn(&Path<'static>) -> Result<T>) -> Result<T> {
+        wait_for(self.identify_async());
+        if self.restarted() {
+            self.resolve()?;
+        }
+        let path = self.path();
+        let err = match self
+            .ss
+            .unlock_for_access(&path)
+            .wait()
+            .and_then(|_| op(&path))
+        {
+            Err(err) if is_stale_path(&err) => err,
+            result => return result,
+        };
+        let Some(path) = self.resolve()? else {
+            return Err(err);
+        };
+        self.ss.unlock_for_access(&path).wait()?;
+        op(&path)
+    }
+
+    /// Like [with_item](Wrapper::with_item), but without blocking.
+    async fn with_item_async<T>(&self, op: impl Fn(&Path<'static>) -> Pending<T>) -> Result<T> {
+        self.identify_async().await;
+        if self.restarted() {
+            self.resolve_async().await?;
+        }
+        let path = self.path();
+        let result = match self.ss.unlock_for_access(&path).await {
+            Ok(()) => op(&path).await,
+            Err(err) => Err(err),
+        };
+        let err = match result {
+            Err(err) if is_stale_path(&err) => err,
+            result => return result,
+        };
+        let Some(path) = self.resolve_async().await? else {
+            return Err(err);
+        };
+        self.ss.unlock_for_access(&path).await?;
+        op(&path).await
+    }
+
+    /// Find the item again by its remembered attributes.
+    ///
+    /// Returns `None` if the attributes aren't known or don't
+    /// identify a unique item.
+    fn resolve(&self) -> Result<Option<Path<'static>>> {
+        wait_for(self.resolve_async())
+    }
+
+    async fn resolve_async(&self) -> Result<Option<Path<'static>>> {
+        self.identify_async().await;
+        let Some(attributes) = self.known_identity() else {
+            return Ok(None);
+        };
+        let paths = self
+            .ss
+            .find_stored_items(&borrow_attributes(&attributes))
+            .await?;
+        self.adopt(paths)
+    }
+
+    /// Describe an access to the wrapped item to the store's hooks.
+    pub(crate) fn access(&self, kind: AccessKind, method: &'static str) -> Access {
+        let specifiers = self
+            .identity()
+            .and_then(|attributes| self.ss.mapper().specifiers(&attributes));
+        let (service, user) = specifiers.unzip();
+        Access {
+            kind,
+            method,
+            service,
+            user,
+            target: None,
+            path: Some(self.path().to_string()),
+        }
+    }
+
+    /// The item's attributes, fetching them if they haven't been.
+    fn identity(&self) -> Option<HashMap<String, String>> {
+        wait_for(self.identify_async());
+        self.known_identity()
+    }
+
+    /// Fetch the item's attributes, unless that's been done.
+    async fn identify_async(&self) {
+        let unknown = self
+            .identity
+            .read()
+            .expect("RwLock failure in credential store: please report a bug")
+            .is_none();
+        if unknown {
+            let identity = self.ss.get_attributes(&self.path()).await.ok();
+            self.set_identity(identity);
+        }
+    }
+
+    /// The item's attributes, if they've been fetched (and could be).
+    fn known_identity(&self) -> Option<HashMap<String, String>> {
+        self.identity
+            .read()
+            .expect("RwLock failure in credential store: please report a bug")
+            .clone()
+            .flatten()
+    }
+
+    /// Wrap the item found by resolution, if it's unique.
+    fn adopt(&self, paths: Vec<Path<'static>>) -> Result<Option<Path<'static>>> {
+        match paths.len() {
+            0 => Err(Error::NoEntry),
+            1 => {
+                *self
+                    .path
+                    .write()
+                    .expect("RwLock failure in credential store: please report a bug") =
+                    paths[0].clone();
+                Ok(Some(paths[0].clone()))
+            }
+            _ => Ok(None),
+        }
     }
 }
 
 impl CredentialApi for Wrapper {
     /// See the keyring-core API docs.
     fn set_secret(&self, secret: &[u8]) -> Result<()> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.set_secret(&self.path, secret)
+        wait_for(self.set_secret_async(secret))
     }
 
     /// See the keyring-core API docs.
     fn get_secret(&self) -> Result<Vec<u8>> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.get_secret(&self.path)
+        wait_for(self.get_secret_async())
     }
 
     /// See the keyring-core API docs.
     fn get_attributes(&self) -> Result<HashMap<String, String>> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.get_attributes(&self.path)
+        wait_for(self.get_attributes_async())
     }
 
     /// See the keyring-core API docs.
     fn update_attributes(&self, attributes: &HashMap<&str, &str>) -> Result<()> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.update_attributes(&self.path, attributes)
+        wait_for(self.update_attributes_async(attributes))
     }
 
     /// See the keyring-core API docs.
     fn delete_credential(&self) -> Result<()> {
-        self.ss.ensure_unlocked(&self.path)?;
-        self.ss.delete(&self.path)
+        wait_for(self.delete_credential_async())
     }
 
     /// See the keyring-core API docs.
     fn get_credential(&self) -> Result<Option<Arc<Credential>>> {
-        self.ss.ensure_unlocked(&self.path)?;
-        Ok(None)
+        self.with_item(|_| Ok(None))
     }
 
     /// See the keyring-core API docs.
     fn get_specifiers(&self) -> Option<(String, String)> {
-        if self.ss.ensure_unlocked(&self.path).is_err() {
-            return None;
-        }
-        let attributes = self.ss.get_attributes(&self.path).unwrap_or_default();
-        if let Some(service) = attributes.get("service") {
-            if let Some(user) = attributes.get("username") {
-                return Some((service.to_string(), user.to_string()));
-            }
-        }
-        None
+        let attributes = self
+            .with_item(|path| self.ss.get_attributes(path).wait())
+            .ok()?;
+        self.ss.mapper().specifiers(&attributes)
     }
 
     /// See the keyring-core API docs.
@@ -6,13 +6,252 @@ use dbus_secret_service::Error as ServiceError;
 
 use keyring_core::error::Error;
 
+/// Failure conditions specific to this store.
+///
+/// Keyring errors have no variants for these conditions, so they are
+/// returned wrapped in a [PlatformFailure](Error::PlatformFailure) or
+/// [NoStorageAccess](Error::NoStorageAccess) error. Clients who want to
+/// react to them can downcast the wrapped error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StoreError {
+    /// The item was modified after the caller read its revision.
+    ///
+    /// The values are the revision the caller expected and the
+    /// revision the item actually has.
+    Conflict(u64, u64),
+    /// A non-blocking operation found the store busy with another operation.
+    ///
+    /// This is returned (wrapped in a [NoStorageAccess](Error::NoStorageAccess)
+    /// error) by the `try_` operations on credentials.
+    WouldBlock,
+    /// An item couldn't be created because its target collection doesn't exist.
+    ///
+    /// This is only returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) by stores configured with `explicit-collections`. The value is
+    /// the name of the collection.
+    MissingCollection(String),
+    /// An item couldn't be created because its target collection is full.
+    ///
+    /// This is only returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) by stores configured with `max-items-per-target`. The values
+    /// are the name of the collection and the configured maximum.
+    QuotaExceeded(String, usize),
+    /// More than one collection has a target's label.
+    ///
+    /// This is only returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) by stores configured with `duplicate-collections=error`. The
+    /// values are the label and the number of collections that have it.
+    AmbiguousCollection(String, usize),
+    /// An operation was cancelled because it couldn't start before its deadline.
+    ///
+    /// This is only returned (wrapped in a [NoStorageAccess](Error::NoStorageAccess)
+    /// error) by stores configured with `operation-deadline`. The value is
+    /// the configured deadline.
+    DeadlineExceeded(std::time::Duration),
+    /// A newly created item couldn't be found again by its attributes,
+    /// or other items matching it remained.
+    ///
+    /// This is only returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) by stores configured with `verify-creation`. The values are
+    /// the path of the created item and the number of items that a search
+    /// for its attributes found.
+    UnverifiedCreation(String, usize),
+    /// An operation didn't finish within its timeout.
+    ///
+    /// This is only returned (wrapped in a [NoStorageAccess](Error::NoStorageAccess)
+    /// error) by stores configured with `operation-timeout`, or by operations
+    /// run inside [Store::with_timeout](crate::Store::with_timeout). The value
+    /// is the timeout. If the operation had already started, it still runs
+    /// to completion, but its result is discarded.
+    TimedOut(std::time::Duration),
+    /// A search found locked items, and the store doesn't unlock them.
+    ///
+    /// This is only returned (wrapped in a [NoStorageAccess](Error::NoStorageAccess)
+    /// error) by stores configured with `locked-items=error`. The value is
+    /// the number of locked items found. Callers can unlock them (say, with
+    /// [Store::unlock_collection](crate::Store::unlock_collection)) and retry.
+    Locked(usize),
+    /// An item couldn't be created because a matching item already exists.
+    ///
+    /// This is only returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) by stores configured with `create-if-absent`. The value is the
+    /// path of the existing item.
+    AlreadyExists(String),
+    /// The item or collection that an operation used is locked.
+    ///
+    /// This is returned (wrapped in a [NoStorageAccess](Error::NoStorageAccess)
+    /// error) when the Secret Service reports
+    /// `org.freedesktop.Secret.Error.IsLocked`. Callers can unlock the
+    /// collection (say, with [Store::unlock_collection](crate::Store::unlock_collection))
+    /// and retry.
+    IsLocked,
+    /// The item or collection that an operation used doesn't exist.
+    ///
+    /// This is returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) when the Secret Service reports
+    /// `org.freedesktop.Secret.Error.NoSuchObject` (or D-Bus reports
+    /// `org.freedesktop.DBus.Error.UnknownObject`), typically because
+    /// another client deleted the item. Searching for it again may find
+    /// its replacement.
+    NoSuchObject,
+    /// The store's session with the Secret Service no longer exists.
+    ///
+    /// This is returned (wrapped in a [PlatformFailure](Error::PlatformFailure)
+    /// error) when the Secret Service reports
+    /// `org.freedesktop.Secret.Error.NoSession`, typically because the
+    /// daemon restarted. Stores open a new session and retry the operation
+    /// once, so callers only see this if that fails too.
+    NoSession,
+    /// The user dismissed a prompt (to unlock a collection, say) that the
+    /// operation needed.
+    ///
+    /// This is returned wrapped in a [NoStorageAccess](Error::NoStorageAccess) error.
+    PromptDismissed,
+    /// Unlocking the items or collections that an operation needed would
+    /// have prompted the user.
+    ///
+    /// This is only returned (wrapped in a [NoStorageAccess](Error::NoStorageAccess)
+    /// error) by stores configured with `no-prompt`. The value is the number
+    /// of locked items or collections. Callers can unlock them when it suits
+    /// them (say, with [Store::unlock_collection](crate::Store::unlock_collection)
+    /// on a store that prompts) and retry.
+    WouldPrompt(usize),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Conflict(expected, actual) => write!(
+                f,
+                "Item was modified: expected revision {expected}, found revision {actual}"
+            ),
+            StoreError::WouldBlock => write!(f, "The store is busy with another operation"),
+            StoreError::MissingCollection(name) => {
+                write!(f, "There is no collection named '{name}'")
+            }
+            StoreError::AmbiguousCollection(name, count) => {
+                write!(f, "There are {count} collections named '{name}'")
+            }
+            StoreError::DeadlineExceeded(deadline) => write!(
+                f,
+                "The store was too busy to start the operation within {} ms",
+                deadline.as_millis()
+            ),
+            StoreError::Locked(count) => write!(f, "{count} matching items are locked"),
+            StoreError::AlreadyExists(path) => write!(f, "Matching item {path} already exists"),
+            StoreError::IsLocked => write!(f, "The item or collection is locked"),
+            StoreError::NoSuchObject => write!(f, "The item or collection doesn't exist"),
+            StoreError::NoSession => write!(f, "The Secret Service session no longer exists"),
+            StoreError::PromptDismissed => write!(f, "The prompt was dismissed"),
+            StoreError::WouldPrompt(count) => write!(
+                f,
+                "Unlocking {count} locked items or collections would prompt the user"
+            ),
+            StoreError::TimedOut(timeout) => write!(
+                f,
+                "The operation didn't finish within {} ms",
+                timeout.as_millis()
+            ),
+            StoreError::UnverifiedCreation(path, found) => write!(
+                f,
+                "Item {path} was created, but searching for it found {found} matching items"
+            ),
+            StoreError::QuotaExceeded(name, max) => {
+                write!(
+                    f,
+                    "Collection '{name}' already has the maximum of {max} items"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// A failure reported over D-Bus that isn't otherwise understood.
+///
+/// D-Bus errors that aren't mapped to a [StoreError] are returned wrapped
+/// in a [PlatformFailure](Error::PlatformFailure) error as one of these,
+/// which keeps the error's name and message and the kind of operation
+/// that the store was running when it occurred (`search`, `create`,
+/// `read`, `write`, `delete`, or `unlock`). Its [Display](std::fmt::Display)
+/// format has all three, for logs and bug reports, and its
+/// [source](std::error::Error::source) is the original error.
+#[derive(Debug)]
+pub struct DbusError {
+    operation: Option<&'static str>,
+    source: ServiceError,
+}
+
+impl DbusError {
+    /// The D-Bus name of the error, such as `org.freedesktop.DBus.Error.AccessDenied`.
+    pub fn name(&self) -> Option<&str> {
+        match &self.source {
+            ServiceError::Dbus(err) => err.name(),
+            _ => None,
+        }
+    }
+
+    /// The message that came with the error.
+    pub fn message(&self) -> Option<&str> {
+        match &self.source {
+            ServiceError::Dbus(err) => err.message(),
+            _ => None,
+        }
+    }
+
+    /// The kind of operation the store was running, if the error
+    /// came from one.
+    pub fn operation(&self) -> Option<&str> {
+        self.operation
+    }
+}
+
+impl std::fmt::Display for DbusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "D-Bus error {}: {}",
+            self.name().unwrap_or("(unnamed)"),
+            self.message().unwrap_or("(no message)")
+        )?;
+        if let Some(operation) = self.operation {
+            write!(f, " (during {operation} operation)")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DbusError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
 /// Map underlying secret-service errors to keyring errors with
 /// appropriate annotation.
+///
+/// Well-known Secret Service errors become [StoreError]s, so callers
+/// can tell them apart without matching on messages.
 pub fn decode_error(err: ServiceError) -> Error {
     match err {
-        ServiceError::Locked => no_access(err),
+        ServiceError::Locked => Error::NoStorageAccess(Box::new(StoreError::IsLocked)),
         ServiceError::NoResult => no_access(err),
-        ServiceError::Prompt => no_access(err),
+        ServiceError::Prompt => Error::NoStorageAccess(Box::new(StoreError::PromptDismissed)),
+        ServiceError::Dbus(ref inner) => match inner.name() {
+            Some("org.freedesktop.Secret.Error.IsLocked") => {
+                Error::NoStorageAccess(Box::new(StoreError::IsLocked))
+            }
+            Some("org.freedesktop.Secret.Error.NoSuchObject")
+            | Some("org.freedesktop.DBus.Error.UnknownObject") => {
+                Error::PlatformFailure(Box::new(StoreError::NoSuchObject))
+            }
+            Some("org.freedesktop.Secret.Error.NoSession") => {
+                Error::PlatformFailure(Box::new(StoreError::NoSession))
+            }
+            _ => platform_failure(err),
+        },
         _ => platform_failure(err),
     }
 }
@@ -22,7 +261,144 @@ pub fn empty_target() -> Error {
 }
 
 pub fn platform_failure(err: ServiceError) -> Error {
-    Error::PlatformFailure(wrap(err))
+    match err {
+        ServiceError::Dbus(_) => Error::PlatformFailure(Box::new(DbusError {
+            operation: None,
+            source: err,
+        })),
+        _ => Error::PlatformFailure(wrap(err)),
+    }
+}
+
+/// Record the kind of operation that a D-Bus error occurred in.
+pub(crate) fn in_operation(mut err: Error, operation: &'static str) -> Error {
+    if let Error::PlatformFailure(inner) = &mut err {
+        if let Some(dbus) = inner.downcast_mut::<DbusError>() {
+            dbus.operation.get_or_insert(operation);
+        }
+    }
+    err
+}
+
+/// The name of the D-Bus error that an error wraps, if any.
+fn dbus_name<'a>(inner: &'a (dyn std::error::Error + Send + Sync + 'static)) -> Option<&'a str> {
+    inner.downcast_ref::<DbusError>().and_then(DbusError::name)
+}
+
+pub fn conflict(expected: u64, actual: u64) -> Error {
+    Error::PlatformFailure(Box::new(StoreError::Conflict(expected, actual)))
+}
+
+pub fn missing_collection(name: &str) -> Error {
+    Error::PlatformFailure(Box::new(StoreError::MissingCollection(name.to_string())))
+}
+
+pub fn quota_exceeded(name: &str, max: usize) -> Error {
+    Error::PlatformFailure(Box::new(StoreError::QuotaExceeded(name.to_string(), max)))
+}
+
+pub fn already_exists(path: &str) -> Error {
+    Error::PlatformFailure(Box::new(StoreError::AlreadyExists(path.to_string())))
+}
+
+pub fn ambiguous_collection(name: &str, count: usize) -> Error {
+    Error::PlatformFailure(Box::new(StoreError::AmbiguousCollection(
+        name.to_string(),
+        count,
+    )))
+}
+
+pub fn deadline_exceeded(deadline: std::time::Duration) -> Error {
+    Error::NoStorageAccess(Box::new(StoreError::DeadlineExceeded(deadline)))
+}
+
+pub fn locked_items(count: usize) -> Error {
+    Error::NoStorageAccess(Box::new(StoreError::Locked(count)))
+}
+
+pub fn timed_out(timeout: std::time::Duration) -> Error {
+    Error::NoStorageAccess(Box::new(StoreError::TimedOut(timeout)))
+}
+
+pub fn unverified_creation(path: &str, found: usize) -> Error {
+    Error::PlatformFailure(Box::new(StoreError::UnverifiedCreation(
+        path.to_string(),
+        found,
+    )))
+}
+
+pub fn would_prompt(count: usize) -> Error {
+    Error::NoStorageAccess(Box::new(StoreError::WouldPrompt(count)))
+}
+
+pub fn would_block() -> Error {
+    Error::NoStorageAccess(Box::new(StoreError::WouldBlock))
+}
+
+/// Whether an error means that the object an operation used is locked.
+pub(crate) fn is_locked_object(err: &Error) -> bool {
+    let inner = match err {
+        Error::PlatformFailure(inner) | Error::NoStorageAccess(inner) => inner,
+        _ => return false,
+    };
+    matches!(
+        inner.downcast_ref::<StoreError>(),
+        Some(StoreError::IsLocked)
+    )
+}
+
+/// Whether an error means that an item's path no longer names an object,
+/// as happens when the item has been deleted by another client.
+pub(crate) fn is_stale_path(err: &Error) -> bool {
+    let Error::PlatformFailure(inner) = err else {
+        return false;
+    };
+    if let Some(StoreError::NoSuchObject) = inner.downcast_ref::<StoreError>() {
+        return true;
+    }
+    // gnome-keyring reports missing objects as lacking the called interface
+    dbus_name(inner.as_ref()) == Some("org.freedesktop.DBus.Error.UnknownMethod")
+}
+
+/// Whether an error means that the connection to the Secret Service has
+/// been lost, as happens when the session bus or the daemon restarts.
+pub(crate) fn is_disconnected(err: &Error) -> bool {
+    let (Error::PlatformFailure(inner) | Error::NoStorageAccess(inner)) = err else {
+        return false;
+    };
+    // the daemon restarted, so our session object is gone
+    if let Some(StoreError::NoSession) = inner.downcast_ref::<StoreError>() {
+        return true;
+    }
+    matches!(
+        dbus_name(inner.as_ref()),
+        Some("org.freedesktop.DBus.Error.Disconnected")
+            | Some("org.freedesktop.DBus.Error.NoServer")
+            | Some("org.freedesktop.DBus.Error.ServiceUnknown")
+            | Some("org.freedesktop.DBus.Error.NameHasNoOwner")
+    )
+}
+
+/// Whether an error is likely to go away if the operation is retried:
+/// the Secret Service (or the bus) didn't answer in time, or wasn't
+/// available, or the connection to it was lost.
+pub(crate) fn is_transient(err: &Error) -> bool {
+    if is_disconnected(err) {
+        return true;
+    }
+    let Error::PlatformFailure(inner) = err else {
+        return false;
+    };
+    if let Some(ServiceError::Unavailable) = inner.downcast_ref::<ServiceError>() {
+        return true;
+    }
+    matches!(
+        dbus_name(inner.as_ref()),
+        Some("org.freedesktop.DBus.Error.NoReply")
+            | Some("org.freedesktop.DBus.Error.Timeout")
+            | Some("org.freedesktop.DBus.Error.TimedOut")
+            | Some("org.freedesktop.DBus.Error.LimitsExceeded")
+    )
 }
 
 fn no_access(err: ServiceError) -> Error {
@@ -0,0 +1,179 @@
+/*!
+
+Notifications of changes to items.
+
+The Secret Service announces every item that is created, changed or deleted
+in any of its collections, by any client. [Store::subscribe](crate::Store::subscribe)
+listens for those announcements on a connection of its own and delivers
+them as [ItemEvent]s through a [Subscription], so that applications can
+notice when another process modifies or deletes a credential they care about.
+
+Events identify items by their D-Bus paths. Events for created and changed
+items also carry the item's attributes (as they are stored), which can be
+compared with those of an entry to decide whether the event concerns it.
+Deleted items can no longer be read, so their events carry only the path.
+
+Applications that only care about particular entries can instead use
+[Store::watch](crate::Store::watch), which does that matching for them and
+calls back when one of the entries' items is created, changed or deleted
+(for example, so a service can reconnect to a database whose password has
+been rotated by another process).
+
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// What happened to an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ItemEventKind {
+    Created,
+    Changed,
+    Deleted,
+}
+
+/// A change to an item in the Secret Service.
+#[derive(Debug, Clone)]
+pub struct ItemEvent {
+    /// What happened to the item.
+    pub kind: ItemEventKind,
+    /// The D-Bus path of the item.
+    pub path: String,
+    /// The D-Bus path of the collection that holds (or held) the item.
+    pub collection: String,
+    /// The attributes of a created or changed item, if they could be read.
+    pub attributes: Option<HashMap<String, String>>,
+}
+
+/// A stream of [ItemEvent]s.
+///
+/// Events are queued until they are received. Dropping the subscription
+/// stops the listening.
+pub struct Subscription {
+    pub(crate) events: Receiver<ItemEvent>,
+    pub(crate) stop: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription").finish_non_exhaustive()
+    }
+}
+
+impl Subscription {
+    /// Wait for the next event.
+    ///
+    /// Returns `None` if the connection to the Secret Service has been lost.
+    pub fn recv(&self) -> Option<ItemEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Wait at most the given time for the next event.
+    ///
+    /// Fails with [Timeout](RecvTimeoutError::Timeout) if none arrives in
+    /// time, and with [Disconnected](RecvTimeoutError::Disconnected) if the
+    /// connection to the Secret Service has been lost (so none ever will).
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ItemEvent, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Returns the next event, if one has already arrived.
+    pub fn try_recv(&self) -> Option<ItemEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+/// What a [Watch] knows about one of its entries' items.
+pub(crate) struct Watched {
+    /// The (stored form of the) attributes that the entry's item has,
+    /// if the entry finds its item by searching.
+    pub(crate) attributes: Option<HashMap<String, String>>,
+    /// The path of the entry's item, when known.
+    pub(crate) path: Option<String>,
+}
+
+impl Watched {
+    /// Whether an event concerns this entry's item, remembering (or
+    /// forgetting) the item's path accordingly.
+    fn concerns(&mut self, event: &ItemEvent) -> bool {
+        let known = self.path.as_deref() == Some(event.path.as_str());
+        let matches = match (&self.attributes, &event.attributes) {
+            (Some(wanted), Some(found)) => wanted.iter().all(|(k, v)| found.get(k) == Some(v)),
+            _ => false,
+        };
+        match event.kind {
+            ItemEventKind::Deleted if known => self.path = None,
+            _ if matches => self.path = Some(event.path.clone()),
+            _ => {}
+        }
+        known || matches
+    }
+}
+
+/// A watch on the items of some entries.
+///
+/// See [Store::watch](crate::Store::watch). Dropping the watch stops it.
+/// It also stops by itself if its connection to the Secret Service is
+/// lost, which [is_active](Watch::is_active) reports.
+pub struct Watch {
+    stop: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for Watch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watch").finish_non_exhaustive()
+    }
+}
+
+impl Watch {
+    /// Call back with the index of each watched entry whose item an event concerns.
+    pub(crate) fn start(
+        subscription: Subscription,
+        mut watched: Vec<Watched>,
+        mut callback: impl FnMut(usize, ItemEventKind) + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        std::thread::Builder::new()
+            .name("secret-service-watch".to_string())
+            .spawn(move || {
+                while !stopped.load(Ordering::Acquire) {
+                    let event = match subscription.recv_timeout(Duration::from_millis(100)) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    for (index, entry) in watched.iter_mut().enumerate() {
+                        if entry.concerns(&event) {
+                            callback(index, event.kind);
+                        }
+                    }
+                }
+                stopped.store(true, Ordering::Release);
+            })?;
+        Ok(Self { stop })
+    }
+
+    /// Whether the watch is still running. Once its connection to the
+    /// Secret Service is lost, its callback is never called again, and
+    /// the application must start a new watch to keep watching.
+    pub fn is_active(&self) -> bool {
+        !self.stop.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
@@ -0,0 +1,181 @@
+/*!
+
+Hooks around credential operations.
+
+A store can be given hooks that are called before and after each
+operation its credentials perform through the keyring-core API (setting
+or getting a secret, getting or updating attributes, and deleting), and
+whenever one of those operations fails. The store's batch operations
+([get_secrets](crate::Store::get_secrets), [set_many](crate::Store::set_many)
+and [delete_many](crate::Store::delete_many)) are hooked too, once for
+each of their entries. Each hook is told what kind of access the
+operation is and which credential it's for, as an [Access].
+
+Hooks that run before an operation can veto it by returning an error,
+which is then returned in place of the operation's result (and passed
+to the error hooks, like any other failure). In a batch, only the
+vetoed entries fail. This lets applications audit their credentials'
+use, restrict it (say, allowing writes only from certain parts of a
+program), or ask the user for confirmation before secrets are revealed.
+
+Nothing else is hooked. In particular, the other inherent methods of
+[Specifier](crate::cred::Specifier) and [Wrapper](crate::cred::Wrapper)
+(such as `set_label`, or those that use revisions), exporting and
+importing collections, finding and resolving duplicates, migrating
+legacy items, and the `low-level` API all bypass the hooks, so hooks
+alone can't enforce a policy on every access to the keyring.
+
+Hooks are called on the thread of the caller, not on the store's worker,
+so they may block (for instance, to prompt the user) without holding up
+other operations. Hooks of each kind are called in the order they were
+added. The blocking methods and their `async` counterparts are hooked
+alike.
+
+*/
+
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+use keyring_core::{Error, Result};
+
+/// The kinds of access to a credential.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AccessKind {
+    /// Getting a credential's secret or attributes.
+    Read,
+    /// Setting a credential's secret (creating its item if need be) or
+    /// updating its attributes.
+    Write,
+    /// Deleting a credential.
+    Delete,
+}
+
+/// A credential operation, as described to hooks.
+#[derive(Debug, Clone)]
+pub struct Access {
+    /// The kind of access.
+    pub kind: AccessKind,
+    /// The name of the keyring-core method, such as `set_secret`
+    /// (without any `_async` suffix), or of the batch operation, such
+    /// as `set_many`.
+    pub method: &'static str,
+    /// The service of the credential, if known. Wrappers know theirs
+    /// if the store's mapper finds it in their item's attributes.
+    pub service: Option<String>,
+    /// The user of the credential, if known (as for the service).
+    pub user: Option<String>,
+    /// The target of the credential, for specifiers that have one.
+    pub target: Option<String>,
+    /// The D-Bus path of the credential's item, for wrappers.
+    pub path: Option<String>,
+}
+
+/// A hook called before an operation, which vetoes it by returning an error.
+pub type BeforeHook = dyn Fn(&Access) -> Result<()> + Send + Sync;
+
+/// A hook called after an operation succeeds.
+pub type AfterHook = dyn Fn(&Access) + Send + Sync;
+
+/// A hook called after an operation fails (or is vetoed), with its error.
+pub type ErrorHook = dyn Fn(&Access, &Error) + Send + Sync;
+
+/// The hooks of a store.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    before: RwLock<Vec<Arc<BeforeHook>>>,
+    after: RwLock<Vec<Arc<AfterHook>>>,
+    error: RwLock<Vec<Arc<ErrorHook>>>,
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks").finish_non_exhaustive()
+    }
+}
+
+/// The hooks of each kind, as they were when an operation started.
+type Snapshot = (
+    Vec<Arc<BeforeHook>>,
+    Vec<Arc<AfterHook>>,
+    Vec<Arc<ErrorHook>>,
+);
+
+impl Hooks {
+    pub(crate) fn add_before(&self, hook: Arc<BeforeHook>) {
+        write(&self.before).push(hook);
+    }
+
+    pub(crate) fn add_after(&self, hook: Arc<AfterHook>) {
+        write(&self.after).push(hook);
+    }
+
+    pub(crate) fn add_error(&self, hook: Arc<ErrorHook>) {
+        write(&self.error).push(hook);
+    }
+
+    /// Run an operation between the hooks.
+    ///
+    /// Blocking credential methods wait for this too, so it's the one
+    /// path through the hooks for every credential operation.
+    pub(crate) async fn run_async<T>(
+        &self,
+        access: impl FnOnce() -> Access,
+        op: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let started = self.start(access)?;
+        let result = op.await;
+        started.finish(&result);
+        result
+    }
+
+    /// Call the before hooks for an operation, which fails if one of them
+    /// vetoes it (after the error hooks have been called).
+    ///
+    /// The hooks are snapshotted, so that hooks can add others without
+    /// deadlocking, and the access is only described if there are hooks
+    /// to tell.
+    pub(crate) fn start(&self, access: impl FnOnce() -> Access) -> Result<Started> {
+        let hooks = (
+            read(&self.before).clone(),
+            read(&self.after).clone(),
+            read(&self.error).clone(),
+        );
+        if hooks.0.is_empty() && hooks.1.is_empty() && hooks.2.is_empty() {
+            return Ok(Started(None));
+        }
+        let access = access();
+        if let Err(err) = hooks.0.iter().try_for_each(|hook| hook(&access)) {
+            hooks.2.iter().for_each(|hook| hook(&access, &err));
+            return Err(err);
+        }
+        Ok(Started(Some((hooks, access))))
+    }
+}
+
+/// An operation that its before hooks have allowed, whose other hooks
+/// are called when its result is known.
+pub(crate) struct Started(Option<(Snapshot, Access)>);
+
+impl Started {
+    /// Call the hooks for the operation's result.
+    pub(crate) fn finish<T>(self, result: &Result<T>) {
+        let Some((hooks, access)) = self.0 else {
+            return;
+        };
+        match result {
+            Ok(_) => hooks.1.iter().for_each(|hook| hook(&access)),
+            Err(err) => hooks.2.iter().for_each(|hook| hook(&access, err)),
+        }
+    }
+}
+
+fn read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read()
+        .expect("RwLock failure in credential store: please report a bug")
+}
+
+fn write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write()
+        .expect("RwLock failure in credential store: please report a bug")
+}
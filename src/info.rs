@@ -0,0 +1,545 @@
+/*!
+
+Store introspection.
+
+The types in this module describe the Secret Service that a store
+is talking to, and the origin of the items in it. They are meant to
+help diagnose problems on systems where the Secret Service doesn't
+behave as expected.
+
+*/
+
+use std::collections::HashMap;
+
+/// Information about the process providing the Secret Service.
+///
+/// See [Store::server_info](crate::Store::server_info).
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    /// The unique bus name of the connection that owns `org.freedesktop.secrets`.
+    pub owner: String,
+    /// The process ID of the owner, if the bus reports it.
+    pub pid: Option<u32>,
+    /// The name of the owner's process, if it can be found.
+    pub process_name: Option<String>,
+    /// The D-Bus interfaces exposed by the Secret Service object.
+    pub interfaces: Vec<String>,
+    /// The well-known bus names held by the owner: `org.freedesktop.secrets`,
+    /// and any of the names that identify particular implementations
+    /// (such as `org.kde.kwalletd6`).
+    pub names: Vec<String>,
+    /// The Secret Service implementation the owner appears to be.
+    pub backend: Backend,
+    /// The number of collections in the Secret Service.
+    pub collection_count: usize,
+}
+
+impl ServerInfo {
+    /// The name of the Secret Service implementation the owner
+    /// appears to be, if it's a known one.
+    pub fn implementation(&self) -> Option<&'static str> {
+        match self.backend {
+            Backend::Unknown => None,
+            backend => Some(backend.name()),
+        }
+    }
+}
+
+/// The known implementations of the Secret Service.
+///
+/// Implementations differ in behavior (for example, in which collections
+/// they have and in how they prompt), so callers may want to branch on
+/// which one they're talking to. See [Store::backend](crate::Store::backend).
+/// None of these implementations reports its version over D-Bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// The GNOME Keyring daemon.
+    GnomeKeyring,
+    /// KDE Wallet (`kwalletd` or `ksecretd`).
+    KWallet,
+    /// The KeePassXC password manager, with Secret Service integration enabled.
+    KeePassXC,
+    /// The oo7 daemon.
+    Oo7,
+    /// An implementation that couldn't be identified.
+    Unknown,
+}
+
+impl Backend {
+    /// The implementation's name, for display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::GnomeKeyring => "GNOME Keyring",
+            Backend::KWallet => "KDE Wallet",
+            Backend::KeePassXC => "KeePassXC",
+            Backend::Oo7 => "oo7",
+            Backend::Unknown => "unknown",
+        }
+    }
+
+    /// Identify an implementation from what the bus says about it.
+    ///
+    /// The interfaces of the Secret Service object and the other names
+    /// its owner holds are checked first, since they don't depend on how
+    /// the daemon was installed; the name of its process is the fallback.
+    pub(crate) fn detect(
+        interfaces: &[String],
+        names: &[String],
+        process_name: Option<&str>,
+    ) -> Self {
+        if interfaces
+            .iter()
+            .any(|i| i.starts_with("org.gnome.keyring."))
+        {
+            return Backend::GnomeKeyring;
+        }
+        let by_name = [
+            ("org.keepassxc.", Backend::KeePassXC),
+            ("org.kde.kwalletd", Backend::KWallet),
+            ("org.kde.secretservicecompat", Backend::KWallet),
+            ("org.gnome.keyring", Backend::GnomeKeyring),
+        ];
+        for (prefix, backend) in by_name {
+            if names.iter().any(|name| name.starts_with(prefix)) {
+                return backend;
+            }
+        }
+        let Some(process) = process_name.map(|n| n.to_ascii_lowercase()) else {
+            return Backend::Unknown;
+        };
+        let by_process = [
+            ("gnome-keyring", Backend::GnomeKeyring),
+            ("kwalletd", Backend::KWallet),
+            ("ksecretd", Backend::KWallet),
+            ("keepassxc", Backend::KeePassXC),
+            ("oo7", Backend::Oo7),
+        ];
+        by_process
+            .iter()
+            .find(|(prefix, _)| process.starts_with(prefix))
+            .map(|(_, backend)| *backend)
+            .unwrap_or(Backend::Unknown)
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The algorithm used to protect secrets in transit to and from the Secret Service.
+///
+/// See [Store::session_algorithm](crate::Store::session_algorithm).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionAlgorithm {
+    /// Secrets cross the bus in plaintext.
+    Plain,
+    /// Secrets are encrypted with AES-128 in CBC mode (PKCS#7 padding),
+    /// using a key derived via HKDF-SHA256 from a Diffie-Hellman exchange
+    /// in the 1024-bit MODP group of RFC 2409.
+    DhAes,
+}
+
+impl SessionAlgorithm {
+    /// The name of the algorithm in the Secret Service specification.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionAlgorithm::Plain => "plain",
+            SessionAlgorithm::DhAes => "dh-ietf1024-sha256-aes128-cbc-pkcs7",
+        }
+    }
+
+    /// Whether secrets are encrypted in transit.
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, SessionAlgorithm::DhAes)
+    }
+}
+
+impl std::fmt::Display for SessionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The origin of an item, as recorded when it was created.
+///
+/// Stores configured with `provenance` stamp each item they create with
+/// this information, in attributes whose names start with `provenance:`.
+/// Use the credential-level `get_provenance` calls to read it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The name and version of the crate that created the item.
+    pub creator: String,
+    /// The name of the program that created the item, if known.
+    pub program: Option<String>,
+    /// The name of the host the item was created on, if known.
+    pub host: Option<String>,
+}
+
+impl Provenance {
+    const CREATOR: &str = "provenance:creator";
+    const PROGRAM: &str = "provenance:program";
+    const HOST: &str = "provenance:host";
+
+    /// The provenance of items created by this process.
+    pub(crate) fn current() -> Self {
+        let program = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()));
+        let host = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty());
+        Self {
+            creator: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            program,
+            host,
+        }
+    }
+
+    /// The attributes that record this provenance.
+    pub(crate) fn to_attributes(&self) -> HashMap<String, String> {
+        let mut result = HashMap::from([(Self::CREATOR.to_string(), self.creator.clone())]);
+        if let Some(program) = &self.program {
+            result.insert(Self::PROGRAM.to_string(), program.clone());
+        }
+        if let Some(host) = &self.host {
+            result.insert(Self::HOST.to_string(), host.clone());
+        }
+        result
+    }
+
+    /// The provenance recorded in an item's attributes, if any.
+    pub(crate) fn from_attributes(attributes: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            creator: attributes.get(Self::CREATOR)?.clone(),
+            program: attributes.get(Self::PROGRAM).cloned(),
+            host: attributes.get(Self::HOST).cloned(),
+        })
+    }
+}
+
+/// Whether the Secret Service can be used, and if not, why not.
+///
+/// See [Store::probe](crate::Store::probe). The checks are made in
+/// order, and stop at the first that fails.
+#[derive(Debug, Clone, Default)]
+pub struct Availability {
+    /// Whether the session bus could be reached.
+    pub bus: bool,
+    /// Whether a Secret Service daemon is running, or can be started
+    /// on demand by the bus.
+    pub service: bool,
+    /// The algorithm of the session that was opened, if one could be.
+    /// (A plain session is tried if an encrypted one can't be negotiated.)
+    pub session: Option<SessionAlgorithm>,
+    /// Whether the Secret Service has a default collection.
+    pub default_collection: bool,
+    /// A description of the check that failed, if one did.
+    pub problem: Option<String>,
+}
+
+impl Availability {
+    /// Whether all the checks passed.
+    pub fn is_available(&self) -> bool {
+        self.bus && self.service && self.session.is_some() && self.default_collection
+    }
+}
+
+/// The state of a collection in the Secret Service.
+#[derive(Debug, Clone)]
+pub struct CollectionInfo {
+    /// The D-Bus path of the collection.
+    pub path: String,
+    /// The label of the collection.
+    pub label: String,
+    /// Whether the collection is locked.
+    pub locked: bool,
+    /// The well-known aliases (such as `default`) that refer to the collection.
+    pub aliases: Vec<String>,
+}
+
+impl CollectionInfo {
+    /// Whether this is the default collection.
+    pub fn is_default(&self) -> bool {
+        self.aliases.iter().any(|a| a == "default")
+    }
+}
+
+/// An item in the Secret Service, as seen without unlocking it.
+#[derive(Debug, Clone)]
+pub struct ItemInfo {
+    /// The D-Bus path of the item.
+    pub path: String,
+    /// The D-Bus path of the collection that holds the item.
+    pub collection: String,
+    /// The label of the item.
+    pub label: String,
+    /// The attributes of the item, as they are stored.
+    pub attributes: HashMap<String, String>,
+    /// Whether the item is locked.
+    pub locked: bool,
+}
+
+/// One of several items that an entry found.
+///
+/// The entries in an [Ambiguous](keyring_core::Error::Ambiguous) error
+/// wrap the items found, and report their details (as they were when the
+/// search was made) with
+/// [Wrapper::candidate](crate::cred::Wrapper::candidate), so applications
+/// can ask the user which one they mean or log something actionable.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// The D-Bus path of the item.
+    pub path: String,
+    /// The D-Bus path of the collection that holds the item.
+    pub collection: String,
+    /// The label of the collection that holds the item.
+    pub collection_label: String,
+    /// The label of the item.
+    pub label: String,
+    /// The attributes of the item, as they are stored.
+    pub attributes: HashMap<String, String>,
+    /// When the item was last modified, in seconds since the Unix epoch.
+    pub modified: u64,
+}
+
+/// An item written by an older version of keyring-rs.
+///
+/// See [Store::migrate_legacy](crate::Store::migrate_legacy).
+#[derive(Debug, Clone)]
+pub struct LegacyItem {
+    /// The item as it was found, before any migration.
+    pub item: ItemInfo,
+    /// The service the item was written for.
+    pub service: String,
+    /// The user the item was written for.
+    pub user: String,
+    /// Whether the item was rewritten into the store's scheme. Items
+    /// aren't rewritten if the store already has another item for
+    /// their service and user.
+    pub migrated: bool,
+}
+
+/// Items that hold credentials for the same service and user.
+///
+/// See [Store::find_duplicates](crate::Store::find_duplicates).
+#[derive(Debug, Clone)]
+pub struct DuplicateItems {
+    /// The service the items are for.
+    pub service: String,
+    /// The user the items are for.
+    pub user: String,
+    /// The items, most recently modified first (with ties broken by path).
+    pub items: Vec<ItemInfo>,
+}
+
+/// A change to the Secret Service that a store configured with `dry-run`
+/// would have made.
+///
+/// See [Store::take_plan](crate::Store::take_plan). Items that would have
+/// been created have no path, so later changes to them are reported with
+/// a stand-in path ending in `/planned`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedChange {
+    /// Create a collection with the given name, as a `target` names it.
+    CreateCollection { name: String },
+    /// Create an item in the named collection (replacing any with the
+    /// same attributes, unless the store has `create-if-absent`).
+    CreateItem {
+        collection: String,
+        label: String,
+        attributes: HashMap<String, String>,
+    },
+    /// Set the secret of an item.
+    SetSecret { path: String },
+    /// Set all the attributes of an item.
+    SetAttributes {
+        path: String,
+        attributes: HashMap<String, String>,
+    },
+    /// Set the label of an item.
+    SetLabel { path: String, label: String },
+    /// Delete an item.
+    DeleteItem { path: String },
+    /// Delete a collection.
+    DeleteCollection { path: String },
+    /// Make an alias refer to the collection with the given path.
+    SetAlias { alias: String, path: String },
+}
+
+/// An item's label, attributes, timestamps and (if requested) secret,
+/// read together.
+///
+/// See [Specifier::get_snapshot](crate::cred::Specifier::get_snapshot).
+/// The secret isn't shown by the [Debug] format.
+#[derive(Clone, Default)]
+pub struct ItemSnapshot {
+    /// The label of the item.
+    pub label: String,
+    /// The attributes of the item, as they are stored.
+    pub attributes: HashMap<String, String>,
+    /// When the item was created, in seconds since the Unix epoch.
+    pub created: u64,
+    /// When the item was last modified, in seconds since the Unix epoch.
+    /// This is the item's revision.
+    pub modified: u64,
+    /// Whether the item was locked when it was read.
+    pub locked: bool,
+    /// The secret of the item, if it was requested.
+    pub secret: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for ItemSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ItemSnapshot")
+            .field("label", &self.label)
+            .field("attributes", &self.attributes)
+            .field("created", &self.created)
+            .field("modified", &self.modified)
+            .field("locked", &self.locked)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Everything a store knows about its environment, for bug reports.
+///
+/// See [Store::diagnostics](crate::Store::diagnostics). The report's
+/// [Display] format is a stable, line-oriented `key: value` text that
+/// can be pasted into a bug report or parsed by telemetry tools.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// The address of the session bus, from `DBUS_SESSION_BUS_ADDRESS`.
+    pub bus_address: Option<String>,
+    /// The process providing the Secret Service, if it could be described.
+    pub server: Option<ServerInfo>,
+    /// The collections in the Secret Service, if they could be listed.
+    pub collections: Option<Vec<CollectionInfo>>,
+    /// The algorithm protecting secrets in transit.
+    pub session_algorithm: SessionAlgorithm,
+    /// Whether the store fell back to a plain session.
+    pub session_downgraded: bool,
+    /// How many times the store has renewed its session.
+    pub session_renewals: u64,
+    /// How many Secret Service connections the store uses: one for its
+    /// main worker, plus one per read worker.
+    pub connections: usize,
+    /// How many Secret Service operations of the store have failed,
+    /// by kind of error, since the store was created.
+    pub error_counts: HashMap<String, u64>,
+    /// Why parts of the report (the server or the collections) are
+    /// missing, if they are.
+    pub problems: Vec<String>,
+}
+
+impl std::fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let none = || "unknown".to_string();
+        writeln!(f, "crate: {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(
+            f,
+            "bus address: {}",
+            self.bus_address.clone().unwrap_or_else(none)
+        )?;
+        match &self.server {
+            Some(server) => {
+                writeln!(f, "server owner: {}", server.owner)?;
+                writeln!(
+                    f,
+                    "server pid: {}",
+                    server.pid.map(|p| p.to_string()).unwrap_or_else(none)
+                )?;
+                writeln!(
+                    f,
+                    "server process: {}",
+                    server.process_name.clone().unwrap_or_else(none)
+                )?;
+                writeln!(
+                    f,
+                    "server implementation: {}",
+                    server.implementation().unwrap_or("unknown")
+                )?;
+                writeln!(f, "server interfaces: {}", server.interfaces.join(", "))?;
+                writeln!(f, "server names: {}", server.names.join(", "))?;
+            }
+            None => writeln!(f, "server: unknown")?,
+        }
+        writeln!(f, "session algorithm: {}", self.session_algorithm)?;
+        writeln!(f, "session downgraded: {}", self.session_downgraded)?;
+        writeln!(f, "session renewals: {}", self.session_renewals)?;
+        writeln!(f, "connections: {}", self.connections)?;
+        match &self.collections {
+            Some(collections) => {
+                for c in collections.iter() {
+                    writeln!(
+                        f,
+                        "collection: {} label={:?} locked={} aliases={}",
+                        c.path,
+                        c.label,
+                        c.locked,
+                        c.aliases.join(",")
+                    )?;
+                }
+            }
+            None => writeln!(f, "collections: unknown")?,
+        }
+        let mut errors: Vec<_> = self.error_counts.iter().collect();
+        errors.sort();
+        for (kind, count) in errors {
+            writeln!(f, "errors: {kind}={count}")?;
+        }
+        for problem in self.problems.iter() {
+            writeln!(f, "problem: {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A snapshot of a store's operation metrics.
+///
+/// See [Store::metrics](crate::Store::metrics). All counts are since the
+/// store was created.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// The operations the store has run, by kind: `search`, `create`,
+    /// `read`, `write`, `delete`, and `unlock` (the same kinds as the
+    /// `latency:<operation>` configuration keys). Kinds that haven't run
+    /// are missing.
+    pub operations: HashMap<String, OperationMetrics>,
+    /// How many times the store has asked the Secret Service to unlock
+    /// locked items or collections. These requests are where the Secret
+    /// Service prompts the user, if it needs to.
+    pub unlock_requests: u64,
+    /// How many operations have failed, by kind of error.
+    pub error_counts: HashMap<String, u64>,
+    /// How many times the store has renewed its session.
+    pub session_renewals: u64,
+}
+
+/// The metrics for one kind of operation.
+#[derive(Debug, Clone, Default)]
+pub struct OperationMetrics {
+    /// How many operations of this kind have run.
+    pub count: u64,
+    /// How many of them failed.
+    pub errors: u64,
+    /// How long they took, from being submitted to finishing (so
+    /// including any time spent waiting for earlier operations).
+    pub latency: LatencyHistogram,
+}
+
+/// A histogram of operation latencies.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// The upper bound of each bucket and how many latencies fell in it
+    /// (above the previous bound), in increasing order of bound. The last
+    /// bound is [Duration::MAX](std::time::Duration::MAX).
+    pub buckets: Vec<(std::time::Duration, u64)>,
+    /// The sum of all the latencies.
+    pub total: std::time::Duration,
+    /// The longest latency.
+    pub max: std::time::Duration,
+}
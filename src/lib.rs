@@ -19,6 +19,23 @@ user's login collection). Specifying a `target` modifier when creating an entry
 will create a new collection named by the target and the item for the entry will
 be created in that collection.
 
+A `target` of `session` is special: items for such entries are created in
+the Secret Service's session collection, which is kept only in memory and
+forgotten when the user logs out. Their credentials report a
+[persistence](cred::Specifier::persistence) of
+[UntilLogout](keyring_core::api::CredentialPersistence::UntilLogout).
+
+A `target` of the form `alias:<alias>` names the collection that the alias
+(such as `login` or `session`) refers to, whatever its label. If the alias
+isn't set, a collection labeled `<alias>` is created with that alias. Aliases
+can be read and set with [Store::read_alias] and [Store::set_alias].
+
+Tools that know exactly which collection they want can instead give the
+collection's D-Bus object path in a `collection` modifier. The item is then
+created in that collection (which must exist), regardless of its label or
+aliases. The `collection` modifier can't be combined with `target`, and it
+doesn't affect the item's attributes.
+
 This implementation controls the following attributes on items:
 
 - `service` (required & taken from the `service` parameter to the entry creation call)
@@ -31,12 +48,304 @@ modifier `label` is set in the entry creation call, that value is used
 as the label. Otherwise, the label is set to the Rust-formatted string:
 `keyring:{user}@{service}`.
 
+The default label can be localized by supplying a [localizer](localize)
+to the store.
+
+Secrets are stored with the content type `application/octet-stream`, unless
+the modifier `content-type` (a MIME type such as `application/json`) is set in
+the entry creation call, in which case that type is used both when the item is
+created and whenever the entry sets its secret. Entries without the modifier
+keep whatever content type an existing item's secret has. The content type is
+accessible and settable via credential-level calls.
+
+Any other modifiers set in the entry creation call (such as `application` or
+`environment`) become attributes of the item when it's created, so apps can
+tag their items without a separate call to update the attributes. They aren't
+used to find existing items. Modifiers can't set the attributes controlled by
+this implementation.
+
+The attributes derived from an entry's specifiers can be customized by
+supplying an [attribute mapper](mapping) to the store.
+
 Client code is allowed to retrieve and to set all attributes _except_ the
 three that are controlled by this implementation. The label is accessible
 and settable via credential-level calls, but not via entry-level calls.
 The example program in this crate shows how to get at the credential object
 in an entry and use its API.
 
+By convention, a human-readable note about a credential is kept in its `note`
+attribute. The credential-level `get_note` and `set_note` calls read and write it.
+
+## Configuration
+
+Stores created with [Store::new] use the default configuration. Use
+[Store::new_with_configuration] to supply configuration keys and values,
+or [Store::builder] to set them with typed methods:
+
+- `attribute:<name>`: A value for the named attribute that is added to every item
+  this store creates. The attributes derived from an entry's specifiers take
+  precedence over these defaults. Defaults are not used when searching.
+- `username-attribute`: The name of the attribute that holds the user, in place
+  of `username` (for example, `account` or `login`, to interoperate with items
+  written by other clients). It is used both when creating and when searching
+  for items, and it is controlled by this store just as `username` would be.
+- `legacy-items`: When set to `true`, an entry whose item can't be found
+  searches again for an item written by an older version of keyring-rs: one
+  with an `application` attribute of `rust-keyring` and the entry's service,
+  user, and target in the `service`, `username`, and `target` attributes. Such
+  items are found anyway by stores with the default settings, but not by
+  stores that rename or hash these attributes or that use `xdg-schema`, so
+  this lets applications that change their settings keep their users'
+  existing credentials. The item found is used as it is; see
+  [Store::migrate_legacy] to rewrite such items.
+- `python-keyring`: When set to `true`, items are created the way the Python
+  [keyring](https://pypi.org/project/keyring/) package creates them: with an
+  `application` attribute of `Python keyring library`, a label of the form
+  `Password for '{user}' on '{service}'` (unless one is given), and secrets of
+  type `text/plain`. Both packages find items by their `service` and
+  `username` attributes, so Rust and Python components of the same product
+  can share credentials (as long as the Rust entries have no target). This
+  can't be combined with settings that change or hide those attributes
+  (`service-attribute`, `username-attribute`, and `username-salt`).
+- `xdg-schema`: The name of a libsecret schema (or `true` for
+  `org.freedesktop.Secret.Generic`, the schema for generic passwords). Items
+  created by the store are given an `xdg:schema` attribute with this value,
+  and searches for entries' items include it, so that libsecret-based tools
+  (such as `secret-tool` and GNOME applications) can find and classify the
+  items. The attribute is controlled by the store. Note that items created
+  without the schema aren't found once it's configured.
+- `service-attribute`, `target-attribute`: The names of the attributes that
+  hold the service and the target, in place of `service` and `target`, as
+  above. Together with `username-attribute`, these let the store read and
+  write items that follow an existing attribute schema without migrating
+  them. The three names must differ.
+- `username-salt`: Turns on privacy mode. Instead of the user, the `username`
+  attribute of items holds a hash of the user salted with this value. Lookups
+  work as usual, but other keyring clients can't read the user from the item's
+  attributes, and the specifiers of searched-for items can't be recovered.
+  In privacy mode, the default label of a new item is `keyring:{service}`,
+  and [Store::set_attribute_mapper] fails.
+- `username-in-label`: When set to `true` in privacy mode, the default label of
+  a new item still includes the plaintext user. (This has no effect on labels
+  given by the `label` modifier.)
+- `opaque-attributes`: A comma-separated list of attribute names whose values
+  are replaced by a keyed hash (HMAC-SHA256) whenever they are written or
+  searched for. Such values remain searchable, because the hash is
+  deterministic, but can't be read by other keyring clients (and are returned
+  in hashed form when attributes are read). Requires `opaque-key`.
+- `opaque-key`: The key used to hash the values of opaque attributes.
+- `encryption`: How secrets are protected on their way to and from the Secret
+  Service: `dh-ietf1024-sha256-aes128-cbc-pkcs7` (the default) encrypts them,
+  and `plain` doesn't (for Secret Services that can't encrypt).
+- `prompt-timeout`: A number of seconds to wait for the user to answer a
+  prompt (such as a request to unlock a collection) before dismissing it and
+  failing the operation. Zero dismisses prompts immediately. By default, the
+  store waits indefinitely.
+- `no-prompt`: When set to `true`, the store never prompts the user to unlock
+  anything: operations that need a locked item or collection, which the
+  Secret Service can only unlock by prompting, fail immediately with a
+  [WouldPrompt](errors::StoreError::WouldPrompt) error. This suits daemons
+  and other programs with no user to answer a prompt. Objects that can be
+  unlocked without a prompt still are.
+- `prompt-window`: The identifier of an application window (such as an X11
+  window id, or an exported Wayland surface handle) that is passed to the
+  Secret Service whenever it prompts the user to unlock something, so the
+  prompt is shown as a dialog of that window rather than on its own. The
+  identifier is platform-specific and isn't interpreted by the store. By
+  default, prompts have no parent window.
+- `default-target`: The target used by entries that are built with neither
+  a `target` nor a `collection` modifier.
+- `allow-plain-fallback`: When set to `true`, and the Secret Service refuses to
+  negotiate an encrypted session, the store uses a plain session instead of
+  failing. (Some minimal Secret Service implementations don't support encryption.)
+  Use [Store::session_downgraded] to find out whether this happened.
+- `lazy-connect`: When set to `true`, the store doesn't connect to the Secret
+  Service until its first operation, so applications that never use their
+  credentials never pay for the connection (or any prompt it causes). Connection
+  failures are then reported by that first operation rather than by store creation.
+- `scoped-search`: When set to `true`, an entry's item is only searched for in
+  the collection the entry creates items in (its target's collection, its
+  `collection` modifier's, or the default), rather than in every collection.
+  Items in other collections that happen to have matching attributes are then
+  never read, written or deleted. Store-level searches aren't affected.
+- `cache-item-paths`: When set to `true`, an entry remembers the path of its
+  item once it has found it, and later operations use the path rather than
+  searching again. If the item has since been deleted or locked, the entry
+  searches for it again and retries the operation. Since the entry doesn't
+  search, it won't notice other matching items created by other clients, or
+  that its item's attributes have been changed so that it no longer matches.
+- `create-if-absent`: When set to `true`, setting an entry's secret only
+  creates its item: if a matching item already exists, nothing is written and
+  the call fails with an [AlreadyExists](errors::StoreError::AlreadyExists)
+  error. This supports workflows that initialize a credential once. Items are
+  then never replaced on creation, including by the low-level API (which
+  fails in the same way if its collection has an item with the same
+  attributes). Credentials that wrap an item can still set its secret.
+- `secret-cache-ttl`: A number of milliseconds for which the secrets read by
+  entries are kept in memory, so that reading the same credential again (say,
+  on every request a server handles) doesn't call the Secret Service. The cache
+  is flushed whenever the store creates, writes or deletes anything, and can be
+  flushed explicitly with [Store::flush_cache]. Changes made by other clients
+  aren't noticed until the cached secret expires. Only reads of entries with
+  attributes are cached; credentials that wrap an item always read it.
+- `ambiguous-items`: What an entry does when it finds several items with its
+  attributes: `error` (the default) fails with an
+  [Ambiguous](keyring_core::Error::Ambiguous) error; `newest` uses the most
+  recently modified item; and `target` uses the item in the entry's collection
+  (the one its item would be created in), failing as `error` does if there
+  isn't exactly one. See also [Store::find_duplicates].
+- `locked-items`: What searches do with the locked items they find: `unlock`
+  (the default) unlocks them, prompting the user if necessary; `skip` leaves
+  them out of the results (so entries whose items are locked report
+  `NoEntry`); and `error` fails with a [Locked](errors::StoreError::Locked)
+  error, leaving the caller to decide when to unlock them.
+- `relock-after-access`: When set to `true`, whatever an operation has to
+  unlock (items, to read, write or delete their secrets, or collections, to
+  export or import them) is locked again once that operation is done, so the
+  store never leaves the keyring more unlocked than it found it. Searches then
+  leave the locked items they find locked, for the operations that access
+  them to unlock. (Secret Services that lock by collection lock the item's
+  whole collection.) Only explicit unlocks, such as
+  [Store::unlock_collection], are kept. Locking is best-effort: failures to
+  lock are ignored.
+- `provenance`: When set to `true`, every item this store creates is stamped
+  with the name and version of this crate, the name of the creating program,
+  and the name of the creating host, in attributes whose names start with
+  `provenance:`. See [Provenance](info::Provenance).
+- `explicit-collections`: When set to `true`, setting the secret of an entry
+  whose `target` collection doesn't exist fails with a
+  [MissingCollection](errors::StoreError::MissingCollection) error rather than
+  creating the collection. Use the `create_target` call on the entry's credential
+  to create it.
+- `create-default-collection`: When set to `true`, and the Secret Service has
+  no collection with the `default` alias (as on WSL), the store creates a
+  collection labeled `Default`, with that alias, the first time it needs the
+  default collection.
+- `max-items-per-target`: The most items this store will create in any
+  collection named by a `target` modifier. (The default collection is not
+  limited.) Only items that follow the store's attribute mapping (and schema)
+  are counted, so other applications' items in the same collection don't use
+  up the quota. Creating an item in a full collection fails with a
+  [QuotaExceeded](errors::StoreError::QuotaExceeded) error.
+- `quota-eviction`: When set to `true`, creating an item in a full collection
+  first deletes some of the store's items in it rather than failing: expired
+  ones first (those whose `expires` attribute holds a time, in seconds since
+  the UNIX epoch, that has passed), then the oldest (by creation time). Items
+  that don't follow the store's attribute mapping are never deleted.
+- `secure-delete`: When set to `true`, before an item is deleted (by any
+  operation, including moves, quota eviction and resolving duplicates) its secret is
+  overwritten with zeros and all its attributes except the ones controlled by
+  this store are removed. This is a best-effort measure to limit what the Secret
+  Service can recover (say, from a journal or a memory snapshot) after the item is
+  gone: failures to overwrite don't prevent the deletion.
+- `latency:<operation>`: A number of milliseconds by which to delay every
+  operation of the given kind, to simulate a slow Secret Service (or one that
+  is waiting on the user). The kinds are `search`, `create`, `read`, `write`,
+  `delete`, and `unlock` (the last of which is where a real Secret Service would
+  prompt the user). This is meant for testing that applications stay responsive;
+  the delays hold up other operations of the store just as a slow service would.
+- `dry-run`: When set to `true`, the store doesn't change anything in the
+  Secret Service. Operations that would do so still perform all their
+  validation and searching (and reading, say, of items to be copied), but
+  each change they would have made (creating, updating, or deleting an item
+  or collection, or setting an alias) is added to the store's plan instead, which is retrieved
+  with [Store::take_plan]. Writes aren't mirrored. Since nothing is created,
+  later operations don't find the items that would have been.
+- `verify-creation`: When set to `true`, after creating an item the store
+  checks that a search for the item's attributes finds it, and only it (once
+  any concurrently created duplicates have been resolved). If not, setting the
+  secret fails with an
+  [UnverifiedCreation](errors::StoreError::UnverifiedCreation) error. This
+  surfaces Secret Service quirks (such as search indexes that lag behind
+  writes, or items replaced by others on creation) when items are created,
+  rather than as unexpected [NoEntry](keyring_core::Error::NoEntry) or
+  [Ambiguous](keyring_core::Error::Ambiguous) errors later.
+- `session-collection`: When set to `true`, items for entries without a
+  `target` or `collection` modifier are created in the session collection
+  (whose items the Secret Service forgets when the user logs out) rather than
+  in the default collection. The store's persistence is then reported as
+  [UntilLogout](keyring_core::api::CredentialPersistence::UntilLogout).
+- `operation-deadline`: A number of milliseconds that an operation may wait
+  for the store's other operations to finish before it starts. (A store's
+  worker performs operations one at a time, as does each of its
+  `read-workers`.) An operation that doesn't start in
+  time is cancelled and fails with a
+  [DeadlineExceeded](errors::StoreError::DeadlineExceeded) error; an operation
+  that has started always runs to completion.
+- `operation-timeout`: A number of milliseconds that a caller waits for an
+  operation to finish, counting both the time it waits to start and the time
+  it runs (including any time spent waiting for the user to answer a prompt).
+  An operation that doesn't finish in time fails with a
+  [TimedOut](errors::StoreError::TimedOut) error. Use [Store::with_timeout]
+  to set a different timeout for particular operations. Timeouts only apply
+  to blocking calls, not to the async ones (which can be raced against a
+  timer from the caller's runtime).
+- `read-workers`: A number of extra worker threads, each with its own Secret
+  Service session, that run operations which only read items (such as getting
+  a secret or attributes). Normally a store runs all its operations, one at a
+  time, on a single worker; with read workers, reads run concurrently with each
+  other and with writes, so a slow read doesn't hold up the rest. The workers
+  form a pool of connections for servers that read many credentials from
+  several threads. Searches, writes, and any reads that may prompt to unlock
+  items (such as [Store::get_secrets], or every read in stores configured with
+  `relock-after-access`) still run one at a time on the main worker.
+- `rekey-after-operations`: A number of operations after which the store closes
+  its Secret Service session and negotiates a new one (and with it a new session
+  key). This bounds how much data any one session key protects in long-lived
+  processes. Renewal is transparent to callers.
+- `rekey-after-seconds`: A number of seconds after which the store renews its
+  session, as above. Renewal happens at the first operation after the time is up.
+- `rebind-on-restart`: When set to `true`, the store watches for the daemon
+  that provides the Secret Service to restart. When it does, the store opens a
+  new session with the new daemon, and credentials find their items again
+  (by their attributes) rather than using paths from the old daemon, so
+  callers can keep using the store without intervention.
+  Even without this setting, an operation that fails because the connection
+  to the Secret Service was lost (the session bus or the daemon restarted)
+  is retried once on a new session.
+- `retry-attempts`: A number of times to retry an operation that fails
+  transiently: because the Secret Service or the bus didn't answer in time or
+  wasn't available (as can happen while a desktop session is starting up), or
+  because the connection to it was lost. Only operations that change nothing
+  (searches, reads and unlocks) are retried, since a change that timed out
+  may still have been made. Operations wait for their retries without holding
+  up the store's other operations. By default, such failures are only retried
+  once, on a new session, if the connection was lost.
+- `retry-backoff`: A number of milliseconds to wait before the first retry
+  (100 by default). Each later retry waits twice as long as the one before.
+- `duplicate-collections`: What to do when several collections have a target's
+  label: `first` (the default) uses the first one the Secret Service lists,
+  `newest` uses the most recently created one, and `error` fails with an
+  [AmbiguousCollection](errors::StoreError::AmbiguousCollection) error. (Since
+  existing items are always searched for in all collections, this only affects
+  the creation of items and the deletion of target collections.) The
+  `target_collection` call on an entry's credential reports which collection
+  is used.
+- `mirror-file`: The path of a file to which every write of an item is
+  mirrored, encrypted, so that the items can be recovered if the Secret
+  Service's storage is lost. Requires `mirror-key`. See the [mirror] module.
+- `mirror-key`: The passphrase from which the keys that encrypt the mirror
+  file are derived.
+- `envelope-key`: If set, secrets are encrypted with this key before they're
+  handed to the Secret Service, and decrypted when they're read, so that other
+  clients of the Secret Service can't read them. The store's transform pipeline
+  starts with an [Envelope](transform::Envelope) stage using the key;
+  applications that would rather not put the key in the configuration can add
+  such a stage (perhaps with a key callback) themselves. Enveloped secrets
+  aren't bound to their items, so they can be swapped between items without
+  being detected.
+- `required-attributes`: A comma-separated list of attribute names. Every item
+  this store creates or updates must have all of these attributes, or the
+  operation fails with an [Invalid](keyring_core::Error::Invalid) error.
+- `pattern:<name>`: A regular expression that the value of the named attribute
+  must match (in full) whenever the store creates or updates an item with that
+  attribute. The common core of regular expression syntax is supported:
+  literal characters, `.`, bracketed classes such as `[a-z_]` and `[^0-9]`, the
+  ASCII classes `\d`, `\w`, and `\s` (and their negations), groups, `|`, the
+  repetitions `*`, `+`, `?`, `{n}`, `{n,}`, and `{n,m}`, and the anchors `^`
+  and `$`. A pattern that can't be parsed is an
+  [Invalid](keyring_core::Error::Invalid) error when the store is created.
+
 ## Ambiguity
 
 Existing items are always searched for at the service level, which means all
@@ -50,17 +359,75 @@ Note that existing items created or updated by 3rd party applications may have
 additional attributes; such items will be found when searching for items with
 the same service and user.
 
+## Concurrent creation
+
+When two processes set the secret of the same, not-yet-existing entry at
+the same time, each may create an item for it. To keep such first-run
+races deterministic, this store searches again after creating an item.
+If it finds more than one match, it keeps the newest item (by creation
+time, with ties broken by item path), copies to it any attributes that
+only the other matches have, and deletes the other matches. Every racing
+process makes the same choice, so exactly one item survives, and its
+secret is the one every process reads afterward.
+
+## Structured credentials
+
+The [record] module maps the fields of a user-defined struct to the
+attributes and secret of a credential, so structured secrets can be
+written and read without hand-written conversion code.
+
+## Async usage
+
+Each store runs its Secret Service operations on worker threads of its
+own: a main worker that runs them one at a time, and, in stores configured
+with `read-workers`, a pool of workers that run reads concurrently. The
+credentials of a store (and its low-level API) have `async` counterparts
+of their operations, named with an `_async` suffix, whose futures complete
+when a worker has finished. Since the
+calling thread never waits on D-Bus (or on a prompt for the user to unlock
+a collection), these can be awaited from any async runtime without
+stalling it. The futures don't depend on a particular runtime: they work
+the same under tokio, async-std, smol, or a hand-rolled executor, so no
+runtime-specific feature is needed (and using them pulls in no runtime).
+They are `Send`, so they can be spawned onto multi-threaded executors.
+
+## Transforms
+
+A store can be given a pipeline of [transform](transform) stages that are
+applied to secrets as they are written to and read from the Secret Service.
+Use [Store::add_transform] to add stages before using the store; see the
+[transform] module for how stages compose.
+
+## Hooks
+
+A store can be given [hooks] that are called before and after its
+credentials' operations (and each entry of its batch operations), and when
+they fail, so that applications can audit or veto them. Other operations,
+such as exports and imports, aren't hooked; see the module for exactly which
+are. Use [Store::on_before], [Store::on_after], and [Store::on_error] to add
+hooks.
+
 ## Features
 
-This crate has no features of its own: all of its features are simply passed on
+Except for `low-level` and `memory-fallback`, this crate's features are simply passed on
 to the [dbus-secret-service crate](https://crates.io/crates/dbus-secret-service)
 that it uses to communicate with Secret Service.
 (See the [docs for that crate](https://docs.rs/docs/dbus-secret-service) for details.)
-You must enable either the `crypto-rust` or the `crypto-openssl` feature because this
-crate always encrypts communication with the Secret Service. You can additionally
-enable the `vendored` feature if you want the required C libraries
+You must enable either the `crypto-rust` or the `crypto-openssl` feature, because
+by default this crate encrypts secrets on their way to and from the Secret Service
+with a Diffie-Hellman session (`dh-ietf1024-sha256-aes128-cbc-pkcs7`), which needs
+one of them. Stores whose `encryption` is `plain`, and stores that fall back to a
+plain session because of `allow-plain-fallback`, don't encrypt secrets. You can
+additionally enable the `vendored` feature if you want the required C libraries
 (dbus and, if specified, openssl) statically linked with your application.
 
+The `low-level` feature adds the `low_level` module, which gives direct access
+to Secret Service items by path, without the keyring entry semantics on top.
+
+The `memory-fallback` feature adds [Store::new_with_fallback], which creates
+an in-memory store (whose secrets last only as long as the process) if the
+Secret Service is unavailable.
+
 ## Headless usage
 
 If you must use the secret-service on a headless linux box, be aware that there
@@ -91,7 +458,9 @@ For an excellent treatment of all the headless dbus issues, see
 As noted in
 [this issue on GitHub](https://github.com/open-source-cooperative/keyring-rs/issues/133),
 there is no "default" collection defined under WSL.  So this crate will not work
-on WSL unless you specify a non-`default` target modifier on every specifier.
+on WSL unless you either specify a non-`default` target modifier on every specifier
+or configure the store with `create-default-collection`, which creates the
+default collection the first time it's needed.
 
 ## Usage with KDE Wallet
 
@@ -105,10 +474,29 @@ passwords or secrets in this interface.
 
  */
 
+pub mod backup;
+mod bus;
+mod cache;
+mod config;
 pub mod cred;
 pub mod errors;
+pub mod events;
+pub mod hooks;
+pub mod info;
+pub mod localize;
+#[cfg(feature = "low-level")]
+pub mod low_level;
+pub mod mapping;
+#[cfg(feature = "memory-fallback")]
+pub mod memory;
+mod metrics;
+pub mod mirror;
+mod pattern;
+pub mod record;
 mod service;
 pub mod store;
-pub use store::Store;
+pub mod transform;
+pub use dbus;
+pub use store::{Store, StoreBuilder};
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,49 @@
+/*!
+
+Localization hooks.
+
+The only user-visible text this crate generates is the default label
+of the items it creates (which appears in Secret Service UIs) and the
+descriptions of its own errors. A [Localizer] supplies both, so
+applications can show them in the user's language. (The prompts that
+ask users to unlock items are generated by the Secret Service itself,
+which localizes them according to the desktop's settings.)
+
+Use [Store::set_localizer](crate::Store::set_localizer) to install a
+localizer, and [Store::describe_error](crate::Store::describe_error) to
+get localized error descriptions.
+
+*/
+
+use crate::errors::StoreError;
+
+/// A source of localized text.
+pub trait Localizer {
+    /// The label of a new item for the given service and user.
+    ///
+    /// The user is `None` when it must not appear in the label
+    /// (as when the store hides users in privacy mode).
+    fn default_label(&self, service: &str, user: Option<&str>) -> String;
+
+    /// A description of a store-specific error.
+    fn describe(&self, error: &StoreError) -> String;
+}
+
+pub type Localization = dyn Localizer + Send + Sync;
+
+/// The localizer used by default, which generates English text.
+#[derive(Debug, Default)]
+pub struct English;
+
+impl Localizer for English {
+    fn default_label(&self, service: &str, user: Option<&str>) -> String {
+        match user {
+            Some(user) => format!("keyring:{user}@{service}"),
+            None => format!("keyring:{service}"),
+        }
+    }
+
+    fn describe(&self, error: &StoreError) -> String {
+        error.to_string()
+    }
+}
@@ -0,0 +1,236 @@
+/*!
+
+Low-level Secret Service access.
+
+This module (available with the `low-level` feature) is a thin, safe
+wrapper over the Secret Service for clients that want to work with items
+directly rather than through keyring entries. Items are identified by
+their D-Bus object paths, which are only meaningful while the items exist.
+
+A [Service] shares the configuration of the store it comes from, so that
+store's transforms, schema, default attributes, and opaque attributes
+apply to the items it reads and writes.
+
+Every operation has an `async` counterpart (whose name ends in `_async`)
+that doesn't block the calling thread: the store's Secret Service session
+is owned by a worker thread, and the returned futures complete when the
+worker has finished the operation. The futures don't depend on any
+particular async runtime. Dropping one before its operation has started
+cancels the operation.
+
+*/
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use dbus::Path;
+use keyring_core::Result;
+
+use crate::Store;
+
+/// Direct access to the Secret Service used by a store.
+#[derive(Clone)]
+pub struct Service {
+    ss: Arc<crate::service::Service>,
+}
+
+impl std::fmt::Debug for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Service").finish_non_exhaustive()
+    }
+}
+
+impl Service {
+    /// Access the Secret Service used by a store.
+    pub fn from_store(store: &Store) -> Self {
+        Self {
+            ss: store.ss.clone(),
+        }
+    }
+
+    /// Find the items that have the given attributes.
+    ///
+    /// Locked items are unlocked (which may prompt the user) before
+    /// their paths are returned.
+    pub fn search(&self, attributes: &HashMap<&str, &str>) -> Result<Vec<Path<'static>>> {
+        self.ss.find_matching_items(attributes).wait()
+    }
+
+    /// Create an item in the named collection, returning its path.
+    ///
+    /// The collection name is interpreted as a `target` modifier would be.
+    /// An existing item in the collection with exactly the same attributes
+    /// is replaced.
+    pub fn create_item(
+        &self,
+        collection: &str,
+        label: &str,
+        attributes: &HashMap<&str, &str>,
+        secret: &[u8],
+    ) -> Result<Path<'static>> {
+        self.ss
+            .create_item(collection, label, attributes.clone(), secret, None)
+            .wait()
+    }
+
+    /// Returns the secret of an item.
+    pub fn get_secret(&self, path: &Path<'static>) -> Result<Vec<u8>> {
+        self.ss.get_secret(path).wait()
+    }
+
+    /// Returns the secrets of several items, in order.
+    ///
+    /// See [Store::get_secrets](crate::Store::get_secrets) for how the
+    /// items are unlocked and read.
+    pub fn get_secrets(&self, paths: &[Path<'static>]) -> Result<Vec<Result<Vec<u8>>>> {
+        self.ss.get_secrets(paths).wait()
+    }
+
+    /// Sets the secret of an item.
+    pub fn set_secret(&self, path: &Path<'static>, secret: &[u8]) -> Result<()> {
+        self.ss.set_secret(path, secret, None).wait()
+    }
+
+    /// Returns the attributes of an item.
+    pub fn get_attributes(&self, path: &Path<'static>) -> Result<HashMap<String, String>> {
+        self.ss.get_attributes(path).wait()
+    }
+
+    /// Merges the given attributes into those of an item.
+    pub fn update_attributes(
+        &self,
+        path: &Path<'static>,
+        attributes: &HashMap<&str, &str>,
+    ) -> Result<()> {
+        self.ss.update_attributes(path, attributes).wait()
+    }
+
+    /// Returns the label of an item.
+    pub fn get_label(&self, path: &Path<'static>) -> Result<String> {
+        self.ss.get_label(path).wait()
+    }
+
+    /// Sets the label of an item.
+    pub fn set_label(&self, path: &Path<'static>, label: &str) -> Result<()> {
+        self.ss.set_label(path, label).wait()
+    }
+
+    /// Deletes an item.
+    pub fn delete(&self, path: &Path<'static>) -> Result<()> {
+        self.ss.delete(path).wait()
+    }
+
+    /// Locks an item.
+    pub fn lock(&self, path: &Path<'static>) -> Result<()> {
+        self.ss.lock_item(path).wait()
+    }
+
+    /// Unlocks an item, if it's locked (which may prompt the user).
+    pub fn unlock(&self, path: &Path<'static>) -> Result<()> {
+        self.ss.ensure_unlocked(path).wait()
+    }
+
+    /// Like [search](Service::search), but without blocking.
+    pub fn search_async(
+        &self,
+        attributes: &HashMap<&str, &str>,
+    ) -> impl Future<Output = Result<Vec<Path<'static>>>> + Send + 'static {
+        self.ss.find_matching_items(attributes)
+    }
+
+    /// Like [create_item](Service::create_item), but without blocking.
+    pub fn create_item_async(
+        &self,
+        collection: &str,
+        label: &str,
+        attributes: &HashMap<&str, &str>,
+        secret: &[u8],
+    ) -> impl Future<Output = Result<Path<'static>>> + Send + 'static {
+        self.ss
+            .create_item(collection, label, attributes.clone(), secret, None)
+    }
+
+    /// Like [get_secret](Service::get_secret), but without blocking.
+    pub fn get_secret_async(
+        &self,
+        path: &Path<'static>,
+    ) -> impl Future<Output = Result<Vec<u8>>> + Send + 'static {
+        self.ss.get_secret(path)
+    }
+
+    /// Like [get_secrets](Service::get_secrets), but without blocking.
+    pub fn get_secrets_async(
+        &self,
+        paths: &[Path<'static>],
+    ) -> impl Future<Output = Result<Vec<Result<Vec<u8>>>>> + Send + 'static {
+        self.ss.get_secrets(paths)
+    }
+
+    /// Like [set_secret](Service::set_secret), but without blocking.
+    pub fn set_secret_async(
+        &self,
+        path: &Path<'static>,
+        secret: &[u8],
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.ss.set_secret(path, secret, None)
+    }
+
+    /// Like [get_attributes](Service::get_attributes), but without blocking.
+    pub fn get_attributes_async(
+        &self,
+        path: &Path<'static>,
+    ) -> impl Future<Output = Result<HashMap<String, String>>> + Send + 'static {
+        self.ss.get_attributes(path)
+    }
+
+    /// Like [update_attributes](Service::update_attributes), but without blocking.
+    pub fn update_attributes_async(
+        &self,
+        path: &Path<'static>,
+        attributes: &HashMap<&str, &str>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.ss.update_attributes(path, attributes)
+    }
+
+    /// Like [get_label](Service::get_label), but without blocking.
+    pub fn get_label_async(
+        &self,
+        path: &Path<'static>,
+    ) -> impl Future<Output = Result<String>> + Send + 'static {
+        self.ss.get_label(path)
+    }
+
+    /// Like [set_label](Service::set_label), but without blocking.
+    pub fn set_label_async(
+        &self,
+        path: &Path<'static>,
+        label: &str,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.ss.set_label(path, label)
+    }
+
+    /// Like [delete](Service::delete), but without blocking.
+    pub fn delete_async(
+        &self,
+        path: &Path<'static>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.ss.delete(path)
+    }
+
+    /// Like [lock](Service::lock), but without blocking.
+    pub fn lock_async(
+        &self,
+        path: &Path<'static>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.ss.lock_item(path)
+    }
+
+    /// Like [unlock](Service::unlock), but without blocking.
+    pub fn unlock_async(
+        &self,
+        path: &Path<'static>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.ss.ensure_unlocked(path)
+    }
+}
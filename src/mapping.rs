@@ -0,0 +1,163 @@
+/*!
+
+Specifier-to-attribute mapping.
+
+Every entry built by a store is identified in the Secret Service by a
+set of item attributes. The [AttributeMapper] trait determines which
+attributes are derived from an entry's specifiers, how specifiers are
+recovered from an item's attributes, and which attributes are
+controlled (and so can't be updated by clients).
+
+The [DefaultMapper] implements the scheme described in the crate
+documentation. Organizations with existing attribute conventions can
+supply their own mapper via [Store::set_attribute_mapper](crate::Store::set_attribute_mapper).
+
+*/
+
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::cred::Specifier;
+
+/// A mapping between keyring specifiers and secret-service attributes.
+pub trait AttributeMapper {
+    /// Returns the attributes that identify the item for a specifier.
+    ///
+    /// These are used both to search for the item and as the
+    /// attributes of the item when it's created.
+    fn attributes(&self, specifier: &Specifier) -> HashMap<String, String>;
+
+    /// Returns the service and user of an item with the given attributes.
+    ///
+    /// Returns `None` if the attributes don't follow this mapping.
+    fn specifiers(&self, attributes: &HashMap<String, String>) -> Option<(String, String)>;
+
+    /// Returns whether the named attribute is controlled by this mapping.
+    fn is_controlled(&self, attribute: &str) -> bool;
+}
+
+pub type Mapper = dyn AttributeMapper + Send + Sync;
+
+/// The mapping used by default.
+///
+/// The service and user are kept in the `service` and `username`
+/// attributes, and the target (if any) in the `target` attribute.
+/// The names of these attributes can be changed with
+/// [with_service_attribute](DefaultMapper::with_service_attribute),
+/// [with_username_attribute](DefaultMapper::with_username_attribute), and
+/// [with_target_attribute](DefaultMapper::with_target_attribute).
+///
+/// In privacy mode, the `username` attribute holds a salted hash
+/// of the user rather than the user itself. Lookups still work,
+/// because the hash is deterministic, but the user can't be
+/// recovered from the item's attributes.
+#[derive(Debug, Default)]
+pub struct DefaultMapper {
+    username_salt: Option<String>,
+    username_attribute: Option<String>,
+    service_attribute: Option<String>,
+    target_attribute: Option<String>,
+}
+
+impl DefaultMapper {
+    /// A default mapper in privacy mode, using the given salt.
+    pub fn with_hashed_username(salt: &str) -> Self {
+        Self {
+            username_salt: Some(salt.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// This mapper, with the user kept in the named attribute
+    /// rather than in `username`.
+    pub fn with_username_attribute(mut self, name: &str) -> Self {
+        self.username_attribute = Some(name.to_string());
+        self
+    }
+
+    /// This mapper, with the service kept in the named attribute
+    /// rather than in `service`.
+    pub fn with_service_attribute(mut self, name: &str) -> Self {
+        self.service_attribute = Some(name.to_string());
+        self
+    }
+
+    /// This mapper, with the target kept in the named attribute
+    /// rather than in `target`.
+    pub fn with_target_attribute(mut self, name: &str) -> Self {
+        self.target_attribute = Some(name.to_string());
+        self
+    }
+
+    fn username_attribute(&self) -> &str {
+        self.username_attribute.as_deref().unwrap_or("username")
+    }
+
+    fn service_attribute(&self) -> &str {
+        self.service_attribute.as_deref().unwrap_or("service")
+    }
+
+    fn target_attribute(&self) -> &str {
+        self.target_attribute.as_deref().unwrap_or("target")
+    }
+
+    fn username(&self, user: &str) -> String {
+        match &self.username_salt {
+            None => user.to_string(),
+            Some(salt) => {
+                let mut hasher = Sha256::new();
+                hasher.update(salt.as_bytes());
+                hasher.update([0u8]);
+                hasher.update(user.as_bytes());
+                to_hex(&hasher.finalize())
+            }
+        }
+    }
+}
+
+impl AttributeMapper for DefaultMapper {
+    fn attributes(&self, specifier: &Specifier) -> HashMap<String, String> {
+        let mut result = HashMap::new();
+        result.insert(
+            self.service_attribute().to_string(),
+            specifier.service.clone(),
+        );
+        result.insert(
+            self.username_attribute().to_string(),
+            self.username(&specifier.user),
+        );
+        if let Some(target) = &specifier.target {
+            result.insert(self.target_attribute().to_string(), target.clone());
+        }
+        result
+    }
+
+    fn specifiers(&self, attributes: &HashMap<String, String>) -> Option<(String, String)> {
+        if self.username_salt.is_some() {
+            return None;
+        }
+        let service = attributes.get(self.service_attribute())?;
+        let user = attributes.get(self.username_attribute())?;
+        Some((service.to_string(), user.to_string()))
+    }
+
+    fn is_controlled(&self, attribute: &str) -> bool {
+        attribute == self.target_attribute()
+            || attribute == self.service_attribute()
+            || attribute == self.username_attribute()
+    }
+}
+
+/// Borrow an owned attribute map as the form used in service calls.
+pub(crate) fn borrow_attributes(attributes: &HashMap<String, String>) -> HashMap<&str, &str> {
+    attributes
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
+
+/// Format bytes as lowercase hex.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
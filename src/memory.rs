@@ -0,0 +1,161 @@
+/*!
+
+In-memory fallback store.
+
+This module (available with the `memory-fallback` feature) provides an
+ephemeral store that keeps secrets in the memory of the current process.
+It's used by [Store::new_with_fallback](crate::Store::new_with_fallback)
+when the Secret Service is unavailable, so that command-line tools on
+minimal systems can keep working with secrets that last as long as the
+process does. Its [persistence](CredentialStoreApi::persistence) is
+reported as [ProcessOnly](CredentialPersistence::ProcessOnly), so
+callers can tell that their secrets won't outlive them.
+
+Credentials in this store have no attributes, and entry modifiers
+are accepted but ignored.
+
+*/
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use keyring_core::api::{Credential, CredentialApi, CredentialPersistence, CredentialStoreApi};
+use keyring_core::{Entry, Error, Result};
+
+type Secrets = Arc<Mutex<HashMap<(String, String), Vec<u8>>>>;
+
+/// An in-memory credential store.
+pub struct Store {
+    pub id: String,
+    secrets: Secrets,
+}
+
+impl std::fmt::Debug for Store {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Store").field("id", &self.id).finish()
+    }
+}
+
+impl Store {
+    /// Create a new, empty, in-memory store.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Store {
+            id: format!(
+                "Crate version {}, in-memory fallback",
+                env!("CARGO_PKG_VERSION")
+            ),
+            secrets: Default::default(),
+        })
+    }
+}
+
+impl CredentialStoreApi for Store {
+    fn vendor(&self) -> String {
+        "In-memory fallback store, https://crates.io/crates/dbus-secret-service-keyring-store"
+            .to_string()
+    }
+
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    /// See the keyring-core API docs.
+    fn build(
+        &self,
+        service: &str,
+        user: &str,
+        _modifiers: Option<&HashMap<&str, &str>>,
+    ) -> Result<Entry> {
+        Ok(Entry::new_with_credential(Arc::new(Cred {
+            secrets: self.secrets.clone(),
+            service: service.to_string(),
+            user: user.to_string(),
+        })))
+    }
+
+    /// See the keyring-core API docs.
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    /// Secrets in this store last only as long as the process.
+    fn persistence(&self) -> CredentialPersistence {
+        CredentialPersistence::ProcessOnly
+    }
+
+    fn debug_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A credential in the in-memory store.
+pub struct Cred {
+    secrets: Secrets,
+    service: String,
+    user: String,
+}
+
+impl std::fmt::Debug for Cred {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cred")
+            .field("service", &self.service)
+            .field("user", &self.user)
+            .finish()
+    }
+}
+
+impl Cred {
+    fn with_secrets<T>(&self, f: impl FnOnce(&mut HashMap<(String, String), Vec<u8>>) -> T) -> T {
+        let mut secrets = self
+            .secrets
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug");
+        f(&mut secrets)
+    }
+
+    fn key(&self) -> (String, String) {
+        (self.service.clone(), self.user.clone())
+    }
+}
+
+impl CredentialApi for Cred {
+    /// See the keyring-core API docs.
+    fn set_secret(&self, secret: &[u8]) -> Result<()> {
+        self.with_secrets(|s| s.insert(self.key(), secret.to_vec()));
+        Ok(())
+    }
+
+    /// See the keyring-core API docs.
+    fn get_secret(&self) -> Result<Vec<u8>> {
+        self.with_secrets(|s| s.get(&self.key()).cloned())
+            .ok_or(Error::NoEntry)
+    }
+
+    /// See the keyring-core API docs.
+    fn delete_credential(&self) -> Result<()> {
+        self.with_secrets(|s| s.remove(&self.key()))
+            .map(|_| ())
+            .ok_or(Error::NoEntry)
+    }
+
+    /// See the keyring-core API docs.
+    fn get_credential(&self) -> Result<Option<Arc<Credential>>> {
+        self.get_secret()?;
+        Ok(None)
+    }
+
+    /// See the keyring-core API docs.
+    fn get_specifiers(&self) -> Option<(String, String)> {
+        Some(self.key())
+    }
+
+    /// See the keyring-core API docs.
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    /// See the keyring-core API docs.
+    fn debug_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
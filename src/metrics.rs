@@ -0,0 +1,79 @@
+//! The operation metrics kept by every store.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::config::Operation;
+use crate::info::{LatencyHistogram, OperationMetrics};
+
+/// The upper bounds of the latency buckets, in milliseconds. Slower
+/// operations go in a final, unbounded bucket.
+const BOUNDS_MS: [u64; 9] = [1, 5, 10, 50, 100, 500, 1000, 5000, 30000];
+
+/// Counts and latencies of a store's operations, and its unlock requests.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    operations: Mutex<HashMap<Operation, OperationMetrics>>,
+    unlock_requests: AtomicU64,
+}
+
+impl Recorder {
+    /// Record a finished operation.
+    pub(crate) fn record(&self, operation: Operation, elapsed: Duration, failed: bool) {
+        let mut operations = self.operations();
+        let metrics = operations
+            .entry(operation)
+            .or_insert_with(|| OperationMetrics {
+                latency: histogram(),
+                ..Default::default()
+            });
+        metrics.count += 1;
+        if failed {
+            metrics.errors += 1;
+        }
+        let latency = &mut metrics.latency;
+        if let Some((_, count)) = latency.buckets.iter_mut().find(|(b, _)| elapsed <= *b) {
+            *count += 1;
+        }
+        latency.total += elapsed;
+        latency.max = latency.max.max(elapsed);
+    }
+
+    /// Count a request to unlock locked items or collections.
+    pub(crate) fn count_unlock(&self) {
+        self.unlock_requests.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn unlock_requests(&self) -> u64 {
+        self.unlock_requests.load(Ordering::Acquire)
+    }
+
+    /// The metrics of each kind of operation that has run, by its name.
+    pub(crate) fn snapshot(&self) -> HashMap<String, OperationMetrics> {
+        self.operations()
+            .iter()
+            .map(|(op, metrics)| (op.name().to_string(), metrics.clone()))
+            .collect()
+    }
+
+    fn operations(&self) -> MutexGuard<'_, HashMap<Operation, OperationMetrics>> {
+        self.operations
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug")
+    }
+}
+
+/// An empty histogram with the standard buckets.
+fn histogram() -> LatencyHistogram {
+    let mut buckets: Vec<(Duration, u64)> = BOUNDS_MS
+        .iter()
+        .map(|ms| (Duration::from_millis(*ms), 0))
+        .collect();
+    buckets.push((Duration::MAX, 0));
+    LatencyHistogram {
+        buckets,
+        ..Default::default()
+    }
+}
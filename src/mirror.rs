@@ -0,0 +1,439 @@
+/*!
+
+Encrypted mirror files.
+
+A store configured with `mirror-file` and `mirror-key` appends a record to
+the mirror file every time it writes an item: the record holds the item's
+path, collection, label, attributes, secret, and the secret's content type (or, for a deletion, just the
+path). Records are encrypted with AES-256-CBC and authenticated with
+HMAC-SHA256, using keys derived from the `mirror-key` value with
+PBKDF2-HMAC-SHA256 and a random salt kept at the start of the file. The file
+is never rewritten, so it holds the history of every item the store has
+touched.
+
+Each store that opens a mirror file numbers the records it appends, and the
+numbers are authenticated along with the records, so [read] notices when
+records have been removed from the middle of the file or moved around in it.
+Records removed from the end of the file can't be noticed, because the file
+is only ever appended to.
+
+If the Secret Service's storage is lost or damaged, or a collection is deleted
+by mistake, [read] recovers the records from a mirror file and
+[Store::restore_from_mirror](crate::Store::restore_from_mirror) recreates the
+items that were last written (and not deleted) by the store. The `restore`
+example in this crate is a command-line tool that does this.
+
+Secrets are mirrored as they are stored in the Secret Service, that is, after
+the store's [transform](crate::transform) pipeline has been applied, and
+the values of opaque attributes are mirrored in their concealed form.
+Restored items are therefore readable by the store that mirrored them.
+
+*/
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use keyring_core::{Error, Result};
+use sha2::Sha256;
+use zeroize::{Zeroize, Zeroizing};
+
+/// The first bytes of every mirror file.
+const MAGIC: &[u8] = b"keyring mirror 2\n";
+const SALT_LENGTH: usize = 16;
+const IV_LENGTH: usize = 16;
+const MAC_LENGTH: usize = 32;
+const ITERATIONS: u32 = 100_000;
+/// The length of the writer id and sequence number that precede each
+/// mirror record.
+const COUNTER_LENGTH: usize = 24;
+
+/// A mirrored write of an item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The D-Bus path the item had when it was written.
+    pub path: String,
+    /// Whether the write deleted the item (in which case the other
+    /// fields are empty).
+    pub deleted: bool,
+    /// The label of the item's collection.
+    pub collection: String,
+    /// The label of the item.
+    pub label: String,
+    /// The item's attributes, as stored.
+    pub attributes: HashMap<String, String>,
+    /// The item's secret, as stored. It's wiped when the record is dropped.
+    pub secret: Vec<u8>,
+    /// The content type of the item's secret.
+    pub content_type: String,
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+impl Record {
+    /// The record of an item's deletion.
+    pub(crate) fn deletion(path: &str) -> Self {
+        Record {
+            path: path.to_string(),
+            deleted: true,
+            collection: String::new(),
+            label: String::new(),
+            attributes: HashMap::new(),
+            secret: Vec::new(),
+            content_type: String::new(),
+        }
+    }
+
+    fn encode(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(vec![self.deleted as u8]);
+        put(&mut out, self.path.as_bytes());
+        put(&mut out, self.collection.as_bytes());
+        put(&mut out, self.label.as_bytes());
+        out.extend_from_slice(&(self.attributes.len() as u32).to_be_bytes());
+        for (k, v) in self.attributes.iter() {
+            put(&mut out, k.as_bytes());
+            put(&mut out, v.as_bytes());
+        }
+        put(&mut out, &self.secret);
+        put(&mut out, self.content_type.as_bytes());
+        out
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let (&deleted, mut rest) = data.split_first()?;
+        let path = take_string(&mut rest)?;
+        let collection = take_string(&mut rest)?;
+        let label = take_string(&mut rest)?;
+        let count = u32::from_be_bytes(take(&mut rest, 4)?.try_into().ok()?);
+        let mut attributes = HashMap::new();
+        for _ in 0..count {
+            let k = take_string(&mut rest)?;
+            let v = take_string(&mut rest)?;
+            attributes.insert(k, v);
+        }
+        let length = take_length(&mut rest)?;
+        let secret = take(&mut rest, length)?.to_vec();
+        let content_type = take_string(&mut rest)?;
+        if !rest.is_empty() || deleted > 1 {
+            return None;
+        }
+        Some(Record {
+            path,
+            deleted: deleted == 1,
+            collection,
+            label,
+            attributes,
+            secret,
+            content_type,
+        })
+    }
+}
+
+fn put(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+    if data.len() < length {
+        return None;
+    }
+    let (head, tail) = data.split_at(length);
+    *data = tail;
+    Some(head)
+}
+
+fn take_length(data: &mut &[u8]) -> Option<usize> {
+    Some(u32::from_be_bytes(take(data, 4)?.try_into().ok()?) as usize)
+}
+
+fn take_string(data: &mut &[u8]) -> Option<String> {
+    let length = take_length(data)?;
+    String::from_utf8(take(data, length)?.to_vec()).ok()
+}
+
+/// Fill a buffer with random bytes.
+pub(crate) fn random(buffer: &mut [u8]) -> Result<()> {
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(buffer))
+        .map_err(|e| Error::PlatformFailure(Box::new(e)))
+}
+
+/// The keys used to encrypt and authenticate records.
+pub(crate) struct Keys {
+    encryption: [u8; 32],
+    authentication: [u8; 32],
+}
+
+impl Keys {
+    pub(crate) fn derive(key: &[u8]) -> Self {
+        let derive = |purpose: &[u8]| -> [u8; 32] {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+            mac.update(purpose);
+            mac.finalize().into_bytes().into()
+        };
+        Keys {
+            encryption: derive(b"keyring mirror encryption"),
+            authentication: derive(b"keyring mirror authentication"),
+        }
+    }
+
+    /// Derive the keys for a passphrase, with PBKDF2-HMAC-SHA256.
+    pub(crate) fn stretch(passphrase: &str, salt: &[u8]) -> Self {
+        let prf = Hmac::<Sha256>::new_from_slice(passphrase.as_bytes())
+            .expect("HMAC can take a key of any size");
+        let mut mac = prf.clone();
+        mac.update(salt);
+        mac.update(&1u32.to_be_bytes());
+        let mut block = Zeroizing::new(<[u8; 32]>::from(mac.finalize().into_bytes()));
+        let mut key = block.clone();
+        for _ in 1..ITERATIONS {
+            let mut mac = prf.clone();
+            mac.update(block.as_slice());
+            *block = mac.finalize().into_bytes().into();
+            for (k, b) in key.iter_mut().zip(block.iter()) {
+                *k ^= b;
+            }
+        }
+        Keys::derive(key.as_slice())
+    }
+
+    /// The MAC of some data, for authenticating more than one record.
+    pub(crate) fn tag(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = self.authenticator();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Whether a [tag](Keys::tag) is the MAC of some data.
+    pub(crate) fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        let mut mac = self.authenticator();
+        mac.update(data);
+        mac.verify_slice(tag).is_ok()
+    }
+
+    fn authenticator(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.authentication)
+            .expect("HMAC can take a key of any size")
+    }
+
+    /// Encrypt and authenticate data: the result is the IV, the
+    /// ciphertext, and the MAC of both.
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_with(plaintext, &[])
+    }
+
+    /// Like [encrypt](Keys::encrypt), but the MAC also covers some
+    /// associated data (which isn't included in the result).
+    fn encrypt_with(&self, plaintext: &[u8], associated: &[u8]) -> Result<Vec<u8>> {
+        let mut iv = [0u8; IV_LENGTH];
+        random(&mut iv)?;
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&self.encryption.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+        let mut mac = self.authenticator();
+        mac.update(associated);
+        mac.update(&iv);
+        mac.update(&ciphertext);
+        let mut sealed = iv.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        sealed.extend_from_slice(&mac.finalize().into_bytes());
+        Ok(sealed)
+    }
+
+    /// Reverse [encrypt](Keys::encrypt), or explain why the data can't be.
+    pub(crate) fn decrypt(&self, sealed: &[u8]) -> std::result::Result<Zeroizing<Vec<u8>>, &str> {
+        self.decrypt_with(sealed, &[])
+    }
+
+    /// Reverse [encrypt_with](Keys::encrypt_with), given the same
+    /// associated data.
+    fn decrypt_with(
+        &self,
+        sealed: &[u8],
+        associated: &[u8],
+    ) -> std::result::Result<Zeroizing<Vec<u8>>, &str> {
+        if sealed.len() < IV_LENGTH + MAC_LENGTH {
+            return Err("is too short");
+        }
+        let (body, tag) = sealed.split_at(sealed.len() - MAC_LENGTH);
+        let mut mac = self.authenticator();
+        mac.update(associated);
+        mac.update(body);
+        mac.verify_slice(tag)
+            .map_err(|_| "can't be authenticated (is the key wrong?)")?;
+        let (iv, ciphertext) = body.split_at(IV_LENGTH);
+        let iv: [u8; IV_LENGTH] = iv.try_into().unwrap();
+        let plaintext = cbc::Decryptor::<aes::Aes256>::new(&self.encryption.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| "can't be decrypted")?;
+        Ok(Zeroizing::new(plaintext))
+    }
+
+    pub(crate) fn seal(&self, record: &Record) -> Result<Vec<u8>> {
+        let encrypted = self.encrypt(&record.encode())?;
+        let mut sealed = (encrypted.len() as u32).to_be_bytes().to_vec();
+        sealed.extend_from_slice(&encrypted);
+        Ok(sealed)
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Record> {
+        self.open_with(sealed, &[])
+    }
+
+    fn open_with(&self, sealed: &[u8], associated: &[u8]) -> Result<Record> {
+        let bad = |reason: &str| Error::BadStoreFormat(format!("Mirror record {reason}"));
+        let plaintext = self.decrypt_with(sealed, associated).map_err(bad)?;
+        Record::decode(&plaintext).ok_or_else(|| bad("is malformed"))
+    }
+}
+
+/// An open mirror file, to which a store appends records.
+pub(crate) struct Mirror {
+    /// The file, and the sequence number of the next record.
+    file: Mutex<(File, u64)>,
+    keys: Keys,
+    /// A random id that distinguishes this store's records from those
+    /// written by other stores.
+    writer: [u8; 16],
+}
+
+impl Mirror {
+    /// Open a mirror file for appending, creating it if necessary.
+    ///
+    /// New files are only readable and writable by their owner. Files
+    /// that aren't mirror files produce a [BadStoreFormat](Error::BadStoreFormat)
+    /// error.
+    pub(crate) fn open(path: &Path, key: &str) -> Result<Self> {
+        let platform = |e: std::io::Error| Error::PlatformFailure(Box::new(e));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(platform)?;
+        let mut header = [0u8; MAGIC.len() + SALT_LENGTH];
+        if file.metadata().map_err(platform)?.len() == 0 {
+            header[..MAGIC.len()].copy_from_slice(MAGIC);
+            random(&mut header[MAGIC.len()..])?;
+            file.write_all(&header).map_err(platform)?;
+        } else {
+            file.read_exact(&mut header)
+                .map_err(|_| Error::BadStoreFormat("Not a mirror file".to_string()))?;
+        }
+        let Some(salt) = header.strip_prefix(MAGIC) else {
+            return Err(Error::BadStoreFormat("Not a mirror file".to_string()));
+        };
+        let keys = Keys::stretch(key, salt);
+        let mut writer = [0u8; 16];
+        random(&mut writer)?;
+        Ok(Mirror {
+            file: Mutex::new((file, 0)),
+            keys,
+            writer,
+        })
+    }
+
+    /// Append a record to the file.
+    ///
+    /// Each record is written with a single call, so records written
+    /// by concurrent processes are not interleaved.
+    pub(crate) fn append(&self, record: &Record) -> Result<()> {
+        let mut guard = self
+            .file
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug");
+        let (file, next) = &mut *guard;
+        let mut counter = self.writer.to_vec();
+        counter.extend_from_slice(&next.to_be_bytes());
+        let encrypted = self.keys.encrypt_with(&record.encode(), &counter)?;
+        let mut sealed = ((counter.len() + encrypted.len()) as u32)
+            .to_be_bytes()
+            .to_vec();
+        sealed.extend_from_slice(&counter);
+        sealed.extend_from_slice(&encrypted);
+        file.write_all(&sealed)
+            .and_then(|_| file.flush())
+            .map_err(|e| Error::PlatformFailure(Box::new(e)))?;
+        *next += 1;
+        Ok(())
+    }
+}
+
+/// Read all the records in a mirror file, oldest first.
+///
+/// A record that was only partly written (say, because its process
+/// was killed) at the end of the file is ignored. Files that aren't
+/// mirror files, records that can't be authenticated with the key,
+/// and records that are missing from the middle of the file or out
+/// of order produce a [BadStoreFormat](Error::BadStoreFormat) error.
+pub fn read(path: &Path, key: &str) -> Result<Vec<Record>> {
+    let bad = |reason: &str| Error::BadStoreFormat(reason.to_string());
+    let data = std::fs::read(path).map_err(|e| Error::PlatformFailure(Box::new(e)))?;
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        return Err(bad("Not a mirror file"));
+    };
+    let Some((salt, mut rest)) = rest.split_at_checked(SALT_LENGTH) else {
+        return Err(bad("Not a mirror file"));
+    };
+    let keys = Keys::stretch(key, salt);
+    let mut expected: HashMap<&[u8], u64> = HashMap::new();
+    let mut records = Vec::new();
+    while let Some(sealed) = take_length(&mut rest).and_then(|length| take(&mut rest, length)) {
+        let Some((counter, encrypted)) = sealed.split_at_checked(COUNTER_LENGTH) else {
+            return Err(bad("Mirror record is too short"));
+        };
+        let record = keys.open_with(encrypted, counter)?;
+        let (writer, sequence) = counter.split_at(16);
+        let sequence = u64::from_be_bytes(sequence.try_into().unwrap());
+        let next = expected.entry(writer).or_default();
+        if sequence != *next {
+            return Err(bad(
+                "Mirror file is missing records, or they are out of order",
+            ));
+        }
+        *next += 1;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Open a sequence of sealed records. A partial record at the end
+/// is a [BadStoreFormat](Error::BadStoreFormat) error.
+pub(crate) fn open_all(data: &[u8], keys: &Keys) -> Result<Vec<Record>> {
+    let mut rest = data;
+    let mut records = Vec::new();
+    while !rest.is_empty() {
+        let Some(sealed) = take_length(&mut rest).and_then(|length| take(&mut rest, length)) else {
+            return Err(Error::BadStoreFormat(
+                "Mirror record is truncated".to_string(),
+            ));
+        };
+        records.push(keys.open(sealed)?);
+    }
+    Ok(records)
+}
+
+/// The records that describe the last state of each item that hasn't
+/// been deleted, in the order the items were last written.
+pub fn surviving(records: Vec<Record>) -> Vec<Record> {
+    let mut latest: HashMap<String, (usize, Record)> = HashMap::new();
+    for (i, record) in records.into_iter().enumerate() {
+        latest.insert(record.path.clone(), (i, record));
+    }
+    let mut result: Vec<(usize, Record)> =
+        latest.into_values().filter(|(_, r)| !r.deleted).collect();
+    result.sort_by_key(|(i, _)| *i);
+    result.into_iter().map(|(_, r)| r).collect()
+}
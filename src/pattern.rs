@@ -0,0 +1,408 @@
+//! The regular expressions that stores configured with `pattern:<name>`
+//! check attribute values against.
+//!
+//! Only the common core of regular expression syntax is supported:
+//! literal characters, `.`, bracketed classes (`[a-z_]`, `[^0-9]`),
+//! the ASCII classes `\d`, `\w`, and `\s` (and their negations `\D`,
+//! `\W`, and `\S`), groups (`(...)` and `(?:...)`), alternation with
+//! `|`, the repetitions `*`, `+`, `?`, `{n}`, `{n,}`, and `{n,m}`, and
+//! the anchors `^` and `$`. A pattern must match the whole of a value.
+
+/// A parsed regular expression.
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Vec<Item>, bool),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+/// A member of a character class.
+#[derive(Debug, Clone)]
+enum Item {
+    Range(char, char),
+    /// One of the ASCII classes (`d`, `w`, or `s`), possibly negated.
+    Ascii(char, bool),
+}
+
+impl Pattern {
+    /// Parse a regular expression, or explain why it can't be.
+    pub(crate) fn new(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            at: 0,
+        };
+        let root = parser.alternate()?;
+        if parser.at < parser.chars.len() {
+            return Err("unbalanced ')'".to_string());
+        }
+        root.compile(&mut Vec::new())?;
+        Ok(Pattern {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    /// The text the pattern was parsed from.
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern matches the whole of a value.
+    ///
+    /// The pattern is run as an automaton, so matching takes time
+    /// proportional to the length of the value (not exponential in it,
+    /// as with backtracking matchers).
+    pub(crate) fn matches(&self, value: &str) -> bool {
+        let mut program = Vec::new();
+        self.root
+            .compile(&mut program)
+            .expect("Patterns are checked when they are parsed");
+        program.push(Step::Match);
+        let chars: Vec<char> = value.chars().collect();
+        let mut threads = Vec::new();
+        add(
+            &program,
+            0,
+            0,
+            chars.len(),
+            &mut threads,
+            &mut vec![false; program.len()],
+        );
+        for (i, c) in chars.iter().enumerate() {
+            let mut next = Vec::new();
+            let mut seen = vec![false; program.len()];
+            for pc in threads {
+                if let Step::Consume(node) = program[pc] {
+                    if node.accepts(*c) {
+                        add(&program, pc + 1, i + 1, chars.len(), &mut next, &mut seen);
+                    }
+                }
+            }
+            threads = next;
+        }
+        threads.iter().any(|pc| matches!(program[*pc], Step::Match))
+    }
+}
+
+impl Node {
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Node::Char(expected) => c == *expected,
+            Node::Any => c != '\n',
+            Node::Class(items, negated) => items.iter().any(|item| item.accepts(c)) != *negated,
+            _ => false,
+        }
+    }
+
+    /// Append the instructions that match this node to a program.
+    fn compile<'a>(&'a self, program: &mut Vec<Step<'a>>) -> Result<(), String> {
+        if program.len() > MAX_PROGRAM {
+            return Err("pattern is too large".to_string());
+        }
+        match self {
+            Node::Empty => {}
+            Node::Char(_) | Node::Any | Node::Class(..) => program.push(Step::Consume(self)),
+            Node::Start => program.push(Step::Start),
+            Node::End => program.push(Step::End),
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    node.compile(program)?;
+                }
+            }
+            Node::Alternate(nodes) => {
+                let mut jumps = Vec::new();
+                for (i, node) in nodes.iter().enumerate() {
+                    if i + 1 == nodes.len() {
+                        node.compile(program)?;
+                        break;
+                    }
+                    let split = program.len();
+                    program.push(Step::Split(split + 1, 0));
+                    node.compile(program)?;
+                    jumps.push(program.len());
+                    program.push(Step::Jump(0));
+                    program[split] = Step::Split(split + 1, program.len());
+                }
+                let end = program.len();
+                for jump in jumps {
+                    program[jump] = Step::Jump(end);
+                }
+            }
+            Node::Repeat(node, min, max) => {
+                for _ in 0..*min {
+                    node.compile(program)?;
+                }
+                match max {
+                    None => {
+                        let split = program.len();
+                        program.push(Step::Split(split + 1, 0));
+                        node.compile(program)?;
+                        program.push(Step::Jump(split));
+                        program[split] = Step::Split(split + 1, program.len());
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(program.len());
+                            program.push(Step::Split(program.len() + 1, 0));
+                            node.compile(program)?;
+                        }
+                        let end = program.len();
+                        for split in splits {
+                            program[split] = Step::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An instruction in the program that a pattern is compiled to.
+#[derive(Debug, Clone, Copy)]
+enum Step<'a> {
+    /// Consume a character accepted by a node.
+    Consume(&'a Node),
+    /// Continue at both of two instructions.
+    Split(usize, usize),
+    Jump(usize),
+    Start,
+    End,
+    Match,
+}
+
+/// The most instructions a pattern may compile to.
+const MAX_PROGRAM: usize = 10_000;
+
+/// Add the threads that can be reached from an instruction, without
+/// consuming a character, to a list.
+fn add(
+    program: &[Step],
+    pc: usize,
+    at: usize,
+    length: usize,
+    list: &mut Vec<usize>,
+    seen: &mut [bool],
+) {
+    if seen[pc] {
+        return;
+    }
+    seen[pc] = true;
+    match program[pc] {
+        Step::Split(first, second) => {
+            add(program, first, at, length, list, seen);
+            add(program, second, at, length, list, seen);
+        }
+        Step::Jump(to) => add(program, to, at, length, list, seen),
+        Step::Start if at == 0 => add(program, pc + 1, at, length, list, seen),
+        Step::End if at == length => add(program, pc + 1, at, length, list, seen),
+        Step::Start | Step::End => {}
+        Step::Consume(_) | Step::Match => list.push(pc),
+    }
+}
+
+impl Item {
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Item::Range(low, high) => (*low..=*high).contains(&c),
+            Item::Ascii(class, negated) => {
+                let member = match class {
+                    'd' => c.is_ascii_digit(),
+                    'w' => c.is_ascii_alphanumeric() || c == '_',
+                    _ => c.is_ascii_whitespace(),
+                };
+                member != *negated
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.at += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alternate(&mut self) -> Result<Node, String> {
+        let mut alternatives = vec![self.concat()?];
+        while self.eat('|') {
+            alternatives.push(self.concat()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Node::Alternate(alternatives),
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repetitions(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('(') => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("only '(?:' groups are supported".to_string());
+                }
+                let inner = self.alternate()?;
+                if !self.eat(')') {
+                    return Err("unclosed '('".to_string());
+                }
+                Ok(inner)
+            }
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => match self.escape()? {
+                Item::Range(c, _) => Ok(Node::Char(c)),
+                ascii => Ok(Node::Class(vec![ascii], false)),
+            },
+            Some(c @ ('*' | '+' | '?' | '{')) => Err(format!("'{c}' has nothing to repeat")),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+
+    fn repetitions(&mut self, mut atom: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = if self.eat('*') {
+                (0, None)
+            } else if self.eat('+') {
+                (1, None)
+            } else if self.eat('?') {
+                (0, Some(1))
+            } else if self.eat('{') {
+                self.counts()?
+            } else {
+                return Ok(atom);
+            };
+            // laziness doesn't change whether a value matches
+            self.eat('?');
+            atom = Node::Repeat(Box::new(atom), min, max);
+        }
+    }
+
+    /// Parse the inside of `{n}`, `{n,}`, or `{n,m}`, after the `{`.
+    fn counts(&mut self) -> Result<(u32, Option<u32>), String> {
+        let invalid = || "invalid repetition count".to_string();
+        let min = self.number().ok_or_else(invalid)??;
+        let max = match self.eat(',') {
+            true => self.number().transpose().map_err(|_| invalid())?,
+            false => Some(min),
+        };
+        if !self.eat('}') || max.is_some_and(|max| max < min) {
+            return Err(invalid());
+        }
+        Ok((min, max))
+    }
+
+    /// Parse a decimal number, if there is one.
+    fn number(&mut self) -> Option<Result<u32, String>> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
+        }
+        (start < self.at).then(|| {
+            String::from_iter(&self.chars[start..self.at])
+                .parse()
+                .map_err(|_| "repetition count is too large".to_string())
+        })
+    }
+
+    /// Parse a bracketed class, after the `[`.
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let item = match self.next() {
+                None => return Err("unclosed '['".to_string()),
+                Some(']') if !first => break,
+                Some('\\') => self.escape()?,
+                Some(c) => Item::Range(c, c),
+            };
+            first = false;
+            let Item::Range(low, _) = item else {
+                items.push(item);
+                continue;
+            };
+            if self.peek() == Some('-') && self.chars.get(self.at + 1).is_some_and(|c| *c != ']') {
+                self.at += 1;
+                let high = match self.next() {
+                    Some('\\') => match self.escape()? {
+                        Item::Range(c, _) => c,
+                        Item::Ascii(..) => return Err("invalid class range".to_string()),
+                    },
+                    Some(c) => c,
+                    None => return Err("unclosed '['".to_string()),
+                };
+                if high < low {
+                    return Err("invalid class range".to_string());
+                }
+                items.push(Item::Range(low, high));
+            } else {
+                items.push(item);
+            }
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    /// Parse an escape sequence, after the `\`.
+    fn escape(&mut self) -> Result<Item, String> {
+        match self.next() {
+            Some(c @ ('d' | 'w' | 's')) => Ok(Item::Ascii(c, false)),
+            Some(c @ ('D' | 'W' | 'S')) => Ok(Item::Ascii(c.to_ascii_lowercase(), true)),
+            Some('n') => Ok(Item::Range('\n', '\n')),
+            Some('t') => Ok(Item::Range('\t', '\t')),
+            Some('r') => Ok(Item::Range('\r', '\r')),
+            Some(c) if !c.is_alphanumeric() => Ok(Item::Range(c, c)),
+            Some(c) => Err(format!("unsupported escape '\\{c}'")),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+}
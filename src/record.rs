@@ -0,0 +1,176 @@
+/*!
+
+Struct-to-credential mapping.
+
+Applications often keep several pieces of structured data in one
+credential: a password along with, say, the server it's for and the
+date it was issued. A [RecordMapping] describes once how the fields of
+such a struct correspond to the parts of a credential, so the struct
+can be written to and read from an entry without repetitive glue:
+
+```no_run
+# use dbus_secret_service_keyring_store::Store;
+# use dbus_secret_service_keyring_store::record::RecordMapping;
+# use keyring_core::Error;
+# use keyring_core::api::CredentialStoreApi;
+#[derive(Default)]
+struct Login {
+    server: String,
+    issued: String,
+    password: String,
+}
+
+let mapping = RecordMapping::new()
+    .attribute("server", |l: &Login| l.server.clone(), |l, v| l.server = v)
+    .attribute("issued", |l: &Login| l.issued.clone(), |l, v| l.issued = v)
+    .secret(
+        |l: &Login| l.password.as_bytes().to_vec(),
+        |l, s| {
+            l.password = String::from_utf8(s).map_err(|e| Error::BadEncoding(e.into_bytes()))?;
+            Ok(())
+        },
+    );
+let entry = Store::new()?.build("my-app", "alice", None)?;
+let login = Login { server: "example.com".into(), issued: "2024".into(), password: "pw".into() };
+mapping.write(&entry, &login)?;
+let login: Login = mapping.read(&entry)?;
+# Ok::<(), Error>(())
+```
+
+Fields mapped to attributes are subject to the usual rules for updating
+attributes, so they can't use the attribute names that the store controls.
+
+*/
+
+use std::collections::HashMap;
+
+use keyring_core::{Entry, Result};
+
+type Getter<T, V> = Box<dyn Fn(&T) -> V + Send + Sync>;
+type Setter<T, V> = Box<dyn Fn(&mut T, V) -> Result<()> + Send + Sync>;
+
+struct AttributeField<T> {
+    name: String,
+    get: Getter<T, String>,
+    set: Setter<T, String>,
+}
+
+struct SecretField<T> {
+    get: Getter<T, Vec<u8>>,
+    set: Setter<T, Vec<u8>>,
+}
+
+/// A mapping between the fields of a struct and the parts of a credential.
+pub struct RecordMapping<T> {
+    attributes: Vec<AttributeField<T>>,
+    secret: Option<SecretField<T>>,
+}
+
+impl<T> Default for RecordMapping<T> {
+    fn default() -> Self {
+        Self {
+            attributes: Vec::new(),
+            secret: None,
+        }
+    }
+}
+
+impl<T> RecordMapping<T> {
+    /// A mapping with no fields.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This mapping, with a field kept in the named attribute.
+    pub fn attribute(
+        mut self,
+        name: &str,
+        get: impl Fn(&T) -> String + Send + Sync + 'static,
+        set: impl Fn(&mut T, String) + Send + Sync + 'static,
+    ) -> Self {
+        self.attributes.push(AttributeField {
+            name: name.to_string(),
+            get: Box::new(get),
+            set: Box::new(move |record, value| {
+                set(record, value);
+                Ok(())
+            }),
+        });
+        self
+    }
+
+    /// This mapping, with a field (or fields) kept in the secret.
+    ///
+    /// The setter can fail, for example if the secret isn't in the
+    /// expected format. Without a secret field, records are written
+    /// with an empty secret.
+    pub fn secret(
+        mut self,
+        get: impl Fn(&T) -> Vec<u8> + Send + Sync + 'static,
+        set: impl Fn(&mut T, Vec<u8>) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.secret = Some(SecretField {
+            get: Box::new(get),
+            set: Box::new(set),
+        });
+        self
+    }
+
+    /// Write a record to an entry, creating its credential if necessary.
+    pub fn write(&self, entry: &Entry, record: &T) -> Result<()> {
+        let secret = match &self.secret {
+            Some(field) => (field.get)(record),
+            None => Vec::new(),
+        };
+        entry.set_secret(&secret)?;
+        if self.attributes.is_empty() {
+            return Ok(());
+        }
+        let values: Vec<(&str, String)> = self
+            .attributes
+            .iter()
+            .map(|field| (field.name.as_str(), (field.get)(record)))
+            .collect();
+        let attributes: HashMap<&str, &str> =
+            values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        entry.update_attributes(&attributes)
+    }
+
+    /// Read a record from an entry.
+    ///
+    /// Fields whose attributes are missing from the credential are
+    /// left with their default values.
+    pub fn read(&self, entry: &Entry) -> Result<T>
+    where
+        T: Default,
+    {
+        let mut record = T::default();
+        if self.secret.is_some() && !self.attributes.is_empty() {
+            // read everything at once if the entry is one of ours
+            if let Some(snapshot) = crate::cred::snapshot(entry, true) {
+                let mut snapshot = snapshot?;
+                if let Some(field) = &self.secret {
+                    (field.set)(&mut record, snapshot.secret.unwrap_or_default())?;
+                }
+                for field in self.attributes.iter() {
+                    if let Some(value) = snapshot.attributes.remove(&field.name) {
+                        (field.set)(&mut record, value)?;
+                    }
+                }
+                return Ok(record);
+            }
+        }
+        if let Some(field) = &self.secret {
+            (field.set)(&mut record, entry.get_secret()?)?;
+        }
+        if !self.attributes.is_empty() {
+            let mut attributes = entry.get_attributes()?;
+            for field in self.attributes.iter() {
+                if let Some(value) = attributes.remove(&field.name) {
+                    (field.set)(&mut record, value)?;
+                }
+            }
+        }
+        Ok(record)
+    }
+}
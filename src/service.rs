@@ -2,221 +2,2737 @@
 
 Shared secret-service access.
 
-This module provides mutex-protected shared access from credentials
-to the Secret Service. Each store holds the singleton used by its creds.
+This module provides shared access from credentials to the Secret Service.
+Each store holds the singleton used by its creds. The singleton's Secret
+Service session is owned by a worker thread, to which operations are
+submitted over an unbounded channel (so submitting never blocks) and
+which runs them one at a time.
+Callers can either wait for a submitted operation or await it.
+
+Stores configured with `read-workers` also have a pool of read workers,
+each with its own session, which share a channel of read operations.
+Reads then run concurrently with each other and with the main worker's
+operations, so (for instance) a read waiting on a slow transform doesn't
+hold up writes.
 
 */
 
 #[cfg(not(any(feature = "crypto-rust", feature = "crypto-openssl")))]
 compile_error!("You must enable one of the features crypto-rust or crypto-openssl");
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, RwLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
-use crate::errors::{decode_error, platform_failure};
-use dbus_secret_service::{EncryptionType, Item, Path, SecretService};
+use crate::backup::{ConflictPolicy, ImportSummary};
+use crate::bus::SESSION_COLLECTION_PATH;
+use crate::cache::SecretCache;
+use crate::config::{AmbiguityPolicy, Config, LockedPolicy, Operation, SCHEMA_ATTRIBUTE};
+use crate::errors::{
+    already_exists, conflict, deadline_exceeded, decode_error, in_operation, is_disconnected,
+    is_stale_path, is_transient, locked_items, missing_collection, platform_failure, timed_out,
+    would_block, would_prompt,
+};
+use crate::hooks::Hooks;
+use crate::info::{
+    Candidate, CollectionInfo, ItemInfo, ItemSnapshot, PlannedChange, Provenance, SessionAlgorithm,
+};
+use crate::localize::{English, Localization};
+use crate::mapping::{DefaultMapper, Mapper, borrow_attributes};
+use crate::metrics::Recorder;
+use crate::mirror::{Mirror, Record};
+use crate::transform::{Envelope, Pipeline};
+use dbus::blocking::Connection;
+use dbus_secret_service::{
+    Collection, EncryptionType, Error as ServiceError, Item, Path, SecretService,
+};
 use keyring_core::{Error, Result};
+use zeroize::Zeroizing;
+
+/// The content type given to the secrets of new items, unless another
+/// is asked for. Secrets that are updated keep the content type they have.
+pub(crate) const CONTENT_TYPE: &str = "application/octet-stream";
+
+thread_local! {
+    static NON_BLOCKING: Cell<bool> = const { Cell::new(false) };
+    static TIMEOUT: Cell<Option<Duration>> = const { Cell::new(None) };
+    /// The items and collections that the operation running on this thread
+    /// has unlocked, in stores configured with `relock-after-access`.
+    static UNLOCKED: RefCell<Vec<Path<'static>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Leave whatever the operation running on this thread has unlocked so far
+/// unlocked, even in stores configured with `relock-after-access`.
+fn keep_unlocked() {
+    UNLOCKED.take();
+}
+
+/// Run a function in which service operations on this thread fail with a
+/// [WouldBlock](crate::errors::StoreError::WouldBlock) error, rather than
+/// waiting, if other operations are running or waiting to run.
+pub(crate) fn without_blocking<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            NON_BLOCKING.set(self.0);
+        }
+    }
+    let _restore = Restore(NON_BLOCKING.replace(true));
+    f()
+}
+
+/// Run a function in which service operations submitted on this thread
+/// time out after the given duration, rather than the store's configured
+/// `operation-timeout`.
+pub(crate) fn with_timeout<T>(timeout: Duration, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Duration>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TIMEOUT.set(self.0);
+        }
+    }
+    let _restore = Restore(TIMEOUT.replace(Some(timeout)));
+    f()
+}
+
+/// A Secret Service session, with the bookkeeping needed to renew it.
+///
+/// A lazily connected service has no session until its first operation.
+struct Session {
+    ss: Option<SecretService>,
+    operations: u64,
+    opened: Instant,
+    generation: u64,
+}
+
+impl Session {
+    fn new(ss: Option<SecretService>) -> Self {
+        Self {
+            ss,
+            operations: 0,
+            opened: Instant::now(),
+            generation: 0,
+        }
+    }
+}
+
+/// Open a Secret Service session, falling back to a plain session if the
+/// configuration allows it and an encrypted one can't be negotiated.
+///
+/// The result includes the algorithm used and whether it was a fallback.
+pub(crate) fn negotiate(config: &Config) -> Result<(SecretService, SessionAlgorithm, bool)> {
+    let preferred = match config.plain_session {
+        true => SessionAlgorithm::Plain,
+        false => SessionAlgorithm::DhAes,
+    };
+    match connect(preferred, config) {
+        Ok(ss) => Ok((ss, preferred, false)),
+        Err(err) if config.allow_plain_fallback && preferred.is_encrypted() => {
+            match connect(SessionAlgorithm::Plain, config) {
+                Ok(ss) => Ok((ss, SessionAlgorithm::Plain, true)),
+                Err(_) => Err(err),
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Open a Secret Service session with the given algorithm.
+///
+/// If the configuration limits how long prompts may wait, so does the session.
+fn connect(algorithm: SessionAlgorithm, config: &Config) -> Result<SecretService> {
+    let encryption = match algorithm {
+        SessionAlgorithm::Plain => EncryptionType::Plain,
+        SessionAlgorithm::DhAes => EncryptionType::Dh,
+    };
+    let result = match config.prompt_timeout {
+        Some(seconds) => SecretService::connect_with_max_prompt_timeout(encryption, seconds),
+        None => SecretService::connect(encryption),
+    };
+    result.map_err(platform_failure)
+}
+
+/// An operation submitted to the worker.
+type Job = Box<dyn FnOnce(&mut Session) + Send>;
+
+/// An item to create.
+pub(crate) struct NewItem {
+    pub(crate) label: String,
+    pub(crate) attributes: HashMap<String, String>,
+    pub(crate) secret: Zeroizing<Vec<u8>>,
+    /// If set, the content type to give the secret instead of the default.
+    pub(crate) content_type: Option<String>,
+}
+
+/// A secret to set: the item's path, the secret and (if it's to change)
+/// its content type.
+pub(crate) type SecretUpdate = (Path<'static>, Zeroizing<Vec<u8>>, Option<String>);
+
+/// Run the operations submitted to a service, until the service is dropped.
+fn work(mut session: Session, jobs: Receiver<Job>) {
+    for job in jobs {
+        job(&mut session);
+    }
+}
+
+/// Run operations from a channel shared with other read workers,
+/// until the service is dropped.
+fn work_shared(mut session: Session, jobs: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = jobs
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug")
+            .recv();
+        match job {
+            Ok(job) => job(&mut session),
+            Err(_) => return,
+        }
+    }
+}
 
 pub(crate) struct Service {
-    ss: Mutex<SecretService>,
+    jobs: Sender<Job>,
+    read_jobs: Option<Sender<Job>>,
+    pending: AtomicUsize,
+    bus: Mutex<Option<Connection>>,
+    negotiated: OnceLock<(SessionAlgorithm, bool)>,
+    pub(crate) config: Config,
+    pub(crate) pipeline: Pipeline,
+    mapper: RwLock<Arc<Mapper>>,
+    localizer: RwLock<Arc<Localization>>,
+    restarted: Arc<AtomicBool>,
+    generation: AtomicU64,
+    renewals: AtomicU64,
+    errors: Mutex<HashMap<String, u64>>,
+    mirror: Option<Mirror>,
+    secrets: Option<SecretCache>,
+    plan: Mutex<Vec<PlannedChange>>,
+    pub(crate) metrics: Recorder,
+    pub(crate) hooks: Hooks,
 }
 
 impl Service {
-    pub(crate) fn new() -> Result<Self> {
-        Ok(Self {
-            ss: Mutex::new(SecretService::connect(EncryptionType::Dh).map_err(platform_failure)?),
-        })
+    /// Connect to the Secret Service.
+    ///
+    /// If a bus connection is supplied, it's used for all the direct D-Bus
+    /// calls made by the store; otherwise one is made when first needed.
+    ///
+    /// If the configuration asks for a lazy connection, the session is
+    /// opened by the first operation instead, and so are any bus watches.
+    pub(crate) fn new(config: Config, bus: Option<Connection>) -> Result<Self> {
+        let mut mapper = match &config.username_salt {
+            Some(salt) => DefaultMapper::with_hashed_username(salt),
+            None => DefaultMapper::default(),
+        };
+        if let Some(name) = &config.username_attribute {
+            mapper = mapper.with_username_attribute(name);
+        }
+        if let Some(name) = &config.service_attribute {
+            mapper = mapper.with_service_attribute(name);
+        }
+        if let Some(name) = &config.target_attribute {
+            mapper = mapper.with_target_attribute(name);
+        }
+        let negotiated = OnceLock::new();
+        let ss = match config.lazy_connect {
+            true => None,
+            false => {
+                let (ss, algorithm, downgraded) = negotiate(&config)?;
+                _ = negotiated.set((algorithm, downgraded));
+                Some(ss)
+            }
+        };
+        // dry runs write nothing, so there's nothing to mirror
+        let mirror = match (&config.mirror_file, &config.mirror_key) {
+            (Some(path), Some(key)) if !config.dry_run => Some(Mirror::open(path, key)?),
+            _ => None,
+        };
+        let (jobs, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("secret-service".to_string())
+            .spawn(move || work(Session::new(ss), receiver))
+            .map_err(|e| Error::PlatformFailure(Box::new(e)))?;
+        let read_jobs = match config.read_workers {
+            0 => None,
+            count => {
+                // read workers open their sessions when they first run
+                let (read_jobs, receiver) = mpsc::channel();
+                let receiver = Arc::new(Mutex::new(receiver));
+                for _ in 0..count {
+                    let receiver = receiver.clone();
+                    std::thread::Builder::new()
+                        .name("secret-service-reader".to_string())
+                        .spawn(move || work_shared(Session::new(None), receiver))
+                        .map_err(|e| Error::PlatformFailure(Box::new(e)))?;
+                }
+                Some(read_jobs)
+            }
+        };
+        let service = Self {
+            jobs,
+            read_jobs,
+            pending: AtomicUsize::new(0),
+            bus: Mutex::new(bus),
+            negotiated,
+            pipeline: Pipeline::default(),
+            mapper: RwLock::new(Arc::new(mapper)),
+            localizer: RwLock::new(Arc::new(English)),
+            restarted: Arc::new(AtomicBool::new(false)),
+            generation: AtomicU64::new(0),
+            renewals: AtomicU64::new(0),
+            errors: Mutex::new(HashMap::new()),
+            mirror,
+            secrets: config.secret_cache_ttl.map(SecretCache::new),
+            plan: Mutex::new(Vec::new()),
+            metrics: Recorder::default(),
+            hooks: Hooks::default(),
+            config,
+        };
+        if let Some(key) = &service.config.envelope_key {
+            service
+                .pipeline
+                .push(Arc::new(Envelope::new(key.as_bytes())));
+        }
+        if service.config.rebind_on_restart && !service.config.lazy_connect {
+            service.watch_owner()?;
+        }
+        Ok(service)
     }
 
-    pub(crate) fn find_matching_items(
-        &self,
-        attributes: &HashMap<&str, &str>,
-    ) -> Result<Vec<Path<'static>>> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let search = ss.search_items(attributes.clone()).map_err(decode_error)?;
-        if !search.locked.is_empty() {
-            let item_refs: Vec<&Item> = search.locked.iter().collect();
-            ss.unlock_all(item_refs.as_slice()).map_err(decode_error)?;
+    /// Watch for restarts of the Secret Service daemon.
+    fn watch_owner(&self) -> Result<()> {
+        let restarted = self.restarted.clone();
+        self.with_bus(|conn| crate::bus::watch_owner(conn, restarted))
+    }
+
+    /// The algorithm of the session, and whether it was a fallback.
+    ///
+    /// A lazily connected service that hasn't connected yet does so now.
+    /// If that fails, the configured algorithm is reported.
+    pub(crate) fn negotiated(self: &Arc<Self>) -> (SessionAlgorithm, bool) {
+        if self.negotiated.get().is_none() {
+            _ = self.submit(Operation::Read, |_, _| Ok(())).wait();
+        }
+        match self.negotiated.get() {
+            Some(negotiated) => *negotiated,
+            None if self.config.plain_session => (SessionAlgorithm::Plain, false),
+            None => (SessionAlgorithm::DhAes, false),
         }
-        let results = search
-            .unlocked
-            .iter()
-            .chain(search.locked.iter())
-            .map(|i| i.path.clone())
-            .collect();
-        Ok(results)
     }
 
-    pub(crate) fn create_item(
-        &self,
-        collection: &str,
-        label: &str,
-        attributes: HashMap<&str, &str>,
-        secret: &[u8],
-    ) -> Result<()> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let collection = match util::get_collection(&ss, collection) {
-            Ok(c) => c,
-            Err(Error::NoEntry) => util::create_collection(&ss, collection)?,
-            Err(e) => return Err(e),
+    /// Submit an operation to the worker (or, for reads, to a read worker
+    /// if there are any). In stores configured with `relock-after-access`,
+    /// reads unlock what they read, which may prompt, so they run on the
+    /// worker too.
+    ///
+    /// The result is a [Pending] operation, which can either be waited
+    /// for or awaited. Inside [without_blocking], the operation fails
+    /// rather than being submitted if other operations are running or
+    /// waiting to run.
+    ///
+    /// Before the operation runs, the session is renewed if it's due, and
+    /// any latency configured for the operation is simulated. Once the
+    /// operation has run, anything it unlocked is locked again (in stores
+    /// configured with `relock-after-access`), and if it creates, writes
+    /// or deletes, the secret cache (if any) is flushed.
+    fn submit<T: Send + 'static>(
+        self: &Arc<Self>,
+        operation: Operation,
+        f: impl Fn(&Service, &SecretService) -> Result<T> + Send + 'static,
+    ) -> Pending<T> {
+        let reader = operation == Operation::Read && !self.config.relock_after_access;
+        self.submit_to(operation, reader, f)
+    }
+
+    /// Submit an operation to the worker, even if it only reads.
+    ///
+    /// Operations that may prompt the user to unlock items run on the
+    /// worker, so that prompts are shown one at a time and unlocks don't
+    /// race with writes.
+    fn submit_on_worker<T: Send + 'static>(
+        self: &Arc<Self>,
+        operation: Operation,
+        f: impl Fn(&Service, &SecretService) -> Result<T> + Send + 'static,
+    ) -> Pending<T> {
+        self.submit_to(operation, false, f)
+    }
+
+    /// Submit an operation to the read workers (if `reader` and there are
+    /// any) or to the worker.
+    fn submit_to<T: Send + 'static>(
+        self: &Arc<Self>,
+        operation: Operation,
+        reader: bool,
+        f: impl Fn(&Service, &SecretService) -> Result<T> + Send + 'static,
+    ) -> Pending<T> {
+        if NON_BLOCKING.get() {
+            if self
+                .pending
+                .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                return Pending::failed(would_block());
+            }
+        } else {
+            self.pending.fetch_add(1, Ordering::AcqRel);
+        }
+        let shared: Arc<Shared<T>> = Arc::new(Shared {
+            claimed: AtomicBool::new(false),
+            slot: Mutex::new(Slot {
+                result: None,
+                waker: None,
+            }),
+            ready: Condvar::new(),
+        });
+        let job = self.job(operation, reader, f, shared.clone(), Instant::now(), 0);
+        self.enqueue(reader, job);
+        Pending(State::Running {
+            service: self.clone(),
+            shared,
+            timeout: TIMEOUT.get().or(self.config.timeout),
+            since: None,
+            timer: None,
+        })
+    }
+
+    /// Make the job that runs an operation, after `retries` earlier
+    /// attempts have failed transiently.
+    ///
+    /// If the operation fails transiently again, and it's one that can be
+    /// retried, the job queues its next attempt to run after the backoff
+    /// (leaving the worker to run other operations meanwhile) rather than
+    /// completing the operation.
+    fn job<T: Send + 'static>(
+        self: &Arc<Self>,
+        operation: Operation,
+        reader: bool,
+        f: impl Fn(&Service, &SecretService) -> Result<T> + Send + 'static,
+        shared: Arc<Shared<T>>,
+        submitted: Instant,
+        retries: u32,
+    ) -> Job {
+        let this = self.clone();
+        Box::new(move |session: &mut Session| {
+            if retries == 0 && shared.claimed.swap(true, Ordering::AcqRel) {
+                return; // cancelled by the caller
+            }
+            let attempt = |session: &mut Session| {
+                this.prepare(session, operation)?;
+                let ss = session
+                    .ss
+                    .as_ref()
+                    .expect("Session failure in credential store: please report a bug");
+                let result = f(&this, ss);
+                this.relock(ss);
+                result
+            };
+            let reconnecting = |session: &mut Session| match attempt(session) {
+                Err(err) if is_disconnected(&err) && session.ss.is_some() => {
+                    this.reconnect(session)?;
+                    attempt(session)
+                }
+                result => result,
+            };
+            let result = catch_unwind(AssertUnwindSafe(|| reconnecting(session)));
+            let retry = &this.config.retry;
+            if let Ok(Err(err)) = &result {
+                if operation.is_idempotent()
+                    && is_transient(err)
+                    && u64::from(retries) < retry.attempts
+                {
+                    let backoff = retry.backoff.saturating_mul(1 << retries.min(16));
+                    let next = this.job(operation, reader, f, shared, submitted, retries + 1);
+                    this.enqueue_after(reader, next, backoff);
+                    return;
+                }
+            }
+            let result = result.map(|r| r.map_err(|err| in_operation(err, operation.name())));
+            let failed = !matches!(result, Ok(Ok(_)));
+            this.metrics.record(operation, submitted.elapsed(), failed);
+            if matches!(
+                operation,
+                Operation::Create | Operation::Write | Operation::Delete
+            ) {
+                this.flush_cache();
+            }
+            this.pending.fetch_sub(1, Ordering::AcqRel);
+            shared.complete(result);
+        })
+    }
+
+    /// Queue a job for the read workers (if `reader` and there are any)
+    /// or for the worker.
+    ///
+    /// The queues are unbounded, so this never blocks, even when the
+    /// workers are stuck: callers are freed by their timeouts instead.
+    fn enqueue(&self, reader: bool, job: Job) {
+        let queue = match (reader, &self.read_jobs) {
+            (true, Some(read_jobs)) => read_jobs,
+            _ => &self.jobs,
         };
-        collection
-            .create_item(
-                label,
-                attributes,
-                secret,
-                true, // replace
-                "application/octet-stream",
-            )
-            .map_err(platform_failure)?;
+        if queue.send(job).is_err() {
+            panic!("Worker failure in credential store: please report a bug");
+        }
+    }
+
+    /// Queue a job once a delay has passed, waiting on a thread of its own.
+    fn enqueue_after(self: &Arc<Self>, reader: bool, job: Job, delay: Duration) {
+        let this = self.clone();
+        let waiting = std::thread::Builder::new()
+            .name("secret-service-retry".to_string())
+            .spawn(move || {
+                std::thread::sleep(delay);
+                this.enqueue(reader, job);
+            });
+        if waiting.is_err() {
+            panic!("Worker failure in credential store: please report a bug");
+        }
+    }
+
+    /// Count a failed operation's error, or resume its panic.
+    fn finish<T>(&self, result: std::thread::Result<Result<T>>) -> Result<T> {
+        match result {
+            Ok(result) => {
+                if let Err(err) = &result {
+                    self.count_error(err);
+                }
+                result
+            }
+            Err(panic) => resume_unwind(panic),
+        }
+    }
+
+    /// Get the session ready for an operation, opening it if necessary.
+    fn prepare(&self, session: &mut Session, operation: Operation) -> Result<()> {
+        if session.ss.is_none() {
+            if let Some((algorithm, _)) = self.negotiated.get() {
+                // another worker has connected, so use its algorithm
+                session.ss = Some(connect(*algorithm, &self.config)?);
+            } else {
+                let (ss, algorithm, downgraded) = negotiate(&self.config)?;
+                if self.config.rebind_on_restart {
+                    self.watch_owner()?;
+                }
+                _ = self.negotiated.set((algorithm, downgraded));
+                session.ss = Some(ss);
+            }
+            session.opened = Instant::now();
+            session.generation = self.generation();
+        }
+        if self.config.rebind_on_restart {
+            self.with_bus(crate::bus::dispatch)?;
+        }
+        self.renew_if_due(session)?;
+        self.config.latency.simulate(operation);
         Ok(())
     }
 
-    pub(crate) fn delete_collection(&self, collection: &str) -> Result<()> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        if collection.eq("default") {
-            return Err(Error::NotSupportedByStore(
-                "You cannot delete the default collection".to_string(),
-            ));
+    /// Count an operation on the session, first renewing it if the
+    /// configured number of operations or time has been reached,
+    /// or if the Secret Service daemon has restarted.
+    ///
+    /// A restart also starts a new generation of item paths, since
+    /// paths from the old daemon are meaningless to the new one.
+    fn renew_if_due(&self, session: &mut Session) -> Result<()> {
+        let rekey = &self.config.rekey;
+        if self.restarted.swap(false, Ordering::AcqRel) {
+            self.generation.fetch_add(1, Ordering::AcqRel);
         }
-        match util::get_collection(&ss, collection) {
-            Ok(c) => c.delete().map_err(decode_error),
-            Err(e) => Err(e),
+        // other workers' sessions are renewed when they next run
+        let due = session.generation != self.generation()
+            || rekey.operations.is_some_and(|n| session.operations >= n)
+            || rekey
+                .interval
+                .is_some_and(|d| session.opened.elapsed() >= d);
+        if due {
+            let (algorithm, _) = self
+                .negotiated
+                .get()
+                .expect("Session failure in credential store: please report a bug");
+            session.ss = Some(connect(*algorithm, &self.config)?);
+            session.operations = 0;
+            session.opened = Instant::now();
+            session.generation = self.generation();
+            self.renewals.fetch_add(1, Ordering::AcqRel);
         }
+        session.operations += 1;
+        Ok(())
     }
 
-    /// Given an item's path, ensure it exists and is unlocked
-    pub(crate) fn ensure_unlocked(&self, path: &Path<'static>) -> Result<()> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        item.ensure_unlocked().map_err(decode_error)
+    /// Replace a session whose connection has been lost.
+    ///
+    /// Like a daemon restart, this starts a new generation of item paths.
+    /// If the direct bus connection has been lost too, it's replaced
+    /// when next needed.
+    fn reconnect(&self, session: &mut Session) -> Result<()> {
+        let (algorithm, _) = self
+            .negotiated
+            .get()
+            .expect("Session failure in credential store: please report a bug");
+        session.ss = Some(connect(*algorithm, &self.config)?);
+        session.operations = 0;
+        session.opened = Instant::now();
+        session.generation = self.generation.fetch_add(1, Ordering::AcqRel) + 1;
+        self.renewals.fetch_add(1, Ordering::AcqRel);
+        let lost = {
+            let mut bus = self
+                .bus
+                .lock()
+                .expect("Mutex failure in credential store: please report a bug");
+            let lost = bus.as_ref().is_some_and(|c| !c.channel().is_connected());
+            if lost {
+                *bus = None;
+            }
+            lost
+        };
+        if lost && self.config.rebind_on_restart {
+            self.watch_owner()?;
+        }
+        Ok(())
     }
 
-    /// Given an item's path, set its secret.
-    pub(crate) fn set_secret(&self, path: &Path<'static>, secret: &[u8]) -> Result<()> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        item.set_secret(secret, "text/plain").map_err(decode_error)
+    /// The generation of item paths, which changes when the daemon restarts.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
     }
 
-    /// Given an existing item's path, retrieve its secret.
-    pub(crate) fn get_secret(&self, path: &Path<'static>) -> Result<Vec<u8>> {
-        let ss = self
-            .ss
+    /// Count a failed operation, by kind of error.
+    fn count_error(&self, err: &Error) {
+        let kind = match err {
+            Error::PlatformFailure(_) => "PlatformFailure",
+            Error::NoStorageAccess(_) => "NoStorageAccess",
+            Error::NoEntry => "NoEntry",
+            Error::BadEncoding(_) => "BadEncoding",
+            Error::BadDataFormat(_, _) => "BadDataFormat",
+            Error::BadStoreFormat(_) => "BadStoreFormat",
+            Error::Invalid(_, _) => "Invalid",
+            Error::Ambiguous(_) => "Ambiguous",
+            _ => "Other",
+        };
+        *self
+            .errors
             .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        let secret = item.get_secret().map_err(decode_error)?;
-        Ok(secret)
+            .expect("Mutex failure in credential store: please report a bug")
+            .entry(kind.to_string())
+            .or_default() += 1;
     }
 
-    /// Given an existing item's path, retrieve its attributes.
-    pub(crate) fn get_attributes(&self, path: &Path<'static>) -> Result<HashMap<String, String>> {
-        let ss = self
-            .ss
+    /// The number of failed operations, by kind of error.
+    pub(crate) fn error_counts(&self) -> HashMap<String, u64> {
+        self.errors
             .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        let attributes = item.get_attributes().map_err(decode_error)?;
-        Ok(attributes)
+            .expect("Mutex failure in credential store: please report a bug")
+            .clone()
     }
 
-    /// Given an existing item's path, update its attributes.
-    pub(crate) fn update_attributes(
-        &self,
-        path: &Path<'static>,
-        attributes: &HashMap<&str, &str>,
-    ) -> Result<()> {
-        let ss = self
-            .ss
+    /// The number of times the session has been renewed.
+    pub(crate) fn renewals(&self) -> u64 {
+        self.renewals.load(Ordering::Acquire)
+    }
+
+    /// The secret cache, in stores configured with `secret-cache-ttl`.
+    pub(crate) fn secret_cache(&self) -> Option<&SecretCache> {
+        self.secrets.as_ref()
+    }
+
+    /// Forget any cached secrets.
+    pub(crate) fn flush_cache(&self) {
+        if let Some(cache) = &self.secrets {
+            cache.flush();
+        }
+    }
+
+    /// Run a function with this service's direct bus connection.
+    pub(crate) fn with_bus<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let mut bus = self
+            .bus
             .lock()
             .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        let existing = item.get_attributes().map_err(decode_error)?;
-        let mut updated: HashMap<&str, &str> = HashMap::new();
-        for (k, v) in existing.iter() {
-            updated.insert(k, v);
+        if bus.is_none() {
+            *bus = Some(crate::bus::connect()?);
         }
-        for (k, v) in attributes.iter() {
-            updated.insert(k, v);
+        f(bus.as_ref().unwrap())
+    }
+
+    /// The attribute mapper used by credentials of this service.
+    pub(crate) fn mapper(&self) -> Arc<Mapper> {
+        self.mapper
+            .read()
+            .expect("RwLock failure in credential store: please report a bug")
+            .clone()
+    }
+
+    /// Whether the named attribute is controlled by this store: either by
+    /// its mapper or (in stores configured with `xdg-schema`) as the schema.
+    pub(crate) fn is_controlled(&self, attribute: &str) -> bool {
+        self.mapper().is_controlled(attribute)
+            || (attribute == SCHEMA_ATTRIBUTE && self.config.xdg_schema.is_some())
+    }
+
+    /// Whether an item's attributes are ones this store's credentials give
+    /// their items: its mapper recognizes them and (in stores configured
+    /// with `xdg-schema`) they have the store's schema.
+    pub(crate) fn is_own_item(&self, attributes: &HashMap<String, String>) -> bool {
+        if let Some(schema) = &self.config.xdg_schema {
+            if attributes.get(SCHEMA_ATTRIBUTE) != Some(schema) {
+                return false;
+            }
         }
-        item.set_attributes(updated).map_err(decode_error)?;
-        Ok(())
+        self.mapper().specifiers(attributes).is_some()
     }
 
-    // Given an existing item's path, delete it.
-    pub(crate) fn delete(&self, path: &Path<'static>) -> Result<()> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        item.delete().map_err(decode_error)
+    pub(crate) fn set_mapper(&self, mapper: Arc<Mapper>) {
+        *self
+            .mapper
+            .write()
+            .expect("RwLock failure in credential store: please report a bug") = mapper;
     }
 
-    // Given an existing item's path, return its label.
-    pub(crate) fn get_label(&self, path: &Path<'static>) -> Result<String> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        let label = item.get_label().map_err(decode_error)?;
-        Ok(label)
+    /// The localizer used for text generated by this service's credentials.
+    pub(crate) fn localizer(&self) -> Arc<Localization> {
+        self.localizer
+            .read()
+            .expect("RwLock failure in credential store: please report a bug")
+            .clone()
     }
 
-    // Given an existing item's path, set its label.
-    pub(crate) fn set_label(&self, path: &Path<'static>, label: &str) -> Result<()> {
-        let ss = self
-            .ss
-            .lock()
-            .expect("Mutex failure in credential store: please report a bug");
-        let item = Item::new(&ss, path.clone());
-        item.set_label(label).map_err(decode_error)
+    pub(crate) fn set_localizer(&self, localizer: Arc<Localization>) {
+        *self
+            .localizer
+            .write()
+            .expect("RwLock failure in credential store: please report a bug") = localizer;
     }
-}
 
-/// Secret Service utilities: this module is private because these can't
-/// be called except from the methods of the Service struct which has
-/// made the service singleton available.
-mod util {
-    use super::{Error, Result, decode_error};
+    /// Count the collections in the Secret Service.
+    pub(crate) fn collection_count(self: &Arc<Self>) -> Pending<usize> {
+        self.submit(Operation::Read, |_, ss| {
+            let collections = ss.get_all_collections().map_err(decode_error)?;
+            Ok(collections.len())
+        })
+    }
 
-    use dbus_secret_service::{Collection, SecretService};
+    /// Describe the collections in the Secret Service.
+    pub(crate) fn collection_infos(self: &Arc<Self>) -> Pending<Vec<CollectionInfo>> {
+        self.submit(Operation::Read, |_, ss| {
+            let mut aliases: Vec<(String, String)> = Vec::new();
+            for alias in ["default", "session", "login"] {
+                if let Ok(c) = ss.get_collection_by_alias(alias) {
+                    aliases.push((alias.to_string(), c.path.to_string()));
+                }
+            }
+            let mut result = Vec::new();
+            for c in ss.get_all_collections().map_err(decode_error)? {
+                let path = c.path.to_string();
+                result.push(CollectionInfo {
+                    label: c.get_label().map_err(decode_error)?,
+                    locked: c.is_locked().map_err(decode_error)?,
+                    aliases: aliases
+                        .iter()
+                        .filter(|(_, p)| *p == path)
+                        .map(|(a, _)| a.clone())
+                        .collect(),
+                    path,
+                });
+            }
+            Ok(result)
+        })
+    }
 
-    /// Find the secret service collection whose label is the given name.
+    /// Describe the items in every collection.
     ///
-    /// The name `default` is treated specially and is interpreted as naming
-    /// the default collection regardless of its label (which might be different).
-    pub(crate) fn get_collection<'a>(ss: &'a SecretService, name: &str) -> Result<Collection<'a>> {
-        let collection = if name.eq("default") {
-            ss.get_default_collection().map_err(decode_error)?
-        } else {
-            let all = ss.get_all_collections().map_err(decode_error)?;
-            let found = all
-                .into_iter()
-                .find(|c| c.get_label().map(|l| l.eq(name)).unwrap_or(false));
-            found.ok_or(Error::NoEntry)?
+    /// Labels and attributes can be read from locked items,
+    /// so this never prompts the user.
+    pub(crate) fn item_infos(self: &Arc<Self>) -> Pending<Vec<ItemInfo>> {
+        self.submit(Operation::Search, |_, ss| {
+            let mut items = Vec::new();
+            for collection in ss.get_all_collections().map_err(decode_error)? {
+                util::describe_items(&collection, &mut items)?;
+            }
+            Ok(items)
+        })
+    }
+
+    /// Read every item in the named collection, as it is stored, for a backup.
+    ///
+    /// The collection is unlocked if need be.
+    pub(crate) fn export_items(self: &Arc<Self>, name: &str) -> Pending<Vec<Record>> {
+        let name = name.to_string();
+        self.submit_on_worker(Operation::Read, move |this, ss| {
+            let collection = util::find_collection(this, ss, &name)?;
+            util::ensure_unlocked(this, ss, &collection)?;
+            let items = collection.get_all_items().map_err(decode_error)?;
+            for looked_up in this.unlock_together(ss, &items)? {
+                looked_up?;
+            }
+            let mut records = Vec::new();
+            for item in items {
+                records.push(Record {
+                    path: item.path.to_string(),
+                    deleted: false,
+                    collection: util::collection_name(ss, &item.path)?,
+                    label: item.get_label().map_err(decode_error)?,
+                    attributes: item.get_attributes().map_err(decode_error)?,
+                    secret: item.get_secret().map_err(decode_error)?,
+                    content_type: item.get_secret_content_type().map_err(decode_error)?,
+                });
+            }
+            Ok(records)
+        })
+    }
+
+    /// Describe the items in the named collection.
+    ///
+    /// Like [item_infos](Service::item_infos), this never prompts.
+    pub(crate) fn collection_item_infos(self: &Arc<Self>, name: &str) -> Pending<Vec<ItemInfo>> {
+        let name = name.to_string();
+        self.submit(Operation::Search, move |this, ss| {
+            let collection = util::find_collection(this, ss, &name)?;
+            let mut items = Vec::new();
+            util::describe_items(&collection, &mut items)?;
+            Ok(items)
+        })
+    }
+
+    pub(crate) fn find_matching_items(
+        self: &Arc<Self>,
+        attributes: &HashMap<&str, &str>,
+    ) -> Pending<Vec<Path<'static>>> {
+        let concealed = self.config.opaque.conceal(attributes);
+        self.search(None, concealed, true)
+    }
+
+    /// Like [find_matching_items](Service::find_matching_items), but only
+    /// finding items in the named collection (if there is one), and leaving
+    /// locked items locked unless `unlock`, so that a batch operation can
+    /// unlock all of its items together.
+    pub(crate) fn find_items(
+        self: &Arc<Self>,
+        scope: Option<&str>,
+        attributes: &HashMap<&str, &str>,
+        unlock: bool,
+    ) -> Pending<Vec<Path<'static>>> {
+        let concealed = self.config.opaque.conceal(attributes);
+        self.search(scope.map(|s| s.to_string()), concealed, unlock)
+    }
+
+    /// Find the items with the given attributes, as they are stored.
+    ///
+    /// Unlike [find_matching_items](Service::find_matching_items), this
+    /// doesn't conceal the values of opaque attributes.
+    pub(crate) fn find_stored_items(
+        self: &Arc<Self>,
+        attributes: &HashMap<&str, &str>,
+    ) -> Pending<Vec<Path<'static>>> {
+        self.search(None, owned_attributes(attributes), true)
+    }
+
+    /// Find the items with the given attributes (in the given collection,
+    /// if there is one), dealing with locked items as configured (except
+    /// that they're only unlocked if `unlock`).
+    fn search(
+        self: &Arc<Self>,
+        scope: Option<String>,
+        attributes: HashMap<String, String>,
+        unlock: bool,
+    ) -> Pending<Vec<Path<'static>>> {
+        self.submit(Operation::Search, move |this, ss| {
+            let (unlocked, locked) = util::search_items(this, ss, scope.as_deref(), &attributes)?;
+            match this.config.locked_items {
+                LockedPolicy::Unlock => {}
+                LockedPolicy::Skip => {
+                    return Ok(unlocked.iter().map(|i| i.path.clone()).collect());
+                }
+                LockedPolicy::Error if locked.is_empty() => {}
+                LockedPolicy::Error => return Err(locked_items(locked.len())),
+            }
+            // stores that relock items leave unlocking to the operations that access them
+            if unlock && !locked.is_empty() && !this.config.relock_after_access {
+                let paths: Vec<_> = locked.iter().map(|i| i.path.clone()).collect();
+                this.config.latency.simulate(Operation::Unlock);
+                this.unlock(ss, &paths)?;
+            }
+            let results = unlocked
+                .iter()
+                .chain(locked.iter())
+                .map(|i| i.path.clone())
+                .collect();
+            Ok(results)
+        })
+    }
+
+    /// Describe the several items found for an entry.
+    ///
+    /// Like [item_infos](Service::item_infos), this never prompts. It runs
+    /// on the worker, though, since it's part of finding an entry's item,
+    /// which may have prompted.
+    pub(crate) fn describe_candidates(
+        self: &Arc<Self>,
+        paths: &[Path<'static>],
+    ) -> Pending<Vec<Candidate>> {
+        let paths = paths.to_vec();
+        self.submit_on_worker(Operation::Read, move |_, ss| {
+            let mut candidates = Vec::with_capacity(paths.len());
+            for path in paths.iter() {
+                let item = Item::new(ss, path.clone());
+                let collection = util::parent_collection(ss, path)?;
+                candidates.push(Candidate {
+                    path: path.to_string(),
+                    collection: collection.path.to_string(),
+                    collection_label: collection.get_label().map_err(decode_error)?,
+                    label: item.get_label().map_err(decode_error)?,
+                    attributes: item.get_attributes().map_err(decode_error)?,
+                    modified: item.get_modified().map_err(decode_error)?,
+                });
+            }
+            Ok(candidates)
+        })
+    }
+
+    /// Narrow down the several items found for an entry, as configured
+    /// by `ambiguous-items`: to the most recently modified one (with ties
+    /// broken by path), or to those in the entry's collection (if any are).
+    pub(crate) fn choose_items(
+        self: &Arc<Self>,
+        paths: Vec<Path<'static>>,
+        collection: &str,
+    ) -> Pending<Vec<Path<'static>>> {
+        let name = collection.to_string();
+        self.submit(Operation::Search, move |this, ss| {
+            match this.config.ambiguous_items {
+                AmbiguityPolicy::Error => Ok(paths.clone()),
+                AmbiguityPolicy::Newest => {
+                    let mut dated = Vec::with_capacity(paths.len());
+                    for path in paths.iter() {
+                        let item = Item::new(ss, path.clone());
+                        dated.push((item.get_modified().map_err(decode_error)?, path.clone()));
+                    }
+                    dated.sort_by(|a, b| (a.0, &*a.1).cmp(&(b.0, &*b.1)));
+                    Ok(dated.pop().map(|(_, path)| path).into_iter().collect())
+                }
+                AmbiguityPolicy::Target => {
+                    let collection = match util::find_collection(this, ss, &name) {
+                        Ok(collection) => collection,
+                        Err(Error::NoEntry) => return Ok(paths.clone()),
+                        Err(err) => return Err(err),
+                    };
+                    let inside: Vec<_> = paths
+                        .iter()
+                        .filter(|p| {
+                            util::parent_collection(ss, p)
+                                .map(|c| c.path == collection.path)
+                                .unwrap_or(false)
+                        })
+                        .cloned()
+                        .collect();
+                    Ok(if inside.is_empty() {
+                        paths.clone()
+                    } else {
+                        inside
+                    })
+                }
+            }
+        })
+    }
+
+    /// Find the items with the given attributes (in the given collection,
+    /// if there is one), without unlocking any.
+    pub(crate) fn peek_matching_items(
+        self: &Arc<Self>,
+        scope: Option<&str>,
+        attributes: &HashMap<&str, &str>,
+    ) -> Pending<Vec<Path<'static>>> {
+        let attributes = self.config.opaque.conceal(attributes);
+        let scope = scope.map(|s| s.to_string());
+        self.submit(Operation::Search, move |this, ss| {
+            let (unlocked, locked) = util::search_items(this, ss, scope.as_deref(), &attributes)?;
+            let results = unlocked
+                .iter()
+                .chain(locked.iter())
+                .map(|i| i.path.clone())
+                .collect();
+            Ok(results)
+        })
+    }
+
+    pub(crate) fn create_item(
+        self: &Arc<Self>,
+        collection: &str,
+        label: &str,
+        attributes: HashMap<&str, &str>,
+        secret: &[u8],
+        content_type: Option<&str>,
+    ) -> Pending<Path<'static>> {
+        let provenance = match self.config.provenance {
+            true => Provenance::current().to_attributes(),
+            false => HashMap::new(),
         };
-        if collection.is_locked().map_err(decode_error)? {
-            collection.unlock().map_err(decode_error)?;
+        let item = NewItem {
+            label: label.to_string(),
+            attributes: match self.item_attributes(attributes, &provenance) {
+                Ok(attributes) => attributes,
+                Err(err) => return Pending::failed(err),
+            },
+            secret: Zeroizing::new(secret.to_vec()),
+            content_type: content_type.map(|t| t.to_string()),
+        };
+        let name = collection.to_string();
+        self.submit(Operation::Create, move |this, ss| {
+            let collection = this.target_collection(ss, &name)?;
+            this.create_in(ss, &collection, &name, &item)
+        })
+    }
+
+    /// Create several items in a named collection, which is only looked up
+    /// (and, if need be, created) once.
+    ///
+    /// The result has the path of each created item, or the error creating it. If any
+    /// item's attributes are invalid, nothing is created.
+    pub(crate) fn create_items(
+        self: &Arc<Self>,
+        collection: &str,
+        items: Vec<NewItem>,
+    ) -> Pending<Vec<Result<Path<'static>>>> {
+        let provenance = match self.config.provenance {
+            true => Provenance::current().to_attributes(),
+            false => HashMap::new(),
+        };
+        let mut prepared = Vec::with_capacity(items.len());
+        for mut item in items {
+            match self.item_attributes(borrow_attributes(&item.attributes), &provenance) {
+                Ok(attributes) => item.attributes = attributes,
+                Err(err) => return Pending::failed(err),
+            }
+            prepared.push(item);
         }
-        Ok(collection)
+        let name = collection.to_string();
+        self.submit(Operation::Create, move |this, ss| {
+            let collection = this.target_collection(ss, &name)?;
+            Ok(prepared
+                .iter()
+                .map(|item| this.create_in(ss, &collection, &name, item))
+                .collect())
+        })
+    }
+
+    /// The attributes to give a new item: the store's default attributes,
+    /// any provenance, and the given ones, checked against the schema and
+    /// with opaque values concealed.
+    fn item_attributes(
+        &self,
+        attributes: HashMap<&str, &str>,
+        provenance: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        let mut all: HashMap<&str, &str> = HashMap::new();
+        for (k, v) in self
+            .config
+            .default_attributes
+            .iter()
+            .chain(provenance.iter())
+        {
+            all.insert(k, v);
+        }
+        all.extend(attributes);
+        self.config.schema.validate(&all, &all)?;
+        Ok(self.config.opaque.conceal(&all))
+    }
+
+    /// The named collection that items are to be created in, which is
+    /// created if it doesn't exist (unless the store has `explicit-collections`).
+    fn target_collection<'a>(&self, ss: &'a SecretService, name: &str) -> Result<Collection<'a>> {
+        match util::get_collection(self, ss, name) {
+            Ok(c) => Ok(c),
+            Err(Error::NoEntry) if self.config.explicit_collections => {
+                Err(missing_collection(name))
+            }
+            Err(Error::NoEntry)
+                if self.applies(|| PlannedChange::CreateCollection {
+                    name: name.to_string(),
+                }) =>
+            {
+                util::create_collection(ss, name)
+            }
+            Err(Error::NoEntry) => Ok(util::planned_collection(ss)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether to make a change to the Secret Service. Stores configured
+    /// with `dry-run` add the change to their plan instead.
+    fn applies(&self, change: impl FnOnce() -> PlannedChange) -> bool {
+        if !self.config.dry_run {
+            return true;
+        }
+        self.plan
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug")
+            .push(change());
+        false
+    }
+
+    /// The changes that this (dry-run) store would have made since the
+    /// plan was last taken, in the order they would have been made.
+    pub(crate) fn take_plan(&self) -> Vec<PlannedChange> {
+        std::mem::take(
+            &mut *self
+                .plan
+                .lock()
+                .expect("Mutex failure in credential store: please report a bug"),
+        )
+    }
+
+    /// Create an item in a collection found by [target_collection](Service::target_collection).
+    ///
+    /// The item's attributes must have been prepared by [item_attributes](Service::item_attributes).
+    fn create_in(
+        &self,
+        ss: &SecretService,
+        collection: &Collection,
+        name: &str,
+        item: &NewItem,
+    ) -> Result<Path<'static>> {
+        let secret = self.pipeline.encode(&item.secret)?;
+        // a collection that's only planned has no items to search or evict
+        let planned = util::is_planned(collection);
+        if self.config.create_if_absent && !planned {
+            let existing = collection
+                .search_items(borrow_attributes(&item.attributes))
+                .map_err(decode_error)?;
+            if let Some(existing) = existing.first() {
+                return Err(already_exists(&existing.path));
+            }
+        }
+        if let Some(max) = self.config.max_items_per_target {
+            if name != "default" && !planned {
+                let evicted = util::enforce_quota(self, collection, name, max)?;
+                for path in evicted {
+                    self.mirror_deletion(&path)?;
+                }
+            }
+        }
+        let change = || PlannedChange::CreateItem {
+            collection: name.to_string(),
+            label: item.label.clone(),
+            attributes: item.attributes.clone(),
+        };
+        if !self.applies(change) {
+            return Ok(util::planned_item(collection));
+        }
+        let created = collection
+            .create_item(
+                &item.label,
+                borrow_attributes(&item.attributes),
+                &secret,
+                !self.config.create_if_absent, // replace
+                item.content_type.as_deref().unwrap_or(CONTENT_TYPE),
+            )
+            .map_err(platform_failure)?;
+        self.mirror_item(ss, &created.path)?;
+        Ok(created.path)
+    }
+
+    /// Given an existing item's path, move it to the named collection,
+    /// which is created if need be (as it is for new items).
+    ///
+    /// The item is recreated there with the same label, attributes, and
+    /// secret, and the original is deleted. Returns the moved item's path.
+    pub(crate) fn move_item(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        collection: &str,
+    ) -> Pending<Path<'static>> {
+        let path = path.clone();
+        let name = collection.to_string();
+        self.submit(Operation::Create, move |this, ss| {
+            let destination = this.target_collection(ss, &name)?;
+            if util::parent_collection(ss, &path)?.path == destination.path {
+                return Ok(path.clone());
+            }
+            this.relocking(ss, &path, || {
+                let created = this.copy_into(ss, &path, &destination, &name)?;
+                if this.applies(|| PlannedChange::DeleteItem {
+                    path: path.to_string(),
+                }) {
+                    util::delete(this, &Item::new(ss, path.clone()))?;
+                    this.mirror_deletion(&path)?;
+                }
+                Ok(created)
+            })
+        })
+    }
+
+    /// Given an existing item's path, copy it to the named collection,
+    /// which is created if need be (as it is for new items).
+    ///
+    /// The copy has the same label, attributes, and secret. Returns the
+    /// copy's path. An item can't be copied to its own collection.
+    pub(crate) fn copy_item(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        collection: &str,
+    ) -> Pending<Path<'static>> {
+        let path = path.clone();
+        let name = collection.to_string();
+        self.submit(Operation::Create, move |this, ss| {
+            let destination = this.target_collection(ss, &name)?;
+            if util::parent_collection(ss, &path)?.path == destination.path {
+                return Err(Error::Invalid(
+                    "target".to_string(),
+                    "already holds the item".to_string(),
+                ));
+            }
+            this.relocking(ss, &path, || this.copy_into(ss, &path, &destination, &name))
+        })
+    }
+
+    /// Create a copy of an item in a named collection.
+    fn copy_into(
+        &self,
+        ss: &SecretService,
+        path: &Path<'static>,
+        destination: &Collection,
+        name: &str,
+    ) -> Result<Path<'static>> {
+        let item = Item::new(ss, path.clone());
+        let secret = item.get_secret().map_err(decode_error)?;
+        let copy = NewItem {
+            label: item.get_label().map_err(decode_error)?,
+            attributes: item.get_attributes().map_err(decode_error)?,
+            secret: Zeroizing::new(self.pipeline.decode(secret)?),
+            content_type: Some(item.get_secret_content_type().map_err(decode_error)?),
+        };
+        self.create_in(ss, destination, name, &copy)
+    }
+
+    /// Resolve items that were created concurrently for the same credential.
+    ///
+    /// The newest of the items (by creation time, with ties broken by path)
+    /// is kept, any attributes it lacks are copied to it from the others,
+    /// and the others are deleted. Since the choice doesn't depend on which
+    /// process is resolving, racing processes all keep the same item. Items
+    /// that another process has already deleted are skipped, but any other
+    /// failure to delete one fails the resolution (once the rest have been
+    /// deleted).
+    pub(crate) fn resolve_duplicates(self: &Arc<Self>, paths: &[Path<'static>]) -> Pending<()> {
+        let paths = paths.to_vec();
+        self.submit(Operation::Write, move |this, ss| {
+            let mut items = Vec::with_capacity(paths.len());
+            for path in &paths {
+                let item = Item::new(ss, path.clone());
+                let created = item.get_created().map_err(decode_error)?;
+                items.push((created, path.to_string(), item));
+            }
+            items.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+            let Some((_, _, newest)) = items.pop() else {
+                return Ok(());
+            };
+            let mut merged = newest.get_attributes().map_err(decode_error)?;
+            let count = merged.len();
+            for (_, _, item) in items.iter() {
+                // another process resolving the same race may already have deleted it
+                if let Ok(attributes) = item.get_attributes() {
+                    for (k, v) in attributes {
+                        merged.entry(k).or_insert(v);
+                    }
+                }
+            }
+            if merged.len() != count
+                && this.applies(|| PlannedChange::SetAttributes {
+                    path: newest.path.to_string(),
+                    attributes: merged.clone(),
+                })
+            {
+                newest
+                    .set_attributes(borrow_attributes(&merged))
+                    .map_err(decode_error)?;
+                this.mirror_item(ss, &newest.path)?;
+            }
+            // the others are all deleted if they can be, and the first failure reported
+            let mut failure = None;
+            for (_, _, item) in items {
+                if !this.applies(|| PlannedChange::DeleteItem {
+                    path: item.path.to_string(),
+                }) {
+                    continue;
+                }
+                match util::delete(this, &item) {
+                    Ok(()) => this.mirror_deletion(&item.path)?,
+                    // another process resolving the same race may already have deleted it
+                    Err(err) if is_stale_path(&err) => {}
+                    Err(err) => {
+                        failure.get_or_insert(err);
+                    }
+                }
+            }
+            match failure {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Returns the path of the collection that items for a target are created in.
+    pub(crate) fn collection_path(self: &Arc<Self>, collection: &str) -> Pending<Path<'static>> {
+        let name = collection.to_string();
+        self.submit_on_worker(Operation::Read, move |this, ss| {
+            Ok(util::get_collection(this, ss, &name)?.path)
+        })
+    }
+
+    /// Create a collection, unless it already exists.
+    pub(crate) fn ensure_collection(self: &Arc<Self>, collection: &str) -> Pending<()> {
+        let name = collection.to_string();
+        self.submit(
+            Operation::Create,
+            move |this, ss| match util::get_collection(this, ss, &name) {
+                Ok(_) => Ok(()),
+                Err(Error::NoEntry)
+                    if !this.applies(|| PlannedChange::CreateCollection {
+                        name: name.to_string(),
+                    }) =>
+                {
+                    Ok(())
+                }
+                Err(Error::NoEntry) => util::create_collection(ss, &name).map(|_| ()),
+                Err(e) => Err(e),
+            },
+        )
+    }
+
+    /// Delete the named collection.
+    ///
+    /// The collections behind the `default` and `session` aliases can't
+    /// be deleted, however they are named.
+    pub(crate) fn delete_collection(self: &Arc<Self>, collection: &str) -> Pending<()> {
+        let name = collection.to_string();
+        self.submit(Operation::Delete, move |this, ss| {
+            let c = util::find_collection(this, ss, &name)?;
+            for alias in ["default", "session"] {
+                match ss.get_collection_by_alias(alias) {
+                    Ok(aliased) if aliased.path == c.path => {
+                        return Err(Error::NotSupportedByStore(format!(
+                            "You cannot delete the {alias} collection"
+                        )));
+                    }
+                    Ok(_) | Err(ServiceError::NoResult) => {}
+                    Err(err) => return Err(decode_error(err)),
+                }
+            }
+            util::ensure_unlocked(this, ss, &c)?;
+            if !this.applies(|| PlannedChange::DeleteCollection {
+                path: c.path.to_string(),
+            }) {
+                return Ok(());
+            }
+            c.delete().map_err(decode_error)
+        })
+    }
+
+    /// Report whether the named collection is the one behind the `session`
+    /// alias, whose items last only until the user logs out.
+    ///
+    /// Collections are looked up without unlocking or creating anything.
+    /// If either collection can't be found (say, because it doesn't exist
+    /// yet), the answer goes by the name: `session`, `alias:session`, and
+    /// the alias's path name the session collection.
+    pub(crate) fn is_session_collection(self: &Arc<Self>, collection: &str) -> Pending<bool> {
+        let name = collection.to_string();
+        self.submit_on_worker(Operation::Read, move |this, ss| {
+            let named =
+                util::alias_name(&name) == Some("session") || name == SESSION_COLLECTION_PATH;
+            let Ok(session) = ss.get_collection_by_alias("session") else {
+                return Ok(named);
+            };
+            // looking up the default collection by name could create it
+            let found = match name.as_str() {
+                "default" => ss.get_collection_by_alias("default").map_err(decode_error),
+                _ => util::find_collection(this, ss, &name),
+            };
+            Ok(found.map_or(named, |c| c.path == session.path))
+        })
+    }
+
+    /// Given an item's path, report whether it or its collection is locked.
+    pub(crate) fn is_locked(self: &Arc<Self>, path: &Path<'static>) -> Pending<bool> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |_, ss| {
+            if Item::new(ss, path.clone())
+                .is_locked()
+                .map_err(decode_error)?
+            {
+                return Ok(true);
+            }
+            util::parent_collection(ss, &path)?
+                .is_locked()
+                .map_err(decode_error)
+        })
+    }
+
+    /// Returns the path of the collection an alias refers to, if any.
+    pub(crate) fn read_alias(self: &Arc<Self>, alias: &str) -> Pending<Option<String>> {
+        let alias = alias.to_string();
+        self.submit(Operation::Read, move |_, ss| {
+            match ss.get_collection_by_alias(&alias) {
+                Ok(c) => Ok(Some(c.path.to_string())),
+                Err(ServiceError::NoResult) => Ok(None),
+                Err(err) => Err(decode_error(err)),
+            }
+        })
+    }
+
+    /// Make an alias refer to the named collection.
+    pub(crate) fn set_alias(self: &Arc<Self>, alias: &str, collection: &str) -> Pending<()> {
+        let alias = alias.to_string();
+        let name = collection.to_string();
+        self.submit(Operation::Write, move |this, ss| {
+            let collection = util::find_collection(this, ss, &name)?;
+            if !this.applies(|| PlannedChange::SetAlias {
+                alias: alias.clone(),
+                path: collection.path.to_string(),
+            }) {
+                return Ok(());
+            }
+            this.with_bus(|conn| crate::bus::set_alias(conn, &alias, &collection.path))
+        })
+    }
+
+    /// Lock the named collection.
+    pub(crate) fn lock_collection(self: &Arc<Self>, collection: &str) -> Pending<()> {
+        let name = collection.to_string();
+        self.submit(Operation::Write, move |this, ss| {
+            let collection = util::find_collection(this, ss, &name)?;
+            collection.lock().map_err(decode_error)
+        })
+    }
+
+    /// Unlock the named collection, prompting the user if necessary.
+    ///
+    /// Since the caller asked for it, the collection is left unlocked
+    /// even in stores configured with `relock-after-access`.
+    pub(crate) fn unlock_collection(self: &Arc<Self>, collection: &str) -> Pending<()> {
+        let name = collection.to_string();
+        self.submit(Operation::Unlock, move |this, ss| {
+            let collection = util::find_collection(this, ss, &name)?;
+            util::ensure_unlocked(this, ss, &collection)?;
+            keep_unlocked();
+            Ok(())
+        })
+    }
+
+    /// Given an item's path, ensure it exists and is unlocked.
+    ///
+    /// As with [unlock_collection](Service::unlock_collection), the item
+    /// is left unlocked even in stores configured with `relock-after-access`.
+    pub(crate) fn ensure_unlocked(self: &Arc<Self>, path: &Path<'static>) -> Pending<()> {
+        let path = path.clone();
+        self.submit(Operation::Unlock, move |this, ss| {
+            this.unlock_item(ss, &path)?;
+            keep_unlocked();
+            Ok(())
+        })
+    }
+
+    /// Given an item's path, ensure it's unlocked before it's accessed.
+    ///
+    /// In stores configured with `relock-after-access`, this does nothing:
+    /// each access unlocks the item itself, so that the item can be locked
+    /// again as soon as the access is done.
+    pub(crate) fn unlock_for_access(self: &Arc<Self>, path: &Path<'static>) -> Pending<()> {
+        match self.config.relock_after_access {
+            true => Pending::ready(()),
+            false => self.ensure_unlocked(path),
+        }
+    }
+
+    /// Given an item's path, lock it.
+    #[cfg(feature = "low-level")]
+    pub(crate) fn lock_item(self: &Arc<Self>, path: &Path<'static>) -> Pending<()> {
+        let path = path.clone();
+        self.submit(Operation::Write, move |_, ss| {
+            let item = Item::new(ss, path.clone());
+            item.lock().map_err(decode_error)
+        })
+    }
+
+    /// Given an item's path, set its secret.
+    ///
+    /// The secret keeps its content type unless another is given.
+    pub(crate) fn set_secret(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        secret: &[u8],
+        content_type: Option<&str>,
+    ) -> Pending<()> {
+        let secret = Zeroizing::new(secret.to_vec());
+        let content_type = content_type.map(|t| t.to_string());
+        let path = path.clone();
+        self.submit(Operation::Write, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let secret = this.pipeline.encode(&secret)?;
+                let item = Item::new(ss, path.clone());
+                let content_type = util::content_type(&item, content_type.as_deref())?;
+                if !this.applies(|| PlannedChange::SetSecret {
+                    path: path.to_string(),
+                }) {
+                    return Ok(());
+                }
+                item.set_secret(&secret, &content_type)
+                    .map_err(decode_error)?;
+                this.mirror_item(ss, &path)
+            })
+        })
+    }
+
+    /// Given an existing item's path, retrieve its secret.
+    pub(crate) fn get_secret(self: &Arc<Self>, path: &Path<'static>) -> Pending<Vec<u8>> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let item = Item::new(ss, path.clone());
+                let secret = item.get_secret().map_err(decode_error)?;
+                this.pipeline.decode(secret)
+            })
+        })
+    }
+
+    /// Given existing items' paths, retrieve all their secrets.
+    ///
+    /// Locked items are unlocked together, so the user sees at most one
+    /// prompt, and the secrets are then fetched in a single `GetSecrets`
+    /// call, in a session that's encrypted if the store's sessions are.
+    pub(crate) fn get_secrets(
+        self: &Arc<Self>,
+        paths: &[Path<'static>],
+    ) -> Pending<Vec<Result<Vec<u8>>>> {
+        let paths = paths.to_vec();
+        self.submit_on_worker(Operation::Read, move |this, ss| {
+            let items: Vec<Item> = paths.iter().map(|p| Item::new(ss, p.clone())).collect();
+            let looked_up = this.unlock_together(ss, &items)?;
+            let encrypted = matches!(this.negotiated.get(), Some((SessionAlgorithm::DhAes, _)));
+            let found: Vec<Path<'static>> = paths
+                .iter()
+                .zip(looked_up.iter())
+                .filter(|(_, l)| l.is_ok())
+                .map(|(p, _)| p.clone())
+                .collect();
+            let found = this.with_bus(|conn| crate::bus::get_secrets(conn, &found, encrypted))?;
+            Ok(items
+                .iter()
+                .zip(looked_up)
+                .map(|(item, looked_up)| {
+                    looked_up?;
+                    let secret = found.get(&item.path).cloned().ok_or(Error::NoEntry)?;
+                    this.pipeline.decode(secret)
+                })
+                .collect())
+        })
+    }
+
+    /// Given existing items' paths and new secrets (and perhaps content
+    /// types) for them, set all the secrets.
+    ///
+    /// As with [get_secrets](Service::get_secrets), locked items are
+    /// unlocked together. The result has the outcome for each item.
+    pub(crate) fn set_secrets(
+        self: &Arc<Self>,
+        updates: Vec<SecretUpdate>,
+    ) -> Pending<Vec<Result<()>>> {
+        self.submit(Operation::Write, move |this, ss| {
+            let items: Vec<Item> = updates
+                .iter()
+                .map(|(p, _, _)| Item::new(ss, p.clone()))
+                .collect();
+            let looked_up = this.unlock_together(ss, &items)?;
+            let results = items
+                .iter()
+                .zip(updates.iter())
+                .zip(looked_up)
+                .map(|((item, (path, secret, content_type)), looked_up)| {
+                    looked_up?;
+                    let secret = this.pipeline.encode(secret)?;
+                    let content_type = util::content_type(item, content_type.as_deref())?;
+                    if !this.applies(|| PlannedChange::SetSecret {
+                        path: path.to_string(),
+                    }) {
+                        return Ok(());
+                    }
+                    item.set_secret(&secret, &content_type)
+                        .map_err(decode_error)?;
+                    this.mirror_item(ss, path)
+                })
+                .collect();
+            Ok(results)
+        })
+    }
+
+    /// Given existing items' paths, delete all the items.
+    ///
+    /// As with [get_secrets](Service::get_secrets), locked items are
+    /// unlocked together. The result has the outcome for each item.
+    pub(crate) fn delete_items(
+        self: &Arc<Self>,
+        paths: &[Path<'static>],
+    ) -> Pending<Vec<Result<()>>> {
+        let paths = paths.to_vec();
+        self.submit(Operation::Delete, move |this, ss| {
+            let items: Vec<Item> = paths.iter().map(|p| Item::new(ss, p.clone())).collect();
+            let looked_up = this.unlock_together(ss, &items)?;
+            let results = items
+                .iter()
+                .zip(looked_up)
+                .map(|(item, looked_up)| {
+                    looked_up?;
+                    if !this.applies(|| PlannedChange::DeleteItem {
+                        path: item.path.to_string(),
+                    }) {
+                        return Ok(());
+                    }
+                    util::delete(this, item)?;
+                    this.mirror_deletion(&item.path)
+                })
+                .collect();
+            Ok(results)
+        })
+    }
+
+    /// Given an existing item's path, retrieve the content type of its secret.
+    pub(crate) fn get_content_type(self: &Arc<Self>, path: &Path<'static>) -> Pending<String> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let item = Item::new(ss, path.clone());
+                item.get_secret_content_type().map_err(decode_error)
+            })
+        })
+    }
+
+    /// Given an existing item's path, change the content type of its secret.
+    ///
+    /// The secret itself is written back unchanged.
+    pub(crate) fn set_content_type(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        content_type: &str,
+    ) -> Pending<()> {
+        let content_type = content_type.to_string();
+        let path = path.clone();
+        self.submit(Operation::Write, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let item = Item::new(ss, path.clone());
+                let secret = Zeroizing::new(item.get_secret().map_err(decode_error)?);
+                if !this.applies(|| PlannedChange::SetSecret {
+                    path: path.to_string(),
+                }) {
+                    return Ok(());
+                }
+                item.set_secret(&secret, &content_type)
+                    .map_err(decode_error)?;
+                this.mirror_item(ss, &path)
+            })
+        })
+    }
+
+    /// Given an existing item's path, retrieve its `Created` timestamp.
+    pub(crate) fn get_created(self: &Arc<Self>, path: &Path<'static>) -> Pending<u64> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |_, ss| {
+            let item = Item::new(ss, path.clone());
+            item.get_created().map_err(decode_error)
+        })
+    }
+
+    /// Given an existing item's path, retrieve its revision.
+    ///
+    /// The revision is the item's `Modified` timestamp.
+    pub(crate) fn get_revision(self: &Arc<Self>, path: &Path<'static>) -> Pending<u64> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |_, ss| {
+            let item = Item::new(ss, path.clone());
+            item.get_modified().map_err(decode_error)
+        })
+    }
+
+    /// Given an existing item's path, read its properties and (optionally) its secret.
+    ///
+    /// The properties are read in a single D-Bus call, and the secret
+    /// (if wanted) in one more.
+    pub(crate) fn get_item_snapshot(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        with_secret: bool,
+    ) -> Pending<ItemSnapshot> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let mut snapshot =
+                    this.with_bus(|conn| crate::bus::item_properties(conn, &path))?;
+                if with_secret {
+                    let secret = Item::new(ss, path.clone())
+                        .get_secret()
+                        .map_err(decode_error)?;
+                    snapshot.secret = Some(this.pipeline.decode(secret)?);
+                }
+                Ok(snapshot)
+            })
+        })
+    }
+
+    /// Given an existing item's path, set its secret if its revision matches.
+    ///
+    /// The check and the write are done in a single operation of the
+    /// service, so they cannot be interleaved with other operations in
+    /// this process.
+    pub(crate) fn set_secret_if_revision(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        secret: &[u8],
+        content_type: Option<&str>,
+        revision: u64,
+    ) -> Pending<()> {
+        let secret = Zeroizing::new(secret.to_vec());
+        let content_type = content_type.map(|t| t.to_string());
+        let path = path.clone();
+        self.submit(Operation::Write, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let secret = this.pipeline.encode(&secret)?;
+                let item = Item::new(ss, path.clone());
+                let actual = item.get_modified().map_err(decode_error)?;
+                if actual != revision {
+                    return Err(conflict(revision, actual));
+                }
+                let content_type = util::content_type(&item, content_type.as_deref())?;
+                if !this.applies(|| PlannedChange::SetSecret {
+                    path: path.to_string(),
+                }) {
+                    return Ok(());
+                }
+                item.set_secret(&secret, &content_type)
+                    .map_err(decode_error)?;
+                this.mirror_item(ss, &path)
+            })
+        })
+    }
+
+    /// Given an existing item's path, retrieve its attributes.
+    pub(crate) fn get_attributes(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+    ) -> Pending<HashMap<String, String>> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |_, ss| {
+            let item = Item::new(ss, path.clone());
+            item.get_attributes().map_err(decode_error)
+        })
+    }
+
+    /// Given an existing item's path, update its attributes.
+    pub(crate) fn update_attributes(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        attributes: &HashMap<&str, &str>,
+    ) -> Pending<()> {
+        let path = path.clone();
+        let attributes = owned_attributes(attributes);
+        self.submit(Operation::Write, move |this, ss| {
+            let item = Item::new(ss, path.clone());
+            util::merge_attributes(this, &item, &borrow_attributes(&attributes))?;
+            this.mirror_item(ss, &path)
+        })
+    }
+
+    /// Given an existing item's path, replace all its attributes.
+    ///
+    /// The store's default attributes are added, as they are to new items.
+    pub(crate) fn replace_attributes(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        attributes: HashMap<&str, &str>,
+    ) -> Pending<()> {
+        let attributes = match self.item_attributes(attributes, &HashMap::new()) {
+            Ok(attributes) => attributes,
+            Err(err) => return Pending::failed(err),
+        };
+        let path = path.clone();
+        self.submit(Operation::Write, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let item = Item::new(ss, path.clone());
+                if !this.applies(|| PlannedChange::SetAttributes {
+                    path: path.to_string(),
+                    attributes: attributes.clone(),
+                }) {
+                    return Ok(());
+                }
+                item.set_attributes(borrow_attributes(&attributes))
+                    .map_err(decode_error)?;
+                this.mirror_item(ss, &path)
+            })
+        })
+    }
+
+    /// Given an existing item's path, give it new controlled attributes
+    /// and, if it has the old default label, the new default label.
+    pub(crate) fn rename_item(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        attributes: &HashMap<String, String>,
+        old_label: &str,
+        new_label: &str,
+    ) -> Pending<()> {
+        let path = path.clone();
+        let attributes = attributes.clone();
+        let (old_label, new_label) = (old_label.to_string(), new_label.to_string());
+        self.submit(Operation::Write, move |this, ss| {
+            let item = Item::new(ss, path.clone());
+            util::merge_attributes(this, &item, &borrow_attributes(&attributes))?;
+            if item.get_label().map_err(decode_error)? == old_label
+                && this.applies(|| PlannedChange::SetLabel {
+                    path: path.to_string(),
+                    label: new_label.clone(),
+                })
+            {
+                item.set_label(&new_label).map_err(decode_error)?;
+            }
+            this.mirror_item(ss, &path)
+        })
+    }
+
+    /// Given an existing item's path, update its attributes if its revision matches.
+    pub(crate) fn update_attributes_if_revision(
+        self: &Arc<Self>,
+        path: &Path<'static>,
+        attributes: &HashMap<&str, &str>,
+        revision: u64,
+    ) -> Pending<()> {
+        let path = path.clone();
+        let attributes = owned_attributes(attributes);
+        self.submit(Operation::Write, move |this, ss| {
+            let item = Item::new(ss, path.clone());
+            let actual = item.get_modified().map_err(decode_error)?;
+            if actual != revision {
+                return Err(conflict(revision, actual));
+            }
+            util::merge_attributes(this, &item, &borrow_attributes(&attributes))?;
+            this.mirror_item(ss, &path)
+        })
+    }
+
+    // Given an existing item's path, delete it.
+    pub(crate) fn delete(self: &Arc<Self>, path: &Path<'static>) -> Pending<()> {
+        let path = path.clone();
+        self.submit(Operation::Delete, move |this, ss| {
+            this.relocking(ss, &path, || {
+                let item = Item::new(ss, path.clone());
+                if !this.applies(|| PlannedChange::DeleteItem {
+                    path: path.to_string(),
+                }) {
+                    return Ok(());
+                }
+                util::delete(this, &item)?;
+                this.mirror_deletion(&path)
+            })
+        })
+    }
+
+    // Given an existing item's path, return its label.
+    pub(crate) fn get_label(self: &Arc<Self>, path: &Path<'static>) -> Pending<String> {
+        let path = path.clone();
+        self.submit(Operation::Read, move |_, ss| {
+            let item = Item::new(ss, path.clone());
+            item.get_label().map_err(decode_error)
+        })
+    }
+
+    // Given an existing item's path, set its label.
+    pub(crate) fn set_label(self: &Arc<Self>, path: &Path<'static>, label: &str) -> Pending<()> {
+        let path = path.clone();
+        let label = label.to_string();
+        self.submit(Operation::Write, move |this, ss| {
+            if !this.applies(|| PlannedChange::SetLabel {
+                path: path.to_string(),
+                label: label.clone(),
+            }) {
+                return Ok(());
+            }
+            let item = Item::new(ss, path.clone());
+            item.set_label(&label).map_err(decode_error)?;
+            this.mirror_item(ss, &path)
+        })
+    }
+
+    /// Recreate an item from its mirror record.
+    ///
+    /// The item's collection is created if it doesn't exist, and an existing
+    /// item in it with the same attributes is replaced. The new item is
+    /// mirrored in place of the recorded one.
+    pub(crate) fn restore_item(self: &Arc<Self>, record: &Record) -> Pending<()> {
+        let record = record.clone();
+        self.submit(Operation::Create, move |this, ss| {
+            let collection = match util::get_collection(this, ss, &record.collection) {
+                Ok(c) => c,
+                Err(Error::NoEntry)
+                    if this.applies(|| PlannedChange::CreateCollection {
+                        name: record.collection.clone(),
+                    }) =>
+                {
+                    util::create_collection(ss, &record.collection)?
+                }
+                Err(Error::NoEntry) => util::planned_collection(ss),
+                Err(e) => return Err(e),
+            };
+            let change = || PlannedChange::CreateItem {
+                collection: record.collection.clone(),
+                label: record.label.clone(),
+                attributes: record.attributes.clone(),
+            };
+            if !this.applies(change) {
+                return Ok(());
+            }
+            let item = collection
+                .create_item(
+                    &record.label,
+                    borrow_attributes(&record.attributes),
+                    &record.secret,
+                    true, // replace
+                    &record.content_type,
+                )
+                .map_err(platform_failure)?;
+            this.mirror_item(ss, &item.path)?;
+            // the item at the recorded path has been superseded by the new one
+            if item.path.to_string() != record.path {
+                this.mirror_deletion(&Path::from(record.path.clone()))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Run an operation that accesses an item.
+    ///
+    /// In stores configured with `relock-after-access`, whose searches
+    /// leave items locked, the item is first unlocked if it's locked, and
+    /// it's locked again once the operation is done.
+    fn relocking<T>(
+        &self,
+        ss: &SecretService,
+        path: &Path<'static>,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if self.config.relock_after_access {
+            self.unlock_item(ss, path)?;
+        }
+        f()
+    }
+
+    /// Unlock an item, if it's locked.
+    fn unlock_item(&self, ss: &SecretService, path: &Path<'static>) -> Result<()> {
+        let item = Item::new(ss, path.clone());
+        if !item.is_locked().map_err(decode_error)? {
+            return Ok(());
+        }
+        self.unlock(ss, std::slice::from_ref(path))
+    }
+
+    /// Unlock those of the given items that are locked, with a single
+    /// request (so the user sees at most one prompt).
+    ///
+    /// The result says, for each item, whether it could be looked up: items
+    /// that can't be (say, because they've since been deleted) are left out
+    /// of the request, so they don't fail the others. Only a failure of the
+    /// request itself fails the batch.
+    fn unlock_together(&self, ss: &SecretService, items: &[Item]) -> Result<Vec<Result<()>>> {
+        let mut looked_up = Vec::with_capacity(items.len());
+        let mut locked = Vec::new();
+        for item in items {
+            match item.is_locked().map_err(decode_error) {
+                Ok(true) => {
+                    locked.push(item.path.clone());
+                    looked_up.push(Ok(()));
+                }
+                Ok(false) => looked_up.push(Ok(())),
+                Err(err) => looked_up.push(Err(err)),
+            }
+        }
+        if !locked.is_empty() {
+            self.unlock(ss, &locked)?;
+        }
+        Ok(looked_up)
+    }
+
+    /// Unlock items or collections with a single request, which may
+    /// prompt the user. Stores configured with `no-prompt` fail with a
+    /// [WouldPrompt](crate::errors::StoreError::WouldPrompt) error instead,
+    /// and those configured with `prompt-window` parent the prompt to it.
+    ///
+    /// In stores configured with `relock-after-access`, whatever is unlocked
+    /// is locked again once the operation is done.
+    fn unlock(&self, ss: &SecretService, paths: &[Path<'static>]) -> Result<()> {
+        self.metrics.count_unlock();
+        if self.config.relock_after_access {
+            UNLOCKED.with_borrow_mut(|unlocked| unlocked.extend(paths.iter().cloned()));
+        }
+        if self.config.no_prompt {
+            return match self.with_bus(|conn| crate::bus::unlock_without_prompt(conn, paths))? {
+                true => Ok(()),
+                false => Err(would_prompt(paths.len())),
+            };
+        }
+        if let Some(window) = &self.config.prompt_window {
+            let timeout = self.config.prompt_timeout;
+            return self
+                .with_bus(|conn| crate::bus::unlock_in_window(conn, paths, window, timeout));
+        }
+        // unlocking only uses the objects' paths, so collections can pass for items
+        let objects: Vec<Item> = paths.iter().map(|p| Item::new(ss, p.clone())).collect();
+        let objects: Vec<&Item> = objects.iter().collect();
+        ss.unlock_all(&objects).map_err(decode_error)
+    }
+
+    /// Lock whatever the operation running on this thread unlocked (in
+    /// stores configured with `relock-after-access`).
+    ///
+    /// Locking is best-effort: failures (because an item has been deleted,
+    /// say) are ignored.
+    fn relock(&self, ss: &SecretService) {
+        for path in UNLOCKED.take() {
+            let _ = Item::new(ss, path).lock();
+        }
+    }
+
+    /// Recreate backed-up items in the named collection, which is created
+    /// if need be (as it is for new items) and unlocked.
+    ///
+    /// Secrets that aren't in their stored form are encoded by the store's
+    /// transform pipeline first.
+    pub(crate) fn import_items(
+        self: &Arc<Self>,
+        name: &str,
+        records: Vec<Record>,
+        policy: ConflictPolicy,
+        encode: bool,
+    ) -> Pending<ImportSummary> {
+        let name = name.to_string();
+        self.submit(Operation::Create, move |this, ss| {
+            let collection = this.target_collection(ss, &name)?;
+            let planned = util::is_planned(&collection);
+            if !planned {
+                util::ensure_unlocked(this, ss, &collection)?;
+            }
+            let mut summary = ImportSummary::default();
+            for record in records.iter().filter(|r| !r.deleted) {
+                let attributes = borrow_attributes(&record.attributes);
+                let existing = match planned {
+                    true => Vec::new(),
+                    false => collection
+                        .search_items(attributes.clone())
+                        .map_err(decode_error)?,
+                };
+                let label = match (existing.is_empty(), &policy) {
+                    (true, _) => {
+                        summary.created += 1;
+                        record.label.clone()
+                    }
+                    (false, ConflictPolicy::Skip) => {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    (false, ConflictPolicy::Overwrite) => {
+                        summary.overwritten += 1;
+                        record.label.clone()
+                    }
+                    (false, ConflictPolicy::DuplicateWithSuffix(suffix)) => {
+                        summary.created += 1;
+                        format!("{}{suffix}", record.label)
+                    }
+                };
+                let change = || PlannedChange::CreateItem {
+                    collection: name.clone(),
+                    label: label.clone(),
+                    attributes: record.attributes.clone(),
+                };
+                if !this.applies(change) {
+                    continue;
+                }
+                let replace = policy == ConflictPolicy::Overwrite;
+                let secret = match encode {
+                    true => this.pipeline.encode(&record.secret)?,
+                    false => Zeroizing::new(record.secret.clone()),
+                };
+                let item = collection
+                    .create_item(&label, attributes, &secret, replace, &record.content_type)
+                    .map_err(platform_failure)?;
+                this.mirror_item(ss, &item.path)?;
+            }
+            Ok(summary)
+        })
+    }
+
+    /// If writes are mirrored, append the current state of an item to the mirror.
+    ///
+    /// This must be called from an operation running on the worker, so that
+    /// records are appended in the order that the writes were made.
+    fn mirror_item(&self, ss: &SecretService, path: &Path<'static>) -> Result<()> {
+        let Some(mirror) = &self.mirror else {
+            return Ok(());
+        };
+        let item = Item::new(ss, path.clone());
+        let record = Record {
+            path: path.to_string(),
+            deleted: false,
+            collection: util::collection_name(ss, path)?,
+            label: item.get_label().map_err(decode_error)?,
+            attributes: item.get_attributes().map_err(decode_error)?,
+            secret: item.get_secret().map_err(decode_error)?,
+            content_type: item.get_secret_content_type().map_err(decode_error)?,
+        };
+        mirror.append(&record)
+    }
+
+    /// If writes are mirrored, record an item's deletion in the mirror.
+    fn mirror_deletion(&self, path: &Path<'static>) -> Result<()> {
+        match &self.mirror {
+            Some(mirror) => mirror.append(&Record::deletion(path)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The state shared by a submitted operation and the worker.
+struct Shared<T> {
+    /// Set by whichever of the worker (starting the operation) and the
+    /// submitter (cancelling it) gets there first.
+    claimed: AtomicBool,
+    slot: Mutex<Slot<T>>,
+    ready: Condvar,
+}
+
+struct Slot<T> {
+    result: Option<std::thread::Result<Result<T>>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn slot(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot
+            .lock()
+            .expect("Mutex failure in credential store: please report a bug")
+    }
+
+    fn complete(&self, result: std::thread::Result<Result<T>>) {
+        let mut slot = self.slot();
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        self.ready.notify_all();
+    }
+
+    /// Check an operation that has been waited for since `since` against
+    /// its timeout and its store's deadline.
+    ///
+    /// An operation that's over either limit is cancelled (or abandoned,
+    /// if it has started) and the limit's error is returned. Otherwise the
+    /// result is how long it is until the nearer limit, if there is one.
+    fn check_limits(
+        &self,
+        service: &Service,
+        timeout: Option<Duration>,
+        since: Instant,
+    ) -> Result<Option<Duration>> {
+        let elapsed = since.elapsed();
+        if let Some(timeout) = timeout {
+            if elapsed >= timeout {
+                if !self.claimed.swap(true, Ordering::AcqRel) {
+                    service.pending.fetch_sub(1, Ordering::AcqRel);
+                }
+                return Err(timed_out(timeout));
+            }
+        }
+        // once the operation has started, its deadline no longer matters
+        let deadline = match self.claimed.load(Ordering::Acquire) {
+            true => None,
+            false => service.config.deadline,
+        };
+        if let Some(deadline) = deadline {
+            if elapsed >= deadline && !self.claimed.swap(true, Ordering::AcqRel) {
+                service.pending.fetch_sub(1, Ordering::AcqRel);
+                return Err(deadline_exceeded(deadline));
+            }
+        }
+        Ok([timeout, deadline]
+            .into_iter()
+            .flatten()
+            .map(|limit| limit.saturating_sub(elapsed))
+            .min())
+    }
+}
+
+enum State<T> {
+    Done(Option<Result<T>>),
+    Running {
+        service: Arc<Service>,
+        shared: Arc<Shared<T>>,
+        timeout: Option<Duration>,
+        /// When the operation was first waited for or polled.
+        since: Option<Instant>,
+        /// When the timer that wakes a polling task at its next limit
+        /// goes off, if one has been started.
+        timer: Option<Instant>,
+    },
+}
+
+/// An operation that has been submitted to a service's worker.
+///
+/// Its result can be waited for, with [wait](Pending::wait), or awaited,
+/// since it's a [Future] (one that works with any async runtime). Dropping
+/// an operation that hasn't started cancels it. If the operation panics,
+/// the panic is resumed in the thread that waits for or awaits it.
+pub(crate) struct Pending<T>(State<T>);
+
+impl<T> Pending<T> {
+    /// An operation that failed before it could be submitted.
+    pub(crate) fn failed(err: Error) -> Self {
+        Pending(State::Done(Some(Err(err))))
+    }
+
+    /// An operation that had nothing to do.
+    pub(crate) fn ready(value: T) -> Self {
+        Pending(State::Done(Some(Ok(value))))
+    }
+
+    /// Wait for the operation's result.
+    ///
+    /// If the store has an operation deadline, and the operation doesn't
+    /// start before the deadline, it's cancelled. If the operation has a
+    /// timeout, and it doesn't finish before the timeout, it's cancelled
+    /// if it hasn't started and abandoned if it has. (Once started, it
+    /// always runs to completion.) Awaiting the operation applies the
+    /// same limits.
+    pub(crate) fn wait(mut self) -> Result<T> {
+        let (service, shared, timeout, since) = match &mut self.0 {
+            State::Done(result) => return result.take().expect("result already taken"),
+            State::Running {
+                service,
+                shared,
+                timeout,
+                since,
+                ..
+            } => (
+                service.clone(),
+                shared.clone(),
+                *timeout,
+                *since.get_or_insert_with(Instant::now),
+            ),
+        };
+        let mut slot = shared.slot();
+        while slot.result.is_none() {
+            let remaining = shared.check_limits(&service, timeout, since)?;
+            slot = match remaining {
+                None => shared
+                    .ready
+                    .wait(slot)
+                    .expect("Mutex failure in credential store: please report a bug"),
+                Some(remaining) => {
+                    shared
+                        .ready
+                        .wait_timeout(slot, remaining)
+                        .expect("Mutex failure in credential store: please report a bug")
+                        .0
+                }
+            };
+        }
+        let result = slot.result.take().unwrap();
+        drop(slot);
+        service.finish(result)
+    }
+}
+
+// results are only ever moved out, never pinned
+impl<T> Unpin for Pending<T> {}
+
+impl<T> Future for Pending<T> {
+    type Output = Result<T>;
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+        let (service, shared, timeout, since, timer) = match &mut self.0 {
+            State::Done(result) => {
+                return Poll::Ready(result.take().expect("polled after completion"));
+            }
+            State::Running {
+                service,
+                shared,
+                timeout,
+                since,
+                timer,
+            } => (
+                service,
+                shared,
+                *timeout,
+                *since.get_or_insert_with(Instant::now),
+                timer,
+            ),
+        };
+        let mut slot = shared.slot();
+        if let Some(result) = slot.result.take() {
+            drop(slot);
+            return Poll::Ready(service.finish(result));
+        }
+        let remaining = match shared.check_limits(service, timeout, since) {
+            Ok(remaining) => remaining,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        slot.waker = Some(cx.waker().clone());
+        drop(slot);
+        // wake the task when its next limit is reached, since nothing else will
+        let now = Instant::now();
+        if let Some(remaining) = remaining {
+            if timer.is_none_or(|at| at <= now) {
+                *timer = Some(now + remaining);
+                let waker = cx.waker().clone();
+                let timing = std::thread::Builder::new()
+                    .name("secret-service-timer".to_string())
+                    .spawn(move || {
+                        std::thread::sleep(remaining);
+                        waker.wake();
+                    });
+                if timing.is_err() {
+                    panic!("Worker failure in credential store: please report a bug");
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Pending<T> {
+    fn drop(&mut self) {
+        if let State::Running {
+            service, shared, ..
+        } = &self.0
+        {
+            if !shared.claimed.swap(true, Ordering::AcqRel) {
+                service.pending.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+}
+
+/// Wakes a thread that's waiting for a future, by unparking it.
+struct Unparker(std::thread::Thread);
+
+impl std::task::Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Wait on this thread for a future made of [Pending] operations, as
+/// [wait](Pending::wait) waits for one of them.
+///
+/// This is how the blocking credential methods share their
+/// implementation with the async ones.
+pub(crate) fn wait_for<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(Unparker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// Copy borrowed attributes, so they can be sent to the worker.
+fn owned_attributes(attributes: &HashMap<&str, &str>) -> HashMap<String, String> {
+    attributes
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Secret Service utilities: this module is private because these can't
+/// be called except from the methods of the Service struct which has
+/// made the service singleton available.
+mod util {
+    use std::collections::HashMap;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::{Error, Result, Service, Zeroizing, decode_error};
+    use crate::bus::ALIASES_PREFIX;
+    use crate::config::{DuplicatePolicy, EXPIRES_ATTRIBUTE};
+    use crate::errors::{ambiguous_collection, is_stale_path, missing_collection, quota_exceeded};
+    use crate::info::{ItemInfo, PlannedChange};
+    use crate::mapping::Mapper;
+    use crate::mapping::borrow_attributes;
+
+    use dbus_secret_service::{Collection, Error as ServiceError, Item, Path, SecretService};
+
+    /// The prefix of collection names that are aliases rather than labels.
+    const ALIAS_PREFIX: &str = "alias:";
+
+    /// The path that stands in for collections that dry-run stores would
+    /// have created. Real collections are all under `collection/`.
+    const PLANNED_COLLECTION: &str = "/org/freedesktop/secrets/planned";
+
+    /// The content type to set an item's secret with: the given one, if
+    /// any, or else the one the secret has.
+    pub(crate) fn content_type(item: &Item, content_type: Option<&str>) -> Result<String> {
+        match content_type {
+            Some(content_type) => Ok(content_type.to_string()),
+            None => item.get_secret_content_type().map_err(decode_error),
+        }
+    }
+
+    /// Merge the given attributes into an item's existing attributes.
+    ///
+    /// The merged attributes must conform to the configured schema,
+    /// and the values of opaque attributes are concealed before writing.
+    pub(crate) fn merge_attributes(
+        service: &Service,
+        item: &Item,
+        attributes: &HashMap<&str, &str>,
+    ) -> Result<()> {
+        let mut updated = item.get_attributes().map_err(decode_error)?;
+        for (k, v) in attributes.iter() {
+            updated.insert(k.to_string(), v.to_string());
+        }
+        service.config.schema.validate(&updated, attributes)?;
+        updated.extend(service.config.opaque.conceal(attributes));
+        if !service.applies(|| PlannedChange::SetAttributes {
+            path: item.path.to_string(),
+            attributes: updated.clone(),
+        }) {
+            return Ok(());
+        }
+        item.set_attributes(borrow_attributes(&updated))
+            .map_err(decode_error)
+    }
+
+    /// Delete an item, scrubbing it first in stores configured with
+    /// `secure-delete`. All of a store's item deletions go through here.
+    pub(crate) fn delete(service: &Service, item: &Item) -> Result<()> {
+        if service.config.secure_delete {
+            scrub(item, service.mapper().as_ref());
+        }
+        item.delete().map_err(decode_error)
+    }
+
+    /// Overwrite an item's secret with zeros and strip its uncontrolled attributes.
+    ///
+    /// This is a best-effort measure to limit what the Secret Service might
+    /// retain about an item after it's deleted, so failures are ignored.
+    fn scrub(item: &Item, mapper: &Mapper) {
+        let length = item
+            .get_secret()
+            .map(|s| Zeroizing::new(s).len())
+            .unwrap_or_default();
+        let _ = item.set_secret(&vec![0u8; length], "text/plain");
+        if let Ok(attributes) = item.get_attributes() {
+            let kept: HashMap<&str, &str> = attributes
+                .iter()
+                .filter(|(k, _)| mapper.is_controlled(k))
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            let _ = item.set_attributes(kept);
+        }
+    }
+
+    /// Search for items with the given attributes, in the named collection
+    /// if there is one and otherwise in all of them.
+    ///
+    /// The results are split into unlocked and locked items. Nothing is
+    /// unlocked, and a missing collection has no items.
+    pub(crate) fn search_items<'a>(
+        service: &Service,
+        ss: &'a SecretService,
+        scope: Option<&str>,
+        attributes: &HashMap<String, String>,
+    ) -> Result<(Vec<Item<'a>>, Vec<Item<'a>>)> {
+        let attributes = borrow_attributes(attributes);
+        let Some(name) = scope else {
+            let search = ss.search_items(attributes).map_err(decode_error)?;
+            return Ok((search.unlocked, search.locked));
+        };
+        let collection = match find_collection(service, ss, name) {
+            Ok(collection) => collection,
+            Err(Error::NoEntry) => return Ok((Vec::new(), Vec::new())),
+            Err(err) => return Err(err),
+        };
+        let mut unlocked = Vec::new();
+        let mut locked = Vec::new();
+        for item in collection.search_items(attributes).map_err(decode_error)? {
+            match item.is_locked().map_err(decode_error)? {
+                true => locked.push(Item::new(ss, item.path.clone())),
+                false => unlocked.push(Item::new(ss, item.path.clone())),
+            }
+        }
+        Ok((unlocked, locked))
+    }
+
+    /// Describe the items in a collection, adding them to `items`.
+    ///
+    /// Items that are deleted while they're being described are skipped.
+    pub(crate) fn describe_items(collection: &Collection, items: &mut Vec<ItemInfo>) -> Result<()> {
+        let describe = |item: &Item| -> Result<ItemInfo> {
+            Ok(ItemInfo {
+                path: item.path.to_string(),
+                collection: collection.path.to_string(),
+                label: item.get_label().map_err(decode_error)?,
+                attributes: item.get_attributes().map_err(decode_error)?,
+                locked: item.is_locked().map_err(decode_error)?,
+            })
+        };
+        for item in collection.get_all_items().map_err(decode_error)? {
+            match describe(&item) {
+                Ok(info) => items.push(info),
+                Err(err) if is_stale_path(&err) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Make room for one more item in a collection that is limited to `max` items.
+    ///
+    /// If the collection is full of this store's items, either fail or
+    /// (when the store has `quota-eviction`) delete some of them: expired
+    /// ones first, then the oldest. Items that don't follow the store's
+    /// attribute mapping (such as other applications') are neither counted
+    /// nor deleted. The paths of deleted items are returned.
+    pub(crate) fn enforce_quota(
+        service: &Service,
+        collection: &Collection,
+        name: &str,
+        max: usize,
+    ) -> Result<Vec<Path<'static>>> {
+        let mut items = Vec::new();
+        for item in collection.get_all_items().map_err(decode_error)? {
+            let attributes = item.get_attributes().map_err(decode_error)?;
+            if service.is_own_item(&attributes) {
+                items.push((attributes, item));
+            }
+        }
+        if items.len() < max {
+            return Ok(Vec::new());
+        }
+        if !service.config.quota_eviction {
+            return Err(quota_exceeded(name, max));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut aged = Vec::with_capacity(items.len());
+        for (attributes, item) in items {
+            let expired = attributes
+                .get(EXPIRES_ATTRIBUTE)
+                .and_then(|expiry| expiry.parse::<u64>().ok())
+                .is_some_and(|expiry| expiry <= now);
+            aged.push((!expired, item.get_created().map_err(decode_error)?, item));
+        }
+        aged.sort_by_key(|(live, created, _)| (*live, *created));
+        let excess = aged.len() + 1 - max;
+        let mut evicted = Vec::with_capacity(excess);
+        for (_, _, item) in aged.into_iter().take(excess) {
+            let change = || PlannedChange::DeleteItem {
+                path: item.path.to_string(),
+            };
+            if service.applies(change) {
+                delete(service, &item)?;
+                evicted.push(item.path);
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// The stand-in for a collection that a dry-run store would have created.
+    pub(crate) fn planned_collection(ss: &SecretService) -> Collection<'_> {
+        Collection::new(ss, Path::from(PLANNED_COLLECTION))
+    }
+
+    /// Whether a collection is the stand-in for one that would have been created.
+    pub(crate) fn is_planned(collection: &Collection) -> bool {
+        &*collection.path == PLANNED_COLLECTION
+    }
+
+    /// The stand-in for the path of an item that a dry-run store would
+    /// have created in a collection.
+    pub(crate) fn planned_item(collection: &Collection) -> Path<'static> {
+        Path::from(format!("{}/planned", collection.path))
+    }
+
+    /// The name of the collection that contains an item, as understood
+    /// by [get_collection]: `default` for the default collection, and
+    /// otherwise the collection's label.
+    pub(crate) fn collection_name(ss: &SecretService, item: &Path<'static>) -> Result<String> {
+        let collection = parent_collection(ss, item)?;
+        if let Ok(default) = ss.get_default_collection() {
+            if default.path == collection.path {
+                return Ok("default".to_string());
+            }
+        }
+        collection.get_label().map_err(decode_error)
+    }
+
+    /// The collection that holds an item.
+    ///
+    /// Item paths are always the path of their collection plus one element.
+    pub(crate) fn parent_collection<'a>(
+        ss: &'a SecretService,
+        item: &Path<'static>,
+    ) -> Result<Collection<'a>> {
+        let (parent, _) = item.rsplit_once('/').unwrap_or(("", ""));
+        let path = Path::new(parent.to_string()).map_err(|_| Error::NoEntry)?;
+        Ok(Collection::new(ss, path))
+    }
+
+    /// Find the secret service collection whose label is the given name.
+    ///
+    /// The name `default` is treated specially and is interpreted as naming
+    /// the default collection regardless of its label (which might be different).
+    /// A name that starts with `/` is the D-Bus path of the collection (or,
+    /// if there's no collection at that path, its label), and one that
+    /// starts with `alias:` names an alias of the collection.
+    /// The name `session` means the session collection.
+    pub(crate) fn get_collection<'a>(
+        service: &Service,
+        ss: &'a SecretService,
+        name: &str,
+    ) -> Result<Collection<'a>> {
+        let collection = find_collection(service, ss, name)?;
+        ensure_unlocked(service, ss, &collection)?;
+        Ok(collection)
+    }
+
+    /// Unlock a collection, if it's locked (which may prompt the user).
+    pub(crate) fn ensure_unlocked(
+        service: &Service,
+        ss: &SecretService,
+        collection: &Collection,
+    ) -> Result<()> {
+        if is_planned(collection) {
+            return Ok(());
+        }
+        if collection.is_locked().map_err(decode_error)? {
+            service.unlock(ss, std::slice::from_ref(&collection.path))?;
+        }
+        Ok(())
+    }
+
+    /// Like [get_collection], but leaves the collection locked if it is.
+    pub(crate) fn find_collection<'a>(
+        service: &Service,
+        ss: &'a SecretService,
+        name: &str,
+    ) -> Result<Collection<'a>> {
+        if name.eq("default") {
+            default_collection(service, ss)
+        } else if let Some(alias) = alias_name(name) {
+            match ss.get_collection_by_alias(alias) {
+                Err(ServiceError::NoResult) => Err(Error::NoEntry),
+                result => result.map_err(decode_error),
+            }
+        } else if name.starts_with('/') {
+            // labels can start with `/` too
+            get_collection_by_path(ss, name).or_else(|err| {
+                match get_collection_by_label(service, ss, name) {
+                    Err(Error::NoEntry) => Err(err),
+                    result => result,
+                }
+            })
+        } else {
+            get_collection_by_label(service, ss, name)
+        }
+    }
+
+    /// The alias named by a collection name, if it names one.
+    ///
+    /// The name `session` is the session collection's alias, since that
+    /// collection is found by alias rather than label.
+    pub(crate) fn alias_name(name: &str) -> Option<&str> {
+        if name.eq("session") {
+            Some(name)
+        } else {
+            name.strip_prefix(ALIAS_PREFIX)
+        }
+    }
+
+    /// Find the collection with the `default` alias.
+    ///
+    /// If there isn't one (as on WSL) and the store is configured with
+    /// `create-default-collection`, a collection labeled `Default` is
+    /// created and given the alias. (A dry-run store plans to create it
+    /// instead, and uses a stand-in.)
+    fn default_collection<'a>(service: &Service, ss: &'a SecretService) -> Result<Collection<'a>> {
+        match ss.get_default_collection() {
+            Ok(collection) => Ok(collection),
+            Err(ServiceError::NoResult) if service.config.create_default_collection => {
+                if !service.applies(|| PlannedChange::CreateCollection {
+                    name: "default".to_string(),
+                }) {
+                    return Ok(planned_collection(ss));
+                }
+                ss.create_collection("Default", "default")
+                    .map_err(decode_error)
+            }
+            Err(err) => Err(decode_error(err)),
+        }
+    }
+
+    /// Find the collection with the given path, which must exist.
+    ///
+    /// Alias paths are resolved to the path of the collection they refer to.
+    fn get_collection_by_path<'a>(ss: &'a SecretService, path: &str) -> Result<Collection<'a>> {
+        if let Some(alias) = path.strip_prefix(ALIASES_PREFIX) {
+            return ss
+                .get_collection_by_alias(alias)
+                .map_err(|_| missing_collection(path));
+        }
+        let object = Path::new(path.to_string()).map_err(|_| missing_collection(path))?;
+        let collection = Collection::new(ss, object);
+        if collection.get_label().is_err() {
+            return Err(missing_collection(path));
+        }
+        Ok(collection)
+    }
+
+    /// Find the collection with the given label.
+    ///
+    /// If several collections have the label, the service's configured
+    /// policy decides which is used.
+    fn get_collection_by_label<'a>(
+        service: &Service,
+        ss: &'a SecretService,
+        name: &str,
+    ) -> Result<Collection<'a>> {
+        let all = ss.get_all_collections().map_err(decode_error)?;
+        let mut found: Vec<Collection> = all
+            .into_iter()
+            .filter(|c| c.get_label().map(|l| l.eq(name)).unwrap_or(false))
+            .collect();
+        if found.len() > 1 {
+            match service.config.duplicate_collections {
+                DuplicatePolicy::First => {}
+                DuplicatePolicy::Error => {
+                    return Err(ambiguous_collection(name, found.len()));
+                }
+                DuplicatePolicy::Newest => {
+                    let mut created = Vec::with_capacity(found.len());
+                    for c in found.iter() {
+                        created.push(
+                            service
+                                .with_bus(|conn| crate::bus::collection_created(conn, &c.path))?,
+                        );
+                    }
+                    let newest = (0..found.len()).max_by_key(|i| created[*i]).unwrap();
+                    found.swap(0, newest);
+                }
+            }
+        }
+        found.into_iter().next().ok_or(Error::NoEntry)
     }
 
     /// Create a secret service collection labeled with the given name.
@@ -224,12 +2740,16 @@ mod util {
     /// If a collection with that name already exists, it is returned.
     ///
     /// The name `default` is specially interpreted to mean the default collection.
+    /// A name `alias:<alias>` (or `session`) creates a collection labeled
+    /// and aliased `<alias>`.
     pub(crate) fn create_collection<'a>(
         ss: &'a SecretService,
         name: &str,
     ) -> Result<Collection<'a>> {
         let collection = if name.to_ascii_lowercase().eq("default") {
             ss.get_default_collection().map_err(decode_error)?
+        } else if let Some(alias) = alias_name(name) {
+            ss.create_collection(alias, alias).map_err(decode_error)?
         } else {
             ss.create_collection(name, "").map_err(decode_error)?
         };
@@ -1,13 +1,31 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use keyring_core::api::CredentialStoreApi;
+#[cfg(feature = "memory-fallback")]
+use keyring_core::CredentialStore;
+use keyring_core::api::{CredentialPersistence, CredentialStoreApi};
 use keyring_core::attributes::parse_attributes;
 use keyring_core::{Entry, Error, Result};
+use zeroize::Zeroizing;
 
-use crate::cred::{Specifier, Wrapper};
-use crate::service::Service;
+use crate::backup::{self, ConflictPolicy, ImportSummary};
+use crate::bus;
+use crate::config::Config;
+use crate::cred::{LEGACY_APPLICATION, Modifiers, Specifier, Wrapper};
+use crate::errors::{StoreError, already_exists, is_stale_path};
+use crate::events::{ItemEventKind, Subscription, Watch, Watched};
+use crate::hooks::{Access, AccessKind, AfterHook, BeforeHook, ErrorHook};
+use crate::info::{
+    Availability, Backend, CollectionInfo, Diagnostics, DuplicateItems, ItemInfo, LegacyItem,
+    Metrics, PlannedChange, ServerInfo, SessionAlgorithm,
+};
+use crate::localize::Localization;
+use crate::mapping::{Mapper, borrow_attributes};
+use crate::mirror;
+use crate::service::{NewItem, Service};
+use crate::transform::TransformStage;
 
 /// Secret service credential store
 pub struct Store {
@@ -22,27 +40,897 @@ impl std::fmt::Debug for Store {
 }
 
 impl Store {
-    /// Create a new store.
-    ///
-    /// Stores are not configurable.
+    /// Create a new store with the default configuration.
     pub fn new() -> Result<Arc<Self>> {
-        Store::new_internal()
+        Store::new_internal(Config::default(), None)
     }
 
     /// Create a store with the specified configuration.
     ///
-    /// Stores are not currently configurable, this entry is provided
-    /// so configurability can be added without a major version change.
+    /// See the crate documentation for the supported configuration keys.
+    /// Unsupported keys produce a [NotSupportedByStore](Error::NotSupportedByStore) error.
     pub fn new_with_configuration(config: &HashMap<&str, &str>) -> Result<Arc<Self>> {
-        if !config.is_empty() {
-            return Err(Error::NotSupportedByStore(
-                "Secret Service configuration is not supported".to_string(),
+        Store::new_internal(Config::parse(config)?, None)
+    }
+
+    /// Start building a store.
+    ///
+    /// See [StoreBuilder] for the options that can be set.
+    pub fn builder() -> StoreBuilder {
+        StoreBuilder::default()
+    }
+
+    /// Create a store with the specified configuration whose helper D-Bus
+    /// calls share an existing session bus connection.
+    ///
+    /// This does *not* make the store use the given connection for its
+    /// Secret Service session. The underlying
+    /// [dbus-secret-service crate](https://crates.io/crates/dbus-secret-service)
+    /// doesn't accept a connection from its clients, so the session (and
+    /// every item operation made in it) always runs over a private connection
+    /// of its own. Only the D-Bus calls that this crate makes directly, such
+    /// as describing the Secret Service provider, watching for its restarts,
+    /// or showing prompts in a `prompt-window`, go over the given connection. An application that
+    /// hands over its connection therefore still has a second one open.
+    pub fn new_with_connection(
+        connection: dbus::blocking::Connection,
+        config: &HashMap<&str, &str>,
+    ) -> Result<Arc<Self>> {
+        Store::new_internal(Config::parse(config)?, Some(connection))
+    }
+
+    /// Create a store with the specified configuration or, if the Secret
+    /// Service is unavailable, an in-memory store.
+    ///
+    /// This is only available with the `memory-fallback` feature. Secrets in
+    /// the in-memory store last only as long as the process, which its
+    /// [persistence](CredentialStoreApi::persistence) reports as
+    /// [ProcessOnly](keyring_core::api::CredentialPersistence::ProcessOnly).
+    /// Configuration errors are returned rather than falling back.
+    #[cfg(feature = "memory-fallback")]
+    pub fn new_with_fallback(config: &HashMap<&str, &str>) -> Result<Arc<CredentialStore>> {
+        match Store::new_with_configuration(config) {
+            Ok(store) => Ok(store),
+            Err(Error::PlatformFailure(_)) | Err(Error::NoStorageAccess(_)) => {
+                Ok(crate::memory::Store::new())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Add a stage to the end of this store's secret transform pipeline.
+    ///
+    /// The stage applies to all credentials of this store, including
+    /// those created before the stage was added.
+    pub fn add_transform(&self, stage: Arc<TransformStage>) {
+        self.ss.pipeline.push(stage)
+    }
+
+    /// Add a hook that's called before each credential operation, and
+    /// can veto it by returning an error.
+    ///
+    /// See the [hooks](crate::hooks) module for details. To hook only
+    /// writes (say), check the [kind](crate::hooks::Access::kind) of access.
+    pub fn on_before(&self, hook: Arc<BeforeHook>) {
+        self.ss.hooks.add_before(hook)
+    }
+
+    /// Add a hook that's called after each credential operation that succeeds.
+    pub fn on_after(&self, hook: Arc<AfterHook>) {
+        self.ss.hooks.add_after(hook)
+    }
+
+    /// Add a hook that's called after each credential operation that fails.
+    pub fn on_error(&self, hook: Arc<ErrorHook>) {
+        self.ss.hooks.add_error(hook)
+    }
+
+    /// Replace the mapping between entry specifiers and item attributes.
+    ///
+    /// See the [mapping](crate::mapping) module for details. Items created
+    /// with one mapping generally can't be found with another, so set the
+    /// mapper before using the store.
+    ///
+    /// The salt of a store configured with `username-salt` belongs to its
+    /// default mapper, and a replacement wouldn't find the items hashed with
+    /// it, so such stores refuse a replacement with an
+    /// [Invalid](Error::Invalid) error. To hash users with a custom mapper,
+    /// configure the store without the salt and give the mapper the salt
+    /// (for example, with
+    /// [DefaultMapper::with_hashed_username](crate::mapping::DefaultMapper::with_hashed_username)).
+    pub fn set_attribute_mapper(&self, mapper: Arc<Mapper>) -> Result<()> {
+        if self.ss.config.username_salt.is_some() {
+            return Err(Error::Invalid(
+                "username-salt".to_string(),
+                "can't be used with a replacement attribute mapper".to_string(),
             ));
         }
-        Store::new_internal()
+        self.ss.set_mapper(mapper);
+        Ok(())
+    }
+
+    /// Replace the source of the text that this store generates.
+    ///
+    /// See the [localize](crate::localize) module for details. Default labels
+    /// are chosen when entries are built, so set the localizer before building
+    /// entries.
+    pub fn set_localizer(&self, localizer: Arc<Localization>) {
+        self.ss.set_localizer(localizer)
+    }
+
+    /// Describe an error returned by this store, using its localizer.
+    ///
+    /// Errors specific to this store are described by the localizer;
+    /// other errors are described by their own formatting.
+    pub fn describe_error(&self, err: &Error) -> String {
+        let inner = match err {
+            Error::PlatformFailure(inner) | Error::NoStorageAccess(inner) => {
+                inner.downcast_ref::<StoreError>()
+            }
+            _ => None,
+        };
+        match inner {
+            Some(err) => self.ss.localizer().describe(err),
+            None => err.to_string(),
+        }
+    }
+
+    /// Recreate the items recorded in a mirror file.
+    ///
+    /// Every item whose last record in the file isn't a deletion is
+    /// recreated in the collection it was last written to (which is created
+    /// if necessary), replacing any existing item with the same attributes.
+    /// See the [mirror](crate::mirror) module for details. Returns the
+    /// number of items restored.
+    pub fn restore_from_mirror(&self, path: &std::path::Path, key: &str) -> Result<usize> {
+        let records = mirror::surviving(mirror::read(path, key)?);
+        for record in records.iter() {
+            self.ss.restore_item(record).wait()?;
+        }
+        Ok(records.len())
+    }
+
+    /// Rewrite the items written by older versions of keyring-rs so that
+    /// this store's entries find them.
+    ///
+    /// Legacy items are those, in any collection, with an `application`
+    /// attribute of `rust-keyring` and `service` and `username` attributes
+    /// (see `legacy-items` in the [crate docs](crate)). Each is given the
+    /// attributes this store would give a new item for its service, user,
+    /// and target, keeping any others it has; its label and secret are
+    /// unchanged. A legacy item is left as it is if the store already has
+    /// another item for the same entry. Returns every legacy item found,
+    /// reporting whether it was migrated.
+    pub fn migrate_legacy(&self) -> Result<Vec<LegacyItem>> {
+        let legacy = ["service", "username", "target", "application"];
+        let mut report = Vec::new();
+        for item in self.ss.item_infos().wait()? {
+            let attributes = &item.attributes;
+            if attributes.get("application").map(|a| a.as_str()) != Some(LEGACY_APPLICATION) {
+                continue;
+            }
+            let (Some(service), Some(user)) =
+                (attributes.get("service"), attributes.get("username"))
+            else {
+                continue;
+            };
+            let (service, user) = (service.clone(), user.clone());
+            let modifiers = attributes
+                .get("target")
+                .map(|t| HashMap::from([("target", t.as_str())]));
+            let entry = self.build(&service, &user, modifiers.as_ref())?;
+            let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+            let path = dbus::Path::from(item.path.clone());
+            let search = specifier.search_attributes();
+            let existing = self
+                .ss
+                .peek_matching_items(None, &borrow_attributes(&search))
+                .wait()?;
+            let migrated = existing.iter().all(|p| *p == path);
+            if migrated {
+                let creation = specifier.creation_attributes();
+                let mut updated: HashMap<&str, &str> = attributes
+                    .iter()
+                    .filter(|(k, _)| !legacy.contains(&k.as_str()))
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                updated.extend(borrow_attributes(&creation));
+                self.ss.unlock_for_access(&path).wait()?;
+                self.ss.replace_attributes(&path, updated).wait()?;
+            }
+            report.push(LegacyItem {
+                item,
+                service,
+                user,
+                migrated,
+            });
+        }
+        Ok(report)
+    }
+
+    /// Write every item in a collection to an encrypted backup file.
+    ///
+    /// The collection is named the way a `target` modifier names it, and is
+    /// unlocked if need be. The file (which is replaced if it exists) holds
+    /// each item's label, attributes, and secret, protected by the
+    /// passphrase; see the [backup](crate::backup) module for details.
+    /// Returns the number of items written.
+    pub fn export_collection(
+        &self,
+        collection: &str,
+        path: &std::path::Path,
+        passphrase: &str,
+    ) -> Result<usize> {
+        let records = self.ss.export_items(collection).wait()?;
+        backup::write(path, passphrase, &records)?;
+        Ok(records.len())
+    }
+
+    /// Recreate the items in an encrypted backup file in a collection.
+    ///
+    /// The backup is one written by
+    /// [export_collection](Store::export_collection) with the same
+    /// passphrase. The collection is named the way a `target` modifier
+    /// names it, and is created if need be (unless the store has
+    /// `explicit-collections`); it needn't be the one the items were
+    /// exported from. Backed-up items with the same attributes as items
+    /// already in the collection are dealt with as the policy says.
+    /// Reports how many items were created, overwritten, and skipped.
+    pub fn import_backup(
+        &self,
+        path: &std::path::Path,
+        passphrase: &str,
+        collection: &str,
+        policy: ConflictPolicy,
+    ) -> Result<ImportSummary> {
+        let records = backup::read(path, passphrase)?;
+        self.ss
+            .import_items(collection, records, policy, false)
+            .wait()
+    }
+
+    /// Create items from the output of `secret-tool search --all`.
+    ///
+    /// The output (see [parse_secret_tool](backup::parse_secret_tool) for
+    /// its format) gives each item's label, attributes, and secret, and the
+    /// items are created in the collection as
+    /// [import_backup](Store::import_backup) creates them. Unlike those in
+    /// backups, the secrets are the ones applications see, so they're
+    /// stored by way of the store's [transform](crate::transform) pipeline.
+    pub fn import_secret_tool(
+        &self,
+        text: &str,
+        collection: &str,
+        policy: ConflictPolicy,
+    ) -> Result<ImportSummary> {
+        let records = backup::parse_secret_tool(text)?;
+        self.ss
+            .import_items(collection, records, policy, true)
+            .wait()
+    }
+
+    /// Search for items with exactly the given attributes, as they are stored.
+    ///
+    /// Unlike [search](CredentialStoreApi::search), this doesn't conceal the
+    /// values of opaque attributes, so it can reach any item in the Secret
+    /// Service, including items that don't follow this store's attribute
+    /// conventions. Every match is returned as an entry wrapping the item.
+    /// The secret and attributes of such entries can always be read, but
+    /// their specifiers are only available if the item follows this store's
+    /// attribute mapping.
+    pub fn search_raw(&self, attributes: &HashMap<&str, &str>) -> Result<Vec<Entry>> {
+        let paths = self.ss.find_stored_items(attributes).wait()?;
+        Ok(paths
+            .into_iter()
+            .map(|path| Entry::new_with_credential(Wrapper::new(self.ss.clone(), path)))
+            .collect())
+    }
+
+    /// Build an entry for an existing item, given its D-Bus object path,
+    /// without searching for it by attributes.
+    ///
+    /// This lets applications manage items created by other programs
+    /// (such as NetworkManager or web browsers), whatever their attributes.
+    /// The entry wraps the item, as the entries returned by
+    /// [search](CredentialStoreApi::search) do. Fails with
+    /// [Invalid](Error::Invalid) if the path isn't a D-Bus object path,
+    /// and with [NoEntry](Error::NoEntry) if there's no item at that path.
+    pub fn credential_from_path(&self, path: &str) -> Result<Entry> {
+        let path = match dbus::Path::new(path.to_string()) {
+            Ok(path) if path.starts_with('/') && !path.ends_with('/') => path,
+            _ => {
+                return Err(Error::Invalid(
+                    "path".to_string(),
+                    "must be a D-Bus object path".to_string(),
+                ));
+            }
+        };
+        let attributes = match self.ss.get_attributes(&path).wait() {
+            Ok(attributes) => attributes,
+            Err(err) if is_stale_path(&err) => return Err(Error::NoEntry),
+            Err(err) => return Err(err),
+        };
+        let wrapper = Wrapper::with_identity(self.ss.clone(), path, attributes);
+        Ok(Entry::new_with_credential(wrapper))
+    }
+
+    /// Find every item, in any collection, whose attributes this store's
+    /// attribute mapper can turn back into specifiers.
+    ///
+    /// That's every item created by a store with the same mapping (and by
+    /// other clients following its conventions). Each item is returned as
+    /// an entry wrapping it, whose
+    /// [get_specifiers](keyring_core::api::CredentialApi::get_specifiers)
+    /// reports its service and user. Listing doesn't prompt to unlock
+    /// anything. (Stores that hash usernames can't recover them, so they
+    /// list nothing.)
+    pub fn list_entries(&self) -> Result<Vec<Entry>> {
+        let mapper = self.ss.mapper();
+        let items = self.ss.item_infos().wait()?;
+        Ok(items
+            .into_iter()
+            .filter(|item| mapper.specifiers(&item.attributes).is_some())
+            .map(|item| {
+                let path = dbus::Path::from(item.path);
+                let wrapper = Wrapper::with_identity(self.ss.clone(), path, item.attributes);
+                Entry::new_with_credential(wrapper)
+            })
+            .collect())
+    }
+
+    /// Find the items, in any collection, that hold credentials for the
+    /// same service and user.
+    ///
+    /// Such duplicates make entries ambiguous; they're typically left by
+    /// bugs in older clients or by writing the same credential to several
+    /// collections. Items are matched by the service and user that this
+    /// store's attribute mapper finds in their attributes (so, as with
+    /// [list_entries](Store::list_entries), stores that hash usernames find
+    /// nothing). Finding duplicates doesn't prompt to unlock anything.
+    pub fn find_duplicates(&self) -> Result<Vec<DuplicateItems>> {
+        let mapper = self.ss.mapper();
+        let mut groups: Vec<DuplicateItems> = Vec::new();
+        for item in self.ss.item_infos().wait()? {
+            let Some((service, user)) = mapper.specifiers(&item.attributes) else {
+                continue;
+            };
+            match groups
+                .iter_mut()
+                .find(|g| g.service == service && g.user == user)
+            {
+                Some(group) => group.items.push(item),
+                None => groups.push(DuplicateItems {
+                    service,
+                    user,
+                    items: vec![item],
+                }),
+            }
+        }
+        groups.retain(|g| g.items.len() > 1);
+        for group in groups.iter_mut() {
+            let mut dated = Vec::with_capacity(group.items.len());
+            for item in group.items.drain(..) {
+                let path = dbus::Path::from(item.path.clone());
+                dated.push((self.ss.get_revision(&path).wait()?, item));
+            }
+            dated.sort_by(|a, b| (b.0, &b.1.path).cmp(&(a.0, &a.1.path)));
+            group.items = dated.into_iter().map(|(_, item)| item).collect();
+        }
+        Ok(groups)
+    }
+
+    /// Delete all but the most recently modified of each set of duplicates.
+    ///
+    /// The sets are those reported by
+    /// [find_duplicates](Store::find_duplicates), perhaps filtered by the
+    /// caller; the first item of each is kept. Any locked items are
+    /// unlocked together, as in [get_secrets](Store::get_secrets).
+    /// Returns the number of items deleted.
+    pub fn dedupe(&self, duplicates: &[DuplicateItems]) -> Result<usize> {
+        let paths: Vec<_> = duplicates
+            .iter()
+            .flat_map(|d| d.items.iter().skip(1))
+            .map(|item| dbus::Path::from(item.path.clone()))
+            .collect();
+        for outcome in self.ss.delete_items(&paths).wait()? {
+            outcome?;
+        }
+        Ok(paths.len())
+    }
+
+    /// Describe the collections in the Secret Service.
+    ///
+    /// Listing doesn't prompt to unlock anything.
+    pub fn list_collections(&self) -> Result<Vec<CollectionInfo>> {
+        self.ss.collection_infos().wait()
+    }
+
+    /// Retrieve the secrets of several entries at once.
+    ///
+    /// Any of the entries' items that are locked are unlocked together, so
+    /// the user sees at most one prompt however many entries there are, and
+    /// the secrets are then fetched in a single D-Bus call.
+    /// The result has one element per entry, in order: the entry's secret,
+    /// or the error that reading it alone would have reported (such as
+    /// [NoEntry](Error::NoEntry) or [Ambiguous](Error::Ambiguous)). The call
+    /// as a whole fails only if the Secret Service can't be used, or if an
+    /// entry doesn't belong to this kind of store.
+    pub fn get_secrets(&self, entries: &[&Entry]) -> Result<Vec<Result<Vec<u8>>>> {
+        let mut result: Vec<Result<Vec<u8>>> = Vec::with_capacity(entries.len());
+        let mut started = Vec::with_capacity(entries.len());
+        let mut found = Vec::new();
+        for entry in entries {
+            let item = entry_item(entry)?;
+            let hooks = self
+                .ss
+                .hooks
+                .start(|| entry_access(entry, AccessKind::Read, "get_secrets"));
+            let item = match hooks {
+                Ok(hooks) => {
+                    started.push((result.len(), hooks));
+                    item
+                }
+                Err(err) => Err(err),
+            };
+            match item {
+                Ok(path) => {
+                    found.push((result.len(), path));
+                    result.push(Err(Error::NoEntry));
+                }
+                Err(err) => result.push(Err(err)),
+            }
+        }
+        let paths: Vec<_> = found.iter().map(|(_, p)| p.clone()).collect();
+        let secrets = self.ss.get_secrets(&paths).wait()?;
+        for ((index, _), secret) in found.into_iter().zip(secrets) {
+            result[index] = secret;
+        }
+        for (index, hooks) in started {
+            hooks.finish(&result[index]);
+        }
+        Ok(result)
+    }
+
+    /// Set the secrets of several entries at once.
+    ///
+    /// Entries that have items get their secrets updated, with any locked
+    /// items unlocked together as in [get_secrets](Store::get_secrets).
+    /// Items for the others are created, looking up each collection only
+    /// once, in the same way as setting an entry's secret creates one.
+    /// (Unlike a single creation, though, a batch doesn't check for items
+    /// created concurrently by other processes, so `verify-creation` has no
+    /// effect on it.) The result has one element per entry, in order, with
+    /// the error (if any) that setting that entry's secret alone would have
+    /// reported. The call as a whole fails if the Secret Service can't be
+    /// used, if an entry doesn't belong to this kind of store, or if any new
+    /// item's attributes are invalid.
+    pub fn set_many(&self, secrets: &[(&Entry, &[u8])]) -> Result<Vec<Result<()>>> {
+        let mut result: Vec<Result<()>> = Vec::with_capacity(secrets.len());
+        let mut updates = Vec::new();
+        let mut creations: Vec<(String, Vec<usize>, Vec<_>)> = Vec::new();
+        let mut started = Vec::with_capacity(secrets.len());
+        for (entry, secret) in secrets {
+            let index = result.len();
+            result.push(Ok(()));
+            let specifier = entry.as_any().downcast_ref::<Specifier>();
+            let content_type = specifier.and_then(|s| s.content_type.clone());
+            let item = entry_item(entry)?;
+            match self
+                .ss
+                .hooks
+                .start(|| entry_access(entry, AccessKind::Write, "set_many"))
+            {
+                Ok(hooks) => started.push((index, hooks)),
+                Err(err) => {
+                    result[index] = Err(err);
+                    continue;
+                }
+            }
+            match item {
+                // stores that only create items don't update entries' existing ones
+                Ok(path) if specifier.is_some() && self.ss.config.create_if_absent => {
+                    result[index] = Err(already_exists(&path))
+                }
+                Ok(path) => {
+                    updates.push((index, (path, Zeroizing::new(secret.to_vec()), content_type)))
+                }
+                Err(Error::NoEntry) => {
+                    // only specifiers can be missing their items
+                    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+                    let collection = specifier.collection_name();
+                    let item = NewItem {
+                        label: specifier.label.clone(),
+                        attributes: specifier.creation_attributes(),
+                        secret: Zeroizing::new(secret.to_vec()),
+                        content_type,
+                    };
+                    match creations.iter_mut().find(|(c, _, _)| *c == collection) {
+                        Some((_, indices, items)) => {
+                            indices.push(index);
+                            items.push(item);
+                        }
+                        None => creations.push((collection, vec![index], vec![item])),
+                    }
+                }
+                Err(err) => result[index] = Err(err),
+            }
+        }
+        let (indices, updates): (Vec<usize>, Vec<_>) = updates.into_iter().unzip();
+        for (index, outcome) in indices
+            .into_iter()
+            .zip(self.ss.set_secrets(updates).wait()?)
+        {
+            result[index] = outcome;
+        }
+        for (collection, indices, items) in creations {
+            let created = self.ss.create_items(&collection, items).wait()?;
+            for (index, outcome) in indices.into_iter().zip(created) {
+                result[index] = outcome.map(|_| ());
+            }
+        }
+        for (index, hooks) in started {
+            hooks.finish(&result[index]);
+        }
+        Ok(result)
+    }
+
+    /// Delete the items of several entries at once.
+    ///
+    /// Any locked items are unlocked together, as in
+    /// [get_secrets](Store::get_secrets). The result has one element per
+    /// entry, in order, with the error (if any) that deleting that entry's
+    /// credential alone would have reported. The call as a whole fails only
+    /// if the Secret Service can't be used, or if an entry doesn't belong
+    /// to this kind of store.
+    pub fn delete_many(&self, entries: &[&Entry]) -> Result<Vec<Result<()>>> {
+        let mut result: Vec<Result<()>> = Vec::with_capacity(entries.len());
+        let mut started = Vec::with_capacity(entries.len());
+        let mut found = Vec::new();
+        for entry in entries {
+            let item = entry_item(entry)?;
+            let hooks = self
+                .ss
+                .hooks
+                .start(|| entry_access(entry, AccessKind::Delete, "delete_many"));
+            let item = match hooks {
+                Ok(hooks) => {
+                    started.push((result.len(), hooks));
+                    item
+                }
+                Err(err) => Err(err),
+            };
+            match item {
+                Ok(path) => {
+                    found.push((result.len(), path));
+                    result.push(Ok(()));
+                }
+                Err(err) => result.push(Err(err)),
+            }
+        }
+        let paths: Vec<_> = found.iter().map(|(_, p)| p.clone()).collect();
+        let deleted = self.ss.delete_items(&paths).wait()?;
+        for ((index, _), outcome) in found.into_iter().zip(deleted) {
+            result[index] = outcome;
+        }
+        for (index, hooks) in started {
+            hooks.finish(&result[index]);
+        }
+        Ok(result)
+    }
+
+    /// Listen for changes to items in the Secret Service, by any client.
+    ///
+    /// The subscription uses a bus connection of its own, so it doesn't
+    /// slow down the store's operations. See [events](crate::events).
+    pub fn subscribe(&self) -> Result<Subscription> {
+        let (sender, events) = std::sync::mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        bus::watch_items(bus::connect()?, sender, stop.clone())?;
+        Ok(Subscription { events, stop })
+    }
+
+    /// Call back whenever the item of one of the given entries is created,
+    /// changed or deleted, by this or any other client.
+    ///
+    /// The callback is given the index of the entry (in `entries`) and what
+    /// happened to its item. It's called on a thread of the watch's own, so
+    /// it mustn't take long. An entry's item is recognized by its attributes,
+    /// or (for a credential that wraps an item) by its path. See
+    /// [events](crate::events) for details, and [Watch] for how to stop.
+    pub fn watch(
+        &self,
+        entries: &[&Entry],
+        callback: impl FnMut(usize, ItemEventKind) + Send + 'static,
+    ) -> Result<Watch> {
+        let mut watched = Vec::with_capacity(entries.len());
+        for entry in entries {
+            // watching an entry doesn't unlock or choose between its items
+            let path = match entry.as_any().downcast_ref::<Specifier>() {
+                Some(specifier) => specifier.peek_item().ok(),
+                None => entry_item(entry)?.ok(),
+            };
+            let path = path.map(|p| p.to_string());
+            let attributes = entry.as_any().downcast_ref::<Specifier>().map(|s| {
+                self.ss
+                    .config
+                    .opaque
+                    .conceal(&borrow_attributes(&s.search_attributes()))
+            });
+            watched.push(Watched { attributes, path });
+        }
+        let subscription = self.subscribe()?;
+        Watch::start(subscription, watched, callback)
+            .map_err(|e| Error::PlatformFailure(Box::new(e)))
+    }
+
+    /// Forget every secret in the store's secret cache, so that entries
+    /// read their secrets from the Secret Service again.
+    ///
+    /// Stores configured with `secret-cache-ttl` flush the cache whenever
+    /// they write, but they don't notice changes made by other clients
+    /// until cached secrets expire. Applications that learn of such a
+    /// change (for instance, from a [Watch]) can flush the cache sooner.
+    /// Does nothing in stores without a cache.
+    pub fn flush_cache(&self) {
+        self.ss.flush_cache()
+    }
+
+    /// Returns the D-Bus path of the collection an alias (such as `default`
+    /// or `login`) refers to, or `None` if the alias isn't set.
+    pub fn read_alias(&self, alias: &str) -> Result<Option<String>> {
+        self.ss.read_alias(alias).wait()
+    }
+
+    /// Make an alias refer to a collection.
+    ///
+    /// The collection is named as in [collection_items](Store::collection_items).
+    /// Setting the `default` alias makes the collection the one used by entries
+    /// without a target. Fails with [NoEntry](Error::NoEntry) if there's no
+    /// such collection.
+    pub fn set_alias(&self, alias: &str, collection: &str) -> Result<()> {
+        self.ss.set_alias(alias, collection).wait()
+    }
+
+    /// Lock a collection, so that reading its secrets requires unlocking it.
+    ///
+    /// The collection is named as in [collection_items](Store::collection_items).
+    /// Applications can use this to lock a collection when they've finished
+    /// with the secrets in it. Fails with [NoEntry](Error::NoEntry) if there's
+    /// no such collection.
+    pub fn lock_collection(&self, collection: &str) -> Result<()> {
+        self.ss.lock_collection(collection).wait()
+    }
+
+    /// Unlock a collection, prompting the user if necessary.
+    ///
+    /// The collection is named as in [collection_items](Store::collection_items).
+    /// Applications can use this before a batch of operations, so that any
+    /// prompt comes at a time of their choosing rather than in the middle of
+    /// the batch. Fails with [NoEntry](Error::NoEntry) if there's no such
+    /// collection, or with [NoStorageAccess](Error::NoStorageAccess) if the
+    /// user dismisses the prompt.
+    pub fn unlock_collection(&self, collection: &str) -> Result<()> {
+        self.ss.unlock_collection(collection).wait()
+    }
+
+    /// Describe every item in a collection, whoever created it.
+    ///
+    /// The collection is named the way a `target` or `collection` modifier
+    /// names it: `default` for the default collection, a D-Bus path,
+    /// `alias:<alias>`, or (otherwise) a label. Listing doesn't prompt to unlock anything;
+    /// attributes are reported as stored. Fails with
+    /// [NoEntry](Error::NoEntry) if there's no such collection.
+    pub fn collection_items(&self, collection: &str) -> Result<Vec<ItemInfo>> {
+        self.ss.collection_item_infos(collection).wait()
+    }
+
+    /// Check whether the Secret Service can be used, without creating a store.
+    ///
+    /// The probe checks that the session bus can be reached, that a Secret
+    /// Service daemon is running (or can be started on demand), that a
+    /// session can be opened, and that there is a default collection.
+    /// Applications can use it to decide whether to fall back to another
+    /// credential store. See [Availability] for the report.
+    ///
+    /// The session is opened as a store with the default configuration
+    /// would open it; use [probe_with_configuration](Store::probe_with_configuration)
+    /// to check for a store configured otherwise.
+    pub fn probe() -> Availability {
+        Store::probe_config(&Config::default())
+    }
+
+    /// Check whether the Secret Service can be used by a store with the
+    /// specified configuration, without creating the store.
+    ///
+    /// This is like [probe](Store::probe), except that the session is
+    /// opened as the configuration says (for instance, a configuration that
+    /// sets `allow-plain-fallback` is available if only a plain session can
+    /// be opened). Invalid configurations produce the same errors as
+    /// [new_with_configuration](Store::new_with_configuration).
+    pub fn probe_with_configuration(config: &HashMap<&str, &str>) -> Result<Availability> {
+        Ok(Store::probe_config(&Config::parse(config)?))
+    }
+
+    fn probe_config(config: &Config) -> Availability {
+        let mut report = Availability::default();
+        let conn = match bus::connect() {
+            Ok(conn) => conn,
+            Err(err) => {
+                report.problem = Some(format!("Can't reach the session bus: {err}"));
+                return report;
+            }
+        };
+        report.bus = true;
+        let running = bus::name_owner(&conn).is_ok();
+        if !running && !bus::is_activatable(&conn).unwrap_or(false) {
+            report.problem = Some("No Secret Service daemon is running or activatable".to_string());
+            return report;
+        }
+        report.service = true;
+        let ss = match crate::service::negotiate(config) {
+            Ok((ss, algorithm, _)) => {
+                report.session = Some(algorithm);
+                ss
+            }
+            Err(err) => {
+                report.problem = Some(format!("Can't open a session: {err}"));
+                return report;
+            }
+        };
+        match ss.get_default_collection() {
+            Ok(_) => report.default_collection = true,
+            Err(err) => report.problem = Some(format!("There is no default collection: {err}")),
+        }
+        report
+    }
+
+    /// Check whether the Secret Service can be used.
+    ///
+    /// This is shorthand for `Store::probe().is_available()`.
+    pub fn is_available() -> bool {
+        Store::probe().is_available()
+    }
+
+    /// Describe the process that provides the Secret Service.
+    ///
+    /// This is the information needed to diagnose problems that
+    /// only occur with particular Secret Service implementations.
+    pub fn server_info(&self) -> Result<ServerInfo> {
+        let (owner, pid, interfaces, names) = self.ss.with_bus(|conn| {
+            let owner = bus::name_owner(conn)?;
+            let pid = bus::process_id(conn, &owner);
+            let interfaces = bus::interfaces(conn, bus::SECRETS_PATH)?;
+            let names = bus::owned_names(conn, &owner);
+            Ok((owner, pid, interfaces, names))
+        })?;
+        let process_name = pid.and_then(bus::process_name);
+        let backend = Backend::detect(&interfaces, &names, process_name.as_deref());
+        let collection_count = self.ss.collection_count().wait()?;
+        Ok(ServerInfo {
+            owner,
+            pid,
+            process_name,
+            interfaces,
+            names,
+            backend,
+            collection_count,
+        })
+    }
+
+    /// Identify the Secret Service implementation, so callers can work
+    /// around its quirks.
+    ///
+    /// See [Backend] for how it's identified.
+    pub fn backend(&self) -> Result<Backend> {
+        Ok(self.server_info()?.backend)
+    }
+
+    /// Gather everything relevant to diagnosing a problem with the store
+    /// into one report.
+    ///
+    /// The report's [Display](std::fmt::Display) format is meant to be
+    /// pasted into bug reports. See [Diagnostics] for its contents. Parts
+    /// of the report that can't be gathered are left out, and the report
+    /// says why, so a report is made however broken the Secret Service is.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut problems = Vec::new();
+        let server = match self.server_info() {
+            Ok(server) => Some(server),
+            Err(err) => {
+                problems.push(format!("Can't describe the server: {err}"));
+                None
+            }
+        };
+        let collections = match self.ss.collection_infos().wait() {
+            Ok(collections) => Some(collections),
+            Err(err) => {
+                problems.push(format!("Can't list the collections: {err}"));
+                None
+            }
+        };
+        Diagnostics {
+            bus_address: std::env::var("DBUS_SESSION_BUS_ADDRESS").ok(),
+            server,
+            collections,
+            session_algorithm: self.ss.negotiated().0,
+            session_downgraded: self.ss.negotiated().1,
+            session_renewals: self.ss.renewals(),
+            connections: 1 + self.ss.config.read_workers,
+            error_counts: self.ss.error_counts(),
+            problems,
+        }
     }
 
-    fn new_internal() -> Result<Arc<Self>> {
+    /// Take a snapshot of the store's operation metrics: how many of each
+    /// kind of operation it has run, how many failed and how long they
+    /// took, how many unlock requests (and so potential prompts) it has
+    /// made, and its errors by kind.
+    ///
+    /// Unlike [diagnostics](Store::diagnostics), this doesn't talk to the
+    /// Secret Service, so it's cheap enough to poll. See [Metrics].
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            operations: self.ss.metrics.snapshot(),
+            unlock_requests: self.ss.metrics.unlock_requests(),
+            error_counts: self.ss.error_counts(),
+            session_renewals: self.ss.renewals(),
+        }
+    }
+
+    /// Report how secrets are protected on their way to and from the Secret Service.
+    ///
+    /// A store configured with `lazy-connect` connects in order to find out.
+    pub fn session_algorithm(&self) -> SessionAlgorithm {
+        self.ss.negotiated().0
+    }
+
+    /// Report whether the store fell back to a plain session.
+    ///
+    /// This can only happen if the store was configured with
+    /// `allow-plain-fallback` and the Secret Service refused to
+    /// negotiate an encrypted session.
+    pub fn session_downgraded(&self) -> bool {
+        self.ss.negotiated().1
+    }
+
+    /// Run a function in which this thread's blocking operations on any
+    /// store time out after the given duration, rather than the store's
+    /// `operation-timeout`.
+    ///
+    /// An operation that times out fails with a
+    /// [TimedOut](StoreError::TimedOut) error.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use dbus_secret_service_keyring_store::Store;
+    /// # use keyring_core::api::CredentialStoreApi;
+    /// let store = Store::new().unwrap();
+    /// let entry = store.build("service", "user", None).unwrap();
+    /// let password = Store::with_timeout(Duration::from_secs(1), || entry.get_password());
+    /// ```
+    pub fn with_timeout<T>(timeout: Duration, f: impl FnOnce() -> T) -> T {
+        crate::service::with_timeout(timeout, f)
+    }
+
+    /// Take the changes that this store, if configured with `dry-run`,
+    /// would have made since its plan was last taken, in the order it
+    /// would have made them. Stores that aren't dry runs plan nothing.
+    pub fn take_plan(&self) -> Vec<PlannedChange> {
+        self.ss.take_plan()
+    }
+
+    /// Report how many times the store has renewed its session.
+    ///
+    /// Sessions are only renewed by stores configured with
+    /// `rekey-after-operations` or `rekey-after-seconds`.
+    pub fn session_renewals(&self) -> u64 {
+        self.ss.renewals()
+    }
+
+    fn new_internal(
+        config: Config,
+        connection: Option<dbus::blocking::Connection>,
+    ) -> Result<Arc<Self>> {
         let now = SystemTime::now();
         let elapsed = if now.lt(&UNIX_EPOCH) {
             UNIX_EPOCH.duration_since(now).unwrap()
@@ -54,11 +942,255 @@ impl Store {
             env!("CARGO_PKG_VERSION"),
             elapsed.as_secs_f64()
         );
-        let ss = Arc::new(Service::new()?);
+        let ss = Arc::new(Service::new(config, connection)?);
         Ok(Arc::new(Store { id, ss }))
     }
 }
 
+/// A builder for stores with non-default options.
+///
+/// Every option corresponds to a configuration key (see the crate
+/// documentation), and [option](StoreBuilder::option) sets any key
+/// by name, so the builder can configure everything that
+/// [Store::new_with_configuration] can.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use dbus_secret_service_keyring_store::{Store, info::SessionAlgorithm};
+/// let store = Store::builder()
+///     .encryption(SessionAlgorithm::DhAes)
+///     .prompt_timeout(Duration::from_secs(30))
+///     .default_target("my-app")
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct StoreBuilder {
+    config: HashMap<String, String>,
+    connection: Option<dbus::blocking::Connection>,
+}
+
+impl std::fmt::Debug for StoreBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // keys don't belong in logs
+        let config: HashMap<&str, &str> = self
+            .config
+            .iter()
+            .map(|(k, v)| match k.as_str() {
+                "envelope-key" | "mirror-key" | "opaque-key" => (k.as_str(), "<redacted>"),
+                _ => (k.as_str(), v.as_str()),
+            })
+            .collect();
+        f.debug_struct("StoreBuilder")
+            .field("config", &config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StoreBuilder {
+    /// Set a configuration key, as [Store::new_with_configuration] would.
+    ///
+    /// Keys are checked when the store is built.
+    pub fn option(mut self, key: &str, value: &str) -> Self {
+        self.config.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Choose how secrets are protected in transit (the `encryption` key).
+    pub fn encryption(self, algorithm: SessionAlgorithm) -> Self {
+        self.option("encryption", algorithm.name())
+    }
+
+    /// Retry searches, reads and unlocks that fail transiently up to `attempts` times,
+    /// waiting `backoff` before the first retry and twice as long before
+    /// each later one (the `retry-attempts` and `retry-backoff` keys).
+    /// The number of attempts must be positive.
+    pub fn retry(self, attempts: u32, backoff: Duration) -> Self {
+        let millis = backoff.as_millis().max(1);
+        self.option("retry-attempts", &attempts.to_string())
+            .option("retry-backoff", &millis.to_string())
+    }
+
+    /// Limit how long a caller waits for an operation to finish
+    /// (the `operation-timeout` key).
+    pub fn operation_timeout(self, timeout: Duration) -> Self {
+        let millis = timeout.as_millis().max(1);
+        self.option("operation-timeout", &millis.to_string())
+    }
+
+    /// Lock items again after reading, writing or deleting them, if
+    /// this store had to unlock them (the `relock-after-access` key).
+    pub fn relock_after_access(self, relock: bool) -> Self {
+        self.option("relock-after-access", &relock.to_string())
+    }
+
+    /// Search for an entry's item only in the collection it would be
+    /// created in (the `scoped-search` key).
+    pub fn scoped_search(self, scoped: bool) -> Self {
+        self.option("scoped-search", &scoped.to_string())
+    }
+
+    /// Fail with [MissingCollection](crate::errors::StoreError::MissingCollection) instead
+    /// of creating a target's collection when an item is first stored in it
+    /// (the `explicit-collections` key).
+    pub fn explicit_collections(self, explicit: bool) -> Self {
+        self.option("explicit-collections", &explicit.to_string())
+    }
+
+    /// Create a default collection if the Secret Service doesn't have one,
+    /// as on WSL (the `create-default-collection` key).
+    pub fn create_default_collection(self, create: bool) -> Self {
+        self.option("create-default-collection", &create.to_string())
+    }
+
+    /// Have entries fall back to items written by older versions of
+    /// keyring-rs (the `legacy-items` key).
+    pub fn legacy_items(self, find: bool) -> Self {
+        self.option("legacy-items", &find.to_string())
+    }
+
+    /// Plan changes to the Secret Service rather than make them
+    /// (the `dry-run` key). See [Store::take_plan].
+    pub fn dry_run(self, dry_run: bool) -> Self {
+        self.option("dry-run", &dry_run.to_string())
+    }
+
+    /// Create items the way the Python keyring package does, so that
+    /// Python programs can share them (the `python-keyring` key).
+    pub fn python_keyring(self, compatible: bool) -> Self {
+        self.option("python-keyring", &compatible.to_string())
+    }
+
+    /// Stamp created items with the given libsecret schema, and only
+    /// find items that have it (the `xdg-schema` key). The schema
+    /// libsecret uses for generic passwords is `org.freedesktop.Secret.Generic`.
+    pub fn xdg_schema(self, schema: &str) -> Self {
+        self.option("xdg-schema", schema)
+    }
+
+    /// Make setting an entry's secret fail with an
+    /// [AlreadyExists](StoreError::AlreadyExists) error, rather than
+    /// replace the secret, if the entry's item exists (the
+    /// `create-if-absent` key).
+    pub fn create_if_absent(self, create_only: bool) -> Self {
+        self.option("create-if-absent", &create_only.to_string())
+    }
+
+    /// Have entries remember the paths of their items rather than search
+    /// for them on every operation (the `cache-item-paths` key).
+    pub fn cache_item_paths(self, cache: bool) -> Self {
+        self.option("cache-item-paths", &cache.to_string())
+    }
+
+    /// Cache the secrets that entries read for the given time (the
+    /// `secret-cache-ttl` key).
+    pub fn secret_cache_ttl(self, ttl: Duration) -> Self {
+        let millis = ttl.as_millis().max(1);
+        self.option("secret-cache-ttl", &millis.to_string())
+    }
+
+    /// Run read operations on a pool of workers of the given size, so they
+    /// don't wait for each other or for other operations (the
+    /// `read-workers` key).
+    pub fn read_workers(self, count: usize) -> Self {
+        self.option("read-workers", &count.to_string())
+    }
+
+    /// Wait until the first operation to connect to the Secret Service
+    /// (the `lazy-connect` key).
+    pub fn lazy_connect(self, lazy: bool) -> Self {
+        self.option("lazy-connect", &lazy.to_string())
+    }
+
+    /// If the Secret Service can't negotiate an encrypted session, use a
+    /// plain one instead of failing (the `allow-plain-fallback` key).
+    pub fn allow_plain_fallback(self, allow: bool) -> Self {
+        self.option("allow-plain-fallback", &allow.to_string())
+    }
+
+    /// Fail with a [WouldPrompt](crate::errors::StoreError::WouldPrompt)
+    /// error rather than prompt the user to unlock anything (the
+    /// `no-prompt` key).
+    pub fn no_prompt(self, no_prompt: bool) -> Self {
+        self.option("no-prompt", &no_prompt.to_string())
+    }
+
+    /// Show unlock prompts as children of an application window (the
+    /// `prompt-window` key), given its platform-specific identifier.
+    pub fn prompt_window(self, window: &str) -> Self {
+        self.option("prompt-window", window)
+    }
+
+    /// Limit how long the store waits for the user to answer an unlock
+    /// prompt (the `prompt-timeout` key). A zero timeout dismisses
+    /// prompts immediately; others are rounded up to whole seconds.
+    pub fn prompt_timeout(self, timeout: Duration) -> Self {
+        let seconds = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        self.option("prompt-timeout", &seconds.to_string())
+    }
+
+    /// Limit how long an operation may wait for the store's other operations
+    /// before it starts (the `operation-deadline` key).
+    pub fn operation_deadline(self, deadline: Duration) -> Self {
+        let millis = deadline.as_millis().max(1);
+        self.option("operation-deadline", &millis.to_string())
+    }
+
+    /// Use this target for entries built without a `target` or `collection`
+    /// modifier (the `default-target` key).
+    pub fn default_target(self, target: &str) -> Self {
+        self.option("default-target", target)
+    }
+
+    /// Make the store's helper D-Bus calls (but not its Secret Service
+    /// session) go over an existing connection, as
+    /// [Store::new_with_connection] does.
+    pub fn connection(mut self, connection: dbus::blocking::Connection) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    /// Build the store.
+    pub fn build(self) -> Result<Arc<Store>> {
+        let config: HashMap<&str, &str> = self
+            .config
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        Store::new_internal(Config::parse(&config)?, self.connection)
+    }
+}
+
+/// Find the item of an entry for a batch operation, as reading the entry
+/// alone would find it, but without unlocking anything.
+///
+/// The outer error (which fails the whole batch) is for entries that
+/// weren't created by a Secret Service store; the inner one is the
+/// entry's own.
+fn entry_item(entry: &Entry) -> Result<Result<dbus::Path<'static>>> {
+    if let Some(specifier) = entry.as_any().downcast_ref::<Specifier>() {
+        Ok(specifier.batch_item())
+    } else if let Some(wrapper) = entry.as_any().downcast_ref::<Wrapper>() {
+        Ok(Ok(wrapper.path()))
+    } else {
+        Err(Error::Invalid(
+            "entry".to_string(),
+            "was not created by a Secret Service store".to_string(),
+        ))
+    }
+}
+
+/// Describe an access to an entry's credential, for the store's hooks.
+fn entry_access(entry: &Entry, kind: AccessKind, method: &'static str) -> Access {
+    if let Some(specifier) = entry.as_any().downcast_ref::<Specifier>() {
+        specifier.access(kind, method)
+    } else if let Some(wrapper) = entry.as_any().downcast_ref::<Wrapper>() {
+        wrapper.access(kind, method)
+    } else {
+        panic!("Entry failure in credential store: please report a bug")
+    }
+}
+
 impl CredentialStoreApi for Store {
     fn vendor(&self) -> String {
         "Secret Service store, https://crates.io/crates/dbus-secret-service-keyring-store"
@@ -76,7 +1208,23 @@ impl CredentialStoreApi for Store {
         user: &str,
         modifiers: Option<&HashMap<&str, &str>>,
     ) -> Result<Entry> {
-        let mods = parse_attributes(&["target", "label"], modifiers)?;
+        // modifiers other than these become attributes of the entry's item
+        let names = ["target", "label", "collection", "content-type"];
+        let mut known = HashMap::new();
+        let mut attributes = HashMap::new();
+        for (key, value) in modifiers.into_iter().flatten() {
+            if names.contains(key) {
+                known.insert(*key, *value);
+            } else if key.is_empty() || self.ss.is_controlled(key) {
+                return Err(Error::Invalid(
+                    key.to_string(),
+                    "cannot be used as an attribute".to_string(),
+                ));
+            } else {
+                attributes.insert(key.to_string(), value.to_string());
+            }
+        }
+        let mods = parse_attributes(&names, Some(&known))?;
         let label = mods.get("label").map(|s| s.as_str());
         if label.map(|l| l.is_empty()).unwrap_or(false) {
             return Err(Error::Invalid(
@@ -84,17 +1232,48 @@ impl CredentialStoreApi for Store {
                 "cannot be empty".to_string(),
             ));
         }
-        let target = mods.get("target").map(|s| s.as_str());
+        let mut target = mods.get("target").map(|s| s.as_str());
         if target.map(|t| t.is_empty()).unwrap_or(false) {
             return Err(Error::Invalid(
                 "target".to_string(),
                 "cannot be empty".to_string(),
             ));
         }
-        Ok(Entry::new_with_credential(Specifier::new(
-            self.ss.clone(),
+        let collection = mods.get("collection").map(|s| s.as_str());
+        if let Some(path) = collection {
+            if target.is_some() {
+                return Err(Error::Invalid(
+                    "collection".to_string(),
+                    "cannot be combined with target".to_string(),
+                ));
+            }
+            if !path.starts_with('/') || dbus::Path::new(path).is_err() {
+                return Err(Error::Invalid(
+                    "collection".to_string(),
+                    "must be a D-Bus object path".to_string(),
+                ));
+            }
+        }
+        let content_type = mods.get("content-type").map(|s| s.as_str());
+        if content_type.map(|t| !t.contains('/')).unwrap_or(false) {
+            return Err(Error::Invalid(
+                "content-type".to_string(),
+                "must be a MIME type".to_string(),
+            ));
+        }
+        if target.is_none() && collection.is_none() {
+            target = self.ss.config.default_target.as_deref();
+        }
+        let modifiers = Modifiers {
             label,
             target,
+            collection,
+            content_type,
+            attributes,
+        };
+        Ok(Entry::new_with_credential(Specifier::new(
+            self.ss.clone(),
+            modifiers,
             service,
             user,
         )))
@@ -103,11 +1282,15 @@ impl CredentialStoreApi for Store {
     /// See the keyring-core API docs.
     ///
     /// All the key-value pairs are interpreted as attribute/value pairs to
-    /// search for (case-sensitive) in the underlying secret service
+    /// search for (case-sensitive) in the underlying secret service.
+    /// Any attributes can be searched for, not just the ones derived from
+    /// specifiers, so this can discover (say) every item tagged with an
+    /// `application` attribute. Every matching item is returned as an
+    /// entry wrapping it, whoever created it.
     /// If the search matches locked items, the search will prompt the user
     /// to unlock them before returning.
     fn search(&self, spec: &HashMap<&str, &str>) -> Result<Vec<Entry>> {
-        let paths = self.ss.find_matching_items(spec)?;
+        let paths = self.ss.find_matching_items(spec).wait()?;
         let mut results = Vec::with_capacity(paths.len());
         for path in paths {
             results.push(Entry::new_with_credential(Wrapper::new(
@@ -123,6 +1306,20 @@ impl CredentialStoreApi for Store {
         self
     }
 
+    /// Items created by stores configured with `session-collection` last
+    /// until the user logs out; otherwise, they last until they are deleted.
+    ///
+    /// Entries whose `target` or `collection` modifier overrides the
+    /// store's choice of collection can report their own persistence with
+    /// [Specifier::persistence], and wrapped items with [Wrapper::persistence].
+    fn persistence(&self) -> CredentialPersistence {
+        if self.ss.config.session_collection {
+            CredentialPersistence::UntilLogout
+        } else {
+            CredentialPersistence::UntilDelete
+        }
+    }
+
     fn debug_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Debug::fmt(self, f)
     }
@@ -1,9 +1,15 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Once};
+use std::sync::{Arc, Mutex, Once, mpsc};
 
 use super::{Store, cred::Specifier};
 use crate::cred::Wrapper;
-use keyring_core::{CredentialStore, Entry, Error, api::CredentialPersistence, get_default_store};
+use crate::errors::StoreError;
+use crate::info::{Backend, PlannedChange, SessionAlgorithm};
+use crate::localize::Localizer;
+use crate::mapping::{AttributeMapper, DefaultMapper};
+use crate::transform::Transform;
+use keyring_core::api::{CredentialPersistence, CredentialStoreApi};
+use keyring_core::{CredentialStore, Entry, Error, get_default_store};
 
 static SET_STORE: Once = Once::new();
 
@@ -45,6 +51,30 @@ fn entry_new_with_target(target: &str, service: &str, user: &str) -> Entry {
     })
 }
 
+/// Run a future to completion on the current thread.
+///
+/// The store's futures don't depend on a runtime, so this is all that
+/// tests need to await them.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
 fn generate_random_string() -> String {
     use fastrand;
     use std::iter::repeat_with;
@@ -291,6 +321,34 @@ fn test_entries_with_and_without_targets() {
         .unwrap_err();
 }
 
+#[test]
+fn test_protected_collections() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let default_path = store.read_alias("default").unwrap().unwrap();
+    let session_path = store.read_alias("session").unwrap().unwrap();
+    for target in [
+        "alias:default",
+        "alias:session",
+        "/org/freedesktop/secrets/aliases/default",
+        &default_path,
+        &session_path,
+    ] {
+        let modifiers = HashMap::from([("target", target)]);
+        let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+        let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+        assert!(
+            matches!(
+                specifier.delete_target(),
+                Err(Error::NotSupportedByStore(_))
+            ),
+            "{target}"
+        );
+    }
+    assert_eq!(store.read_alias("default").unwrap().unwrap(), default_path);
+    assert_eq!(store.read_alias("session").unwrap().unwrap(), session_path);
+}
+
 #[test]
 fn test_create_then_move() {
     let name = generate_random_string();
@@ -433,3 +491,3420 @@ fn test_persistence() {
         CredentialPersistence::UntilDelete
     ));
 }
+
+#[test]
+fn test_item_snapshot() {
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert!(matches!(specifier.get_snapshot(false), Err(Error::NoEntry)));
+    entry.set_password("snapshot").unwrap();
+    let snapshot = specifier.get_snapshot(false).unwrap();
+    assert_eq!(snapshot.label, specifier.get_label().unwrap());
+    assert_eq!(snapshot.attributes, entry.get_attributes().unwrap());
+    assert_eq!(snapshot.modified, specifier.get_revision().unwrap());
+    assert!(snapshot.created <= snapshot.modified);
+    assert!(!snapshot.locked);
+    assert!(snapshot.secret.is_none());
+    let snapshot = specifier.get_snapshot(true).unwrap();
+    assert_eq!(snapshot.secret.as_deref(), Some(b"snapshot".as_slice()));
+    assert!(!format!("{snapshot:?}").contains("snapshot\""));
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        wrapper.get_snapshot(true).unwrap().secret.unwrap(),
+        b"snapshot"
+    );
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_content_type() {
+    use dbus_secret_service::{EncryptionType, Item, SecretService};
+
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    entry.set_password("typed").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        specifier.get_content_type().unwrap(),
+        "application/octet-stream"
+    );
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let path = entry.get_credential().unwrap();
+    let path = path.as_any().downcast_ref::<Wrapper>().unwrap().path();
+    Item::new(&ss, path)
+        .set_secret(b"typed elsewhere", "application/json")
+        .unwrap();
+    // updates keep the content type the item has
+    entry.set_password("typed again").unwrap();
+    assert_eq!(specifier.get_content_type().unwrap(), "application/json");
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(wrapper.get_content_type().unwrap(), "application/json");
+    assert_eq!(entry.get_password().unwrap(), "typed again");
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_content_type_modifier() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let modifiers = HashMap::from([("content-type", "application/json")]);
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    entry.set_password("{}").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert_eq!(specifier.get_content_type().unwrap(), "application/json");
+    specifier.set_content_type("text/plain").unwrap();
+    assert_eq!(specifier.get_content_type().unwrap(), "text/plain");
+    assert_eq!(entry.get_password().unwrap(), "{}");
+    // setting the secret honors the modifier
+    entry.set_password("[]").unwrap();
+    assert_eq!(specifier.get_content_type().unwrap(), "application/json");
+    entry.delete_credential().unwrap();
+    let modifiers = HashMap::from([("content-type", "json")]);
+    assert!(matches!(
+        store.build(&name, &name, Some(&modifiers)),
+        Err(Error::Invalid(key, _)) if key == "content-type"
+    ));
+}
+
+#[test]
+fn test_item_timestamps() {
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert!(matches!(specifier.get_created(), Err(Error::NoEntry)));
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    entry.set_password("timestamped").unwrap();
+    let created = specifier.get_created().unwrap();
+    let modified = specifier.get_modified().unwrap();
+    assert!(created + 1 >= now && created <= modified);
+    assert_eq!(modified, specifier.get_revision().unwrap());
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(wrapper.get_created().unwrap(), created);
+    assert_eq!(wrapper.get_modified().unwrap(), modified);
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_attribute_modifiers() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let modifiers = HashMap::from([("application", "tests"), ("environment", "ci")]);
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    entry.set_password("tagged").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["application"], "tests");
+    assert_eq!(attributes["environment"], "ci");
+    // the tags aren't needed to find the item
+    assert_eq!(entry_new(&name, &name).get_password().unwrap(), "tagged");
+    entry.delete_credential().unwrap();
+    let modifiers = HashMap::from([("service", "other")]);
+    assert!(matches!(
+        store.build(&name, &name, Some(&modifiers)),
+        Err(Error::Invalid(key, _)) if key == "service"
+    ));
+}
+
+#[test]
+fn test_revision_conflict() {
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    entry.set_password("initial password").unwrap();
+    let specifier = entry.as_any().downcast_ref::<Specifier>().unwrap();
+    let (secret, revision) = specifier.get_secret_with_revision().unwrap();
+    assert_eq!(secret, b"initial password");
+    assert_eq!(specifier.get_revision().unwrap(), revision);
+    match specifier.set_secret_if_revision(b"stale password", revision + 1) {
+        Err(Error::PlatformFailure(err)) => assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::Conflict(_, _))
+        )),
+        other => panic!("Expected a conflict, got {other:?}"),
+    }
+    assert_eq!(entry.get_password().unwrap(), "initial password");
+    specifier
+        .set_secret_if_revision(b"updated password", revision)
+        .unwrap();
+    assert_eq!(entry.get_password().unwrap(), "updated password");
+    entry.delete_credential().unwrap();
+}
+
+struct Reverse;
+
+impl Transform for Reverse {
+    fn encode(&self, secret: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        Ok(secret.iter().rev().copied().collect())
+    }
+
+    fn decode(&self, data: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        Ok(data.iter().rev().copied().collect())
+    }
+}
+
+struct Prefix;
+
+impl Transform for Prefix {
+    fn encode(&self, secret: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        Ok([b"tag:".as_slice(), secret].concat())
+    }
+
+    fn decode(&self, data: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        match data.strip_prefix(b"tag:") {
+            Some(secret) => Ok(secret.to_vec()),
+            None => Err(Error::BadDataFormat(data.to_vec(), "missing tag".into())),
+        }
+    }
+}
+
+#[test]
+fn test_transform_pipeline() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    store.add_transform(Arc::new(Reverse));
+    store.add_transform(Arc::new(Prefix));
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip_no_delete("transformed password", &entry, "abc");
+    let raw = entry_new(&name, &name);
+    assert_eq!(raw.get_secret().unwrap(), b"tag:cba");
+    raw.set_password("untagged").unwrap();
+    assert!(matches!(
+        entry.get_secret(),
+        Err(Error::BadDataFormat(_, _))
+    ));
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_envelope() {
+    use crate::transform::Envelope;
+
+    let name = generate_random_string();
+    let config = HashMap::from([("envelope-key", "sesame")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("sealed").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "sealed");
+    let raw = entry_new(&name, &name).get_secret().unwrap();
+    assert!(!raw.windows(6).any(|w| w == b"sealed"));
+    // a callback supplying the same key reads the secret
+    let other = Store::new().unwrap();
+    other.add_transform(Arc::new(Envelope::with_key_callback(|| {
+        Ok(b"sesame".to_vec())
+    })));
+    let reader = other.build(&name, &name, None).unwrap();
+    assert_eq!(reader.get_password().unwrap(), "sealed");
+    // but other keys don't
+    let wrong = Store::new().unwrap();
+    wrong.add_transform(Arc::new(Envelope::new(b"wrong")));
+    let reader = wrong.build(&name, &name, None).unwrap();
+    assert!(matches!(
+        reader.get_secret(),
+        Err(Error::BadDataFormat(_, _))
+    ));
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_builder_debug_redacts_keys() {
+    let builder = Store::builder()
+        .option("envelope-key", "open sesame")
+        .option("mirror-key", "hidden mirror")
+        .default_target("visible");
+    let shown = format!("{builder:?}");
+    assert!(!shown.contains("open sesame"));
+    assert!(!shown.contains("hidden mirror"));
+    assert!(shown.contains("visible"));
+}
+
+struct AccountMapper;
+
+impl AttributeMapper for AccountMapper {
+    fn attributes(&self, specifier: &Specifier) -> HashMap<String, String> {
+        HashMap::from([
+            ("svc".to_string(), specifier.service.clone()),
+            ("account".to_string(), specifier.user.clone()),
+        ])
+    }
+
+    fn specifiers(&self, attributes: &HashMap<String, String>) -> Option<(String, String)> {
+        Some((
+            attributes.get("svc")?.clone(),
+            attributes.get("account")?.clone(),
+        ))
+    }
+
+    fn is_controlled(&self, attribute: &str) -> bool {
+        attribute == "svc" || attribute == "account"
+    }
+}
+
+#[test]
+fn test_attribute_mapper() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    store.set_attribute_mapper(Arc::new(AccountMapper)).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("mapped password").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["svc"], name);
+    assert_eq!(attributes["account"], name);
+    assert!(!attributes.contains_key("username"));
+    entry
+        .update_attributes(&HashMap::from([("account", "other")]))
+        .unwrap_err();
+    entry
+        .update_attributes(&HashMap::from([("username", "other")]))
+        .unwrap();
+    assert!(matches!(
+        entry_new(&name, &name).get_password(),
+        Err(Error::NoEntry)
+    ));
+    let found = store
+        .search(&HashMap::from([("svc", name.as_str())]))
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        found[0].get_specifiers().unwrap(),
+        (name.clone(), name.clone())
+    );
+    entry.delete_credential().unwrap();
+    // a store that hashes users keeps its salted mapper
+    let config = HashMap::from([("username-salt", "pepper")]);
+    let private = Store::new_with_configuration(&config).unwrap();
+    let hashed = private.build(&name, &name, None).unwrap();
+    hashed.set_password("hashed password").unwrap();
+    assert!(matches!(
+        private.set_attribute_mapper(Arc::new(AccountMapper)),
+        Err(Error::Invalid(key, _)) if key == "username-salt"
+    ));
+    assert_eq!(hashed.get_password().unwrap(), "hashed password");
+    let salted = Store::new().unwrap();
+    salted
+        .set_attribute_mapper(Arc::new(DefaultMapper::with_hashed_username("pepper")))
+        .unwrap();
+    let same = salted.build(&name, &name, None).unwrap();
+    assert_eq!(same.get_password().unwrap(), "hashed password");
+    same.delete_credential().unwrap();
+}
+
+#[test]
+fn test_attribute_schema() {
+    let name = generate_random_string();
+    let config = HashMap::from([("required-attributes", "environment, owner")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    assert!(matches!(
+        entry.set_password("untagged"),
+        Err(Error::Invalid(attr, _)) if attr == "environment"
+    ));
+    let config = HashMap::from([("pattern:environment", r"prod|staging-\d+")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("tagged later").unwrap();
+    entry
+        .update_attributes(&HashMap::from([("environment", "dev")]))
+        .unwrap_err();
+    entry
+        .update_attributes(&HashMap::from([("environment", "staging-2")]))
+        .unwrap();
+    assert_eq!(entry.get_attributes().unwrap()["environment"], "staging-2");
+    entry
+        .update_attributes(&HashMap::from([("environment", "staging-2b")]))
+        .unwrap_err();
+    entry.delete_credential().unwrap();
+    let config = HashMap::from([("pattern:environment", "prod|(staging")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(key, _)) if key == "pattern:environment"
+    ));
+}
+
+#[test]
+fn test_patterns() {
+    use crate::pattern::Pattern;
+
+    let cases = [
+        ("abc", "abc", true),
+        ("abc", "abcd", false),
+        ("a.c", "a-c", true),
+        ("a*", "", true),
+        ("a+", "", false),
+        ("colou?r", "color", true),
+        ("(ab)+", "ababab", true),
+        ("(ab)+", "ababa", false),
+        ("(?:x|y){2,3}", "xyx", true),
+        ("(?:x|y){2,3}", "xyxy", false),
+        ("x{2,}", "xxxxx", true),
+        ("x{2}", "xxx", false),
+        ("[a-c_]+", "ab_c", true),
+        ("[^0-9]+", "abc1", false),
+        (r"[\d.-]+", "1.2-3", true),
+        (r"\w+@\w+\.com", "dev@example.com", true),
+        (r"\S+", "has space", false),
+        (r"\$\d", "$5", true),
+        ("^team-(a|b)$", "team-b", true),
+        ("prod|staging", "production", false),
+        ("(a|ab)(c|bcd)", "abcd", true),
+        // would take a backtracking matcher ages
+        ("(a*)*b", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaac", false),
+    ];
+    for (pattern, value, expected) in cases {
+        assert_eq!(
+            Pattern::new(pattern).unwrap().matches(value),
+            expected,
+            "'{pattern}' against '{value}'"
+        );
+    }
+    for invalid in [
+        "(a",
+        "a)",
+        "[a",
+        "*a",
+        "a{2",
+        "a{3,2}",
+        "[z-a]",
+        r"\q",
+        "(?i)a",
+        "x{5000}{5000}",
+    ] {
+        assert!(Pattern::new(invalid).is_err(), "'{invalid}'");
+    }
+}
+
+#[test]
+fn test_default_attributes() {
+    let name = generate_random_string();
+    let config = HashMap::from([
+        ("attribute:application", "keyring-tests"),
+        ("attribute:service", "ignored"),
+        ("required-attributes", "application"),
+    ]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("tagged").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["application"], "keyring-tests");
+    assert_eq!(attributes["service"], name);
+    assert_eq!(entry_new(&name, &name).get_password().unwrap(), "tagged");
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_search_by_attribute() {
+    let name = generate_random_string();
+    let config = HashMap::from([("attribute:application", name.as_str())]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let first = store.build(&format!("{name}-1"), &name, None).unwrap();
+    let second = store.build(&format!("{name}-2"), &name, None).unwrap();
+    first.set_password("first").unwrap();
+    second.set_password("second").unwrap();
+    let found = store
+        .search(&HashMap::from([("application", name.as_str())]))
+        .unwrap();
+    assert_eq!(found.len(), 2);
+    let mut passwords: Vec<String> = found.iter().map(|e| e.get_password().unwrap()).collect();
+    passwords.sort();
+    assert_eq!(passwords, vec!["first", "second"]);
+    for entry in found {
+        entry.delete_credential().unwrap();
+    }
+    assert!(matches!(first.get_password(), Err(Error::NoEntry)));
+}
+
+#[test]
+fn test_list_entries() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let entry1 = entry_new(&name, "user1");
+    let entry2 = entry_new_with_target(&name, &name, "user2");
+    entry1.set_password("first").unwrap();
+    entry2.set_password("second").unwrap();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let collection = ss.get_default_collection().unwrap();
+    let foreign = collection
+        .create_item(
+            &name,
+            HashMap::from([("service", name.as_str())]),
+            b"foreign",
+            false,
+            "text/plain",
+        )
+        .unwrap();
+    let store = Store::new().unwrap();
+    let mut users: Vec<String> = store
+        .list_entries()
+        .unwrap()
+        .iter()
+        .filter_map(|e| e.get_specifiers())
+        .filter(|(service, _)| *service == name)
+        .map(|(_, user)| user)
+        .collect();
+    users.sort();
+    assert_eq!(users, vec!["user1", "user2"]);
+    foreign.delete().unwrap();
+    entry1.delete_credential().unwrap();
+    entry2.delete_credential().unwrap();
+}
+
+#[test]
+fn test_list_collections() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let collection = ss.create_collection(&name, "").unwrap();
+    let store = Store::new().unwrap();
+    let collections = store.list_collections().unwrap();
+    assert_eq!(collections.iter().filter(|c| c.is_default()).count(), 1);
+    let created = collections.iter().find(|c| c.label == name).unwrap();
+    assert_eq!(created.path, collection.path.to_string());
+    assert!(!created.is_default());
+    assert!(!created.locked);
+    collection.delete().unwrap();
+}
+
+#[test]
+fn test_lock_collection() {
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("locked away").unwrap();
+    let store = Store::new().unwrap();
+    let locked = |store: &Store| {
+        let collections = store.list_collections().unwrap();
+        collections.iter().find(|c| c.label == name).unwrap().locked
+    };
+    store.lock_collection(&name).unwrap();
+    assert!(locked(&store));
+    assert!(store.collection_items(&name).unwrap()[0].locked);
+    assert!(locked(&store), "listing items doesn't unlock");
+    store.unlock_collection(&name).unwrap();
+    assert!(!locked(&store));
+    assert_eq!(entry.get_password().unwrap(), "locked away");
+    assert!(matches!(
+        store.lock_collection(&generate_random_string()),
+        Err(Error::NoEntry)
+    ));
+    entry
+        .as_any()
+        .downcast_ref::<Specifier>()
+        .unwrap()
+        .delete_target()
+        .unwrap();
+}
+
+#[test]
+fn test_relock_after_access() {
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("relocked").unwrap();
+    let store = Store::builder().relock_after_access(true).build().unwrap();
+    let locked = |store: &Store| store.collection_items(&name).unwrap()[0].locked;
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let relocking = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert_eq!(relocking.get_password().unwrap(), "relocked");
+    assert!(!locked(&store), "unlocked items are left unlocked");
+    store.lock_collection(&name).unwrap();
+    assert_eq!(relocking.get_password().unwrap(), "relocked");
+    assert!(locked(&store), "locked items are locked again");
+    relocking.set_password("still relocked").unwrap();
+    assert!(locked(&store));
+    let found = store
+        .search(&HashMap::from([("target", name.as_str())]))
+        .unwrap();
+    assert!(locked(&store), "searches don't unlock");
+    assert_eq!(found[0].get_password().unwrap(), "still relocked");
+    assert!(locked(&store));
+    let secrets = store.get_secrets(&[&relocking]).unwrap();
+    assert_eq!(secrets[0].as_ref().unwrap(), b"still relocked");
+    assert!(locked(&store));
+    let file = std::env::temp_dir().join(format!("{name}.backup"));
+    assert_eq!(store.export_collection(&name, &file, "sesame").unwrap(), 1);
+    std::fs::remove_file(&file).unwrap();
+    assert!(locked(&store), "collections are locked again too");
+    store.unlock_collection(&name).unwrap();
+    let collections = store.list_collections().unwrap();
+    let collection = collections.iter().find(|c| c.label == name).unwrap();
+    assert!(!collection.locked, "explicit unlocks are kept");
+    assert_eq!(entry.get_password().unwrap(), "still relocked");
+    assert!(!locked(&store));
+    entry
+        .as_any()
+        .downcast_ref::<Specifier>()
+        .unwrap()
+        .delete_target()
+        .unwrap();
+}
+
+#[test]
+fn test_locked_items_policy() {
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("behind a lock").unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let store = Store::builder()
+        .option("locked-items", "skip")
+        .build()
+        .unwrap();
+    store.lock_collection(&name).unwrap();
+    let skipping = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert!(matches!(skipping.get_password(), Err(Error::NoEntry)));
+    let secrets = store.get_secrets(&[&skipping]).unwrap();
+    assert!(
+        matches!(secrets[0], Err(Error::NoEntry)),
+        "batches skip too"
+    );
+    let deleted = store.delete_many(&[&skipping]).unwrap();
+    assert!(matches!(deleted[0], Err(Error::NoEntry)));
+    let store = Store::builder()
+        .option("locked-items", "error")
+        .build()
+        .unwrap();
+    let refusing = store.build(&name, &name, Some(&modifiers)).unwrap();
+    let Err(Error::NoStorageAccess(err)) = refusing.get_password() else {
+        panic!("Expected a locked error");
+    };
+    assert!(matches!(
+        err.downcast_ref::<StoreError>(),
+        Some(StoreError::Locked(1))
+    ));
+    let secrets = store.get_secrets(&[&refusing]).unwrap();
+    assert!(matches!(secrets[0], Err(Error::NoStorageAccess(_))));
+    assert!(store.collection_items(&name).unwrap()[0].locked);
+    store.unlock_collection(&name).unwrap();
+    assert_eq!(refusing.get_password().unwrap(), "behind a lock");
+    assert!(matches!(
+        Store::builder().option("locked-items", "ignore").build(),
+        Err(Error::Invalid(_, _))
+    ));
+    entry
+        .as_any()
+        .downcast_ref::<Specifier>()
+        .unwrap()
+        .delete_target()
+        .unwrap();
+}
+
+#[test]
+fn test_is_locked() {
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert!(matches!(specifier.is_locked(), Err(Error::NoEntry)));
+    entry.set_password("lockable").unwrap();
+    assert!(!specifier.is_locked().unwrap());
+    let store = Store::new().unwrap();
+    let wrapper = store
+        .search(&HashMap::from([("target", name.as_str())]))
+        .unwrap();
+    let wrapper: &Wrapper = wrapper[0].as_any().downcast_ref().unwrap();
+    store.lock_collection(&name).unwrap();
+    assert!(specifier.is_locked().unwrap());
+    assert!(wrapper.is_locked().unwrap());
+    assert!(specifier.is_locked().unwrap(), "asking doesn't unlock");
+    assert_eq!(entry.get_password().unwrap(), "lockable");
+    assert!(!specifier.is_locked().unwrap());
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_get_secrets() {
+    let name = generate_random_string();
+    let entries: Vec<Entry> = (0..3)
+        .map(|i| entry_new(&format!("{name}-{i}"), &name))
+        .collect();
+    for (i, entry) in entries.iter().enumerate() {
+        entry.set_password(&format!("secret {i}")).unwrap();
+    }
+    let missing = entry_new(&name, &name);
+    let wrapper = entries[2].get_credential().unwrap();
+    // a wrapper whose item has been deleted fails on its own
+    let stale = entries[0].get_credential().unwrap();
+    let doomed = entry_new(&format!("{name}-doomed"), &name);
+    doomed.set_password("doomed").unwrap();
+    let stale_wrapper = doomed.get_credential().unwrap();
+    doomed.delete_credential().unwrap();
+    for plain in [false, true] {
+        let store = Store::builder()
+            .encryption(if plain {
+                SessionAlgorithm::Plain
+            } else {
+                SessionAlgorithm::DhAes
+            })
+            .build()
+            .unwrap();
+        let all = [
+            &entries[0],
+            &missing,
+            &entries[1],
+            &wrapper,
+            &stale_wrapper,
+            &stale,
+        ];
+        let secrets = store.get_secrets(&all).unwrap();
+        assert_eq!(secrets.len(), 6);
+        assert_eq!(secrets[0].as_ref().unwrap(), b"secret 0");
+        assert!(matches!(secrets[1], Err(Error::NoEntry)));
+        assert_eq!(secrets[2].as_ref().unwrap(), b"secret 1");
+        assert_eq!(secrets[3].as_ref().unwrap(), b"secret 2");
+        assert!(secrets[4].is_err());
+        assert_eq!(secrets[5].as_ref().unwrap(), b"secret 0");
+    }
+    for entry in entries {
+        entry.delete_credential().unwrap();
+    }
+}
+
+#[test]
+fn test_item_events() {
+    use crate::events::{ItemEvent, ItemEventKind};
+
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let subscription = store.subscribe().unwrap();
+    let next = |kind: ItemEventKind, path: Option<&str>| -> ItemEvent {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            let event = subscription.recv_timeout(left).expect("no event arrived");
+            let ours = match path {
+                Some(path) => event.path == path,
+                None => event
+                    .attributes
+                    .as_ref()
+                    .is_some_and(|a| a.get("service") == Some(&name)),
+            };
+            if ours && event.kind == kind {
+                return event;
+            }
+        }
+    };
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("watched").unwrap();
+    let created = next(ItemEventKind::Created, None);
+    assert!(created.path.starts_with(&created.collection));
+    entry.set_password("changed").unwrap();
+    next(ItemEventKind::Changed, Some(&created.path));
+    entry.delete_credential().unwrap();
+    let deleted = next(ItemEventKind::Deleted, Some(&created.path));
+    assert!(deleted.attributes.is_none());
+}
+
+#[test]
+fn test_watch() {
+    use crate::events::ItemEventKind;
+
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let quiet = store.build(&name, "quiet", None).unwrap();
+    let watched = store.build(&name, &name, None).unwrap();
+    let (sender, events) = mpsc::channel();
+    let watch = store
+        .watch(&[&quiet, &watched], move |index, kind| {
+            _ = sender.send((index, kind));
+        })
+        .unwrap();
+    // another process rotates the password
+    let other = entry_new(&name, &name);
+    let timeout = std::time::Duration::from_secs(5);
+    other.set_password("first").unwrap();
+    assert_eq!(
+        events.recv_timeout(timeout).unwrap(),
+        (1, ItemEventKind::Created)
+    );
+    other.set_password("rotated").unwrap();
+    assert_eq!(
+        events.recv_timeout(timeout).unwrap(),
+        (1, ItemEventKind::Changed)
+    );
+    other.delete_credential().unwrap();
+    assert_eq!(
+        events.recv_timeout(timeout).unwrap(),
+        (1, ItemEventKind::Deleted)
+    );
+    assert!(watch.is_active());
+    drop(watch);
+}
+
+#[test]
+fn test_watch_disconnected() {
+    use crate::events::{Subscription, Watch};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let timeout = std::time::Duration::from_millis(10);
+    let subscription = |connected: bool| {
+        let (sender, events) = mpsc::channel();
+        if connected {
+            std::mem::forget(sender);
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        Subscription { events, stop }
+    };
+    assert_eq!(
+        subscription(true).recv_timeout(timeout).unwrap_err(),
+        RecvTimeoutError::Timeout
+    );
+    assert_eq!(
+        subscription(false).recv_timeout(timeout).unwrap_err(),
+        RecvTimeoutError::Disconnected
+    );
+    let watch = Watch::start(subscription(false), Vec::new(), |_, _| {}).unwrap();
+    for _ in 0..100 {
+        if !watch.is_active() {
+            return;
+        }
+        std::thread::sleep(timeout);
+    }
+    panic!("The watch didn't stop when its connection was lost");
+}
+
+#[test]
+fn test_batch_set_and_delete() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let mut entries: Vec<Entry> = (0..3)
+        .map(|i| entry_new(&format!("{name}-{i}"), &name))
+        .collect();
+    entries.push(entry_new_with_target(&name, &name, &name));
+    entries[0].set_password("existing").unwrap();
+    let secrets: Vec<(&Entry, &[u8])> =
+        entries.iter().map(|e| (e, b"batched".as_slice())).collect();
+    let results = store.set_many(&secrets).unwrap();
+    assert!(results.iter().all(|r| r.is_ok()));
+    for entry in entries.iter() {
+        assert_eq!(entry.get_password().unwrap(), "batched");
+    }
+    let all: Vec<&Entry> = entries.iter().collect();
+    let results = store.delete_many(&all).unwrap();
+    assert!(results.iter().all(|r| r.is_ok()));
+    for entry in entries.iter() {
+        assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    }
+    let results = store.delete_many(&all[..1]).unwrap();
+    assert!(matches!(results[0], Err(Error::NoEntry)));
+    // a wrapper whose item has been deleted doesn't fail the rest
+    entries[0].set_password("stale").unwrap();
+    let stale = entries[0].get_credential().unwrap();
+    entries[0].delete_credential().unwrap();
+    entries[1].set_password("fresh").unwrap();
+    let results = store
+        .set_many(&[(&stale, b"again".as_slice()), (&entries[1], b"again")])
+        .unwrap();
+    assert!(results[0].is_err());
+    assert!(results[1].is_ok());
+    let results = store.delete_many(&[&stale, &entries[1]]).unwrap();
+    assert!(results[0].is_err());
+    assert!(results[1].is_ok());
+    assert!(matches!(entries[1].get_password(), Err(Error::NoEntry)));
+    let specifier: &Specifier = entries[3].as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_collection_alias() {
+    let name = generate_random_string();
+    let alias = generate_random_string();
+    let store = Store::new().unwrap();
+    assert!(store.read_alias(&alias).unwrap().is_none());
+    assert!(matches!(
+        store.set_alias(&alias, &name),
+        Err(Error::NoEntry)
+    ));
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("aliased").unwrap();
+    store.set_alias(&alias, &name).unwrap();
+    let path = store.read_alias(&alias).unwrap().unwrap();
+    let target = format!("alias:{alias}");
+    assert_eq!(store.collection_items(&target).unwrap()[0].collection, path);
+    let modifiers = HashMap::from([("target", target.as_str())]);
+    let aliased = store.build(&name, &name, Some(&modifiers)).unwrap();
+    aliased.set_password("by alias").unwrap();
+    assert_eq!(aliased.get_password().unwrap(), "by alias");
+    assert_eq!(store.collection_items(&name).unwrap().len(), 2);
+    aliased.delete_credential().unwrap();
+    entry.delete_credential().unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+    assert!(store.read_alias(&alias).unwrap().is_none());
+    let created = store.build(&name, &name, Some(&modifiers)).unwrap();
+    created.set_password("new alias").unwrap();
+    let path = store.read_alias(&alias).unwrap().unwrap();
+    let info = store.list_collections().unwrap();
+    assert!(info.iter().any(|c| c.path == path && c.label == alias));
+    created.delete_credential().unwrap();
+    let specifier: &Specifier = created.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_cache_item_paths() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let store = Store::builder().cache_item_paths(true).build().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("cached").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "cached");
+    let attributes = entry.get_attributes().unwrap();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let default = ss.get_default_collection().unwrap();
+    default
+        .create_item(
+            "impostor",
+            attributes
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+            b"impostor",
+            false,
+            "text/plain",
+        )
+        .unwrap();
+    // the cached path is used without searching
+    assert_eq!(entry.get_password().unwrap(), "cached");
+    assert!(matches!(
+        entry_new(&name, &name).get_password(),
+        Err(Error::Ambiguous(_))
+    ));
+    // a stale path is searched for again
+    entry.get_credential().unwrap().delete_credential().unwrap();
+    assert_eq!(entry.get_password().unwrap(), "impostor");
+    entry.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+}
+
+#[test]
+fn test_secret_cache() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let store = Store::builder()
+        .secret_cache_ttl(std::time::Duration::from_secs(60))
+        .build()
+        .unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("cached").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "cached");
+    let attributes = entry.get_attributes().unwrap();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let found = ss
+        .search_items(
+            attributes
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+        )
+        .unwrap();
+    found.unlocked[0]
+        .set_secret(b"changed elsewhere", "text/plain")
+        .unwrap();
+    // other clients' changes aren't noticed until the cache is flushed
+    assert_eq!(entry.get_password().unwrap(), "cached");
+    store.flush_cache();
+    assert_eq!(entry.get_password().unwrap(), "changed elsewhere");
+    // the store's own writes flush the cache
+    entry.set_password("changed here").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "changed here");
+    entry.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+}
+
+#[test]
+fn test_create_if_absent() {
+    let name = generate_random_string();
+    let store = Store::builder().create_if_absent(true).build().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("first").unwrap();
+    let err = entry.set_password("second").unwrap_err();
+    let Error::PlatformFailure(inner) = err else {
+        panic!("Unexpected error: {err:?}");
+    };
+    assert!(matches!(
+        inner.downcast_ref::<StoreError>(),
+        Some(StoreError::AlreadyExists(_))
+    ));
+    let outcomes = store.set_many(&[(&entry, b"third".as_slice())]).unwrap();
+    assert!(outcomes[0].is_err());
+    assert_eq!(entry.get_password().unwrap(), "first");
+    // the wrapped item can still be updated
+    entry
+        .get_credential()
+        .unwrap()
+        .set_password("fourth")
+        .unwrap();
+    assert_eq!(entry.get_password().unwrap(), "fourth");
+    entry.delete_credential().unwrap();
+    entry.set_password("fifth").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "fifth");
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_scoped_search() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("in the target").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let default = ss.get_default_collection().unwrap();
+    let impostor = default
+        .create_item(
+            "impostor",
+            attributes
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+            b"impostor",
+            false,
+            "text/plain",
+        )
+        .unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::Ambiguous(_))));
+    let store = Store::builder().scoped_search(true).build().unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let scoped = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert_eq!(scoped.get_password().unwrap(), "in the target");
+    scoped.set_password("updated").unwrap();
+    assert_eq!(impostor.get_secret().unwrap(), b"impostor");
+    let specifier: &Specifier = scoped.as_any().downcast_ref().unwrap();
+    assert!(!specifier.is_locked().unwrap());
+    scoped.delete_credential().unwrap();
+    assert!(matches!(scoped.get_password(), Err(Error::NoEntry)));
+    impostor.delete().unwrap();
+    specifier.delete_target().unwrap();
+    let missing = store
+        .build(
+            &name,
+            &name,
+            Some(&HashMap::from([("target", "no-such-target")])),
+        )
+        .unwrap();
+    assert!(matches!(missing.get_password(), Err(Error::NoEntry)));
+}
+
+#[test]
+fn test_collection_items() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let collection = ss.create_collection(&name, "").unwrap();
+    collection
+        .create_item(
+            "third party",
+            HashMap::from([("application", "other")]),
+            b"foreign",
+            false,
+            "text/plain",
+        )
+        .unwrap();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("listed").unwrap();
+    let store = Store::new().unwrap();
+    let mut items = store.collection_items(&name).unwrap();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].attributes["service"], name);
+    assert_eq!(items[1].label, "third party");
+    assert_eq!(items[1].attributes["application"], "other");
+    assert_eq!(items[0].collection, collection.path.to_string());
+    assert!(matches!(
+        store.collection_items(&generate_random_string()),
+        Err(Error::NoEntry)
+    ));
+    collection.delete().unwrap();
+}
+
+#[test]
+fn test_hashed_username() {
+    let name = generate_random_string();
+    let config = HashMap::from([("username-salt", "pepper")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("private").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["service"], name);
+    assert_ne!(attributes["username"], name);
+    assert_eq!(attributes["username"].len(), 64);
+    let cred = entry.get_credential().unwrap();
+    let wrapper = cred.as_any().downcast_ref::<Wrapper>().unwrap();
+    assert_eq!(wrapper.get_label().unwrap(), format!("keyring:{name}"));
+    assert!(cred.get_specifiers().is_none());
+    let again = store.build(&name, &name, None).unwrap();
+    assert_eq!(again.get_password().unwrap(), "private");
+    assert!(matches!(
+        entry_new(&name, &name).get_password(),
+        Err(Error::NoEntry)
+    ));
+    let config = HashMap::from([("username-salt", "salt"), ("username-in-label", "yes")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_opaque_attributes() {
+    let name = generate_random_string();
+    let config = HashMap::from([
+        ("opaque-attributes", "tenant"),
+        ("opaque-key", "secret key"),
+        ("pattern:tenant", "tenant-.+"),
+    ]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("opaque").unwrap();
+    entry
+        .update_attributes(&HashMap::from([("tenant", "acme")]))
+        .unwrap_err();
+    entry
+        .update_attributes(&HashMap::from([("tenant", "tenant-acme")]))
+        .unwrap();
+    let stored = entry.get_attributes().unwrap()["tenant"].clone();
+    assert_ne!(stored, "tenant-acme");
+    entry
+        .update_attributes(&HashMap::from([("note", "still valid")]))
+        .unwrap();
+    let found = store
+        .search(&HashMap::from([("tenant", "tenant-acme")]))
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    let other = Store::new_with_configuration(&HashMap::from([
+        ("opaque-attributes", "tenant"),
+        ("opaque-key", "another key"),
+    ]))
+    .unwrap();
+    let found = other
+        .search(&HashMap::from([("tenant", "tenant-acme")]))
+        .unwrap();
+    assert!(found.is_empty());
+    let config = HashMap::from([("opaque-attributes", "tenant")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_server_info() {
+    let store = Store::new().unwrap();
+    let info = store.server_info().unwrap();
+    assert!(info.owner.starts_with(':'));
+    assert!(
+        info.interfaces
+            .contains(&"org.freedesktop.Secret.Service".to_string())
+    );
+    assert!(info.collection_count >= 1);
+    if info.pid.is_some() {
+        assert!(info.process_name.is_some());
+    }
+    assert!(info.names.contains(&"org.freedesktop.secrets".to_string()));
+    assert_eq!(store.backend().unwrap(), info.backend);
+    let strings = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    let gnome = strings(&["org.gnome.keyring.InternalUnsupportedGuiltRiddenInterface"]);
+    assert_eq!(Backend::detect(&gnome, &[], None), Backend::GnomeKeyring);
+    let keepassxc = strings(&["org.keepassxc.KeePassXC.MainWindow"]);
+    assert_eq!(
+        Backend::detect(&[], &keepassxc, Some("gnome-keyring-d")),
+        Backend::KeePassXC
+    );
+    assert_eq!(
+        Backend::detect(&[], &[], Some("kwalletd6")),
+        Backend::KWallet
+    );
+    assert_eq!(Backend::detect(&[], &[], None), Backend::Unknown);
+}
+
+#[test]
+fn test_probe() {
+    let report = Store::probe();
+    assert!(report.is_available(), "{report:?}");
+    assert!(report.bus && report.service && report.default_collection);
+    assert_eq!(report.session, Some(SessionAlgorithm::DhAes));
+    assert!(report.problem.is_none());
+    assert!(Store::is_available());
+    assert!(!crate::info::Availability::default().is_available());
+    let config = HashMap::from([("encryption", "plain")]);
+    let report = Store::probe_with_configuration(&config).unwrap();
+    assert_eq!(report.session, Some(SessionAlgorithm::Plain));
+    let config = HashMap::from([("encryption", "rot13")]);
+    assert!(Store::probe_with_configuration(&config).is_err());
+}
+
+#[test]
+fn test_diagnostics() {
+    let store = Store::new().unwrap();
+    let name = generate_random_string();
+    let entry = store.build(&name, &name, None).unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    let report = store.diagnostics();
+    assert!(
+        report
+            .collections
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|c| c.aliases.contains(&"default".to_string()))
+    );
+    assert!(report.error_counts.is_empty());
+    assert!(report.problems.is_empty());
+    let modifiers = HashMap::from([("collection", "/org/freedesktop/secrets/collection/none")]);
+    let missing = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert!(missing.set_password("lost password").is_err());
+    let report = store.diagnostics();
+    assert_eq!(report.error_counts["PlatformFailure"], 1);
+    let text = report.to_string();
+    assert!(text.contains("session algorithm: dh-ietf1024-sha256-aes128-cbc-pkcs7"));
+    let owner = &report.server.as_ref().unwrap().owner;
+    assert!(text.contains(&format!("server owner: {owner}")));
+    assert!(text.contains("errors: PlatformFailure=1"));
+    assert!(text.contains("connections: 1"));
+    let store = Store::builder().read_workers(3).build().unwrap();
+    assert_eq!(store.diagnostics().connections, 4);
+    // what can be gathered is reported even if the rest can't be
+    let config = HashMap::from([("latency:read", "300"), ("operation-timeout", "50")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let report = store.diagnostics();
+    assert!(report.server.is_none());
+    assert!(report.collections.is_none());
+    assert_eq!(report.problems.len(), 2);
+    assert_eq!(report.connections, 1);
+    let text = report.to_string();
+    assert!(text.contains("server: unknown"));
+    assert!(text.contains("collections: unknown"));
+    assert!(text.contains("session algorithm: dh-ietf1024-sha256-aes128-cbc-pkcs7"));
+    assert!(text.contains("problem: Can't list the collections"));
+}
+
+#[test]
+fn test_session_algorithm() {
+    let store = Store::new().unwrap();
+    let algorithm = store.session_algorithm();
+    assert_eq!(algorithm, SessionAlgorithm::DhAes);
+    assert!(algorithm.is_encrypted());
+    assert_eq!(algorithm.to_string(), "dh-ietf1024-sha256-aes128-cbc-pkcs7");
+}
+
+#[test]
+fn test_plain_fallback() {
+    let config = HashMap::from([("allow-plain-fallback", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    if store.session_downgraded() {
+        assert_eq!(store.session_algorithm(), SessionAlgorithm::Plain);
+    } else {
+        assert_eq!(store.session_algorithm(), SessionAlgorithm::DhAes);
+    }
+    let name = generate_random_string();
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip("fallback password", &entry, "fallback");
+    let config = HashMap::from([("allow-plain-fallback", "maybe")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_store_builder() {
+    let name = generate_random_string();
+    let store = Store::builder()
+        .encryption(SessionAlgorithm::Plain)
+        .prompt_timeout(std::time::Duration::from_secs(5))
+        .operation_deadline(std::time::Duration::from_secs(5))
+        .default_target(&name)
+        .build()
+        .unwrap();
+    assert_eq!(store.session_algorithm(), SessionAlgorithm::Plain);
+    assert!(!store.session_downgraded());
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("builder password").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["target"], name);
+    let modifiers = HashMap::from([("target", "default")]);
+    let untargeted = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert!(matches!(untargeted.get_password(), Err(Error::NoEntry)));
+    for (millis, seconds) in [(0, "0"), (500, "1"), (1500, "2")] {
+        let builder = Store::builder().prompt_timeout(std::time::Duration::from_millis(millis));
+        let shown = format!("{builder:?}");
+        assert!(shown.contains(&format!("\"prompt-timeout\": \"{seconds}\"")));
+    }
+    entry.delete_credential().unwrap();
+    let store = Store::builder().allow_plain_fallback(true).build().unwrap();
+    if !store.session_downgraded() {
+        assert_eq!(store.session_algorithm(), SessionAlgorithm::DhAes);
+    }
+    assert!(matches!(
+        Store::builder().option("encryption", "rot13").build(),
+        Err(Error::Invalid(_, _))
+    ));
+    assert!(matches!(
+        Store::builder().option("prompt-timeout", "soon").build(),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_lazy_connect() {
+    let name = generate_random_string();
+    let store = Store::builder().lazy_connect(true).build().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip("lazy password", &entry, "lazy");
+    assert_eq!(store.session_algorithm(), SessionAlgorithm::DhAes);
+    let store = Store::builder()
+        .lazy_connect(true)
+        .option("rebind-on-restart", "true")
+        .build()
+        .unwrap();
+    assert_eq!(store.session_algorithm(), SessionAlgorithm::DhAes);
+    assert!(!store.session_downgraded());
+    let config = HashMap::from([("lazy-connect", "later")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_external_connection() {
+    let connection = dbus::blocking::Connection::new_session().unwrap();
+    let unique_name = connection.unique_name().to_string();
+    let store = Store::new_with_connection(connection, &HashMap::new()).unwrap();
+    let info = store.server_info().unwrap();
+    assert_ne!(info.owner, unique_name);
+    let name = generate_random_string();
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip("external connection", &entry, "connected");
+}
+
+/// A transform whose decoding waits until it's released.
+struct Gate {
+    entered: Mutex<mpsc::Sender<()>>,
+    release: Mutex<mpsc::Receiver<()>>,
+}
+
+impl Transform for Gate {
+    fn encode(&self, secret: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        Ok(secret.to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        self.entered.lock().unwrap().send(()).unwrap();
+        self.release.lock().unwrap().recv().unwrap();
+        Ok(data.to_vec())
+    }
+}
+
+#[test]
+fn test_try_operations() {
+    let name = generate_random_string();
+    let (entered_tx, entered_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+    let store = Store::new().unwrap();
+    store.add_transform(Arc::new(Gate {
+        entered: Mutex::new(entered_tx),
+        release: Mutex::new(release_rx),
+    }));
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("try password").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let busy = std::thread::scope(|scope| {
+        let reader = scope.spawn(|| entry.get_password());
+        entered_rx.recv().unwrap();
+        let busy = specifier.try_set_secret(b"other password");
+        release_tx.send(()).unwrap();
+        assert_eq!(reader.join().unwrap().unwrap(), "try password");
+        busy
+    });
+    match busy {
+        Err(Error::NoStorageAccess(err)) => assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::WouldBlock)
+        )),
+        other => panic!("Expected the store to be busy, got {other:?}"),
+    }
+    release_tx.send(()).unwrap();
+    assert_eq!(specifier.try_get_secret().unwrap(), b"try password");
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_read_workers() {
+    let name = generate_random_string();
+    let (entered_tx, entered_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+    let store = Store::builder().read_workers(2).build().unwrap();
+    store.add_transform(Arc::new(Gate {
+        entered: Mutex::new(entered_tx),
+        release: Mutex::new(release_rx),
+    }));
+    let entry = store.build(&name, &name, None).unwrap();
+    let other = store.build(&name, &format!("{name}-other"), None).unwrap();
+    entry.set_password("first password").unwrap();
+    other.set_password("other password").unwrap();
+    std::thread::scope(|scope| {
+        let first = scope.spawn(|| entry.get_password());
+        let second = scope.spawn(|| other.get_password());
+        // both reads are in progress at once
+        entered_rx.recv().unwrap();
+        entered_rx.recv().unwrap();
+        // and writes aren't held up by them
+        let third = store.build(&name, &format!("{name}-third"), None).unwrap();
+        third.set_password("third password").unwrap();
+        third.delete_credential().unwrap();
+        release_tx.send(()).unwrap();
+        release_tx.send(()).unwrap();
+        assert_eq!(first.join().unwrap().unwrap(), "first password");
+        assert_eq!(second.join().unwrap().unwrap(), "other password");
+    });
+    release_tx.send(()).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "first password");
+    entry.delete_credential().unwrap();
+    other.delete_credential().unwrap();
+}
+
+/// A transform that records the name of the thread that last decoded.
+#[derive(Default)]
+struct ThreadRecorder(Mutex<Option<String>>);
+
+impl Transform for ThreadRecorder {
+    fn encode(&self, secret: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        Ok(secret.to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        *self.0.lock().unwrap() = std::thread::current().name().map(String::from);
+        Ok(data.to_vec())
+    }
+}
+
+#[test]
+fn test_prompting_reads_on_worker() {
+    let name = generate_random_string();
+    for relock in [false, true] {
+        let recorder = Arc::new(ThreadRecorder::default());
+        let store = Store::builder()
+            .read_workers(2)
+            .relock_after_access(relock)
+            .build()
+            .unwrap();
+        store.add_transform(recorder.clone());
+        let last = || recorder.0.lock().unwrap().clone().unwrap();
+        let entry = store.build(&name, &name, None).unwrap();
+        entry.set_password("routed").unwrap();
+        entry.get_password().unwrap();
+        match relock {
+            false => assert_eq!(last(), "secret-service-reader"),
+            true => assert_eq!(last(), "secret-service"),
+        }
+        store.get_secrets(&[&entry]).unwrap();
+        assert_eq!(last(), "secret-service");
+        entry.delete_credential().unwrap();
+    }
+}
+
+/// A transform that, the first time it's used, has a rival store
+/// create a matching item (as if another process were racing).
+struct Rival {
+    name: String,
+    fired: Mutex<bool>,
+}
+
+impl Transform for Rival {
+    fn encode(&self, secret: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        let mut fired = self.fired.lock().unwrap();
+        if !*fired {
+            *fired = true;
+            let config = HashMap::from([("attribute:origin", "rival")]);
+            let rival = Store::new_with_configuration(&config).unwrap();
+            let entry = rival.build(&self.name, &self.name, None).unwrap();
+            entry.set_password("rival password").unwrap();
+        }
+        Ok(secret.to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> keyring_core::Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+#[test]
+fn test_creation_race() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    store.add_transform(Arc::new(Rival {
+        name: name.clone(),
+        fired: Mutex::new(false),
+    }));
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("racing password").unwrap();
+    let password = entry.get_password().unwrap();
+    assert!(password == "racing password" || password == "rival password");
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes.get("origin").unwrap(), "rival");
+    entry.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+}
+
+#[test]
+fn test_verify_creation() {
+    let name = generate_random_string();
+    let config = HashMap::from([("verify-creation", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("verified password").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "verified password");
+    entry.delete_credential().unwrap();
+    // a concurrently created duplicate is resolved before verification
+    store.add_transform(Arc::new(Rival {
+        name: name.clone(),
+        fired: Mutex::new(false),
+    }));
+    entry.set_password("racing password").unwrap();
+    assert_eq!(
+        store
+            .search(&HashMap::from([("service", name.as_str())]))
+            .unwrap()
+            .len(),
+        1
+    );
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_stale_path_recovery() {
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    entry.set_password("original password").unwrap();
+    let wrapper = entry.get_credential().unwrap();
+    wrapper.as_any().downcast_ref::<Wrapper>().unwrap();
+    entry.delete_credential().unwrap();
+    entry.set_password("recreated password").unwrap();
+    assert_eq!(wrapper.get_password().unwrap(), "recreated password");
+    wrapper.set_password("updated password").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "updated password");
+    entry.delete_credential().unwrap();
+    assert!(matches!(wrapper.get_password(), Err(Error::NoEntry)));
+}
+
+#[test]
+fn test_wrappers_identified_lazily() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let entries: Vec<Entry> = (0..3)
+        .map(|i| store.build(&name, &format!("{name}-{i}"), None).unwrap())
+        .collect();
+    for entry in entries.iter() {
+        entry.set_password("lazy password").unwrap();
+    }
+    let reads = || {
+        store
+            .metrics()
+            .operations
+            .get("read")
+            .map(|m| m.count)
+            .unwrap_or_default()
+    };
+    let before = reads();
+    let found = store
+        .search(&HashMap::from([("service", name.as_str())]))
+        .unwrap();
+    assert_eq!(found.len(), 3);
+    assert_eq!(reads(), before);
+    assert_eq!(found[0].get_password().unwrap(), "lazy password");
+    assert!(found[0].get_specifiers().is_some());
+    for entry in entries {
+        entry.delete_credential().unwrap();
+    }
+}
+
+#[test]
+fn test_username_attribute() {
+    let name = generate_random_string();
+    let config = HashMap::from([("username-attribute", "account")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("account password").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["account"], name);
+    assert!(!attributes.contains_key("username"));
+    assert!(matches!(
+        entry.update_attributes(&HashMap::from([("account", "other")])),
+        Err(Error::Invalid(_, _))
+    ));
+    let found = store
+        .search(&HashMap::from([("service", name.as_str())]))
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        found[0].get_specifiers().unwrap(),
+        (name.clone(), name.clone())
+    );
+    assert!(matches!(
+        entry_new(&name, &name).get_password(),
+        Err(Error::NoEntry)
+    ));
+    entry.delete_credential().unwrap();
+    let config = HashMap::from([("username-attribute", "service")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_attribute_names() {
+    let name = generate_random_string();
+    let config = HashMap::from([
+        ("service-attribute", "svc"),
+        ("username-attribute", "user"),
+        ("target-attribute", "realm"),
+    ]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    entry.set_password("renamed").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["svc"], name);
+    assert_eq!(attributes["user"], name);
+    assert_eq!(attributes["realm"], name);
+    assert!(!attributes.contains_key("service") && !attributes.contains_key("target"));
+    assert!(matches!(
+        entry.update_attributes(&HashMap::from([("svc", "other")])),
+        Err(Error::Invalid(_, _))
+    ));
+    let found = store
+        .search(&HashMap::from([("svc", name.as_str())]))
+        .unwrap();
+    assert_eq!(
+        found[0].get_specifiers().unwrap(),
+        (name.clone(), name.clone())
+    );
+    entry.delete_credential().unwrap();
+    let config = HashMap::from([("service-attribute", "username")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(key, _)) if key == "service-attribute"
+    ));
+}
+
+#[test]
+fn test_xdg_schema() {
+    let name = generate_random_string();
+    let store = Store::builder().xdg_schema("true").build().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("schematic").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["xdg:schema"], "org.freedesktop.Secret.Generic");
+    assert!(matches!(
+        entry.update_attributes(&HashMap::from([("xdg:schema", "other")])),
+        Err(Error::Invalid(_, _))
+    ));
+    // items without the schema aren't found
+    let other = Store::builder()
+        .xdg_schema("org.example.Other")
+        .build()
+        .unwrap();
+    let unschematic = other.build(&name, &name, None).unwrap();
+    assert!(matches!(unschematic.get_password(), Err(Error::NoEntry)));
+    assert_eq!(entry_new(&name, &name).get_password().unwrap(), "schematic");
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_python_keyring() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let store = Store::builder().python_keyring(true).build().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("from rust").unwrap();
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["application"], "Python keyring library");
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        specifier.get_label().unwrap(),
+        format!("Password for '{name}' on '{name}'")
+    );
+    assert_eq!(specifier.get_content_type().unwrap(), "text/plain");
+    entry.delete_credential().unwrap();
+    // an item written by Python keyring
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let default = ss.get_default_collection().unwrap();
+    default
+        .create_item(
+            "Password for python",
+            HashMap::from([
+                ("service", name.as_str()),
+                ("username", name.as_str()),
+                ("application", "Python keyring library"),
+            ]),
+            b"from python",
+            true,
+            "text/plain",
+        )
+        .unwrap();
+    assert_eq!(entry.get_password().unwrap(), "from python");
+    entry.delete_credential().unwrap();
+    let config = HashMap::from([("python-keyring", "true"), ("username-salt", "salt")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(key, _)) if key == "username-salt"
+    ));
+}
+
+#[test]
+fn test_notes() {
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert!(matches!(specifier.get_note(), Err(Error::NoEntry)));
+    entry.set_password("noted password").unwrap();
+    assert_eq!(specifier.get_note().unwrap(), None);
+    specifier
+        .set_note("recovery codes are in the safe")
+        .unwrap();
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        wrapper.get_note().unwrap().unwrap(),
+        "recovery codes are in the safe"
+    );
+    wrapper.set_note("moved to the drawer").unwrap();
+    assert_eq!(
+        entry.get_attributes().unwrap()["note"],
+        "moved to the drawer"
+    );
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_provenance() {
+    let name = generate_random_string();
+    let config = HashMap::from([("provenance", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("stamped password").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let provenance = specifier.get_provenance().unwrap().unwrap();
+    assert_eq!(
+        provenance.creator,
+        format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    );
+    assert!(provenance.program.is_some());
+    let attributes = entry.get_attributes().unwrap();
+    assert_eq!(attributes["provenance:creator"], provenance.creator);
+    entry.delete_credential().unwrap();
+    let entry = entry_new(&name, &name);
+    entry.set_password("unstamped password").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert_eq!(specifier.get_provenance().unwrap(), None);
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_explicit_collections() {
+    let name = generate_random_string();
+    let config = HashMap::from([("explicit-collections", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    match entry.set_password("targeted password") {
+        Err(Error::PlatformFailure(err)) => match err.downcast_ref::<StoreError>() {
+            Some(StoreError::MissingCollection(collection)) => assert_eq!(collection, &name),
+            other => panic!("Expected a missing collection, got {other:?}"),
+        },
+        other => panic!("Expected a missing collection, got {other:?}"),
+    }
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    specifier.create_target().unwrap();
+    specifier.create_target().unwrap();
+    test_round_trip_no_delete("explicit collection", &entry, "targeted password");
+    entry.delete_credential().unwrap();
+    specifier.delete_target().unwrap();
+    let store = Store::builder().explicit_collections(true).build().unwrap();
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert!(matches!(
+        entry.set_password("targeted password"),
+        Err(Error::PlatformFailure(_))
+    ));
+}
+
+#[test]
+fn test_target_quota() {
+    let target = generate_random_string();
+    let modifiers = HashMap::from([("target", target.as_str())]);
+    let build = |store: &Store, name: &str| store.build(name, name, Some(&modifiers)).unwrap();
+    let config = HashMap::from([("max-items-per-target", "2")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let names: Vec<String> = (0..3).map(|_| generate_random_string()).collect();
+    build(&store, &names[0]).set_password("first").unwrap();
+    build(&store, &names[1]).set_password("second").unwrap();
+    match build(&store, &names[2]).set_password("third") {
+        Err(Error::PlatformFailure(err)) => assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::QuotaExceeded(_, 2))
+        )),
+        other => panic!("Expected the quota to be exceeded, got {other:?}"),
+    }
+    build(&store, &names[1])
+        .set_password("second again")
+        .unwrap();
+    let config = HashMap::from([("max-items-per-target", "2"), ("quota-eviction", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    build(&store, &names[2]).set_password("third").unwrap();
+    let remaining = names
+        .iter()
+        .filter(|name| build(&store, name).get_password().is_ok())
+        .count();
+    assert_eq!(remaining, 2);
+    assert_eq!(build(&store, &names[2]).get_password().unwrap(), "third");
+    let entry = build(&store, &names[2]);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_quota_eviction_order() {
+    let target = generate_random_string();
+    let modifiers = HashMap::from([("target", target.as_str())]);
+    let foreign = Store::new().unwrap();
+    foreign
+        .set_attribute_mapper(Arc::new(AccountMapper))
+        .unwrap();
+    let browser = foreign.build("browser", &target, Some(&modifiers)).unwrap();
+    browser.set_password("not ours").unwrap();
+    let config = HashMap::from([("max-items-per-target", "2"), ("quota-eviction", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let build = |name: &str| store.build(name, name, Some(&modifiers)).unwrap();
+    let names: Vec<String> = (0..4).map(|_| generate_random_string()).collect();
+    build(&names[0]).set_password("oldest").unwrap();
+    build(&names[1]).set_password("expired").unwrap();
+    build(&names[1])
+        .update_attributes(&HashMap::from([("expires", "1")]))
+        .unwrap();
+    build(&names[2])
+        .set_password("evicts the expired item")
+        .unwrap();
+    assert!(build(&names[0]).get_password().is_ok());
+    assert!(matches!(
+        build(&names[1]).get_password(),
+        Err(Error::NoEntry)
+    ));
+    build(&names[3])
+        .set_password("evicts the oldest item")
+        .unwrap();
+    assert!(matches!(
+        build(&names[0]).get_password(),
+        Err(Error::NoEntry)
+    ));
+    assert!(build(&names[2]).get_password().is_ok());
+    assert_eq!(browser.get_password().unwrap(), "not ours");
+    let entry = build(&names[3]);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_secure_delete() {
+    use crate::events::ItemEventKind;
+
+    let name = generate_random_string();
+    let config = HashMap::from([("secure-delete", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("doomed password").unwrap();
+    entry
+        .update_attributes(&HashMap::from([("custom", "value")]))
+        .unwrap();
+    entry.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    // items deleted along the way, such as by moving, are scrubbed too
+    let subscription = store.subscribe().unwrap();
+    entry.set_password("moving password").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let original = specifier.item_path().unwrap().to_string();
+    specifier.move_to_target(&name).unwrap();
+    let mut seen = Vec::new();
+    while !seen.contains(&ItemEventKind::Deleted) {
+        let event = subscription
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("no event arrived");
+        if event.path == original {
+            seen.push(event.kind);
+        }
+    }
+    assert!(
+        seen.contains(&ItemEventKind::Changed),
+        "the original was scrubbed before it was deleted"
+    );
+    entry.delete_credential().unwrap();
+    let target = store.build(
+        &name,
+        &name,
+        Some(&HashMap::from([("target", name.as_str())])),
+    );
+    let target = target.unwrap();
+    let targeted: &Specifier = target.as_any().downcast_ref().unwrap();
+    targeted.delete_target().unwrap();
+}
+
+#[test]
+fn test_simulated_latency() {
+    let name = generate_random_string();
+    let config = HashMap::from([("latency:read", "300")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    entry.set_password("slow password").unwrap();
+    let start = std::time::Instant::now();
+    let busy = std::thread::scope(|scope| {
+        let reader = scope.spawn(|| entry.get_password());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let busy = specifier.try_get_secret();
+        assert_eq!(reader.join().unwrap().unwrap(), "slow password");
+        busy
+    });
+    assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+    assert!(matches!(busy, Err(Error::NoStorageAccess(_))));
+    entry.delete_credential().unwrap();
+    let config = HashMap::from([("latency:teleport", "10")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_operation_deadline() {
+    let name = generate_random_string();
+    let config = HashMap::from([("latency:read", "300"), ("operation-deadline", "100")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("slow password").unwrap();
+    let late = std::thread::scope(|scope| {
+        let reader = scope.spawn(|| entry.get_password());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let late = entry.get_password();
+        assert_eq!(reader.join().unwrap().unwrap(), "slow password");
+        late
+    });
+    let Err(Error::NoStorageAccess(err)) = late else {
+        panic!("Expected the late read to be cancelled");
+    };
+    assert!(matches!(
+        err.downcast_ref::<StoreError>(),
+        Some(StoreError::DeadlineExceeded(_))
+    ));
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+fn test_operation_timeout() {
+    let name = generate_random_string();
+    let config = HashMap::from([("latency:read", "300"), ("operation-timeout", "100")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    let timeout = std::time::Duration::from_secs(5);
+    Store::with_timeout(timeout, || entry.set_password("slow password")).unwrap();
+    let Err(Error::NoStorageAccess(err)) = entry.get_password() else {
+        panic!("Expected the read to time out");
+    };
+    assert!(matches!(
+        err.downcast_ref::<StoreError>(),
+        Some(StoreError::TimedOut(_))
+    ));
+    let password = Store::with_timeout(timeout, || entry.get_password()).unwrap();
+    assert_eq!(password, "slow password");
+    // awaiting applies the same limit as waiting
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let Err(Error::NoStorageAccess(err)) = block_on(specifier.get_secret_async()) else {
+        panic!("Expected the awaited read to time out");
+    };
+    assert!(matches!(
+        err.downcast_ref::<StoreError>(),
+        Some(StoreError::TimedOut(_))
+    ));
+    Store::with_timeout(timeout, || entry.delete_credential()).unwrap();
+}
+
+#[test]
+fn test_timeout_with_full_queue() {
+    let name = generate_random_string();
+    let config = HashMap::from([("latency:read", "2000"), ("operation-timeout", "100")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = Arc::new(store.build(&name, &name, None).unwrap());
+    let timeout = std::time::Duration::from_secs(5);
+    Store::with_timeout(timeout, || entry.set_password("slow password")).unwrap();
+    // many more callers than the worker can get to before they time out
+    let started = std::time::Instant::now();
+    let callers: Vec<_> = (0..100)
+        .map(|_| {
+            let entry = entry.clone();
+            std::thread::spawn(move || entry.get_password())
+        })
+        .collect();
+    for caller in callers {
+        let Err(Error::NoStorageAccess(err)) = caller.join().unwrap() else {
+            panic!("Expected the read to time out");
+        };
+        assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::TimedOut(_))
+        ));
+    }
+    assert!(started.elapsed() < std::time::Duration::from_millis(1500));
+    Store::with_timeout(timeout, || entry.delete_credential()).unwrap();
+}
+
+#[test]
+fn test_search_raw() {
+    let name = generate_random_string();
+    let foreign = Store::new().unwrap();
+    foreign
+        .set_attribute_mapper(Arc::new(AccountMapper))
+        .unwrap();
+    let entry = foreign.build(&name, &name, None).unwrap();
+    entry.set_password("foreign password").unwrap();
+    let config = HashMap::from([("opaque-attributes", "svc"), ("opaque-key", "k")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let spec = HashMap::from([("svc", name.as_str())]);
+    assert!(store.search(&spec).unwrap().is_empty());
+    let found = store.search_raw(&spec).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get_password().unwrap(), "foreign password");
+    assert_eq!(found[0].get_attributes().unwrap()["account"], name);
+    assert!(found[0].get_specifiers().is_none());
+    entry.delete_credential().unwrap();
+}
+
+#[test]
+#[cfg(feature = "low-level")]
+fn test_low_level() {
+    use crate::low_level::Service;
+
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let service = Service::from_store(&store);
+    let attributes = HashMap::from([("low-level", name.as_str())]);
+    let path = service
+        .create_item("default", "low-level item", &attributes, b"raw secret")
+        .unwrap();
+    assert_eq!(service.search(&attributes).unwrap(), vec![path.clone()]);
+    assert_eq!(service.get_secret(&path).unwrap(), b"raw secret");
+    service.set_secret(&path, b"new secret").unwrap();
+    service.lock(&path).unwrap();
+    service.unlock(&path).unwrap();
+    assert_eq!(service.get_secret(&path).unwrap(), b"new secret");
+    assert_eq!(service.get_attributes(&path).unwrap()["low-level"], name);
+    service.delete(&path).unwrap();
+    assert!(service.search(&attributes).unwrap().is_empty());
+}
+
+#[test]
+#[cfg(feature = "low-level")]
+fn test_low_level_async() {
+    use crate::low_level::Service;
+
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let service = Service::from_store(&store);
+    let attributes = HashMap::from([("low-level", name.as_str())]);
+    block_on(async {
+        let path = service
+            .create_item_async("default", "async item", &attributes, b"async secret")
+            .await
+            .unwrap();
+        let found = service.search_async(&attributes).await.unwrap();
+        assert_eq!(found, vec![path.clone()]);
+        // operations submitted together run in order on the worker
+        let write = service.set_secret_async(&path, b"new secret");
+        let relabel = service.set_label_async(&path, "relabeled item");
+        let read = service.get_secret_async(&path);
+        relabel.await.unwrap();
+        write.await.unwrap();
+        assert_eq!(read.await.unwrap(), b"new secret");
+        assert_eq!(
+            service.get_label_async(&path).await.unwrap(),
+            "relabeled item"
+        );
+        let update = HashMap::from([("extra", "value")]);
+        service
+            .update_attributes_async(&path, &update)
+            .await
+            .unwrap();
+        let stored = service.get_attributes_async(&path).await.unwrap();
+        assert_eq!(stored["extra"], "value");
+        service.lock_async(&path).await.unwrap();
+        service.unlock_async(&path).await.unwrap();
+        service.delete_async(&path).await.unwrap();
+        assert!(service.search_async(&attributes).await.unwrap().is_empty());
+    });
+}
+
+#[derive(Default)]
+struct Login {
+    server: String,
+    port: u16,
+    password: String,
+}
+
+#[test]
+fn test_record_mapping() {
+    use crate::record::RecordMapping;
+
+    let name = generate_random_string();
+    let mapping = RecordMapping::new()
+        .attribute("server", |l: &Login| l.server.clone(), |l, v| l.server = v)
+        .attribute(
+            "port",
+            |l: &Login| l.port.to_string(),
+            |l, v| l.port = v.parse().unwrap_or_default(),
+        )
+        .secret(
+            |l: &Login| l.password.as_bytes().to_vec(),
+            |l, s| {
+                l.password =
+                    String::from_utf8(s).map_err(|e| Error::BadEncoding(e.into_bytes()))?;
+                Ok(())
+            },
+        );
+    let entry = entry_new(&name, &name);
+    let login = Login {
+        server: "imap.example.com".to_string(),
+        port: 993,
+        password: "structured password".to_string(),
+    };
+    mapping.write(&entry, &login).unwrap();
+    assert_eq!(entry.get_attributes().unwrap()["port"], "993");
+    let read: Login = mapping.read(&entry).unwrap();
+    assert_eq!(read.server, login.server);
+    assert_eq!(read.port, login.port);
+    assert_eq!(read.password, login.password);
+    entry.delete_credential().unwrap();
+    assert!(matches!(mapping.read(&entry), Err(Error::NoEntry)));
+}
+
+struct French;
+
+impl Localizer for French {
+    fn default_label(&self, service: &str, user: Option<&str>) -> String {
+        match user {
+            Some(user) => format!("trousseau : {user} pour {service}"),
+            None => format!("trousseau : {service}"),
+        }
+    }
+
+    fn describe(&self, error: &StoreError) -> String {
+        match error {
+            StoreError::WouldBlock => "Le trousseau est occupé".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+#[test]
+fn test_localizer() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    store.set_localizer(Arc::new(French));
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("mot de passe").unwrap();
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        wrapper.get_label().unwrap(),
+        format!("trousseau : {name} pour {name}")
+    );
+    entry.delete_credential().unwrap();
+    let busy = Error::NoStorageAccess(Box::new(StoreError::WouldBlock));
+    assert_eq!(store.describe_error(&busy), "Le trousseau est occupé");
+    assert_eq!(
+        store.describe_error(&Error::NoEntry),
+        Error::NoEntry.to_string()
+    );
+}
+
+#[test]
+fn test_session_rekeying() {
+    let name = generate_random_string();
+    let config = HashMap::from([("rekey-after-operations", "2")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    assert_eq!(store.session_renewals(), 0);
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip("rekeyed password", &entry, "rekeyed");
+    assert!(store.session_renewals() > 0);
+    let config = HashMap::from([("rekey-after-seconds", "0")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_rebind_on_restart() {
+    let name = generate_random_string();
+    let config = HashMap::from([("rebind-on-restart", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip("rebinding password", &entry, "rebinding");
+    assert_eq!(store.session_renewals(), 0);
+}
+
+#[test]
+fn test_disconnect_detection() {
+    use crate::errors::{decode_error, is_disconnected, is_stale_path};
+    use dbus_secret_service::Error as ServiceError;
+
+    let error = |name: &str| decode_error(ServiceError::Dbus(dbus::Error::new_custom(name, "")));
+    for name in [
+        "org.freedesktop.DBus.Error.Disconnected",
+        "org.freedesktop.DBus.Error.ServiceUnknown",
+        "org.freedesktop.Secret.Error.NoSession",
+    ] {
+        assert!(is_disconnected(&error(name)), "{name} is a disconnect");
+    }
+    let stale = error("org.freedesktop.Secret.Error.NoSuchObject");
+    assert!(is_stale_path(&stale));
+    assert!(!is_disconnected(&stale));
+    assert!(!is_disconnected(&Error::NoEntry));
+}
+
+#[test]
+fn test_typed_errors() {
+    use crate::errors::{StoreError, decode_error};
+    use dbus_secret_service::Error as ServiceError;
+
+    let store_error = |err: ServiceError| match decode_error(err) {
+        Error::PlatformFailure(inner) => ("failure", inner.downcast::<StoreError>().ok()),
+        Error::NoStorageAccess(inner) => ("access", inner.downcast::<StoreError>().ok()),
+        _ => ("other", None),
+    };
+    let dbus = |name: &str| ServiceError::Dbus(dbus::Error::new_custom(name, "message"));
+    assert!(matches!(
+        store_error(dbus("org.freedesktop.Secret.Error.IsLocked")),
+        ("access", Some(e)) if matches!(*e, StoreError::IsLocked)
+    ));
+    assert!(matches!(
+        store_error(ServiceError::Locked),
+        ("access", Some(e)) if matches!(*e, StoreError::IsLocked)
+    ));
+    assert!(matches!(
+        store_error(dbus("org.freedesktop.Secret.Error.NoSuchObject")),
+        ("failure", Some(e)) if matches!(*e, StoreError::NoSuchObject)
+    ));
+    assert!(matches!(
+        store_error(dbus("org.freedesktop.Secret.Error.NoSession")),
+        ("failure", Some(e)) if matches!(*e, StoreError::NoSession)
+    ));
+    assert!(matches!(
+        store_error(ServiceError::Prompt),
+        ("access", Some(e)) if matches!(*e, StoreError::PromptDismissed)
+    ));
+    assert!(matches!(
+        store_error(dbus("org.freedesktop.DBus.Error.AccessDenied")),
+        ("failure", None)
+    ));
+}
+
+#[test]
+fn test_retry() {
+    use crate::config::Operation;
+    use crate::errors::{decode_error, is_disconnected, is_transient, platform_failure};
+    use dbus_secret_service::Error as ServiceError;
+
+    let error = |name: &str| decode_error(ServiceError::Dbus(dbus::Error::new_custom(name, "")));
+    for name in [
+        "org.freedesktop.DBus.Error.NoReply",
+        "org.freedesktop.DBus.Error.Timeout",
+        "org.freedesktop.DBus.Error.ServiceUnknown",
+    ] {
+        assert!(is_transient(&error(name)), "{name} is transient");
+    }
+    assert!(is_transient(&platform_failure(ServiceError::Unavailable)));
+    assert!(!is_transient(&error(
+        "org.freedesktop.DBus.Error.AccessDenied"
+    )));
+    assert!(!is_transient(&error(
+        "org.freedesktop.Secret.Error.IsLocked"
+    )));
+    assert!(!is_transient(&Error::NoEntry));
+    assert!(!is_disconnected(&error(
+        "org.freedesktop.DBus.Error.NoReply"
+    )));
+    assert!(Operation::Read.is_idempotent());
+    assert!(!Operation::Create.is_idempotent());
+    assert!(!Operation::Delete.is_idempotent());
+    assert!(matches!(
+        Store::new_with_configuration(&HashMap::from([("retry-attempts", "0")])),
+        Err(Error::Invalid(_, _))
+    ));
+    let store = Store::builder()
+        .retry(3, std::time::Duration::from_millis(10))
+        .build()
+        .unwrap();
+    let name = generate_random_string();
+    let entry = store.build(&name, &name, None).unwrap();
+    test_round_trip("retrying password", &entry, "retrying");
+}
+
+#[test]
+fn test_dbus_error_details() {
+    use crate::errors::{DbusError, decode_error, in_operation};
+    use dbus_secret_service::Error as ServiceError;
+
+    let denied = ServiceError::Dbus(dbus::Error::new_custom(
+        "org.freedesktop.DBus.Error.AccessDenied",
+        "not allowed",
+    ));
+    let err = in_operation(decode_error(denied), "write");
+    let Error::PlatformFailure(inner) = err else {
+        panic!("D-Bus error wasn't a platform failure: {err:?}");
+    };
+    let details = inner.downcast_ref::<DbusError>().unwrap();
+    assert_eq!(
+        details.name(),
+        Some("org.freedesktop.DBus.Error.AccessDenied")
+    );
+    assert_eq!(details.message(), Some("not allowed"));
+    assert_eq!(details.operation(), Some("write"));
+    assert_eq!(
+        details.to_string(),
+        "D-Bus error org.freedesktop.DBus.Error.AccessDenied: not allowed (during write operation)"
+    );
+    assert!(std::error::Error::source(details).is_some());
+}
+
+#[test]
+fn test_duplicate_collections() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let older = ss.create_collection(&name, "").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let newer = ss.create_collection(&name, "").unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let config = HashMap::from([("duplicate-collections", "error")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    match entry.set_password("ambiguous") {
+        Err(Error::PlatformFailure(err)) => assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::AmbiguousCollection(_, 2))
+        )),
+        other => panic!("Expected an ambiguous collection, got {other:?}"),
+    }
+    let config = HashMap::from([("duplicate-collections", "newest")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        specifier.target_collection().unwrap(),
+        newer.path.to_string()
+    );
+    assert_ne!(older.path, newer.path);
+    older.delete().unwrap();
+    newer.delete().unwrap();
+}
+
+#[test]
+fn test_collection_path_modifier() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let collection = ss.create_collection(&name, "").unwrap();
+    let path = collection.path.to_string();
+    let store = Store::new().unwrap();
+    let modifiers = HashMap::from([("collection", path.as_str())]);
+    let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+    collection.set_label("renamed").unwrap();
+    entry.set_password("pathed password").unwrap();
+    assert_eq!(collection.get_all_items().unwrap().len(), 1);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert_eq!(specifier.target_collection().unwrap(), path);
+    entry.delete_credential().unwrap();
+    collection.delete().unwrap();
+    match entry.set_password("orphaned password") {
+        Err(Error::PlatformFailure(err)) => assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::MissingCollection(_))
+        )),
+        other => panic!("Expected a missing collection, got {other:?}"),
+    }
+    let modifiers = HashMap::from([("collection", "not a path")]);
+    assert!(matches!(
+        store.build(&name, &name, Some(&modifiers)),
+        Err(Error::Invalid(_, _))
+    ));
+    // a target that isn't a collection's path is still a label
+    let label = format!("/{name}");
+    let labeled = ss.create_collection(&label, "").unwrap();
+    let entry = entry_new_with_target(&label, &name, &name);
+    entry.set_password("labeled password").unwrap();
+    assert_eq!(labeled.get_all_items().unwrap().len(), 1);
+    entry.delete_credential().unwrap();
+    labeled.delete().unwrap();
+}
+
+#[test]
+#[cfg(feature = "memory-fallback")]
+fn test_memory_fallback() {
+    let store = Store::new_with_fallback(&HashMap::new()).unwrap();
+    assert!(matches!(
+        store.persistence(),
+        CredentialPersistence::UntilDelete
+    ));
+    let memory = crate::memory::Store::new();
+    assert!(matches!(
+        memory.persistence(),
+        CredentialPersistence::ProcessOnly
+    ));
+    let name = generate_random_string();
+    let entry = memory.build(&name, &name, None).unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    entry.set_password("ephemeral").unwrap();
+    let again = memory.build(&name, &name, None).unwrap();
+    assert_eq!(again.get_password().unwrap(), "ephemeral");
+    again.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    let config = HashMap::from([("no-such-key", "value")]);
+    assert!(matches!(
+        Store::new_with_fallback(&config),
+        Err(Error::NotSupportedByStore(_))
+    ));
+}
+
+#[test]
+fn test_mirror_file() {
+    let name = generate_random_string();
+    let file = std::env::temp_dir().join(format!("mirror-{name}"));
+    let file_name = file.to_str().unwrap();
+    let config = HashMap::from([("mirror-file", file_name), ("mirror-key", "sesame")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("first").unwrap();
+    entry.set_password("second").unwrap();
+    entry
+        .update_attributes(&HashMap::from([("custom", "value")]))
+        .unwrap();
+    let records = crate::mirror::read(&file, "sesame").unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[2].secret, b"second");
+    assert_eq!(records[2].collection, "default");
+    assert_eq!(records[2].attributes["custom"], "value");
+    assert_eq!(records[2].content_type, "application/octet-stream");
+    assert!(matches!(
+        crate::mirror::read(&file, "wrong"),
+        Err(Error::BadStoreFormat(_))
+    ));
+    // records removed from the middle of the file, or reordered, are noticed
+    let data = std::fs::read(&file).unwrap();
+    let header = b"keyring mirror 2\n".len() + 16;
+    let mut records = Vec::new();
+    let mut start = header;
+    while start < data.len() {
+        let length = u32::from_be_bytes(data[start..start + 4].try_into().unwrap()) as usize;
+        records.push(&data[start..start + 4 + length]);
+        start += 4 + length;
+    }
+    let tampered = file.with_extension("tampered");
+    for order in [[0, 2], [1, 2], [1, 0]] {
+        let mut copy = data[..header].to_vec();
+        order
+            .iter()
+            .for_each(|&i| copy.extend_from_slice(records[i]));
+        std::fs::write(&tampered, &copy).unwrap();
+        assert!(matches!(
+            crate::mirror::read(&tampered, "sesame"),
+            Err(Error::BadStoreFormat(_))
+        ));
+    }
+    // but records removed from the end are not
+    std::fs::write(&tampered, &data[..data.len() - records[2].len()]).unwrap();
+    assert_eq!(crate::mirror::read(&tampered, "sesame").unwrap().len(), 2);
+    // files that aren't mirror files are rejected
+    std::fs::write(&tampered, b"not a mirror file").unwrap();
+    let bad = HashMap::from([
+        ("mirror-file", tampered.to_str().unwrap()),
+        ("mirror-key", "sesame"),
+    ]);
+    assert!(matches!(
+        Store::new_with_configuration(&bad),
+        Err(Error::BadStoreFormat(_))
+    ));
+    std::fs::remove_file(&tampered).unwrap();
+    // another store can append to the same file
+    let other = Store::new_with_configuration(&config).unwrap();
+    let other_entry = other.build(&name, &name, None).unwrap();
+    other_entry.set_password("third").unwrap();
+    entry.set_password("second").unwrap();
+    let records = crate::mirror::read(&file, "sesame").unwrap();
+    assert_eq!(records.len(), 5);
+    assert_eq!(records[4].secret, b"second");
+    // an item deleted behind the store's back is restored, content type and all
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    specifier.set_content_type("text/plain").unwrap();
+    let plain = Store::new().unwrap();
+    plain
+        .build(&name, &name, None)
+        .unwrap()
+        .delete_credential()
+        .unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    assert_eq!(store.restore_from_mirror(&file, "sesame").unwrap(), 1);
+    assert_eq!(entry.get_password().unwrap(), "second");
+    assert_eq!(specifier.get_content_type().unwrap(), "text/plain");
+    // an item deleted by the store is not
+    entry.delete_credential().unwrap();
+    let records = crate::mirror::read(&file, "sesame").unwrap();
+    assert!(crate::mirror::surviving(records).is_empty());
+    assert_eq!(store.restore_from_mirror(&file, "sesame").unwrap(), 0);
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    std::fs::remove_file(&file).unwrap();
+    let config = HashMap::from([("mirror-file", file_name)]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(_, _))
+    ));
+}
+
+#[test]
+fn test_session_collection() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    assert!(matches!(
+        store.persistence(),
+        CredentialPersistence::UntilDelete
+    ));
+    let config = HashMap::from([("session-collection", "true")]);
+    let store = Store::new_with_configuration(&config).unwrap();
+    assert!(matches!(
+        store.persistence(),
+        CredentialPersistence::UntilLogout
+    ));
+    let entry = store.build(&name, &name, None).unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert!(matches!(
+        specifier.persistence(),
+        CredentialPersistence::UntilLogout
+    ));
+    entry.set_password("session password").unwrap();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let session = ss.get_collection_by_alias("session").unwrap();
+    assert_eq!(
+        specifier.target_collection().unwrap(),
+        session.path.to_string()
+    );
+    assert_eq!(entry.get_password().unwrap(), "session password");
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let targeted = store.build(&name, &name, Some(&modifiers)).unwrap();
+    let specifier: &Specifier = targeted.as_any().downcast_ref().unwrap();
+    assert!(matches!(
+        specifier.persistence(),
+        CredentialPersistence::UntilDelete
+    ));
+    entry.delete_credential().unwrap();
+    let store = Store::new().unwrap();
+    let modifiers = HashMap::from([("target", "session")]);
+    let ephemeral = store.build(&name, &name, Some(&modifiers)).unwrap();
+    let specifier: &Specifier = ephemeral.as_any().downcast_ref().unwrap();
+    assert!(matches!(
+        specifier.persistence(),
+        CredentialPersistence::UntilLogout
+    ));
+    ephemeral.set_password("ephemeral password").unwrap();
+    assert_eq!(
+        specifier.target_collection().unwrap(),
+        session.path.to_string()
+    );
+    // the session collection is recognized by any of its names
+    let real_path = session.path.to_string();
+    for target in ["alias:session", real_path.as_str()] {
+        let modifiers = HashMap::from([("target", target)]);
+        let entry = store.build(&name, &name, Some(&modifiers)).unwrap();
+        let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+        assert!(
+            matches!(specifier.persistence(), CredentialPersistence::UntilLogout),
+            "{target} is the session collection"
+        );
+    }
+    // as are the items found in it
+    let wrapper = ephemeral.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert!(matches!(
+        wrapper.persistence(),
+        CredentialPersistence::UntilLogout
+    ));
+    let lasting = entry_new(&format!("{name}-lasting"), &name);
+    lasting.set_password("lasting password").unwrap();
+    let wrapper = lasting.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert!(matches!(
+        wrapper.persistence(),
+        CredentialPersistence::UntilDelete
+    ));
+    lasting.delete_credential().unwrap();
+    assert!(matches!(
+        specifier.delete_target(),
+        Err(Error::NotSupportedByStore(_))
+    ));
+    ephemeral.delete_credential().unwrap();
+}
+
+#[test]
+fn test_async_credentials() {
+    fn assert_send<T: Send>(_: &T) {}
+
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let future = specifier.get_secret_async();
+    assert_send(&future);
+    assert!(matches!(block_on(future), Err(Error::NoEntry)));
+    block_on(async {
+        specifier.set_secret_async(b"async secret").await.unwrap();
+        assert_eq!(specifier.get_secret_async().await.unwrap(), b"async secret");
+        let update = HashMap::from([("color", "blue")]);
+        specifier.update_attributes_async(&update).await.unwrap();
+        assert_eq!(
+            specifier.get_attributes_async().await.unwrap()["color"],
+            "blue"
+        );
+    });
+    let credential = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = credential.as_any().downcast_ref().unwrap();
+    block_on(async {
+        wrapper.set_secret_async(b"wrapped secret").await.unwrap();
+        assert_eq!(wrapper.get_secret_async().await.unwrap(), b"wrapped secret");
+        let update = HashMap::from([("color", "green")]);
+        wrapper.update_attributes_async(&update).await.unwrap();
+        assert_eq!(
+            wrapper.get_attributes_async().await.unwrap()["color"],
+            "green"
+        );
+        wrapper.delete_credential_async().await.unwrap();
+    });
+    assert!(matches!(
+        block_on(specifier.delete_credential_async()),
+        Err(Error::NoEntry)
+    ));
+}
+
+#[test]
+fn test_concurrent_futures() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    /// Counts wakeups, as an executor's task queue would.
+    struct Counter(Mutex<usize>, std::thread::Thread);
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            *self.0.lock().unwrap() += 1;
+            self.1.unpark();
+        }
+    }
+
+    let names: Vec<String> = (0..4).map(|_| generate_random_string()).collect();
+    let store = Store::new().unwrap();
+    let entries: Vec<Entry> = names
+        .iter()
+        .map(|n| store.build(n, n, None).unwrap())
+        .collect();
+    let specifiers: Vec<&Specifier> = entries
+        .iter()
+        .map(|e| e.as_any().downcast_ref().unwrap())
+        .collect();
+    let counter = Arc::new(Counter(Mutex::new(0), std::thread::current()));
+    let waker = Waker::from(counter.clone());
+    let mut context = Context::from_waker(&waker);
+    // poll all the futures on one thread until they are all done
+    let mut futures: Vec<Pin<Box<dyn Future<Output = keyring_core::Result<()>> + Send + '_>>> =
+        specifiers
+            .iter()
+            .zip(names.iter())
+            .map(|(s, n)| {
+                Box::pin(s.set_secret_async(n.as_bytes()))
+                    as Pin<Box<dyn Future<Output = _> + Send>>
+            })
+            .collect();
+    while !futures.is_empty() {
+        futures.retain_mut(|f| match f.as_mut().poll(&mut context) {
+            Poll::Ready(result) => {
+                result.unwrap();
+                false
+            }
+            Poll::Pending => true,
+        });
+        if !futures.is_empty() {
+            std::thread::park_timeout(std::time::Duration::from_millis(100));
+        }
+    }
+    assert!(*counter.0.lock().unwrap() > 0);
+    for (specifier, name) in specifiers.iter().zip(names.iter()) {
+        assert_eq!(
+            block_on(specifier.get_secret_async()).unwrap(),
+            name.as_bytes()
+        );
+        block_on(specifier.delete_credential_async()).unwrap();
+    }
+}
+
+#[test]
+fn test_legacy_items() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let default = ss.get_default_collection().unwrap();
+    default
+        .create_item(
+            "Legacy item",
+            HashMap::from([
+                ("service", name.as_str()),
+                ("username", name.as_str()),
+                ("application", "rust-keyring"),
+            ]),
+            b"legacy",
+            true,
+            "text/plain",
+        )
+        .unwrap();
+    // stores that search differently don't see the legacy item by default
+    let store = Store::builder().xdg_schema("true").build().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    let store = Store::builder()
+        .xdg_schema("true")
+        .legacy_items(true)
+        .build()
+        .unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "legacy");
+    let fresh = store.build(&name, &name, None).unwrap();
+    let secrets = store.get_secrets(&[&fresh]).unwrap();
+    assert_eq!(
+        secrets[0].as_ref().unwrap(),
+        b"legacy",
+        "batches find them too"
+    );
+    // migration rewrites the item for stores that don't look for legacy items
+    let store = Store::builder().xdg_schema("true").build().unwrap();
+    let report = store.migrate_legacy().unwrap();
+    let migrated = report.iter().find(|l| l.service == name).unwrap();
+    assert!(migrated.migrated);
+    assert_eq!(migrated.user, name);
+    assert_eq!(migrated.item.attributes["application"], "rust-keyring");
+    let entry = store.build(&name, &name, None).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "legacy");
+    let attributes = entry.get_attributes().unwrap();
+    assert!(!attributes.contains_key("application"));
+    // a legacy item isn't migrated over an existing one
+    default
+        .create_item(
+            "Legacy duplicate",
+            HashMap::from([
+                ("service", name.as_str()),
+                ("username", name.as_str()),
+                ("application", "rust-keyring"),
+            ]),
+            b"stale",
+            true,
+            "text/plain",
+        )
+        .unwrap();
+    let report = store.migrate_legacy().unwrap();
+    let skipped = report.iter().find(|l| l.service == name).unwrap();
+    assert!(!skipped.migrated);
+    assert_eq!(entry.get_password().unwrap(), "legacy");
+    entry.delete_credential().unwrap();
+    let leftover = store
+        .search_raw(&HashMap::from([
+            ("service", name.as_str()),
+            ("application", "rust-keyring"),
+        ]))
+        .unwrap();
+    assert_eq!(leftover.len(), 1);
+    leftover[0].delete_credential().unwrap();
+}
+
+#[test]
+fn test_duplicates() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("in the default").unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let targeted = store.build(&name, &name, Some(&modifiers)).unwrap();
+    targeted.set_password("in the target").unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::Ambiguous(_))));
+    let duplicates: Vec<_> = store
+        .find_duplicates()
+        .unwrap()
+        .into_iter()
+        .filter(|d| d.service == name)
+        .collect();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].user, name);
+    assert_eq!(duplicates[0].items.len(), 2);
+    let kept = duplicates[0].items[0].path.clone();
+    assert_eq!(store.dedupe(&duplicates).unwrap(), 1);
+    let wrapper = entry.get_credential().unwrap();
+    let remaining: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(remaining.path().to_string(), kept);
+    entry.delete_credential().unwrap();
+    let specifier: &Specifier = targeted.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_ambiguous_items() {
+    let name = generate_random_string();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let store = Store::new().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("in the default").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let targeted = store.build(&name, &name, Some(&modifiers)).unwrap();
+    targeted.set_password("in the target").unwrap();
+    let Err(Error::Ambiguous(candidates)) = entry.get_password() else {
+        panic!("Expected an ambiguous error");
+    };
+    assert_eq!(candidates.len(), 2);
+    for candidate in candidates.iter() {
+        let wrapper: &Wrapper = candidate.as_any().downcast_ref().unwrap();
+        let details = wrapper.candidate().unwrap();
+        assert_eq!(details.path, wrapper.path().to_string());
+        assert_eq!(details.attributes["service"], name);
+        assert!(details.label.contains(&name));
+        assert!(details.modified > 0);
+        assert!(details.collection_label == name || details.collection_label == "Login");
+    }
+    let config = HashMap::from([("ambiguous-items", "newest")]);
+    let newest = Store::new_with_configuration(&config).unwrap();
+    let entry = newest.build(&name, &name, None).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "in the target");
+    let config = HashMap::from([("ambiguous-items", "target")]);
+    let target = Store::new_with_configuration(&config).unwrap();
+    let entry = target.build(&name, &name, Some(&modifiers)).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "in the target");
+    entry.delete_credential().unwrap();
+    let entry = target.build(&name, &name, None).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "in the default");
+    entry.delete_credential().unwrap();
+    let specifier: &Specifier = targeted.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+    let config = HashMap::from([("ambiguous-items", "oldest")]);
+    assert!(matches!(
+        Store::new_with_configuration(&config),
+        Err(Error::Invalid(key, _)) if key == "ambiguous-items"
+    ));
+}
+
+#[test]
+fn test_credential_from_path() {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let name = generate_random_string();
+    let ss = SecretService::connect(EncryptionType::Plain).unwrap();
+    let default = ss.get_default_collection().unwrap();
+    let item = default
+        .create_item(
+            "Foreign item",
+            HashMap::from([("connection-uuid", name.as_str())]),
+            b"foreign",
+            true,
+            "text/plain",
+        )
+        .unwrap();
+    let store = Store::new().unwrap();
+    let entry = store.credential_from_path(&item.path).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "foreign");
+    entry.set_password("adopted").unwrap();
+    assert_eq!(item.get_secret().unwrap(), b"adopted");
+    assert_eq!(entry.get_attributes().unwrap()["connection-uuid"], name);
+    entry.delete_credential().unwrap();
+    assert!(matches!(
+        store.credential_from_path(&item.path),
+        Err(Error::NoEntry)
+    ));
+    assert!(matches!(
+        store.credential_from_path("not a path"),
+        Err(Error::Invalid(key, _)) if key == "path"
+    ));
+}
+
+#[test]
+fn test_item_paths() {
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    assert!(matches!(specifier.item_path(), Err(Error::NoEntry)));
+    entry.set_password("located").unwrap();
+    let path = specifier.item_path().unwrap();
+    let collection = specifier.item_collection().unwrap();
+    assert_eq!(collection, specifier.target_collection().unwrap());
+    assert!(path.starts_with(&format!("{collection}/")));
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    assert_eq!(wrapper.item_path(), path);
+    assert_eq!(wrapper.item_collection(), collection);
+    entry.delete_credential().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_move_to_target() {
+    let name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    entry.set_password("moving").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let original = specifier.item_path().unwrap();
+    assert!(matches!(
+        specifier.move_to_target(""),
+        Err(Error::Invalid(_, _))
+    ));
+    specifier.move_to_target(&name).unwrap();
+    let target = entry_new_with_target(&name, &name, &name);
+    let targeted: &Specifier = target.as_any().downcast_ref().unwrap();
+    assert_eq!(
+        specifier.item_collection().unwrap(),
+        targeted.target_collection().unwrap()
+    );
+    assert_ne!(specifier.item_path().unwrap(), original);
+    assert_eq!(entry.get_password().unwrap(), "moving");
+    // wrappers follow their items
+    let wrapper = entry.get_credential().unwrap();
+    let wrapper: &Wrapper = wrapper.as_any().downcast_ref().unwrap();
+    wrapper.move_to_target("default").unwrap();
+    assert_eq!(wrapper.item_path(), specifier.item_path().unwrap());
+    assert_eq!(wrapper.try_get_secret().unwrap(), b"moving");
+    entry.delete_credential().unwrap();
+    targeted.delete_target().unwrap();
+}
+
+#[test]
+fn test_rename() {
+    let name = generate_random_string();
+    let renamed_name = generate_random_string();
+    let entry = entry_new(&name, &name);
+    entry.set_password("renamed").unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    let old_label = specifier.get_label().unwrap();
+    let renamed = specifier.rename(&renamed_name, &renamed_name).unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    assert_eq!(renamed.get_password().unwrap(), "renamed");
+    assert_eq!(
+        renamed.get_specifiers().unwrap(),
+        (renamed_name.clone(), renamed_name.clone())
+    );
+    let attributes = renamed.get_attributes().unwrap();
+    assert_eq!(attributes["service"], renamed_name);
+    assert_eq!(attributes["username"], renamed_name);
+    let renamed_specifier: &Specifier = renamed.as_any().downcast_ref().unwrap();
+    let new_label = renamed_specifier.get_label().unwrap();
+    assert_ne!(new_label, old_label);
+    assert!(new_label.contains(&renamed_name));
+    // custom labels are kept, and existing items aren't overwritten
+    renamed_specifier.set_label("custom").unwrap();
+    entry.set_password("original").unwrap();
+    assert!(matches!(
+        renamed_specifier.rename(&name, &name),
+        Err(Error::PlatformFailure(err))
+            if matches!(err.downcast_ref(), Some(StoreError::AlreadyExists(_)))
+    ));
+    entry.delete_credential().unwrap();
+    let back = renamed_specifier.rename(&name, &name).unwrap();
+    let back_specifier: &Specifier = back.as_any().downcast_ref().unwrap();
+    assert_eq!(back_specifier.get_label().unwrap(), "custom");
+    back.delete_credential().unwrap();
+}
+
+#[test]
+fn test_clone_to() {
+    let name = generate_random_string();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let staging = Store::builder().scoped_search(true).build().unwrap();
+    let staged = staging.build(&name, &name, Some(&modifiers)).unwrap();
+    staged.set_password("promoted").unwrap();
+    let specifier: &Specifier = staged.as_any().downcast_ref().unwrap();
+    assert!(matches!(
+        specifier.clone_to(&name),
+        Err(Error::Invalid(_, _))
+    ));
+    specifier.clone_to("default").unwrap();
+    let entry = staging.build(&name, &name, None).unwrap();
+    assert_eq!(entry.get_password().unwrap(), "promoted");
+    assert_eq!(
+        entry.get_attributes().unwrap(),
+        staged.get_attributes().unwrap()
+    );
+    assert_eq!(staged.get_password().unwrap(), "promoted");
+    assert_ne!(
+        entry
+            .as_any()
+            .downcast_ref::<Specifier>()
+            .unwrap()
+            .item_path()
+            .unwrap(),
+        specifier.item_path().unwrap()
+    );
+    entry.delete_credential().unwrap();
+    staged.delete_credential().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_export_collection() {
+    let name = generate_random_string();
+    let file = std::env::temp_dir().join(format!("backup-{name}"));
+    let first = entry_new_with_target(&name, &name, "first");
+    first.set_password("first secret").unwrap();
+    let second = entry_new_with_target(&name, &name, "second");
+    second.set_password("second secret").unwrap();
+    let store = Store::new().unwrap();
+    assert_eq!(store.export_collection(&name, &file, "sesame").unwrap(), 2);
+    let mut records = crate::backup::read(&file, "sesame").unwrap();
+    records.sort_by(|a, b| a.attributes["username"].cmp(&b.attributes["username"]));
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].collection, name);
+    assert_eq!(records[0].attributes["username"], "first");
+    assert_eq!(records[0].secret, b"first secret");
+    assert_eq!(records[1].secret, b"second secret");
+    assert!(matches!(
+        crate::backup::read(&file, "wrong"),
+        Err(Error::BadStoreFormat(_))
+    ));
+    // cut off mid-record, and right after the first record
+    let data = std::fs::read(&file).unwrap();
+    let header = b"keyring backup 2\n".len() + 16;
+    let first_length = u32::from_be_bytes(data[header..header + 4].try_into().unwrap());
+    for cut in [data.len() - 1, header + 4 + first_length as usize] {
+        std::fs::write(&file, &data[..cut]).unwrap();
+        assert!(matches!(
+            crate::backup::read(&file, "sesame"),
+            Err(Error::BadStoreFormat(_))
+        ));
+    }
+    assert!(matches!(
+        store.export_collection(&name, &file, ""),
+        Err(Error::Invalid(key, _)) if key == "passphrase"
+    ));
+    first.delete_credential().unwrap();
+    second.delete_credential().unwrap();
+    let specifier: &Specifier = first.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn test_import_backup() {
+    use crate::backup::{ConflictPolicy, ImportSummary};
+
+    let name = generate_random_string();
+    let restored = generate_random_string();
+    let file = std::env::temp_dir().join(format!("backup-{name}"));
+    let first = entry_new_with_target(&name, &name, "first");
+    first.set_password("first secret").unwrap();
+    let specifier: &Specifier = first.as_any().downcast_ref().unwrap();
+    specifier.set_content_type("text/plain").unwrap();
+    let second = entry_new_with_target(&name, &name, "second");
+    second.set_password("second secret").unwrap();
+    let store = Store::builder().scoped_search(true).build().unwrap();
+    store.export_collection(&name, &file, "sesame").unwrap();
+    // into an empty collection
+    let summary = store
+        .import_backup(&file, "sesame", &restored, ConflictPolicy::Skip)
+        .unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            created: 2,
+            ..Default::default()
+        }
+    );
+    // the items keep their attributes, so they're found by their new collection's path
+    let collections = store.list_collections().unwrap();
+    let collection = collections.iter().find(|c| c.label == restored).unwrap();
+    let modifiers = HashMap::from([("collection", collection.path.as_str())]);
+    let copy = store.build(&name, "first", Some(&modifiers)).unwrap();
+    assert_eq!(copy.get_password().unwrap(), "first secret");
+    let copied: &Specifier = copy.as_any().downcast_ref().unwrap();
+    assert_eq!(copied.get_content_type().unwrap(), "text/plain");
+    // over existing items
+    copy.set_password("changed").unwrap();
+    let summary = store
+        .import_backup(&file, "sesame", &restored, ConflictPolicy::Skip)
+        .unwrap();
+    assert_eq!(summary.skipped, 2);
+    assert_eq!(copy.get_password().unwrap(), "changed");
+    let summary = store
+        .import_backup(&file, "sesame", &restored, ConflictPolicy::Overwrite)
+        .unwrap();
+    assert_eq!(summary.overwritten, 2);
+    assert_eq!(copy.get_password().unwrap(), "first secret");
+    let policy = ConflictPolicy::DuplicateWithSuffix(" (restored)".to_string());
+    let summary = store
+        .import_backup(&file, "sesame", &restored, policy)
+        .unwrap();
+    assert_eq!(summary.created, 2);
+    let Err(Error::Ambiguous(items)) = copy.get_password() else {
+        panic!("Expected duplicates");
+    };
+    let wrappers: Vec<_> = items
+        .iter()
+        .map(|i| i.as_any().downcast_ref::<Wrapper>().unwrap())
+        .collect();
+    assert!(
+        wrappers
+            .iter()
+            .any(|w| w.get_label().unwrap().ends_with(" (restored)"))
+    );
+    assert!(matches!(
+        store.import_backup(&file, "wrong", &restored, ConflictPolicy::Skip),
+        Err(Error::BadStoreFormat(_))
+    ));
+    for target in [&name, &restored] {
+        let entry = entry_new_with_target(target, &name, &name);
+        let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+        specifier.delete_target().unwrap();
+    }
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn test_import_secret_tool() {
+    use crate::backup::{ConflictPolicy, parse_secret_tool};
+
+    let name = generate_random_string();
+    let dump = format!(
+        "[/org/freedesktop/secrets/collection/login/1]
+label = Password for alice
+secret = alice secret
+created = 2024-01-01 12:00:00
+modified = 2024-01-01 12:00:00
+schema = org.freedesktop.Secret.Generic
+attribute.service = {name}
+attribute.username = alice
+
+[/org/freedesktop/secrets/collection/login/2]
+label = Password for bob
+secret = bob = builder
+attribute.service = {name}
+attribute.username = bob
+"
+    );
+    let records = parse_secret_tool(&dump).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(
+        records[0].attributes["xdg:schema"],
+        "org.freedesktop.Secret.Generic"
+    );
+    assert_eq!(records[1].secret, b"bob = builder");
+    assert!(matches!(
+        parse_secret_tool("label = orphan"),
+        Err(Error::BadStoreFormat(_))
+    ));
+    assert!(matches!(
+        parse_secret_tool("[/path]\nlabel = no secret"),
+        Err(Error::BadStoreFormat(_))
+    ));
+    let store = Store::new().unwrap();
+    let summary = store
+        .import_secret_tool(&dump, &name, ConflictPolicy::Skip)
+        .unwrap();
+    assert_eq!(summary.created, 2);
+    let alice = entry_new(&name, "alice");
+    assert_eq!(alice.get_password().unwrap(), "alice secret");
+    let specifier: &Specifier = alice.as_any().downcast_ref().unwrap();
+    assert_eq!(specifier.get_label().unwrap(), "Password for alice");
+    assert_eq!(
+        entry_new(&name, "bob").get_password().unwrap(),
+        "bob = builder"
+    );
+    let target = entry_new_with_target(&name, &name, &name);
+    let specifier: &Specifier = target.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+fn test_dry_run() {
+    let name = generate_random_string();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let dry = Store::builder().dry_run(true).build().unwrap();
+    let planned = dry.build(&name, &name, Some(&modifiers)).unwrap();
+    planned.set_password("planned").unwrap();
+    let plan = dry.take_plan();
+    assert_eq!(plan.len(), 2, "{plan:?}");
+    assert_eq!(
+        plan[0],
+        PlannedChange::CreateCollection { name: name.clone() }
+    );
+    assert!(matches!(
+        &plan[1],
+        PlannedChange::CreateItem { collection, attributes, .. }
+            if *collection == name && attributes["username"] == name
+    ));
+    assert!(matches!(planned.get_password(), Err(Error::NoEntry)));
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("actual").unwrap();
+    let path = entry
+        .as_any()
+        .downcast_ref::<Specifier>()
+        .unwrap()
+        .item_path()
+        .unwrap();
+    planned.set_password("planned").unwrap();
+    planned.delete_credential().unwrap();
+    assert_eq!(
+        dry.take_plan(),
+        vec![
+            PlannedChange::SetSecret { path: path.clone() },
+            PlannedChange::DeleteItem { path },
+        ]
+    );
+    assert!(dry.take_plan().is_empty());
+    assert_eq!(entry.get_password().unwrap(), "actual");
+    // aliases aren't changed
+    let alias = generate_random_string();
+    dry.set_alias(&alias, &name).unwrap();
+    let collections = dry.list_collections().unwrap();
+    let collection = collections.iter().find(|c| c.label == name).unwrap();
+    assert_eq!(
+        dry.take_plan(),
+        vec![PlannedChange::SetAlias {
+            alias: alias.clone(),
+            path: collection.path.clone(),
+        }]
+    );
+    assert!(dry.read_alias(&alias).unwrap().is_none());
+    entry.delete_credential().unwrap();
+    let specifier: &Specifier = entry.as_any().downcast_ref().unwrap();
+    specifier.delete_target().unwrap();
+}
+
+#[test]
+#[ignore = "Removes the default alias while it runs"]
+fn test_dry_run_without_default() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let default = store.read_alias("default").unwrap().unwrap();
+    crate::bus::set_alias(
+        &dbus::blocking::Connection::new_session().unwrap(),
+        "default",
+        "/",
+    )
+    .unwrap();
+    let dry = Store::builder()
+        .dry_run(true)
+        .option("create-default-collection", "true")
+        .build()
+        .unwrap();
+    let planned = dry.build(&name, &name, None).unwrap();
+    let result = planned.set_password("planned");
+    let plan = dry.take_plan();
+    let collections = store.list_collections();
+    store.set_alias("default", &default).unwrap();
+    result.unwrap();
+    assert_eq!(
+        plan[0],
+        PlannedChange::CreateCollection {
+            name: "default".to_string()
+        }
+    );
+    assert!(matches!(&plan[1], PlannedChange::CreateItem { .. }));
+    assert!(!collections.unwrap().iter().any(|c| c.label == "Default"));
+}
+
+#[test]
+fn test_metrics() {
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let entry = store.build(&name, &name, None).unwrap();
+    assert!(store.metrics().operations.is_empty());
+    entry.set_password("measured").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "measured");
+    entry.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    let metrics = store.metrics();
+    assert_eq!(metrics.operations["create"].count, 1);
+    assert_eq!(metrics.operations["delete"].count, 1);
+    let searches = &metrics.operations["search"];
+    assert!(searches.count >= 3);
+    let latency = &searches.latency;
+    assert_eq!(
+        latency.buckets.iter().map(|(_, n)| n).sum::<u64>(),
+        searches.count
+    );
+    assert_eq!(latency.buckets.last().unwrap().0, std::time::Duration::MAX);
+    assert!(latency.max <= latency.total);
+    assert!(store.unlock_collection(&name).is_err());
+    let metrics = store.metrics();
+    assert_eq!(metrics.operations["unlock"].errors, 1);
+    assert_eq!(metrics.error_counts.values().sum::<u64>(), 1);
+}
+
+#[test]
+fn test_hooks() {
+    use crate::hooks::{Access, AccessKind};
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let log: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let denied = format!("{name}-denied");
+    let veto = denied.clone();
+    store.on_before(Arc::new(move |access: &Access| {
+        match access.kind == AccessKind::Write && access.service.as_deref() == Some(&veto) {
+            true => Err(Error::NoStorageAccess("read only".into())),
+            false => Ok(()),
+        }
+    }));
+    let after = log.clone();
+    store.on_after(Arc::new(move |access: &Access| {
+        after
+            .lock()
+            .unwrap()
+            .push(format!("after {}", access.method));
+    }));
+    let errors = log.clone();
+    store.on_error(Arc::new(move |access: &Access, err: &Error| {
+        let kind = match err {
+            Error::NoEntry => "NoEntry",
+            Error::NoStorageAccess(_) => "NoStorageAccess",
+            _ => "other",
+        };
+        errors
+            .lock()
+            .unwrap()
+            .push(format!("error {} {kind}", access.method));
+    }));
+    let entry = store.build(&name, &name, None).unwrap();
+    entry.set_password("hooked").unwrap();
+    assert_eq!(entry.get_password().unwrap(), "hooked");
+    let wrapper = entry.get_credential().unwrap();
+    wrapper.get_attributes().unwrap();
+    entry.delete_credential().unwrap();
+    assert!(matches!(entry.get_password(), Err(Error::NoEntry)));
+    let vetoed = store.build(&denied, &name, None).unwrap();
+    assert!(matches!(
+        vetoed.set_password("vetoed"),
+        Err(Error::NoStorageAccess(_))
+    ));
+    assert!(matches!(vetoed.get_password(), Err(Error::NoEntry)));
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "after set_secret",
+            "after get_secret",
+            "after get_attributes",
+            "after delete_credential",
+            "error get_secret NoEntry",
+            "error set_secret NoStorageAccess",
+            "error get_secret NoEntry",
+        ]
+    );
+}
+
+#[test]
+fn test_hooks_batch() {
+    use crate::hooks::{Access, AccessKind};
+    let name = generate_random_string();
+    let store = Store::new().unwrap();
+    let denied = format!("{name}-denied");
+    let veto = denied.clone();
+    store.on_before(Arc::new(move |access: &Access| {
+        match access.kind != AccessKind::Read && access.service.as_deref() == Some(&veto) {
+            true => Err(Error::NoStorageAccess("read only".into())),
+            false => Ok(()),
+        }
+    }));
+    let allowed = store.build(&name, &name, None).unwrap();
+    let protected = store.build(&denied, &name, None).unwrap();
+    let results = store
+        .set_many(&[(&allowed, b"allowed"), (&protected, b"denied")])
+        .unwrap();
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(Error::NoStorageAccess(_))));
+    assert!(matches!(protected.get_password(), Err(Error::NoEntry)));
+    entry_new(&denied, &name).set_password("existing").unwrap();
+    let results = store.delete_many(&[&allowed, &protected]).unwrap();
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(Error::NoStorageAccess(_))));
+    assert!(matches!(allowed.get_password(), Err(Error::NoEntry)));
+    assert_eq!(protected.get_password().unwrap(), "existing");
+    protected.delete_credential().unwrap_err();
+    entry_new(&denied, &name).delete_credential().unwrap();
+}
+
+#[test]
+fn test_no_prompt() {
+    use crate::errors::StoreError;
+
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("unprompted").unwrap();
+    let store = Store::new().unwrap();
+    let quiet = Store::builder().no_prompt(true).build().unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let unprompted = quiet.build(&name, &name, Some(&modifiers)).unwrap();
+    assert_eq!(unprompted.get_password().unwrap(), "unprompted");
+    store.lock_collection(&name).unwrap();
+    let would_prompt = |result: keyring_core::Result<()>| match result {
+        Err(Error::NoStorageAccess(inner)) => {
+            matches!(
+                *inner.downcast::<StoreError>().unwrap(),
+                StoreError::WouldPrompt(1)
+            )
+        }
+        _ => false,
+    };
+    assert!(would_prompt(unprompted.get_password().map(|_| ())));
+    assert!(would_prompt(quiet.unlock_collection(&name)));
+    assert!(store.collection_items(&name).unwrap()[0].locked);
+    store.unlock_collection(&name).unwrap();
+    assert_eq!(unprompted.get_password().unwrap(), "unprompted");
+    entry
+        .as_any()
+        .downcast_ref::<Specifier>()
+        .unwrap()
+        .delete_target()
+        .unwrap();
+}
+
+#[test]
+fn test_prompt_window() {
+    let name = generate_random_string();
+    let entry = entry_new_with_target(&name, &name, &name);
+    entry.set_password("parented").unwrap();
+    let store = Store::builder().prompt_window("0x2a").build().unwrap();
+    store.lock_collection(&name).unwrap();
+    store.unlock_collection(&name).unwrap();
+    assert!(!store.collection_items(&name).unwrap()[0].locked);
+    store.lock_collection(&name).unwrap();
+    let modifiers = HashMap::from([("target", name.as_str())]);
+    let parented = store.build(&name, &name, Some(&modifiers)).unwrap();
+    assert_eq!(parented.get_password().unwrap(), "parented");
+    assert!(matches!(
+        Store::new_with_configuration(&HashMap::from([("prompt-window", "")])),
+        Err(Error::Invalid(_, _))
+    ));
+    entry
+        .as_any()
+        .downcast_ref::<Specifier>()
+        .unwrap()
+        .delete_target()
+        .unwrap();
+}
@@ -0,0 +1,152 @@
+/*!
+
+Secret transforms.
+
+A store can be given a pipeline of transform stages that are applied
+to secrets on their way into and out of the Secret Service. Stages
+are applied in the order they were added when a secret is written,
+and in the reverse order when a secret is read, so each stage sees
+exactly the data it produced. This lets independent transforms
+(compression, client-side encryption, integrity tags) be composed
+without knowing about each other.
+
+The intermediate data passed between stages is wiped from memory
+once the next stage has used it, as is the data a secret is read from.
+
+Because transforms change the data that is stored, items written
+with one pipeline can only be read with the same pipeline. Add all
+the stages to a store before using it.
+
+The [Envelope] stage encrypts secrets with a key that the application
+holds, so that they're unreadable even to other processes that can talk
+to the Secret Service. Stores configured with `envelope-key` start with
+an envelope stage using that key.
+
+*/
+
+use std::sync::{Arc, RwLock};
+
+use keyring_core::{Error, Result};
+use zeroize::Zeroizing;
+
+use crate::mirror::Keys;
+
+/// A stage in a store's secret transform pipeline.
+pub trait Transform {
+    /// Transform a secret on its way into the store.
+    fn encode(&self, secret: &[u8]) -> Result<Vec<u8>>;
+
+    /// Reverse [encode](Transform::encode) on data read from the store.
+    ///
+    /// Data that can't be decoded should produce a
+    /// [BadDataFormat](keyring_core::Error::BadDataFormat) error.
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+pub type TransformStage = dyn Transform + Send + Sync;
+
+/// Supplies the key of an [Envelope] each time it's needed.
+pub type KeyCallback = dyn Fn() -> Result<Vec<u8>> + Send + Sync;
+
+/// A stage that encrypts secrets on the client side.
+///
+/// Secrets are encrypted with AES-256-CBC and authenticated with
+/// HMAC-SHA256, using keys derived from the envelope's key (as the
+/// records of [mirror](crate::mirror) files are). The key is either given
+/// when the envelope is created or fetched from a callback whenever a
+/// secret is encoded or decoded, so applications can keep it in (say) a
+/// hardware token or a key agent. Data that can't be authenticated with
+/// the key produces a [BadDataFormat](Error::BadDataFormat) error.
+///
+/// The envelope only authenticates the secret itself, not the item it's
+/// stored in: stages don't know which item they're working for. Anyone who
+/// can write to the Secret Service can therefore move an enveloped secret
+/// from one item to another (of the same store) without being detected.
+pub struct Envelope {
+    key: Box<KeyCallback>,
+}
+
+impl Envelope {
+    /// An envelope using the given key. A copy of the key is kept,
+    /// and wiped when the envelope is dropped.
+    pub fn new(key: &[u8]) -> Self {
+        let key = Zeroizing::new(key.to_vec());
+        Self::with_key_callback(move || Ok(key.to_vec()))
+    }
+
+    /// An envelope that gets its key from a callback.
+    ///
+    /// Errors from the callback are reported by the operation that
+    /// needed the key.
+    pub fn with_key_callback(key: impl Fn() -> Result<Vec<u8>> + Send + Sync + 'static) -> Self {
+        Envelope { key: Box::new(key) }
+    }
+
+    fn keys(&self) -> Result<Keys> {
+        let key = Zeroizing::new((self.key)()?);
+        Ok(Keys::derive(&key))
+    }
+}
+
+impl std::fmt::Debug for Envelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Envelope").finish_non_exhaustive()
+    }
+}
+
+impl Transform for Envelope {
+    fn encode(&self, secret: &[u8]) -> Result<Vec<u8>> {
+        self.keys()?.encrypt(secret)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.keys()?.decrypt(data) {
+            Ok(secret) => Ok(secret.to_vec()),
+            Err(reason) => Err(Error::BadDataFormat(
+                data.to_vec(),
+                format!("Enveloped secret {reason}").into(),
+            )),
+        }
+    }
+}
+
+/// The ordered stages used by a store.
+#[derive(Default)]
+pub(crate) struct Pipeline {
+    stages: RwLock<Vec<Arc<TransformStage>>>,
+}
+
+impl Pipeline {
+    pub(crate) fn push(&self, stage: Arc<TransformStage>) {
+        self.stages
+            .write()
+            .expect("RwLock failure in credential store: please report a bug")
+            .push(stage);
+    }
+
+    /// Encode a secret for the Secret Service; the result is wiped when dropped.
+    pub(crate) fn encode(&self, secret: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let stages = self
+            .stages
+            .read()
+            .expect("RwLock failure in credential store: please report a bug");
+        let mut data = Zeroizing::new(secret.to_vec());
+        for stage in stages.iter() {
+            data = Zeroizing::new(stage.encode(&data)?);
+        }
+        Ok(data)
+    }
+
+    /// Decode data read from the Secret Service, which is then wiped.
+    pub(crate) fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let stages = self
+            .stages
+            .read()
+            .expect("RwLock failure in credential store: please report a bug");
+        let mut secret = Zeroizing::new(data);
+        for stage in stages.iter().rev() {
+            secret = Zeroizing::new(stage.decode(&secret)?);
+        }
+        Ok(std::mem::take(&mut *secret))
+    }
+}